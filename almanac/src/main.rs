@@ -0,0 +1,203 @@
+mod record;
+mod trigger;
+
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use ephemeris::{
+	keyboard::Keyboard,
+	script::{self, Step},
+};
+use ipc::{
+	message_from_frame, receive_frame_async_ipc, send_frame_async_ipc, send_input_async_ipc,
+	send_input_ipc, Error, Message,
+};
+use logging::{info, warn, LogArgs};
+use record::Recorder;
+use std::{
+	collections::BTreeSet,
+	io::IsTerminal,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+use trigger::{Edge, Trigger};
+
+#[derive(Debug, Parser)]
+#[command(about = "Record input going past into a script and play it back on a chord")]
+struct Args {
+	/// Script to record to and play from, in ephemeris' format
+	#[arg(value_name = "FILE")]
+	script: PathBuf,
+	/// Chord that starts and stops recording
+	#[arg(long, default_value = "ctrl+alt+r")]
+	record_chord: String,
+	/// Chord that plays the script
+	#[arg(long, default_value = "ctrl+alt+p")]
+	play_chord: String,
+	/// Milliseconds between keys while playing `type` and `key` commands
+	#[arg(long, default_value_t = 12)]
+	key_delay: u64,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[derive(Debug)]
+enum Recording {
+	Off,
+	/// Waiting for the chord to be let go of, so it isn't in the script.
+	Starting,
+	On(Recorder),
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() || std::io::stdout().is_terminal() {
+		panic!("You need to put this between a source and a sink e.g. `eclipse | almanac macro.txt | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	// so the sink can still pause the source through this
+	ipc::reverse::relay();
+	let key_delay = Duration::from_millis(args.key_delay);
+
+	// names are looked up in the source's keymap once it sends one
+	let mut keyboard =
+		Keyboard::new("").ok_or_else(|| eyre!("Couldn't make the default keymap"))?;
+	let chord = |keyboard: &Keyboard, combo: &str| {
+		combo
+			.split('+')
+			.map(|name| {
+				keyboard
+					.named_key(name)
+					.ok_or_else(|| eyre!("No key called {name:?} in {combo:?}"))
+			})
+			.collect::<Result<Vec<_>>>()
+	};
+	let mut record_trigger = Trigger::new(chord(&keyboard, &args.record_chord)?);
+	let mut play_trigger = Trigger::new(chord(&keyboard, &args.play_chord)?);
+
+	let mut recording = Recording::Off;
+	let mut held = BTreeSet::new();
+	let playing = Arc::new(AtomicBool::new(false));
+	loop {
+		tokio::select! {
+			frame = receive_frame_async_ipc() => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				let Ok(message) = message_from_frame(&frame) else {
+					match send_frame_async_ipc(&frame).await {
+						Ok(()) => (),
+						Err(Error::Disconnected) => break,
+						Err(e) => warn!("{e}"),
+					}
+					continue;
+				};
+				if let Recording::On(recorder) = &mut recording {
+					recorder.record(&keyboard, &message, Instant::now());
+				}
+				match &message {
+					Message::Keymap(keymap) => match Keyboard::from_keymap_string(keymap) {
+						Some(new_keyboard) => {
+							match (chord(&new_keyboard, &args.record_chord), chord(&new_keyboard, &args.play_chord)) {
+								(Ok(record), Ok(play)) => {
+									record_trigger = Trigger::new(record);
+									play_trigger = Trigger::new(play);
+								}
+								(Err(e), _) | (_, Err(e)) => warn!("Keeping the chords from the last keymap: {e}"),
+							}
+							keyboard = new_keyboard;
+						}
+						None => warn!("Couldn't read the source's keymap, keeping the last one"),
+					},
+					Message::Key { keycode, pressed } => {
+						if *pressed {
+							held.insert(*keycode);
+						} else {
+							held.remove(keycode);
+						}
+						match (record_trigger.update(&held, *keycode, *pressed), recording) {
+							(Some(Edge::Completed), Recording::On(recorder)) => {
+								save(&args.script, &recorder.finish(&keyboard, record_trigger.keys()));
+								recording = Recording::Off;
+							}
+							(Some(Edge::Completed), Recording::Off) => recording = Recording::Starting,
+							(Some(Edge::Released), Recording::Starting) => {
+								info!("Recording to {}", args.script.display());
+								recording = Recording::On(Recorder::new(Instant::now()));
+							}
+							(_, state) => recording = state,
+						}
+						// played once the chord is let go of, so it isn't held through the script
+						if play_trigger.update(&held, *keycode, *pressed) == Some(Edge::Released) {
+							play(&args.script, &keyboard, key_delay, &playing);
+						}
+					}
+					Message::ResetInput => held.clear(),
+					_ => (),
+				}
+				let disconnect = message == Message::Disconnect;
+				match send_input_async_ipc(message).await {
+					Ok(()) => (),
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+				if disconnect {
+					break;
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	if let Recording::On(recorder) = recording {
+		save(&args.script, &recorder.finish(&keyboard, &[]));
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+fn save(path: &Path, script: &str) {
+	match std::fs::write(path, script) {
+		Ok(()) => info!("Saved the recording to {}", path.display()),
+		Err(e) => warn!("Couldn't save the recording to {}: {e}", path.display()),
+	}
+}
+
+/// Send the script's input alongside the stream, unless it's already playing.
+fn play(path: &Path, keyboard: &Keyboard, key_delay: Duration, playing: &Arc<AtomicBool>) {
+	if playing.load(Ordering::Relaxed) {
+		return;
+	}
+	let steps = std::fs::read_to_string(path)
+		.map_err(|e| e.to_string())
+		.and_then(|text| script::parse_script(&text))
+		.and_then(|commands| script::steps(&commands, keyboard, key_delay));
+	let steps = match steps {
+		Ok(steps) => steps,
+		Err(e) => {
+			warn!("Couldn't play {}: {e}", path.display());
+			return;
+		}
+	};
+	playing.store(true, Ordering::Relaxed);
+	let playing = playing.clone();
+	thread::spawn(move || {
+		for step in steps {
+			match step {
+				Step::Send(message) => match send_input_ipc(message) {
+					Ok(()) => (),
+					// the stream ends too, and with it this
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				},
+				Step::Sleep(duration) => thread::sleep(duration),
+			}
+		}
+		playing.store(false, Ordering::Relaxed);
+	});
+}
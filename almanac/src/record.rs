@@ -0,0 +1,171 @@
+use ephemeris::{
+	keyboard::Keyboard,
+	script::{Action, Command},
+};
+use ipc::Message;
+use logging::warn;
+use std::{
+	collections::BTreeSet,
+	time::{Duration, Instant},
+};
+
+// shorter gaps aren't worth a line of their own, they're added to the next one that is
+const MIN_SLEEP: Duration = Duration::from_millis(10);
+
+/// Turns the input going past into script commands, with the pauses between them.
+#[derive(Debug)]
+pub struct Recorder {
+	commands: Vec<Command>,
+	/// Keys and buttons pressed since recording started, so ones held from before aren't released.
+	held_keys: BTreeSet<u32>,
+	held_buttons: BTreeSet<u32>,
+	/// When the last pause was written, or recording started.
+	last: Instant,
+	warned_smooth_scroll: bool,
+}
+impl Recorder {
+	pub fn new(now: Instant) -> Self {
+		Recorder {
+			commands: Vec::new(),
+			held_keys: BTreeSet::new(),
+			held_buttons: BTreeSet::new(),
+			last: now,
+			warned_smooth_scroll: false,
+		}
+	}
+
+	pub fn record(&mut self, keyboard: &Keyboard, message: &Message, now: Instant) {
+		let command = match message {
+			Message::Key { keycode, pressed } => {
+				let action = if *pressed {
+					self.held_keys.insert(*keycode);
+					Action::Press
+				} else if self.held_keys.remove(keycode) {
+					Action::Release
+				} else {
+					return;
+				};
+				Command::Key {
+					combo: vec![keyboard.key_name(*keycode)],
+					action,
+				}
+			}
+			Message::MouseButton { button, pressed } => {
+				let action = if *pressed {
+					self.held_buttons.insert(*button);
+					Action::Press
+				} else if self.held_buttons.remove(button) {
+					Action::Release
+				} else {
+					return;
+				};
+				Command::Click {
+					button: *button,
+					action,
+				}
+			}
+			Message::MouseMove(delta) => Command::Move(delta.x, delta.y),
+			Message::MouseAxisDiscrete(scroll) => Command::Scroll(scroll.x, scroll.y),
+			Message::MouseAxisContinuous(_) => {
+				if !std::mem::replace(&mut self.warned_smooth_scroll, true) {
+					warn!("Scripts can only scroll in whole detents, leaving smooth scrolling out");
+				}
+				return;
+			}
+			_ => return,
+		};
+		let waited = now.saturating_duration_since(self.last);
+		if waited >= MIN_SLEEP {
+			self.commands.push(Command::Sleep(Duration::from_millis(
+				waited.as_millis() as u64
+			)));
+			self.last = now;
+		} else if let (Command::Move(dx, dy), Some(Command::Move(x, y))) =
+			(&command, self.commands.last_mut())
+		{
+			// a line per motion event would bury everything else
+			*x += dx;
+			*y += dy;
+			return;
+		}
+		self.commands.push(command);
+	}
+
+	/// The script, without the presses of the chord that stopped it and with anything still held
+	/// released at the end.
+	pub fn finish(mut self, keyboard: &Keyboard, chord: &[u32]) -> String {
+		let is_chord_key = |combo: &[String]| {
+			combo
+				.iter()
+				.all(|name| keyboard.named_key(name).is_some_and(|k| chord.contains(&k)))
+		};
+		while let Some(command) = self.commands.last() {
+			match command {
+				Command::Sleep(_) => (),
+				Command::Key {
+					combo,
+					action: Action::Press,
+				} if is_chord_key(combo) => (),
+				_ => break,
+			}
+			self.commands.pop();
+		}
+		for keycode in self.held_keys.iter().rev() {
+			if !chord.contains(keycode) {
+				self.commands.push(Command::Key {
+					combo: vec![keyboard.key_name(*keycode)],
+					action: Action::Release,
+				});
+			}
+		}
+		for button in &self.held_buttons {
+			self.commands.push(Command::Click {
+				button: *button,
+				action: Action::Release,
+			});
+		}
+		let mut script = String::from("# play with `ephemeris --script FILE` or the play chord\n");
+		for command in &self.commands {
+			script.push_str(&command.to_string());
+			script.push('\n');
+		}
+		script
+	}
+}
+
+#[test]
+fn test_record() {
+	let keyboard = Keyboard::new("us").unwrap();
+	let start = Instant::now();
+	let at = |ms| start + Duration::from_millis(ms);
+	let key = |keycode, pressed| Message::Key { keycode, pressed };
+	let mut recorder = Recorder::new(start);
+	// released from before recording started
+	recorder.record(&keyboard, &key(46, false), at(0));
+	recorder.record(&keyboard, &key(30, true), at(2));
+	recorder.record(&keyboard, &key(30, false), at(50));
+	recorder.record(&keyboard, &Message::MouseMove([1.0, 2.0].into()), at(100));
+	recorder.record(&keyboard, &Message::MouseMove([3.0, -1.0].into()), at(105));
+	recorder.record(
+		&keyboard,
+		&Message::MouseButton {
+			button: 273,
+			pressed: true,
+		},
+		at(108),
+	);
+	// the stop chord
+	recorder.record(&keyboard, &key(29, true), at(300));
+	recorder.record(&keyboard, &key(19, true), at(310));
+	assert_eq!(
+		recorder.finish(&keyboard, &[29, 19]),
+		"# play with `ephemeris --script FILE` or the play chord\n\
+		keydown a\n\
+		sleep 50\n\
+		keyup a\n\
+		sleep 50\n\
+		move 4 1\n\
+		mousedown right\n\
+		mouseup right\n"
+	);
+}
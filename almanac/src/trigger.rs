@@ -0,0 +1,66 @@
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+	/// The last of the chord's keys went down.
+	Completed,
+	/// Every key of a completed chord is up again.
+	Released,
+}
+
+/// A chord of keys that sets something off.
+#[derive(Debug, Default)]
+pub struct Trigger {
+	keys: Vec<u32>,
+	completed: bool,
+}
+impl Trigger {
+	pub fn new(keys: Vec<u32>) -> Self {
+		Trigger {
+			keys,
+			completed: false,
+		}
+	}
+
+	pub fn keys(&self) -> &[u32] {
+		&self.keys
+	}
+
+	/// What a key changing does to the chord, given the keys held after it.
+	pub fn update(&mut self, held: &BTreeSet<u32>, keycode: u32, pressed: bool) -> Option<Edge> {
+		if self.keys.is_empty() || !self.keys.contains(&keycode) {
+			return None;
+		}
+		if pressed && !self.completed && self.keys.iter().all(|k| held.contains(k)) {
+			self.completed = true;
+			return Some(Edge::Completed);
+		}
+		if !pressed && self.completed && !self.keys.iter().any(|k| held.contains(k)) {
+			self.completed = false;
+			return Some(Edge::Released);
+		}
+		None
+	}
+}
+
+#[test]
+fn test_trigger() {
+	let mut trigger = Trigger::new(vec![29, 25]);
+	let mut held = BTreeSet::new();
+	let mut press = |held: &mut BTreeSet<u32>, keycode, pressed| {
+		if pressed {
+			held.insert(keycode);
+		} else {
+			held.remove(&keycode);
+		}
+		trigger.update(held, keycode, pressed)
+	};
+	assert_eq!(press(&mut held, 25, true), None);
+	assert_eq!(press(&mut held, 25, false), None);
+	assert_eq!(press(&mut held, 29, true), None);
+	assert_eq!(press(&mut held, 30, true), None);
+	assert_eq!(press(&mut held, 25, true), Some(Edge::Completed));
+	assert_eq!(press(&mut held, 25, false), None);
+	assert_eq!(press(&mut held, 29, false), Some(Edge::Released));
+	assert_eq!(press(&mut held, 29, true), None);
+}
@@ -0,0 +1,62 @@
+use crate::keymaps::Keymaps;
+use logging::info;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use zbus::{connection, fdo, interface, Connection};
+
+const PATH: &str = "/org/stardustxr/Azimuth";
+
+/// Switching layouts from outside azimuth, e.g. with a keybinding in the desktop.
+pub struct Layouts(pub Arc<Mutex<Keymaps>>);
+#[interface(name = "org.stardustxr.Azimuth.Layouts")]
+impl Layouts {
+	/// Send key events with the next keymap from now on, returning its id.
+	fn cycle(&self) -> fdo::Result<u64> {
+		let id = self
+			.0
+			.lock()
+			.cycle()
+			.ok_or_else(|| fdo::Error::Failed("No keymaps have been registered".to_string()))?;
+		info!(id, "Switched keymaps over D-Bus");
+		Ok(id)
+	}
+
+	/// How many keymaps there are to switch between.
+	#[zbus(property)]
+	fn count(&self) -> u32 {
+		self.0.lock().count() as u32
+	}
+}
+
+/// What was last copied on the source's desktop, for pasting into what's in XR.
+#[derive(Default)]
+pub struct Clipboard(String);
+#[interface(name = "org.stardustxr.Azimuth.Clipboard")]
+impl Clipboard {
+	/// The copied text, empty until the source sends some.
+	#[zbus(property)]
+	fn text(&self) -> String {
+		self.0.clone()
+	}
+}
+
+/// Serve [`Layouts`] and [`Clipboard`] on the session bus for as long as the connection is kept.
+pub async fn serve(keymaps: Arc<Mutex<Keymaps>>) -> zbus::Result<Connection> {
+	connection::Builder::session()?
+		.name("org.stardustxr.Azimuth")?
+		.serve_at(PATH, Layouts(keymaps))?
+		.serve_at(PATH, Clipboard::default())?
+		.build()
+		.await
+}
+
+/// Offer `text` as the clipboard, letting anyone watching the property know.
+pub async fn set_clipboard(connection: &Connection, text: String) -> zbus::Result<()> {
+	let clipboard = connection
+		.object_server()
+		.interface::<_, Clipboard>(PATH)
+		.await?;
+	let mut current = clipboard.get_mut().await;
+	current.0 = text;
+	current.text_changed(clipboard.signal_context()).await
+}
@@ -1,14 +1,22 @@
+use crate::reticle::{Reticle, ReticleState};
+use crate::targeting::{closest_hits, Captures};
+use config::reload::Live;
 use glam::Vec3;
-use rustc_hash::{FxHashMap, FxHashSet};
+use logging::debug;
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
 use stardust_xr_fusion::{
 	data::{PulseReceiver, PulseSenderHandler},
-	drawable::Lines,
 	fields::{Field, FieldRefAspect, RayMarchResult},
 	input::{InputHandler, InputMethod, InputMethodAspect, InputMethodHandler},
 	node::NodeType,
 	spatial::{SpatialAspect, Transform},
 };
-use tokio::task::JoinSet;
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::{task::JoinSet, time::timeout};
 
 #[derive(Debug, Default)]
 pub struct PulseReceiverCollector(pub FxHashMap<u64, (PulseReceiver, Field)>);
@@ -26,8 +34,9 @@ impl PulseSenderHandler for PulseReceiverCollector {
 pub struct PointerHandler {
 	pointer: InputMethod,
 	handlers: FxHashMap<u64, (InputHandler, Field)>,
-	capture_requests: FxHashSet<u64>,
-	captured: Option<u64>,
+	captures: Captures,
+	ray_march_timeout: Live<Duration>,
+	hover: HoverReport,
 }
 impl InputMethodHandler for PointerHandler {
 	fn create_handler(&mut self, handler: InputHandler, field: Field) {
@@ -35,33 +44,29 @@ impl InputMethodHandler for PointerHandler {
 			.insert(handler.node().get_id().unwrap(), (handler, field));
 	}
 	fn request_capture_handler(&mut self, uid: u64) {
-		dbg!(uid);
-		self.capture_requests.insert(uid);
+		debug!(uid, "capture requested");
+		self.captures.request(uid);
 	}
 	fn destroy_handler(&mut self, uid: u64) {
 		self.handlers.remove(&uid);
 	}
 }
 impl PointerHandler {
-	pub fn new(pointer: InputMethod) -> Self {
+	pub fn new(pointer: InputMethod, ray_march_timeout: Live<Duration>) -> Self {
 		PointerHandler {
 			pointer,
 			handlers: FxHashMap::default(),
-			capture_requests: FxHashSet::default(),
-			captured: None,
+			captures: Captures::default(),
+			ray_march_timeout,
+			hover: HoverReport::default(),
 		}
 	}
-	pub fn update_pointer(&mut self, pointer_reticle: Lines) {
-		if let Some(captured_id) = self.captured {
-			dbg!(captured_id);
-			if !self.capture_requests.contains(&captured_id) {
-				self.captured = None;
-			}
-		}
-		if self.captured.is_none() {
-			self.captured = self.capture_requests.drain().next();
-		}
-		if let Some((captured, _)) = self.captured.and_then(|id| self.handlers.get(&id)) {
+	pub fn update_pointer(&mut self, reticle: Arc<Mutex<Reticle>>) {
+		let captured_id = self.captures.frame();
+		if let Some((captured, _)) = captured_id.and_then(|id| self.handlers.get(&id)) {
+			debug!(captured_id, "captured");
+			self.hover.report(captured_id, true);
+			reticle.lock().set_state(ReticleState::Captured);
 			self.pointer.set_handler_order(&[captured.alias()]).unwrap();
 			self.pointer.set_captures(&[captured.alias()]).unwrap();
 			return;
@@ -69,58 +74,47 @@ impl PointerHandler {
 		let _ = self.pointer.set_captures(&[]);
 
 		let mut join = JoinSet::new();
+		let ray_march_timeout = *self.ray_march_timeout.get();
 		for (handler, field) in self.handlers.values() {
 			let handler = handler.alias();
 			let field = field.alias();
 			let pointer = self.pointer.alias();
 			join.spawn(async move {
-				(
-					handler,
-					field.ray_march(&pointer, [0.0; 3], [0.0, 0.0, -1.0]).await,
+				let start = Instant::now();
+				let result = timeout(
+					ray_march_timeout,
+					field.ray_march(&pointer, [0.0; 3], [0.0, 0.0, -1.0]),
 				)
+				.await;
+				metrics::ray_marched(start.elapsed(), result.is_err());
+				(handler, result)
 			});
 		}
 
 		let pointer = self.pointer.alias();
+		let hover = self.hover.clone();
 		tokio::spawn(async move {
 			let mut handlers: Vec<(InputHandler, RayMarchResult)> = Vec::new();
 			while let Some(res) = join.join_next().await {
-				let Ok((handler, Ok(ray_info))) = res else {
+				let Ok((handler, Ok(Ok(ray_info)))) = res else {
 					continue;
 				};
-				if ray_info.min_distance > 0.0 {
-					continue;
-				}
-				if ray_info.deepest_point_distance < 0.01 {
-					continue;
-				}
 				handlers.push((handler, ray_info));
 			}
-			let closest_hits = handlers
-				.into_iter()
-				.map(|(a, b)| (vec![a], b))
-				// now collect all handlers that are same distance if they're the closest
-				.reduce(|(mut handlers_a, result_a), (handlers_b, result_b)| {
-					if (result_a.deepest_point_distance - result_b.deepest_point_distance).abs()
-						< 0.001
-					{
-						// distance is basically the same
-						handlers_a.extend(handlers_b);
-						(handlers_a, result_a)
-					} else if result_a.deepest_point_distance < result_b.deepest_point_distance {
-						(handlers_a, result_a)
-					} else {
-						(handlers_b, result_b)
-					}
-				});
+			let closest_hits = closest_hits(handlers, |ray_info: &RayMarchResult| {
+				(ray_info.min_distance, ray_info.deepest_point_distance)
+			});
 			// let dbg_info = closest_hits
 			// 	.as_ref()
 			// 	.map(|(handlers, info)| (handlers.len(), info.deepest_point_distance));
 			// dbg!(dbg_info);
 			if let Some((hit_handlers, hit_info)) = closest_hits {
-				dbg!(hit_handlers.len());
+				debug!(count = hit_handlers.len(), "handlers hit");
+				hover.report(hit_handlers[0].node().get_id().ok(), false);
 				let _ = pointer.set_handler_order(hit_handlers.as_slice());
-				let _ = pointer_reticle.set_relative_transform(
+				let mut reticle = reticle.lock();
+				reticle.set_state(ReticleState::Hover);
+				let _ = reticle.root().set_relative_transform(
 					&pointer,
 					Transform::from_translation(
 						Vec3::from(hit_info.ray_origin)
@@ -130,8 +124,11 @@ impl PointerHandler {
 					),
 				);
 			} else {
+				hover.report(None, false);
 				let _ = pointer.set_handler_order(&[]);
-				let _ = pointer_reticle.set_relative_transform(
+				let mut reticle = reticle.lock();
+				reticle.set_state(ReticleState::Idle);
+				let _ = reticle.root().set_relative_transform(
 					&pointer,
 					Transform::from_translation([0.0, 0.0, -0.5]),
 				);
@@ -139,3 +136,18 @@ impl PointerHandler {
 		});
 	}
 }
+
+/// Tells the source which handler the pointer is on whenever that changes, see
+/// [`ipc::Message::Hover`].
+#[derive(Debug, Clone, Default)]
+struct HoverReport(Arc<Mutex<Option<(Option<u64>, bool)>>>);
+impl HoverReport {
+	fn report(&self, handler: Option<u64>, captured: bool) {
+		let mut last = self.0.lock();
+		if *last == Some((handler, captured)) {
+			return;
+		}
+		*last = Some((handler, captured));
+		let _ = ipc::reverse::send_upstream(&ipc::Message::Hover { handler, captured });
+	}
+}
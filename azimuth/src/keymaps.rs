@@ -0,0 +1,52 @@
+/// The keymaps sources have registered, and which one key events are sent with.
+#[derive(Debug, Default)]
+pub struct Keymaps {
+	ids: Vec<u64>,
+	current: usize,
+}
+impl Keymaps {
+	/// Add a keymap a source just sent and switch to it, since that's what it's typing with now.
+	pub fn add(&mut self, id: u64) {
+		self.current = match self.ids.iter().position(|&known| known == id) {
+			Some(index) => index,
+			None => {
+				self.ids.push(id);
+				self.ids.len() - 1
+			}
+		};
+	}
+
+	pub fn current(&self) -> Option<u64> {
+		self.ids.get(self.current).copied()
+	}
+
+	/// Switch to the next keymap, or back to the first after the last.
+	pub fn cycle(&mut self) -> Option<u64> {
+		if self.ids.is_empty() {
+			return None;
+		}
+		self.current = (self.current + 1) % self.ids.len();
+		self.current()
+	}
+
+	pub fn count(&self) -> usize {
+		self.ids.len()
+	}
+}
+
+#[test]
+fn test_cycle() {
+	let mut keymaps = Keymaps::default();
+	assert_eq!(keymaps.cycle(), None);
+	keymaps.add(7);
+	assert_eq!(keymaps.cycle(), Some(7));
+	keymaps.add(3);
+	keymaps.add(9);
+	assert_eq!(keymaps.current(), Some(9));
+	assert_eq!(keymaps.cycle(), Some(7));
+	assert_eq!(keymaps.cycle(), Some(3));
+	// sent again, e.g. by a source that reconnected
+	keymaps.add(7);
+	assert_eq!(keymaps.current(), Some(7));
+	assert_eq!(keymaps.count(), 3);
+}
@@ -0,0 +1,534 @@
+pub mod dbus;
+pub mod handlers;
+pub mod keymaps;
+pub mod pointing;
+pub mod reticle;
+pub mod targeting;
+pub mod trail;
+pub mod velocity;
+
+use clap::Parser;
+use color_eyre::eyre::Result;
+use config::{reload::Live, Config};
+use glam::Quat;
+use handlers::{PointerHandler, PulseReceiverCollector};
+use input_event_codes::{KEY_LEFTMETA, KEY_SPACE};
+use ipc::{heartbeat::HEARTBEAT_TIMEOUT, receive_input_async_ipc, Capabilities};
+use keymap_cache::KeymapCache;
+use keymaps::Keymaps;
+use logging::{info, info_span, warn, LogArgs};
+use metrics::MetricsArgs;
+use parking_lot::Mutex;
+use pointing::{PointerSettings, PointerState};
+use reticle::Reticle;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+use stardust_xr_fusion::{
+	client::Client,
+	core::values::{Datamap, Vector2},
+	data::{PulseReceiver, PulseSender, PulseSenderAspect},
+	fields::{FieldRefAspect, RayMarchResult},
+	input::{InputDataType, InputMethod, InputMethodAspect, Pointer},
+	node::NodeType,
+	objects::hmd,
+	root::{ClientState, FrameInfo, RootAspect, RootHandler},
+	spatial::{SpatialAspect, SpatialRef, SpatialRefAspect, Transform},
+	HandlerWrapper,
+};
+use stardust_xr_molecules::keyboard::{KeyboardEvent, KEYBOARD_MASK};
+use std::{
+	io::IsTerminal,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::{sync::watch, task::JoinSet, time::timeout};
+use trail::Trail;
+use velocity::Velocity;
+
+// degrees per pixel, unless the config says otherwise
+const MOUSE_SENSITIVITY: f32 = 0.1;
+// degrees across and down that an absolute device like a touchpad in absolute mode covers
+const ABSOLUTE_FOV: f32 = 90.0;
+// how long a single handler/receiver gets to answer a ray march before it's skipped
+const RAY_MARCH_TIMEOUT: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PointerDatamap {
+	mouse: (),
+	select: f32,
+	middle: f32,
+	context: f32,
+	grab: f32,
+	scroll_continuous: Vector2<f32>,
+	scroll_discrete: Vector2<f32>,
+	raw_input_events: FxHashSet<u32>,
+	/// 2 for a double click, 3 for a triple click and so on, 1 for a lone click.
+	click_count: f32,
+	/// Touchpad pinching and turning this frame, see [`ipc::Message::Magnify`] and
+	/// [`ipc::Message::Rotate`].
+	magnify: f32,
+	rotate: f32,
+	/// 1 on a frame with a two finger double tap.
+	smart_magnify: f32,
+	/// How fast the pointer is turning in degrees a second, to the right and down, see [`Velocity`].
+	angular_velocity: Vector2<f32>,
+	angular_acceleration: Vector2<f32>,
+	/// How hard a tablet's tool is pressed from 0 to 1, and how far it's tilted in degrees to the
+	/// right and towards the user, see [`ipc::Message::TabletAxis`].
+	pressure: f32,
+	tilt: Vector2<f32>,
+	/// 1 while the tool in reach of a tablet is an eraser, so drawing apps can rub out with it.
+	eraser: f32,
+}
+impl Default for PointerDatamap {
+	fn default() -> Self {
+		Self {
+			mouse: (),
+			select: 0.0,
+			middle: 0.0,
+			context: 0.0,
+			grab: 0.0,
+			scroll_continuous: [0.0; 2].into(),
+			scroll_discrete: [0.0; 2].into(),
+			raw_input_events: FxHashSet::default(),
+			click_count: 0.0,
+			magnify: 0.0,
+			rotate: 0.0,
+			smart_magnify: 0.0,
+			angular_velocity: [0.0; 2].into(),
+			angular_acceleration: [0.0; 2].into(),
+			pressure: 0.0,
+			tilt: [0.0; 2].into(),
+			eraser: 0.0,
+		}
+	}
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Desktop style pointer all around you from the input on stdin")]
+pub struct Args {
+	#[command(flatten)]
+	pub log: LogArgs,
+	#[command(flatten)]
+	pub metrics: MetricsArgs,
+	/// Read input from the source serving a socket in $XDG_RUNTIME_DIR called NAME, instead of stdin
+	#[arg(long, value_name = "NAME")]
+	pub socket: Option<String>,
+}
+
+#[tokio::main]
+pub async fn run(args: Args) -> Result<()> {
+	match &args.socket {
+		Some(name) => ipc::socket::connect(name),
+		None if std::io::stdin().is_terminal() => {
+			panic!(
+				"You need to pipe azimuth or eclipse's output into this e.g. `eclipse | azimuth`"
+			)
+		}
+		None => (),
+	}
+	// console_subscriber::init();
+	color_eyre::install().unwrap();
+	logging::init(&args.log)?;
+	metrics::init(&args.metrics)?;
+	config::reload::watch();
+	ipc::heartbeat::set_timeout(
+		config::reload::current()
+			.section("azimuth")
+			.get_millis("heartbeat-timeout-ms", HEARTBEAT_TIMEOUT),
+	);
+	let mouse_sensitivity = Live::new(|config: &Config| {
+		config
+			.section("azimuth")
+			.get("mouse-sensitivity", MOUSE_SENSITIVITY)
+	});
+	let absolute_fov =
+		Live::new(|config: &Config| config.section("azimuth").get("absolute-fov", ABSOLUTE_FOV));
+	// while the grab button is held, motion scrolls instead of turning the pointer
+	let grab_pans = Live::new(|config: &Config| config.section("azimuth").get("grab-pans", false));
+	let show_trail =
+		Live::new(|config: &Config| config.section("azimuth").get("pointer-trail", false));
+	// held together, with the last one pressed last, to type with the next keymap
+	let cycle_layout_keys = Live::new(|config: &Config| {
+		match config
+			.section("azimuth")
+			.get_list::<u32>("cycle-layout-keys")
+		{
+			keys if keys.is_empty() => vec![KEY_LEFTMETA!(), KEY_SPACE!()],
+			keys => keys,
+		}
+	});
+	let ray_march_timeout = Live::new(|config: &Config| {
+		config
+			.section("azimuth")
+			.get_millis("ray-march-timeout-ms", RAY_MARCH_TIMEOUT)
+	});
+	let (client, event_loop) = Client::connect_with_async_loop()
+		.await
+		.expect("Couldn't connect");
+	let hmd = hmd(&client).await.unwrap();
+
+	// Pointer stuff
+	let pointer = InputMethod::create(
+		client.get_root(),
+		Transform::identity(),
+		InputDataType::Pointer(Pointer {
+			origin: [0.0; 3].into(),
+			orientation: Quat::IDENTITY.into(),
+			deepest_point: [0.0; 3].into(),
+		}),
+		&Datamap::from_typed(PointerDatamap::default())?,
+	)?;
+	let handler = PointerHandler::new(pointer.alias(), ray_march_timeout.clone());
+	let pointer = pointer.wrap(handler)?;
+	let _ = pointer
+		.node()
+		.set_relative_transform(&hmd, Transform::from_translation([0.0; 3]));
+
+	let reticle = Arc::new(Mutex::new(Reticle::create(
+		pointer.node().as_ref(),
+		&config::reload::current(),
+	)?));
+	let trail = Arc::new(Mutex::new(Trail::create(client.get_root())?));
+
+	// Keyboard stuff
+	let keyboard_sender = PulseSender::create(
+		pointer.node().as_ref(),
+		Transform::identity(),
+		&KEYBOARD_MASK,
+	)?
+	.wrap(PulseReceiverCollector::default())?;
+	let (hovered_keyboard_tx, hovered_keyboard) = watch::channel::<Option<PulseReceiver>>(None);
+	let (frame_count_tx, frame_count_rx) = watch::channel(0);
+	let keymaps = Arc::new(Mutex::new(Keymaps::default()));
+	let dbus = dbus::serve(keymaps.clone())
+		.await
+		.map_err(|e| warn!("Can't switch layouts or share the clipboard over D-Bus: {e}"))
+		.ok();
+
+	// doing the actual handling
+	let input_loop = tokio::task::spawn(input_loop(
+		client.clone(),
+		pointer.node().alias(),
+		keyboard_sender.node().alias(),
+		hovered_keyboard,
+		frame_count_rx,
+		keymaps,
+		dbus,
+		mouse_sensitivity,
+		absolute_fov,
+		grab_pans,
+		cycle_layout_keys,
+	));
+	tokio::spawn(reconnect_keyboard_loop(
+		pointer.node().alias(),
+		keyboard_sender.wrapped().clone(),
+		hovered_keyboard_tx,
+		ray_march_timeout,
+	));
+	let _client_root = client.get_root().alias().wrap(Root {
+		root: client.get_root().alias(),
+		hmd,
+		pointer,
+		reticle,
+		trail,
+		show_trail,
+		frame_count_tx,
+	})?;
+
+	tokio::select! {
+		biased;
+		e = event_loop => e?.map_err(|e| e.into()),
+		_ = input_loop => Ok(()),
+	}
+}
+
+async fn input_loop(
+	client: Arc<Client>,
+	pointer: InputMethod,
+	keyboard_sender: PulseSender,
+	hovered_keyboard: watch::Receiver<Option<PulseReceiver>>,
+	mut frame_count_rx: watch::Receiver<u32>,
+	keymaps: Arc<Mutex<Keymaps>>,
+	dbus: Option<zbus::Connection>,
+	mut mouse_sensitivity: Live<f32>,
+	mut absolute_fov: Live<f32>,
+	mut grab_pans: Live<bool>,
+	mut cycle_layout_keys: Live<Vec<u32>>,
+) {
+	// the key that switched keymaps, which isn't sent when it's let go either
+	let mut cycling_key = None;
+
+	let mut held_keys = FxHashSet::default();
+	let mut keymap_cache = KeymapCache::open();
+	let mut pointer_state = PointerState::default();
+	let mut old_frame_count = 0_u32;
+	let mut velocity = Velocity::default();
+	// whether a batch from the source is partway through, which is applied before the next frame
+	let mut in_batch = false;
+	// let mut past_time = Instant::now();
+
+	// touchscreens and gamepads aren't worth the source sending
+	ipc::handshake::consume(
+		Capabilities::KEYBOARD
+			| Capabilities::POINTER
+			| Capabilities::TABLET
+			| Capabilities::GESTURES,
+	);
+	// kept between frames rather than started over, so the heartbeat timeout keeps counting
+	let next_message = receive_input_async_ipc();
+	tokio::pin!(next_message);
+	let signalled = ipc::shutdown::signalled();
+	tokio::pin!(signalled);
+	loop {
+		let message = tokio::select! {
+			message = &mut next_message => message,
+			// let go of everything just like when the source disconnects
+			_ = &mut signalled => Ok(ipc::Message::Disconnect),
+			Ok(()) = frame_count_rx.changed(), if !in_batch => {
+				if velocity.update([pointer_state.yaw, pointer_state.pitch], Instant::now()) {
+					let datamap = &mut pointer_state.datamap;
+					datamap.angular_velocity = velocity.velocity.into();
+					datamap.angular_acceleration = velocity.acceleration.into();
+					set_datamap(&pointer, datamap);
+				}
+				continue;
+			}
+		};
+		next_message.set(receive_input_async_ipc());
+		let Ok(message) = message else {
+			break;
+		};
+		metrics::received(message.name(), ipc::queued_frames());
+		let span = info_span!("handle ipc message");
+		let _span_enter = span.enter();
+		if !in_batch && *frame_count_rx.borrow() > old_frame_count {
+			old_frame_count = *frame_count_rx.borrow();
+			pointer_state.frame();
+		}
+		let settings = PointerSettings {
+			mouse_sensitivity: *mouse_sensitivity.get(),
+			absolute_fov: *absolute_fov.get(),
+			grab_pans: *grab_pans.get(),
+		};
+		// println!(
+		// 	"time since last event: {}",
+		// 	past_time.elapsed().as_secs_f32()
+		// );
+		// past_time = Instant::now();
+		match message {
+			ipc::Message::Keymap(keymap) => {
+				info!("IPC keymap message");
+				if let Some(cached_id) = keymap_cache.get(&keymap) {
+					info!(cached_id, "Keymap was registered before");
+					keymaps.lock().add(cached_id);
+					continue;
+				}
+				// keys sent while the keymap registers would otherwise pile up behind it
+				let _paused = ipc::flow::pause_source();
+				let Ok(future) = client.register_xkb_keymap(keymap.clone()) else {
+					continue;
+				};
+				let Ok(new_keymap_id) = future.await else {
+					continue;
+				};
+				keymap_cache.insert(&keymap, new_keymap_id);
+				keymaps.lock().add(new_keymap_id);
+			}
+			ipc::Message::Key { keycode, pressed } => {
+				info!("IPC key message");
+				if pressed {
+					if let Some((&last, held)) = cycle_layout_keys.get().split_last() {
+						if keycode == last && held.iter().all(|key| held_keys.contains(key)) {
+							if let Some(id) = keymaps.lock().cycle() {
+								info!(id, "Switched keymaps");
+							}
+							cycling_key = Some(keycode);
+							continue;
+						}
+					}
+				} else if cycling_key == Some(keycode) {
+					cycling_key = None;
+					continue;
+				}
+				if pressed {
+					held_keys.insert(keycode);
+				} else {
+					held_keys.remove(&keycode);
+				}
+				let Some(hovered_keyboard) = &*hovered_keyboard.borrow() else {
+					continue;
+				};
+				let Some(keymap_id) = keymaps.lock().current() else {
+					continue;
+				};
+				KeyboardEvent {
+					keyboard: (),
+					xkbv1: (),
+					keymap_id,
+					keys: vec![if pressed {
+						keycode as i32
+					} else {
+						-(keycode as i32)
+					}]
+					.into_iter()
+					.collect(),
+				}
+				.send_event(&keyboard_sender, &[hovered_keyboard])
+			}
+			// also what a source that stopped sending heartbeats turns into, so let go of everything
+			// here rather than counting on the source to send the releases
+			message @ (ipc::Message::ResetInput | ipc::Message::Disconnect) => {
+				info!("IPC reset input message");
+				let keymap_id = keymaps.lock().current();
+				if let (Some(hovered_keyboard), Some(keymap_id)) =
+					(&*hovered_keyboard.borrow(), keymap_id)
+				{
+					KeyboardEvent {
+						keyboard: (),
+						xkbv1: (),
+						keymap_id,
+						keys: held_keys.iter().map(|k| -(*k as i32)).collect(),
+					}
+					.send_event(&keyboard_sender, &[hovered_keyboard])
+				}
+				held_keys.clear();
+				cycling_key = None;
+				// the rest of a batch from a source that went away isn't coming
+				in_batch = false;
+				pointer_state.handle(&message, &settings);
+				set_datamap(&pointer, &pointer_state.datamap);
+				if message == ipc::Message::Disconnect {
+					break;
+				}
+			}
+			ipc::Message::BatchBegin => in_batch = true,
+			ipc::Message::BatchEnd => in_batch = false,
+			ipc::Message::ClipboardSet(text) => {
+				let Some(dbus) = &dbus else {
+					continue;
+				};
+				if let Err(e) = dbus::set_clipboard(dbus, text).await {
+					warn!("Couldn't share the clipboard over D-Bus: {e}");
+				}
+			}
+			message => {
+				let changed = pointer_state.handle(&message, &settings);
+				if changed.aim {
+					aim(&pointer, pointer_state.yaw, pointer_state.pitch);
+				}
+				if changed.datamap {
+					set_datamap(&pointer, &pointer_state.datamap);
+				}
+			}
+		}
+	}
+}
+
+fn set_datamap(pointer: &InputMethod, datamap: &PointerDatamap) {
+	pointer
+		.set_datamap(&Datamap::from_typed(datamap.clone()).unwrap())
+		.unwrap();
+}
+
+fn aim(pointer: &InputMethod, yaw: f32, pitch: f32) {
+	let rotation_x = Quat::from_rotation_x(-pitch.to_radians());
+	let rotation_y = Quat::from_rotation_y(-yaw.to_radians());
+	let _ = pointer.set_local_transform(Transform::from_rotation(rotation_y * rotation_x));
+}
+
+async fn reconnect_keyboard_loop(
+	pointer: InputMethod,
+	keyboard_sender: Arc<Mutex<PulseReceiverCollector>>,
+	hovered_keyboard_tx: watch::Sender<Option<PulseReceiver>>,
+	mut ray_march_timeout: Live<Duration>,
+) {
+	loop {
+		let ray_march_timeout = *ray_march_timeout.get();
+		let mut closest_hit: Option<(PulseReceiver, RayMarchResult)> = None;
+		let mut join = JoinSet::new();
+		for (receiver, field) in keyboard_sender.lock().0.values() {
+			let field = field.alias();
+			let pointer = pointer.alias();
+			let receiver = receiver.alias();
+			join.spawn(async move {
+				let start = Instant::now();
+				let result = timeout(
+					ray_march_timeout,
+					field.ray_march(&pointer, [0.0; 3], [0.0, 0.0, -1.0]),
+				)
+				.await;
+				metrics::ray_marched(start.elapsed(), result.is_err());
+				(receiver, result)
+			});
+		}
+		while let Some(res) = join.join_next().await {
+			let Ok((receiver, Ok(Ok(ray_info)))) = res else {
+				continue;
+			};
+			if ray_info.min_distance > 0.0 || ray_info.deepest_point_distance <= 0.001 {
+				continue;
+			}
+			if let Some((hit_receiver, hit_info)) = &mut closest_hit {
+				if ray_info.deepest_point_distance < hit_info.deepest_point_distance {
+					*hit_receiver = receiver;
+					*hit_info = ray_info;
+				}
+			} else {
+				closest_hit.replace((receiver, ray_info));
+			}
+		}
+		let hovered_keyboard = closest_hit.map(|(r, _)| r);
+		if receiver_id(&hovered_keyboard) != receiver_id(&hovered_keyboard_tx.borrow()) {
+			metrics::FOCUS_SWITCHES.inc("keyboard");
+		}
+		let _ = hovered_keyboard_tx.send(hovered_keyboard);
+		tokio::time::sleep(Duration::from_secs_f64(0.1)).await
+	}
+}
+
+fn receiver_id(receiver: &Option<PulseReceiver>) -> Option<u64> {
+	receiver.as_ref().and_then(|r| r.node().get_id().ok())
+}
+
+struct Root {
+	root: stardust_xr_fusion::root::Root,
+	hmd: SpatialRef,
+	pointer: HandlerWrapper<InputMethod, PointerHandler>,
+	reticle: Arc<Mutex<Reticle>>,
+	trail: Arc<Mutex<Trail>>,
+	show_trail: Live<bool>,
+	frame_count_tx: watch::Sender<u32>,
+}
+impl RootHandler for Root {
+	fn frame(&mut self, _info: FrameInfo) {
+		self.frame_count_tx.send_modify(|i| *i += 1);
+		let _ = self
+			.pointer
+			.node()
+			.set_relative_transform(&self.hmd, Transform::from_translation([0.0; 3]));
+		self.pointer
+			.wrapped()
+			.lock()
+			.update_pointer(self.reticle.clone());
+		if !*self.show_trail.get() {
+			self.trail.lock().clear();
+			return;
+		}
+		// where the reticle ended up last frame, since it's moved once the ray marches finish
+		let reticle = self.reticle.lock().root().alias();
+		let root = self.root.alias();
+		let trail = self.trail.clone();
+		tokio::spawn(async move {
+			let Ok(transform) = reticle.get_transform(&root).await else {
+				return;
+			};
+			if let Some(position) = transform.translation {
+				trail.lock().push(position.into(), Instant::now());
+			}
+		});
+	}
+	fn save_state(&mut self) -> Result<ClientState> {
+		ClientState::from_root(&self.root)
+	}
+}
@@ -0,0 +1,303 @@
+//! What the stream does to the pointer's aim and datamap, apart from the fusion calls that carry
+//! it out.
+
+use crate::PointerDatamap;
+use input_event_codes::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_STYLUS, BTN_STYLUS2};
+use ipc::Message;
+use logging::info;
+use rustc_hash::FxHashSet;
+use stardust_xr_fusion::core::values::Vector2;
+
+/// The settings that change what messages do to the pointer, from the `azimuth` section of the
+/// config.
+#[derive(Debug, Clone, Copy)]
+pub struct PointerSettings {
+	pub mouse_sensitivity: f32,
+	pub absolute_fov: f32,
+	/// Whether motion scrolls instead of turning the pointer while the grab button is held.
+	pub grab_pans: bool,
+}
+
+/// What a message changed, so only that is sent on to the server.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Changed {
+	pub aim: bool,
+	pub datamap: bool,
+}
+impl Changed {
+	const AIM: Self = Changed {
+		aim: true,
+		datamap: false,
+	};
+	const DATAMAP: Self = Changed {
+		aim: false,
+		datamap: true,
+	};
+	const BOTH: Self = Changed {
+		aim: true,
+		datamap: true,
+	};
+}
+
+/// Where the pointer aims and what's in its datamap, as the stream has left them.
+#[derive(Debug, Default)]
+pub struct PointerState {
+	pub datamap: PointerDatamap,
+	/// Degrees to the right.
+	pub yaw: f32,
+	/// Degrees down.
+	pub pitch: f32,
+	/// Mouse and tablet buttons held, for the datamap's raw input events.
+	buttons: FxHashSet<u32>,
+}
+impl PointerState {
+	/// Start a new frame, with nothing scrolled, pinched or turned in it yet.
+	pub fn frame(&mut self) {
+		self.datamap.scroll_continuous = [0.0; 2].into();
+		self.datamap.scroll_discrete = [0.0; 2].into();
+		self.datamap.magnify = 0.0;
+		self.datamap.rotate = 0.0;
+		self.datamap.smart_magnify = 0.0;
+	}
+
+	/// Apply a message to the pointer, returning what it changed.
+	pub fn handle(&mut self, message: &Message, settings: &PointerSettings) -> Changed {
+		let datamap = &mut self.datamap;
+		match message {
+			// panning the way dragging with a touchpad would, keeping the pointer where it is
+			Message::MouseMove(delta) if datamap.grab > 0.0 && settings.grab_pans => {
+				info!("IPC mouse move message");
+				let scroll_continuous = &mut datamap.scroll_continuous;
+				*scroll_continuous =
+					[scroll_continuous.x + delta.x, scroll_continuous.y + delta.y].into();
+				Changed::DATAMAP
+			}
+			Message::MouseMove(delta) => {
+				info!("IPC mouse move message");
+				self.yaw += delta.x * settings.mouse_sensitivity;
+				self.pitch += delta.y * settings.mouse_sensitivity;
+				self.pitch = self.pitch.clamp(-90.0, 90.0);
+				Changed::AIM
+			}
+			// straight ahead is the middle of the device, so touching a spot points at it
+			Message::MousePosition(position) => {
+				info!("IPC mouse position message");
+				self.point_at(*position, settings.absolute_fov);
+				Changed::AIM
+			}
+			Message::MouseButton { button, pressed } => {
+				info!("IPC mouse button message");
+				let (button, pressed) = (*button, *pressed);
+				if pressed {
+					// a MultiClick right after says otherwise
+					datamap.click_count = 1.0;
+				}
+				if button > 255 {
+					if pressed {
+						self.buttons.insert(button);
+					} else {
+						self.buttons.remove(&button);
+					}
+				}
+				datamap.raw_input_events.clone_from(&self.buttons);
+				let value = if pressed { 1.0 } else { 0.0 };
+				match button {
+					BTN_LEFT!() => datamap.select = value,
+					BTN_MIDDLE!() => datamap.middle = value,
+					BTN_RIGHT!() => datamap.context = value,
+					// idk why this number but that's what it spits out for side mousebuttons lol
+					_ => datamap.grab = value,
+				}
+				Changed::DATAMAP
+			}
+			Message::MultiClick { count, .. } => {
+				info!("IPC multi click message");
+				datamap.click_count = *count as f32;
+				Changed::DATAMAP
+			}
+			Message::MouseAxisContinuous(scroll) => {
+				info!("IPC mouse axis continuous message");
+				let scroll_continuous = &mut datamap.scroll_continuous;
+				*scroll_continuous = [
+					scroll_continuous.x + scroll.x,
+					scroll_continuous.y + scroll.y,
+				]
+				.into();
+				Changed::DATAMAP
+			}
+			Message::MouseAxisDiscrete(scroll) => {
+				info!("IPC mouse axis discrete message");
+				let scroll_discrete = &mut datamap.scroll_discrete;
+				*scroll_discrete =
+					[scroll_discrete.x + scroll.x, scroll_discrete.y + scroll.y].into();
+				Changed::DATAMAP
+			}
+			Message::Magnify(delta) => {
+				info!("IPC magnify message");
+				datamap.magnify += delta;
+				Changed::DATAMAP
+			}
+			Message::Rotate(delta) => {
+				info!("IPC rotate message");
+				datamap.rotate += delta;
+				Changed::DATAMAP
+			}
+			Message::SmartMagnify => {
+				info!("IPC smart magnify message");
+				datamap.smart_magnify = 1.0;
+				Changed::DATAMAP
+			}
+			Message::TabletProximity { tool, in_proximity } => {
+				info!("IPC tablet proximity message");
+				datamap.eraser = if *in_proximity && *tool == ipc::TabletTool::Eraser {
+					1.0
+				} else {
+					0.0
+				};
+				if !in_proximity {
+					datamap.pressure = 0.0;
+					datamap.tilt = [0.0; 2].into();
+				}
+				Changed::DATAMAP
+			}
+			// pointing with the tablet the same way as with an absolute touchpad
+			Message::TabletAxis {
+				position,
+				pressure,
+				tilt,
+			} => {
+				info!("IPC tablet axis message");
+				datamap.pressure = *pressure;
+				datamap.tilt = *tilt;
+				self.point_at(*position, settings.absolute_fov);
+				Changed::BOTH
+			}
+			// the tip clicks like the left mouse button so apps that don't know about pressure work
+			Message::TabletTip { down } => {
+				info!("IPC tablet tip message");
+				if *down {
+					datamap.click_count = 1.0;
+				}
+				datamap.select = if *down { 1.0 } else { 0.0 };
+				Changed::DATAMAP
+			}
+			Message::TabletButton { button, pressed } => {
+				info!("IPC tablet button message");
+				if *pressed {
+					self.buttons.insert(*button);
+				} else {
+					self.buttons.remove(button);
+				}
+				datamap.raw_input_events.clone_from(&self.buttons);
+				let value = if *pressed { 1.0 } else { 0.0 };
+				match *button {
+					BTN_STYLUS!() => datamap.context = value,
+					BTN_STYLUS2!() => datamap.middle = value,
+					_ => (),
+				}
+				Changed::DATAMAP
+			}
+			// let go of everything rather than counting on the source to send the releases
+			Message::ResetInput | Message::Disconnect => {
+				self.buttons.clear();
+				datamap.raw_input_events.clear();
+				datamap.select = 0.0;
+				datamap.middle = 0.0;
+				datamap.context = 0.0;
+				datamap.grab = 0.0;
+				datamap.pressure = 0.0;
+				datamap.eraser = 0.0;
+				Changed::DATAMAP
+			}
+			_ => Changed::default(),
+		}
+	}
+
+	fn point_at(&mut self, position: Vector2<f32>, absolute_fov: f32) {
+		self.yaw = (position.x - 0.5) * absolute_fov;
+		self.pitch = ((position.y - 0.5) * absolute_fov).clamp(-90.0, 90.0);
+	}
+}
+
+#[test]
+fn test_pointer_state() {
+	use input_event_codes::BTN_SIDE;
+
+	let settings = PointerSettings {
+		mouse_sensitivity: 0.5,
+		absolute_fov: 90.0,
+		grab_pans: true,
+	};
+	let mut state = PointerState::default();
+	let button = |button, pressed| Message::MouseButton { button, pressed };
+	// what each message changes, and the datamap and aim after it
+	let mut step = |message: Message, changed: Changed| {
+		assert_eq!(state.handle(&message, &settings), changed, "{message:?}");
+		(state.datamap.clone(), state.yaw, state.pitch)
+	};
+
+	let (_, yaw, pitch) = step(Message::MouseMove([20.0, 300.0].into()), Changed::AIM);
+	assert_eq!((yaw, pitch), (10.0, 90.0));
+	let (datamap, ..) = step(button(BTN_LEFT!(), true), Changed::DATAMAP);
+	assert_eq!((datamap.select, datamap.click_count), (1.0, 1.0));
+	assert!(datamap.raw_input_events.contains(&BTN_LEFT!()));
+	let (datamap, ..) = step(
+		Message::MultiClick {
+			button: BTN_LEFT!(),
+			count: 2,
+		},
+		Changed::DATAMAP,
+	);
+	assert_eq!(datamap.click_count, 2.0);
+
+	// holding grab pans instead of turning
+	step(button(BTN_SIDE!(), true), Changed::DATAMAP);
+	let (datamap, yaw, _) = step(Message::MouseMove([4.0, 2.0].into()), Changed::DATAMAP);
+	assert_eq!(yaw, 10.0);
+	step(
+		Message::MouseAxisContinuous([1.0, 1.0].into()),
+		Changed::DATAMAP,
+	);
+	let (scrolled, ..) = step(Message::Magnify(0.25), Changed::DATAMAP);
+	assert_eq!(datamap.scroll_continuous, Vector2::from([4.0, 2.0]));
+	assert_eq!(scrolled.scroll_continuous, Vector2::from([5.0, 3.0]));
+	assert_eq!(scrolled.magnify, 0.25);
+	// only for the frame it happened in
+	state.frame();
+	assert_eq!(state.datamap.scroll_continuous, Vector2::from([0.0, 0.0]));
+	assert_eq!(state.datamap.magnify, 0.0);
+
+	let mut step = |message: Message, changed: Changed| {
+		assert_eq!(state.handle(&message, &settings), changed, "{message:?}");
+		(state.datamap.clone(), state.yaw, state.pitch)
+	};
+	let (datamap, yaw, pitch) = step(
+		Message::TabletAxis {
+			position: [1.0, 0.25].into(),
+			pressure: 0.5,
+			tilt: [10.0, 0.0].into(),
+		},
+		Changed::BOTH,
+	);
+	assert_eq!((yaw, pitch), (45.0, -22.5));
+	assert_eq!(datamap.pressure, 0.5);
+	let (datamap, ..) = step(
+		Message::TabletProximity {
+			tool: ipc::TabletTool::Pen,
+			in_proximity: false,
+		},
+		Changed::DATAMAP,
+	);
+	assert_eq!(
+		(datamap.pressure, datamap.tilt),
+		(0.0, Vector2::from([0.0, 0.0]))
+	);
+
+	// nothing stays held once the source is gone
+	let (datamap, ..) = step(Message::ResetInput, Changed::DATAMAP);
+	assert_eq!((datamap.select, datamap.grab), (0.0, 0.0));
+	assert!(datamap.raw_input_events.is_empty());
+	// which doesn't move the pointer back
+	let (_, yaw, _) = step(Message::Heartbeat, Changed::default());
+	assert_eq!(yaw, 45.0);
+}
@@ -0,0 +1,110 @@
+//! What's drawn where the pointer points: a circle of lines, or glTF models from the config.
+
+use color_eyre::{eyre::Result, Report};
+use config::Config;
+use logging::warn;
+use stardust_xr_fusion::{
+	core::values::{color::rgba_linear, ResourceID},
+	drawable::{Lines, Model},
+	spatial::{Spatial, SpatialAspect, SpatialRefAspect, Transform},
+};
+use stardust_xr_molecules::lines::{circle, LineExt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReticleState {
+	/// Not on any handler.
+	Idle,
+	/// On a handler, which gets the next click.
+	Hover,
+	/// A handler captured the pointer.
+	Captured,
+}
+impl ReticleState {
+	const ALL: [ReticleState; 3] = [
+		ReticleState::Idle,
+		ReticleState::Hover,
+		ReticleState::Captured,
+	];
+
+	fn config_key(self) -> &'static str {
+		match self {
+			ReticleState::Idle => "reticle-idle",
+			ReticleState::Hover => "reticle-hover",
+			ReticleState::Captured => "reticle-captured",
+		}
+	}
+}
+
+pub struct Reticle {
+	/// Moved to where the pointer hits, with the circle and models under it.
+	root: Spatial,
+	circle: Lines,
+	/// One per [`ReticleState`], in the order of [`ReticleState::ALL`].
+	models: [Option<Model>; 3],
+	state: Option<ReticleState>,
+}
+impl Reticle {
+	/// The circle, plus a model for each state the config has a glTF file for.
+	pub fn create(parent: &impl SpatialRefAspect, config: &Config) -> Result<Self> {
+		let config = config.section("azimuth");
+		let root = Spatial::create(parent, Transform::from_translation([0.0, 0.0, -0.5]), false)?;
+		let line = circle(8, 0.0, 0.001)
+			.thickness(0.0025)
+			.color(rgba_linear!(1.0, 1.0, 1.0, 1.0));
+		let circle = Lines::create(&root, Transform::identity(), &[line])?;
+		let models = ReticleState::ALL.map(|state| {
+			let path: String = config.get(state.config_key(), String::new());
+			if path.is_empty() {
+				return None;
+			}
+			let model = ResourceID::new_direct(&path)
+				.map_err(Report::from)
+				.and_then(|resource| Ok(Model::create(&root, Transform::identity(), &resource)?));
+			match model {
+				Ok(model) => Some(model),
+				Err(e) => {
+					warn!("Couldn't load reticle model {path}: {e}");
+					None
+				}
+			}
+		});
+		let mut reticle = Reticle {
+			root,
+			circle,
+			models,
+			state: None,
+		};
+		reticle.set_state(ReticleState::Idle);
+		Ok(reticle)
+	}
+
+	/// Where to move the reticle to.
+	pub fn root(&self) -> &Spatial {
+		&self.root
+	}
+
+	/// Show the model for `state`, or the idle one if it has none, or else the circle.
+	pub fn set_state(&mut self, state: ReticleState) {
+		if self.state == Some(state) {
+			return;
+		}
+		self.state = Some(state);
+		let shown = [state, ReticleState::Idle]
+			.into_iter()
+			.map(|state| state as usize)
+			.find(|&index| self.models[index].is_some());
+		let visible = |shown: bool| {
+			if shown {
+				Transform::from_scale([1.0; 3])
+			} else {
+				Transform::from_scale([0.0; 3])
+			}
+		};
+		let _ = self.circle.set_local_transform(visible(shown.is_none()));
+		for (index, model) in self.models.iter().enumerate() {
+			if let Some(model) = model {
+				let _ = model.set_local_transform(visible(shown == Some(index)));
+			}
+		}
+	}
+}
@@ -0,0 +1,108 @@
+//! Which handlers the pointer goes to, apart from the fusion calls that carry it out.
+
+use rustc_hash::FxHashSet;
+
+// handlers closer together than this are hit at the same time
+const SAME_DISTANCE: f32 = 0.001;
+// closer than this is inside the pointer, e.g. the pointer's own field
+const MIN_DISTANCE: f32 = 0.01;
+
+/// Which handler has the pointer captured, from the capture requests that come in.
+///
+/// A capture lasts as long as the handler keeps asking for it every frame.
+#[derive(Debug, Default)]
+pub struct Captures {
+	requests: FxHashSet<u64>,
+	captured: Option<u64>,
+}
+impl Captures {
+	pub fn request(&mut self, handler: u64) {
+		self.requests.insert(handler);
+	}
+
+	/// Settle which handler has the pointer captured this frame, if any.
+	pub fn frame(&mut self) -> Option<u64> {
+		if let Some(captured) = self.captured {
+			if !self.requests.contains(&captured) {
+				self.captured = None;
+			}
+		}
+		if self.captured.is_none() {
+			self.captured = self.requests.iter().next().copied();
+		}
+		// asked for again next frame if it's still wanted
+		self.requests.clear();
+		self.captured
+	}
+}
+
+/// The handlers the pointer's ray hits first, with the ray march result of one of them.
+///
+/// `distances` gives a result's minimum distance and the distance to its deepest point. Handlers
+/// the ray doesn't go into, or that are right on top of the pointer, are left out, and handlers
+/// at the same distance all go together.
+pub fn closest_hits<H, R>(
+	hits: impl IntoIterator<Item = (H, R)>,
+	distances: impl Fn(&R) -> (f32, f32),
+) -> Option<(Vec<H>, R)> {
+	hits.into_iter()
+		.filter(|(_, result)| {
+			let (min_distance, deepest_point_distance) = distances(result);
+			min_distance <= 0.0 && deepest_point_distance >= MIN_DISTANCE
+		})
+		.map(|(handler, result)| (vec![handler], result))
+		.reduce(|(mut handlers_a, result_a), (handlers_b, result_b)| {
+			let distance_a = distances(&result_a).1;
+			let distance_b = distances(&result_b).1;
+			if (distance_a - distance_b).abs() < SAME_DISTANCE {
+				handlers_a.extend(handlers_b);
+				(handlers_a, result_a)
+			} else if distance_a < distance_b {
+				(handlers_a, result_a)
+			} else {
+				(handlers_b, result_b)
+			}
+		})
+}
+
+#[test]
+fn test_captures() {
+	let mut captures = Captures::default();
+	assert_eq!(captures.frame(), None);
+	captures.request(1);
+	assert_eq!(captures.frame(), Some(1));
+	// let go of once it stops asking
+	assert_eq!(captures.frame(), None);
+	captures.request(2);
+	assert_eq!(captures.frame(), Some(2));
+	// another handler can't take it while the first keeps asking
+	captures.request(2);
+	captures.request(3);
+	assert_eq!(captures.frame(), Some(2));
+	// and gets it as soon as the first stops asking
+	captures.request(3);
+	assert_eq!(captures.frame(), Some(3));
+	assert_eq!(captures.frame(), None);
+}
+
+#[test]
+fn test_closest_hits() {
+	let distances = |result: &(f32, f32)| *result;
+	let order = |hits: Vec<(u64, (f32, f32))>| closest_hits(hits, distances).map(|(h, _)| h);
+	assert_eq!(order(vec![]), None);
+	// missed, and right on top of the pointer
+	assert_eq!(order(vec![(1, (0.2, 0.5)), (2, (-0.1, 0.005))]), None);
+	assert_eq!(
+		order(vec![(1, (-0.1, 0.8)), (2, (-0.1, 0.3)), (3, (0.1, 0.1))]),
+		Some(vec![2])
+	);
+	// stacked panels at the same depth both get the pointer
+	assert_eq!(
+		order(vec![
+			(1, (-0.1, 0.3)),
+			(2, (-0.2, 0.9)),
+			(3, (-0.1, 0.3005))
+		]),
+		Some(vec![1, 3])
+	);
+}
@@ -0,0 +1,69 @@
+//! A fading trail behind the reticle, for recordings and for showing someone where the pointer is.
+
+use color_eyre::eyre::Result;
+use glam::Vec3;
+use stardust_xr_fusion::{
+	core::values::color::rgba_linear,
+	drawable::{Line, LinePoint, Lines, LinesAspect},
+	spatial::{SpatialRefAspect, Transform},
+};
+use std::{
+	collections::VecDeque,
+	time::{Duration, Instant},
+};
+
+// how long a point stays on the trail, fading out all the while
+const TRAIL_LENGTH: Duration = Duration::from_millis(500);
+const THICKNESS: f32 = 0.002;
+
+pub struct Trail {
+	lines: Lines,
+	/// Where the reticle was, oldest first.
+	points: VecDeque<(Vec3, Instant)>,
+}
+impl Trail {
+	/// An empty trail, with points relative to `parent`, which should stay put.
+	pub fn create(parent: &impl SpatialRefAspect) -> Result<Self> {
+		Ok(Trail {
+			lines: Lines::create(parent, Transform::identity(), &[])?,
+			points: VecDeque::new(),
+		})
+	}
+
+	/// Add where the reticle is now, dropping the points that have faded out.
+	pub fn push(&mut self, point: Vec3, now: Instant) {
+		while self
+			.points
+			.front()
+			.is_some_and(|(_, time)| now.duration_since(*time) > TRAIL_LENGTH)
+		{
+			self.points.pop_front();
+		}
+		self.points.push_back((point, now));
+		let points = self
+			.points
+			.iter()
+			.map(|(point, time)| {
+				let alpha =
+					1.0 - now.duration_since(*time).as_secs_f32() / TRAIL_LENGTH.as_secs_f32();
+				LinePoint {
+					point: (*point).into(),
+					thickness: THICKNESS * alpha,
+					color: rgba_linear!(1.0, 1.0, 1.0, alpha),
+				}
+			})
+			.collect();
+		let _ = self.lines.set_lines(&[Line {
+			points,
+			cyclic: false,
+		}]);
+	}
+
+	pub fn clear(&mut self) {
+		if self.points.is_empty() {
+			return;
+		}
+		self.points.clear();
+		let _ = self.lines.set_lines(&[]);
+	}
+}
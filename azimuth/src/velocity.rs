@@ -0,0 +1,79 @@
+//! How fast the pointer is turning, smoothed over a few frames so it's steady enough for flicks.
+
+use std::time::{Duration, Instant};
+
+// how quickly the velocity follows the pointer, independent of the frame rate
+const SMOOTHING: Duration = Duration::from_millis(50);
+// anything slower is rounded to standing still, so a still pointer isn't resent every frame
+const VELOCITY_REST: f32 = 0.01;
+const ACCELERATION_REST: f32 = 0.1;
+
+/// The pointer's angular velocity and acceleration, in degrees a second (and a second squared)
+/// to the right and down like mouse motion.
+#[derive(Debug, Default)]
+pub struct Velocity {
+	last: Option<([f32; 2], Instant)>,
+	pub velocity: [f32; 2],
+	pub acceleration: [f32; 2],
+}
+impl Velocity {
+	/// Take in where the pointer is aimed now, in degrees, returning whether either changed.
+	pub fn update(&mut self, angles: [f32; 2], now: Instant) -> bool {
+		let Some((last_angles, last_time)) = self.last.replace((angles, now)) else {
+			return false;
+		};
+		let dt = now.duration_since(last_time).as_secs_f32();
+		if dt <= 0.0 {
+			return false;
+		}
+		let blend = 1.0 - (-dt / SMOOTHING.as_secs_f32()).exp();
+		let old = self.velocity;
+		let velocity = [0, 1].map(|i| {
+			let raw = (angles[i] - last_angles[i]) / dt;
+			let smoothed = old[i] + (raw - old[i]) * blend;
+			if smoothed.abs() < VELOCITY_REST {
+				0.0
+			} else {
+				smoothed
+			}
+		});
+		let acceleration = [0, 1].map(|i| {
+			let acceleration = (velocity[i] - old[i]) / dt;
+			if acceleration.abs() < ACCELERATION_REST {
+				0.0
+			} else {
+				acceleration
+			}
+		});
+		let changed = velocity != self.velocity || acceleration != self.acceleration;
+		self.velocity = velocity;
+		self.acceleration = acceleration;
+		changed
+	}
+}
+
+#[test]
+fn test_velocity() {
+	let start = Instant::now();
+	let frame = |n: u32| start + Duration::from_millis(10) * n;
+	let mut velocity = Velocity::default();
+	assert!(!velocity.update([0.0, 0.0], frame(0)));
+	// turning right at 100 degrees a second
+	for n in 1..=50 {
+		assert!(velocity.update([n as f32, 0.0], frame(n)));
+	}
+	assert!((velocity.velocity[0] - 100.0).abs() < 1.0);
+	assert_eq!(velocity.velocity[1], 0.0);
+	assert!(velocity.acceleration[0].abs() < 10.0);
+	// stopping slows it down, and it comes to rest instead of creeping forever
+	velocity.update([50.0, 0.0], frame(51));
+	assert!(velocity.velocity[0] < 100.0);
+	assert!(velocity.acceleration[0] < 0.0);
+	let mut n = 52;
+	while velocity.update([50.0, 0.0], frame(n)) {
+		n += 1;
+		assert!(n < 200);
+	}
+	assert_eq!(velocity.velocity, [0.0, 0.0]);
+	assert_eq!(velocity.acceleration, [0.0, 0.0]);
+}
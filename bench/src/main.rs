@@ -0,0 +1,151 @@
+use clap::{Parser, ValueEnum};
+use color_eyre::Result;
+use input_event_codes::{BTN_LEFT, KEY_A, KEY_D, KEY_F, KEY_J, KEY_K, KEY_L, KEY_S, KEY_SPACE};
+use ipc::{frame_from_message, send_frame_ipc, send_input_ipc, Message};
+use logging::{warn, LogArgs};
+use std::{
+	f32::consts::TAU,
+	io::IsTerminal,
+	thread::sleep,
+	time::{Duration, Instant},
+};
+use xkbcommon::xkb::{Context, Keymap, KEYMAP_FORMAT_TEXT_V1};
+
+// home row keys mashed in typing bursts
+const TYPING_KEYS: [u32; 9] = [
+	KEY_A!(),
+	KEY_S!(),
+	KEY_D!(),
+	KEY_F!(),
+	KEY_SPACE!(),
+	KEY_J!(),
+	KEY_K!(),
+	KEY_L!(),
+	KEY_SPACE!(),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Load {
+	/// Small circular mouse motion, like a high polling rate mouse
+	Mouse,
+	/// Key presses and releases
+	Typing,
+	/// Smooth scrolling
+	Scroll,
+	/// Motion, scrolling, clicks and keys all interleaved
+	Storm,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Generate synthetic input load and measure how fast the sink takes it")]
+struct Args {
+	#[arg(long, value_enum, default_value_t = Load::Mouse)]
+	load: Load,
+	/// Messages per second to aim for, 0 to send as fast as the sink takes them
+	#[arg(long, default_value_t = 8000)]
+	rate: u32,
+	/// Send this many messages back to back, then wait as long as they'd have taken at the rate
+	#[arg(long, default_value_t = 1)]
+	burst: u32,
+	/// How long to run for, in seconds
+	#[arg(long, default_value_t = 10.0)]
+	duration: f32,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `bench | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+
+	let keymap = Keymap::new_from_names(&Context::new(0), "evdev", "", "", "", None, 0)
+		.unwrap()
+		.get_as_string(KEYMAP_FORMAT_TEXT_V1);
+	send_input_ipc(Message::Keymap(keymap))?;
+
+	let duration = Duration::from_secs_f32(args.duration);
+	let interval = (args.rate > 0).then(|| Duration::from_secs(1) / args.rate);
+	let burst = args.burst.max(1) as u64;
+	let mut write_times = Vec::new();
+	let start = Instant::now();
+	let mut sent = 0_u64;
+	while start.elapsed() < duration {
+		if let Some(interval) = interval {
+			// bursts all start at once, then everything waits for the rate to catch up
+			let due = start + interval * (sent - sent % burst) as u32;
+			if let Some(wait) = due.checked_duration_since(Instant::now()) {
+				sleep(wait);
+			}
+		}
+		let message = generate(args.load, sent);
+		let write_start = Instant::now();
+		// straight to the pipe, motion sent as input would be merged once the sink falls behind
+		let written = send_frame_ipc(&frame_from_message(&message));
+		write_times.push(write_start.elapsed());
+		// what was sent until then is still worth reporting
+		if let Err(e) = written {
+			warn!("Stopping early, {e}");
+			break;
+		}
+		sent += 1;
+	}
+	let elapsed = start.elapsed();
+	if let Err(e) = send_input_ipc(Message::ResetInput) {
+		warn!("Couldn't let go of everything: {e}");
+	}
+
+	write_times.sort_unstable();
+	let percentile = |p: f64| write_times[((write_times.len() - 1) as f64 * p) as usize];
+	let blocked: Duration = write_times.iter().sum();
+	eprintln!(
+		"Sent {sent} messages in {:.2}s, {:.0}/s{}",
+		elapsed.as_secs_f64(),
+		sent as f64 / elapsed.as_secs_f64(),
+		interval
+			.map(|_| format!(" of the {}/s asked for", args.rate))
+			.unwrap_or_default()
+	);
+	if !write_times.is_empty() {
+		eprintln!(
+			"Writes took {:?} median, {:?} at the 99th percentile, {:?} at worst",
+			percentile(0.5),
+			percentile(0.99),
+			write_times.last().unwrap()
+		);
+	}
+	eprintln!(
+		"Spent {:.1}% of the time writing, most of which is waiting on the sink when it can't keep up",
+		blocked.as_secs_f64() / elapsed.as_secs_f64() * 100.0
+	);
+	Ok(())
+}
+
+/// The `i`th message of a load.
+fn generate(load: Load, i: u64) -> Message {
+	match load {
+		Load::Mouse => {
+			let angle = (i % 360) as f32 / 360.0 * TAU;
+			Message::MouseMove([angle.cos() * 2.0, angle.sin() * 2.0].into())
+		}
+		Load::Typing => Message::Key {
+			keycode: TYPING_KEYS[(i / 2) as usize % TYPING_KEYS.len()],
+			pressed: i.is_multiple_of(2),
+		},
+		Load::Scroll => {
+			Message::MouseAxisContinuous([0.0, if i % 200 < 100 { 0.1 } else { -0.1 }].into())
+		}
+		Load::Storm => match i % 8 {
+			0 | 2 | 4 | 6 => generate(Load::Mouse, i),
+			1 => generate(Load::Scroll, i),
+			3 => Message::MouseButton {
+				button: BTN_LEFT!(),
+				pressed: i % 16 == 3,
+			},
+			_ => generate(Load::Typing, i / 8 * 2 + (i % 8 == 7) as u64),
+		},
+	}
+}
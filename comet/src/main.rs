@@ -0,0 +1,96 @@
+use clap::Parser;
+use color_eyre::Result;
+use ipc::{send_input_ipc, Message};
+use logging::{info, warn, LogArgs};
+use std::{
+	io::{BufRead, IsTerminal},
+	process::exit,
+};
+use xkbcommon::xkb::{Context, Keymap, KEYMAP_FORMAT_TEXT_V1};
+
+#[derive(Debug, Parser)]
+#[command(about = "Turn JSON messages on stdin, one per line, into the input stream on stdout")]
+struct Args {
+	/// Don't send the default keymap first, for when the lines bring their own
+	#[arg(long)]
+	no_keymap: bool,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `./script.py | comet | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+
+	// don't leave anything held down if the script is cut short
+	ipc::shutdown::on_signal(|| {
+		ipc::shutdown::end_stream();
+		exit(0)
+	});
+	ipc::heartbeat::start_heartbeat();
+	match send_lines(args.no_keymap) {
+		// nothing left to send the rest to
+		Err(e) if matches!(e.downcast_ref(), Some(ipc::Error::Disconnected)) => {
+			info!("The sink went away, stopping");
+			Ok(())
+		}
+		result => result,
+	}
+}
+
+fn send_lines(no_keymap: bool) -> Result<()> {
+	if !no_keymap {
+		let keymap = Keymap::new_from_names(&Context::new(0), "evdev", "", "", "", None, 0)
+			.unwrap()
+			.get_as_string(KEYMAP_FORMAT_TEXT_V1);
+		send_input_ipc(Message::Keymap(keymap))?;
+	}
+	for (number, line) in std::io::stdin().lock().lines().enumerate() {
+		match parse_line(&line?) {
+			Some(Ok(message)) => send_input_ipc(message)?,
+			Some(Err(e)) => warn!("Skipping line {}: {e}", number + 1),
+			None => (),
+		}
+	}
+	send_input_ipc(Message::ResetInput)?;
+	Ok(())
+}
+
+/// The message on a line, or `None` for blank lines and `#` comments.
+fn parse_line(line: &str) -> Option<serde_json::Result<Message>> {
+	let line = line.trim();
+	if line.is_empty() || line.starts_with('#') {
+		return None;
+	}
+	Some(serde_json::from_str(line))
+}
+
+#[test]
+fn test_parse_line() {
+	assert_eq!(
+		parse_line(r#"{"t":"Key","c":{"keycode":30,"pressed":true}}"#)
+			.unwrap()
+			.unwrap(),
+		Message::Key {
+			keycode: 30,
+			pressed: true
+		}
+	);
+	assert_eq!(
+		parse_line(r#" {"t":"MouseMove","c":[1.5,-2]} "#)
+			.unwrap()
+			.unwrap(),
+		Message::MouseMove([1.5, -2.0].into())
+	);
+	assert_eq!(
+		parse_line(r#"{"t":"ResetInput"}"#).unwrap().unwrap(),
+		Message::ResetInput
+	);
+	assert!(parse_line("").is_none());
+	assert!(parse_line("# comment").is_none());
+	assert!(parse_line(r#"{"t":"Nope"}"#).unwrap().is_err());
+}
@@ -0,0 +1,275 @@
+//! The config file shared by the tools, in KDL with a section per tool.
+//!
+//! Settings are read with a default, so a missing file, section or key is the same as leaving it
+//! at the compiled-in value. Environment variables named `STARDUST_INPUT_<SECTION>_<KEY>` override
+//! the file. See [`reload`] for picking up changes while running.
+
+pub mod reload;
+
+use kdl::KdlDocument;
+pub use kdl::KdlValue;
+use std::{env, path::PathBuf, time::Duration};
+use tracing::warn;
+
+// overrides where the config file is read from
+const PATH_VAR: &str = "STARDUST_INPUT_CONFIG";
+// prefix of the per-setting override variables
+const ENV_PREFIX: &str = "STARDUST_INPUT";
+
+/// A type a setting can be read as.
+pub trait Value: Sized {
+	fn from_kdl(value: &KdlValue) -> Option<Self>;
+	fn from_env(value: &str) -> Option<Self>;
+}
+impl Value for String {
+	fn from_kdl(value: &KdlValue) -> Option<Self> {
+		value.as_string().map(str::to_string)
+	}
+	fn from_env(value: &str) -> Option<Self> {
+		Some(value.to_string())
+	}
+}
+impl Value for bool {
+	fn from_kdl(value: &KdlValue) -> Option<Self> {
+		value.as_bool()
+	}
+	fn from_env(value: &str) -> Option<Self> {
+		value.parse().ok()
+	}
+}
+impl Value for f32 {
+	fn from_kdl(value: &KdlValue) -> Option<Self> {
+		// `1` is an integer in KDL but should still be fine for a float setting
+		value
+			.as_f64()
+			.or_else(|| value.as_i64().map(|i| i as f64))
+			.map(|f| f as f32)
+	}
+	fn from_env(value: &str) -> Option<Self> {
+		value.parse().ok()
+	}
+}
+impl Value for u64 {
+	fn from_kdl(value: &KdlValue) -> Option<Self> {
+		value.as_i64()?.try_into().ok()
+	}
+	fn from_env(value: &str) -> Option<Self> {
+		value.parse().ok()
+	}
+}
+impl Value for u32 {
+	fn from_kdl(value: &KdlValue) -> Option<Self> {
+		value.as_i64()?.try_into().ok()
+	}
+	fn from_env(value: &str) -> Option<Self> {
+		value.parse().ok()
+	}
+}
+
+/// Any value as it's written, for settings that mix types.
+impl Value for KdlValue {
+	fn from_kdl(value: &KdlValue) -> Option<Self> {
+		Some(value.clone())
+	}
+	fn from_env(value: &str) -> Option<Self> {
+		Some(KdlValue::String(value.to_string()))
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+	document: KdlDocument,
+}
+impl Config {
+	/// `$STARDUST_INPUT_CONFIG`, or `stardust-input/config.kdl` in the XDG config directory.
+	pub fn path() -> Option<PathBuf> {
+		if let Some(path) = env::var_os(PATH_VAR) {
+			return Some(path.into());
+		}
+		let config_home = env::var_os("XDG_CONFIG_HOME")
+			.filter(|dir| !dir.is_empty())
+			.map(PathBuf::from)
+			.or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+		Some(config_home.join("stardust-input").join("config.kdl"))
+	}
+
+	/// Read the config file, falling back to all defaults if there isn't one or it can't be parsed.
+	pub fn load() -> Self {
+		let Some(path) = Self::path() else {
+			return Config::default();
+		};
+		let Ok(text) = std::fs::read_to_string(&path) else {
+			return Config::default();
+		};
+		Self::parse(&text).unwrap_or_else(|e| {
+			warn!("Couldn't parse {}, using the defaults: {e}", path.display());
+			Config::default()
+		})
+	}
+
+	pub fn parse(text: &str) -> Result<Self, kdl::KdlError> {
+		Ok(Config {
+			document: text.parse()?,
+		})
+	}
+
+	/// The settings for one tool.
+	pub fn section<'a>(&'a self, name: &'a str) -> Section<'a> {
+		Section {
+			name,
+			document: self.document.get(name).and_then(|node| node.children()),
+		}
+	}
+}
+
+pub struct Section<'a> {
+	name: &'a str,
+	document: Option<&'a KdlDocument>,
+}
+impl Section<'_> {
+	/// A setting's first argument, or the default if it isn't set.
+	pub fn get<T: Value>(&self, key: &str, default: T) -> T {
+		if let Ok(value) = env::var(self.env_var(key)) {
+			return T::from_env(&value).unwrap_or_else(|| {
+				warn!(
+					"Ignoring {}={value:?}, it's not the right type",
+					self.env_var(key)
+				);
+				default
+			});
+		}
+		let Some(value) = self.document.and_then(|document| document.get_arg(key)) else {
+			return default;
+		};
+		T::from_kdl(value).unwrap_or_else(|| {
+			warn!(
+				"Ignoring {}.{key} {value}, it's not the right type",
+				self.name
+			);
+			default
+		})
+	}
+
+	/// A setting's arguments, or nothing if it isn't set.
+	pub fn get_list<T: Value>(&self, key: &str) -> Vec<T> {
+		if let Ok(value) = env::var(self.env_var(key)) {
+			return value
+				.split(',')
+				.filter(|item| !item.is_empty())
+				.filter_map(T::from_env)
+				.collect();
+		}
+		let Some(document) = self.document else {
+			return Vec::new();
+		};
+		document
+			.get_args(key)
+			.into_iter()
+			.filter_map(T::from_kdl)
+			.collect()
+	}
+
+	/// The arguments of every setting with this key, for ones that can be given more than once.
+	///
+	/// These can't be overridden from the environment.
+	pub fn get_each<T: Value>(&self, key: &str) -> Vec<Vec<T>> {
+		let Some(document) = self.document else {
+			return Vec::new();
+		};
+		document
+			.nodes()
+			.iter()
+			.filter(|node| node.name().value() == key)
+			.map(|node| {
+				node.entries()
+					.iter()
+					.filter(|entry| entry.name().is_none())
+					.filter_map(|entry| T::from_kdl(entry.value()))
+					.collect()
+			})
+			.collect()
+	}
+
+	/// A setting in milliseconds.
+	pub fn get_millis(&self, key: &str, default: Duration) -> Duration {
+		Duration::from_millis(self.get(key, default.as_millis() as u64))
+	}
+
+	fn env_var(&self, key: &str) -> String {
+		format!("{ENV_PREFIX}_{}_{}", self.name, key)
+			.to_uppercase()
+			.replace('-', "_")
+	}
+}
+
+#[test]
+fn test_sections() {
+	let config = Config::parse(
+		r#"
+		azimuth {
+			mouse-sensitivity 0.25
+			ray-march-timeout-ms 20
+		}
+		eclipse {
+			seat "seat1"
+			ignore-device "Yubico" "Wacom"
+		}
+		manifold {
+			mouse-sensitivity 2
+			release-key 5
+		}
+		"#,
+	)
+	.unwrap();
+	let azimuth = config.section("azimuth");
+	assert_eq!(azimuth.get("mouse-sensitivity", 0.1_f32), 0.25);
+	assert_eq!(
+		azimuth.get_millis("ray-march-timeout-ms", Duration::from_millis(50)),
+		Duration::from_millis(20)
+	);
+	assert!(azimuth.get("missing", true));
+
+	let eclipse = config.section("eclipse");
+	assert_eq!(eclipse.get("seat", "seat0".to_string()), "seat1");
+	assert_eq!(
+		eclipse.get_list::<String>("ignore-device"),
+		["Yubico", "Wacom"]
+	);
+
+	let manifold = config.section("manifold");
+	assert_eq!(manifold.get("mouse-sensitivity", 1.0_f32), 2.0);
+	// wrong type falls back to the default
+	assert_eq!(manifold.get("release-key", "q".to_string()), "q");
+
+	let prism = Config::parse(
+		r#"
+		prism {
+			remap-key 58 1
+			remap-key 1 58
+		}
+		"#,
+	)
+	.unwrap();
+	assert_eq!(
+		prism.section("prism").get_each::<u32>("remap-key"),
+		[[58, 1], [1, 58]]
+	);
+	let mixed = Config::parse(r#"nebula { key "/1/push1" 30; }"#).unwrap();
+	assert_eq!(
+		mixed.section("nebula").get_each::<KdlValue>("key"),
+		[[KdlValue::from("/1/push1"), KdlValue::from(30)]]
+	);
+
+	assert!(config
+		.section("simular")
+		.get_list::<String>("anything")
+		.is_empty());
+
+	env::set_var("STARDUST_INPUT_SIMULAR_RAY_MARCH_TIMEOUT_MS", "75");
+	assert_eq!(
+		config
+			.section("simular")
+			.get_millis("ray-march-timeout-ms", Duration::from_millis(50)),
+		Duration::from_millis(75)
+	);
+}
@@ -0,0 +1,152 @@
+//! Picking up changes to the config file without restarting.
+//!
+//! After [`watch`], the file is read again on SIGHUP or when it changes on disk. Settings read
+//! through a [`Live`] follow along, everything else keeps what it read at startup.
+
+use crate::Config;
+use signal_hook::consts::SIGHUP;
+use std::{
+	fmt::Debug,
+	sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Arc, Once, RwLock,
+	},
+	thread,
+	time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+
+// how often to look at whether the file changed
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+static CURRENT: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+// bumped on every reload, so a `Live` only has to read the config again when it moved on
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static WATCH: Once = Once::new();
+
+/// The config as of the last reload, loaded on first use.
+pub fn current() -> Arc<Config> {
+	if let Some(config) = &*CURRENT.read().unwrap() {
+		return config.clone();
+	}
+	CURRENT
+		.write()
+		.unwrap()
+		.get_or_insert_with(|| Arc::new(Config::load()))
+		.clone()
+}
+
+/// Reload the config on SIGHUP or when the file changes from now on.
+pub fn watch() {
+	WATCH.call_once(|| {
+		let hangup = Arc::new(AtomicBool::new(false));
+		if let Err(e) = signal_hook::flag::register(SIGHUP, hangup.clone()) {
+			warn!("Couldn't listen for SIGHUP, only file changes will reload the config: {e}");
+		}
+		let mut last_modified = modified();
+		thread::spawn(move || loop {
+			thread::sleep(POLL_INTERVAL);
+			let now_modified = modified();
+			if !hangup.swap(false, Ordering::Relaxed) && now_modified == last_modified {
+				continue;
+			}
+			last_modified = now_modified;
+			reload();
+		});
+	});
+}
+
+fn modified() -> Option<SystemTime> {
+	std::fs::metadata(Config::path()?).ok()?.modified().ok()
+}
+
+fn reload() {
+	let Some(path) = Config::path() else {
+		return;
+	};
+	let config = match std::fs::read_to_string(&path) {
+		Ok(text) => match Config::parse(&text) {
+			Ok(config) => config,
+			Err(e) => {
+				warn!(
+					"Couldn't parse {}, keeping the current settings: {e}",
+					path.display()
+				);
+				return;
+			}
+		},
+		// the file being gone means back to the defaults, same as at startup
+		Err(_) => Config::default(),
+	};
+	CURRENT.write().unwrap().replace(Arc::new(config));
+	GENERATION.fetch_add(1, Ordering::Relaxed);
+	info!("Reloaded the config");
+}
+
+/// A setting that's read again whenever the config is reloaded.
+///
+/// Clones each keep their own copy, so they can be handed to separate tasks.
+#[derive(Clone)]
+pub struct Live<T> {
+	read: Arc<dyn Fn(&Config) -> T + Send + Sync>,
+	value: T,
+	generation: u64,
+}
+impl<T> Live<T> {
+	pub fn new(read: impl Fn(&Config) -> T + Send + Sync + 'static) -> Self {
+		let generation = GENERATION.load(Ordering::Relaxed);
+		Live {
+			value: read(&current()),
+			read: Arc::new(read),
+			generation,
+		}
+	}
+
+	/// The setting as of the last reload.
+	pub fn get(&mut self) -> &T {
+		self.reload();
+		&self.value
+	}
+
+	/// Read the setting again if the config was reloaded since, returning whether it was.
+	pub fn reload(&mut self) -> bool {
+		let generation = GENERATION.load(Ordering::Relaxed);
+		if generation == self.generation {
+			return false;
+		}
+		self.generation = generation;
+		self.value = (self.read)(&current());
+		true
+	}
+}
+impl<T: Debug> Debug for Live<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_tuple("Live").field(&self.value).finish()
+	}
+}
+
+#[test]
+fn test_live() {
+	let path = std::env::temp_dir().join(format!("stardust-input-{}.kdl", std::process::id()));
+	std::env::set_var("STARDUST_INPUT_CONFIG", &path);
+	std::fs::write(&path, "azimuth { mouse-sensitivity 0.5; }").unwrap();
+	reload();
+	let mut sensitivity =
+		Live::new(|config: &Config| config.section("azimuth").get("mouse-sensitivity", 0.1_f32));
+	assert_eq!(*sensitivity.get(), 0.5);
+	assert!(!sensitivity.reload());
+
+	std::fs::write(&path, "azimuth { mouse-sensitivity 0.75; }").unwrap();
+	reload();
+	assert_eq!(*sensitivity.get(), 0.75);
+
+	// a broken file keeps what was there
+	std::fs::write(&path, "azimuth {").unwrap();
+	reload();
+	assert!(!sensitivity.reload());
+	assert_eq!(*sensitivity.get(), 0.75);
+
+	std::fs::remove_file(&path).unwrap();
+	reload();
+	assert_eq!(*sensitivity.get(), 0.1);
+}
@@ -0,0 +1,533 @@
+//! Golden frames and a mock source/sink for checking that sources and sinks speak the ipc stream.
+//!
+//! [`capture`] stands in for a sink, collecting everything a source writes so [`decode_stream`] and
+//! [`check`] can look it over. [`feed`] stands in for a source, writing frames to a sink's stdin.
+
+use ipc::{codec::Codecs, message_from_frame, ButtonBlot, Capabilities, Message, TabletTool};
+use std::{
+	fmt::Display,
+	io::{self, Read, Write},
+	process::{Command, ExitStatus, Stdio},
+	thread,
+	time::{Duration, Instant},
+};
+
+// how often to look at whether a captured source has exited yet
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A message and the exact bytes it has to be on the wire, length prefix included.
+pub struct Fixture {
+	pub name: &'static str,
+	pub message: Message,
+	pub frame: &'static [u8],
+}
+
+macro_rules! fixture {
+	($name:literal, $message:expr) => {
+		Fixture {
+			name: $name,
+			message: $message,
+			frame: include_bytes!(concat!("../fixtures/", $name, ".bin")),
+		}
+	};
+}
+
+/// One fixture per message, in an order that makes a conforming stream.
+pub fn fixtures() -> Vec<Fixture> {
+	vec![
+		fixture!(
+			"hello",
+			Message::Hello {
+				version: 1,
+				capabilities: Capabilities::KEYBOARD | Capabilities::POINTER,
+				codecs: Codecs::FLEXBUFFERS,
+			}
+		),
+		fixture!(
+			"device_added",
+			Message::DeviceAdded {
+				id: 3,
+				name: "Mouse".to_string(),
+				capabilities: Capabilities::POINTER,
+			}
+		),
+		fixture!("keymap", Message::Keymap("xkb_keymap {};".to_string())),
+		fixture!(
+			"key_press",
+			Message::Key {
+				keycode: 30,
+				pressed: true,
+			}
+		),
+		fixture!(
+			"key_release",
+			Message::Key {
+				keycode: 30,
+				pressed: false,
+			}
+		),
+		fixture!("mouse_move", Message::MouseMove([1.5, -2.0].into())),
+		fixture!(
+			"mouse_button_press",
+			Message::MouseButton {
+				button: 272,
+				pressed: true,
+			}
+		),
+		fixture!("mouse_position", Message::MousePosition([0.5, 0.25].into())),
+		fixture!(
+			"multi_click",
+			Message::MultiClick {
+				button: 272,
+				count: 2,
+			}
+		),
+		fixture!(
+			"mouse_button_release",
+			Message::MouseButton {
+				button: 272,
+				pressed: false,
+			}
+		),
+		fixture!(
+			"mouse_axis_continuous",
+			Message::MouseAxisContinuous([0.0, 1.25].into())
+		),
+		fixture!(
+			"mouse_axis_discrete",
+			Message::MouseAxisDiscrete([0.0, -1.0].into())
+		),
+		fixture!("magnify", Message::Magnify(0.125)),
+		fixture!("rotate", Message::Rotate(-15.0)),
+		fixture!("smart_magnify", Message::SmartMagnify),
+		fixture!(
+			"touch_down",
+			Message::TouchDown {
+				slot: 0,
+				position: [0.25, 0.5].into(),
+			}
+		),
+		fixture!(
+			"touch_motion",
+			Message::TouchMotion {
+				slot: 0,
+				position: [0.375, 0.5].into(),
+			}
+		),
+		fixture!("touch_up", Message::TouchUp { slot: 0 }),
+		fixture!(
+			"tablet_proximity",
+			Message::TabletProximity {
+				tool: TabletTool::Pen,
+				in_proximity: true,
+			}
+		),
+		fixture!(
+			"tablet_axis",
+			Message::TabletAxis {
+				position: [0.5, 0.75].into(),
+				pressure: 0.5,
+				tilt: [10.0, -20.0].into(),
+			}
+		),
+		fixture!("tablet_tip", Message::TabletTip { down: true }),
+		fixture!(
+			"tablet_button",
+			Message::TabletButton {
+				button: 331,
+				pressed: true,
+			}
+		),
+		fixture!(
+			"gamepad_connected",
+			Message::GamepadConnected {
+				id: 0,
+				name: "Gamepad".to_string(),
+			}
+		),
+		fixture!(
+			"gamepad_button",
+			Message::GamepadButton {
+				id: 0,
+				button: 304,
+				pressed: true,
+			}
+		),
+		fixture!(
+			"gamepad_axis",
+			Message::GamepadAxis {
+				id: 0,
+				axis: 0,
+				value: -0.5,
+			}
+		),
+		fixture!(
+			"gamepad_disconnected",
+			Message::GamepadDisconnected { id: 0 }
+		),
+		fixture!(
+			"key_repeat",
+			Message::KeyRepeat {
+				rate: 25,
+				delay: 600,
+			}
+		),
+		fixture!(
+			"clipboard_set",
+			Message::ClipboardSet("copied text".to_string())
+		),
+		fixture!("batch_begin", Message::BatchBegin),
+		fixture!("batch_end", Message::BatchEnd),
+		fixture!("device_removed", Message::DeviceRemoved { id: 3 }),
+		fixture!("reset_input", Message::ResetInput),
+		fixture!("heartbeat", Message::Heartbeat),
+		fixture!("disconnect", Message::Disconnect),
+	]
+}
+
+/// Every fixture's frame back to back, as a source would write them.
+pub fn fixture_stream() -> Vec<u8> {
+	fixtures().iter().flat_map(|f| f.frame).copied().collect()
+}
+
+/// A message as it goes on the wire, length prefix included.
+pub fn encode(message: &Message) -> Vec<u8> {
+	let payload = flexbuffers::to_vec(message).unwrap();
+	let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+	frame.extend_from_slice(&payload);
+	frame
+}
+
+/// Something a stream did that sources and sinks don't agree on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+	/// The frame at this index isn't a message.
+	Undecodable { index: usize },
+	/// The stream ends partway through a frame.
+	Truncated { index: usize },
+	/// A key was sent before any keymap, so the sink can't know what it means.
+	KeyBeforeKeymap { index: usize },
+	/// A motion or axis value is NaN or infinite.
+	NotFinite { index: usize },
+	/// A message that only goes from the sink back to the source, like `Pause`, was sent downstream.
+	Upstream { index: usize },
+	/// A `Hello` came after other messages, which the sink has read without knowing the version.
+	LateHello { index: usize },
+	/// A `BatchBegin` inside another batch, a `BatchEnd` outside of one, or a batch that never
+	/// ends.
+	UnbalancedBatch { index: usize },
+	/// These were still pressed when the stream ended.
+	StillHeld { keys: Vec<u32>, buttons: Vec<u32> },
+}
+impl Display for Violation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Violation::Undecodable { index } => write!(f, "frame {index} isn't a message"),
+			Violation::Truncated { index } => {
+				write!(f, "stream ends partway through frame {index}")
+			}
+			Violation::KeyBeforeKeymap { index } => {
+				write!(f, "message {index} is a key but no keymap was sent yet")
+			}
+			Violation::NotFinite { index } => write!(f, "message {index} isn't a finite number"),
+			Violation::Upstream { index } => {
+				write!(
+					f,
+					"message {index} only goes from the sink back to the source"
+				)
+			}
+			Violation::LateHello { index } => {
+				write!(f, "message {index} is a hello but isn't the first message")
+			}
+			Violation::UnbalancedBatch { index } => {
+				write!(
+					f,
+					"message {index} doesn't begin or end a batch where it should"
+				)
+			}
+			Violation::StillHeld { keys, buttons } => write!(
+				f,
+				"keys {keys:?} and mouse buttons {buttons:?} were never released"
+			),
+		}
+	}
+}
+impl std::error::Error for Violation {}
+
+/// Split a captured stream into its messages.
+pub fn decode_stream(mut stream: &[u8]) -> Result<Vec<Message>, Violation> {
+	let mut messages = Vec::new();
+	while !stream.is_empty() {
+		let index = messages.len();
+		let Some((length, rest)) = stream.split_first_chunk::<4>() else {
+			return Err(Violation::Truncated { index });
+		};
+		let length = u32::from_be_bytes(*length) as usize;
+		if rest.len() < length {
+			return Err(Violation::Truncated { index });
+		}
+		let (frame, rest) = rest.split_at(length);
+		messages.push(message_from_frame(frame).map_err(|_| Violation::Undecodable { index })?);
+		stream = rest;
+	}
+	Ok(messages)
+}
+
+/// Everything wrong with a stream of messages, empty if it conforms.
+pub fn check(messages: &[Message]) -> Vec<Violation> {
+	let mut violations = Vec::new();
+	let mut keymap = false;
+	let mut keys = ButtonBlot::default();
+	let mut buttons = ButtonBlot::default();
+	// where the batch that's still open began
+	let mut batch = None;
+	for (index, message) in messages.iter().enumerate() {
+		match message {
+			Message::Keymap(_) => keymap = true,
+			Message::Key { keycode, pressed } => {
+				if !keymap {
+					violations.push(Violation::KeyBeforeKeymap { index });
+				}
+				keys.key_update(*keycode, *pressed);
+			}
+			Message::MouseButton { button, pressed } => buttons.key_update(*button, *pressed),
+			Message::MouseMove(v)
+			| Message::MousePosition(v)
+			| Message::MouseAxisContinuous(v)
+			| Message::MouseAxisDiscrete(v)
+			| Message::TouchDown { position: v, .. }
+			| Message::TouchMotion { position: v, .. } => {
+				if !v.x.is_finite() || !v.y.is_finite() {
+					violations.push(Violation::NotFinite { index });
+				}
+			}
+			Message::TabletAxis {
+				position,
+				pressure,
+				tilt,
+			} => {
+				if ![position.x, position.y, *pressure, tilt.x, tilt.y]
+					.iter()
+					.all(|v| v.is_finite())
+				{
+					violations.push(Violation::NotFinite { index });
+				}
+			}
+			Message::Magnify(delta)
+			| Message::Rotate(delta)
+			| Message::GamepadAxis { value: delta, .. } => {
+				if !delta.is_finite() {
+					violations.push(Violation::NotFinite { index });
+				}
+			}
+			Message::ResetInput => {
+				keys = ButtonBlot::default();
+				buttons = ButtonBlot::default();
+			}
+			Message::Hello { .. } => {
+				if index > 0 {
+					violations.push(Violation::LateHello { index });
+				}
+			}
+			Message::BatchBegin => {
+				if batch.replace(index).is_some() {
+					violations.push(Violation::UnbalancedBatch { index });
+				}
+			}
+			Message::BatchEnd => {
+				if batch.take().is_none() {
+					violations.push(Violation::UnbalancedBatch { index });
+				}
+			}
+			Message::Pause | Message::Resume | Message::Hover { .. } => {
+				violations.push(Violation::Upstream { index })
+			}
+			Message::MultiClick { .. }
+			| Message::SmartMagnify
+			| Message::KeyRepeat { .. }
+			| Message::Heartbeat
+			| Message::Disconnect
+			| Message::ClipboardSet(_)
+			| Message::TouchUp { .. }
+			| Message::TabletProximity { .. }
+			| Message::TabletTip { .. }
+			| Message::TabletButton { .. }
+			| Message::GamepadConnected { .. }
+			| Message::GamepadDisconnected { .. }
+			| Message::GamepadButton { .. }
+			| Message::DeviceAdded { .. }
+			| Message::DeviceRemoved { .. } => (),
+		}
+	}
+	if let Some(index) = batch {
+		violations.push(Violation::UnbalancedBatch { index });
+	}
+	// a release without a press is fine, the key could have been down before the source started
+	let held = |blot: &ButtonBlot| {
+		let mut held: Vec<u32> = blot
+			.unbalanced()
+			.filter(|(_, count)| *count > 0)
+			.map(|(code, _)| code)
+			.collect();
+		held.sort_unstable();
+		held
+	};
+	let (keys, buttons) = (held(&keys), held(&buttons));
+	if !keys.is_empty() || !buttons.is_empty() {
+		violations.push(Violation::StillHeld { keys, buttons });
+	}
+	violations
+}
+
+/// Mock sink: run a source and collect everything it writes until it exits or the timeout is up.
+pub fn capture(command: &mut Command, timeout: Duration) -> io::Result<Vec<u8>> {
+	let mut child = command
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.spawn()?;
+	let mut stdout = child.stdout.take().unwrap();
+	let reader = thread::spawn(move || {
+		let mut stream = Vec::new();
+		let _ = stdout.read_to_end(&mut stream);
+		stream
+	});
+	let deadline = Instant::now() + timeout;
+	while child.try_wait()?.is_none() {
+		if Instant::now() >= deadline {
+			// sources that run until ctrl+c end up here
+			child.kill()?;
+			break;
+		}
+		thread::sleep(POLL_INTERVAL);
+	}
+	child.wait()?;
+	Ok(reader.join().unwrap())
+}
+
+/// Mock source: run a sink, write the stream to its stdin, then close it and wait for the sink to exit.
+pub fn feed(command: &mut Command, stream: &[u8]) -> io::Result<ExitStatus> {
+	let mut child = command.stdin(Stdio::piped()).spawn()?;
+	let mut stdin = child.stdin.take().unwrap();
+	// a sink that quits early on a Disconnect stops reading, which isn't a failure
+	match stdin.write_all(stream) {
+		Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+		_ => (),
+	}
+	drop(stdin);
+	child.wait()
+}
+
+#[test]
+fn golden_frames() {
+	let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+	for fixture in fixtures() {
+		let frame = encode(&fixture.message);
+		if update {
+			let path = format!(
+				"{}/fixtures/{}.bin",
+				env!("CARGO_MANIFEST_DIR"),
+				fixture.name
+			);
+			std::fs::write(path, &frame).unwrap();
+			continue;
+		}
+		assert_eq!(frame, fixture.frame, "{} encodes differently", fixture.name);
+		assert_eq!(
+			message_from_frame(&fixture.frame[4..]).unwrap(),
+			fixture.message,
+			"{} decodes differently",
+			fixture.name
+		);
+	}
+}
+
+#[test]
+fn fixture_stream_conforms() {
+	let messages = decode_stream(&fixture_stream()).unwrap();
+	assert_eq!(messages.len(), fixtures().len());
+	assert!(check(&messages).is_empty());
+}
+
+#[test]
+fn violations() {
+	let press = Message::Key {
+		keycode: 30,
+		pressed: true,
+	};
+	assert_eq!(
+		check(std::slice::from_ref(&press)),
+		[
+			Violation::KeyBeforeKeymap { index: 0 },
+			Violation::StillHeld {
+				keys: vec![30],
+				buttons: vec![]
+			}
+		]
+	);
+	assert_eq!(
+		check(&[Message::MouseMove([f32::NAN, 0.0].into())]),
+		[Violation::NotFinite { index: 0 }]
+	);
+	assert_eq!(
+		check(&[Message::Heartbeat, Message::Pause]),
+		[Violation::Upstream { index: 1 }]
+	);
+	assert_eq!(
+		check(&[
+			Message::Heartbeat,
+			Message::Hello {
+				version: 1,
+				capabilities: Capabilities::KEYBOARD,
+				codecs: Codecs::FLEXBUFFERS,
+			}
+		]),
+		[Violation::LateHello { index: 1 }]
+	);
+	assert_eq!(
+		check(&[
+			Message::BatchBegin,
+			Message::BatchBegin,
+			Message::BatchEnd,
+			Message::BatchEnd,
+			Message::BatchBegin,
+		]),
+		[
+			Violation::UnbalancedBatch { index: 1 },
+			Violation::UnbalancedBatch { index: 3 },
+			Violation::UnbalancedBatch { index: 4 },
+		]
+	);
+	assert!(check(&[Message::Keymap(String::new()), press, Message::ResetInput]).is_empty());
+
+	let stream = encode(&Message::Disconnect);
+	assert_eq!(
+		decode_stream(&stream[..stream.len() - 1]),
+		Err(Violation::Truncated { index: 0 })
+	);
+	assert_eq!(
+		decode_stream(&[0, 0, 0, 1, 0xff]),
+		Err(Violation::Undecodable { index: 0 })
+	);
+}
+
+#[test]
+fn mock_source_and_sink() {
+	let keymap = fixtures()
+		.into_iter()
+		.find(|fixture| fixture.name == "keymap")
+		.unwrap();
+	let path = format!(
+		"{}/fixtures/{}.bin",
+		env!("CARGO_MANIFEST_DIR"),
+		keymap.name
+	);
+	assert_eq!(
+		capture(Command::new("cat").arg(path), Duration::from_secs(5)).unwrap(),
+		keymap.frame
+	);
+	assert!(
+		feed(Command::new("cat").stdout(Stdio::null()), &fixture_stream())
+			.unwrap()
+			.success()
+	);
+}
@@ -0,0 +1,299 @@
+use crate::layout::{fixed_label, is_modifier, ROWS, ROW_WIDTH};
+use ephemeris::wayland::compositor_keymap;
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use ipc::{send_input_ipc, Error, Message};
+use logging::warn;
+use softbuffer::Surface;
+use std::{
+	collections::{BTreeSet, HashMap},
+	num::NonZeroU32,
+	process::exit,
+	rc::Rc,
+};
+use winit::{
+	dpi::{LogicalSize, PhysicalPosition},
+	event::{ElementState, Event, MouseButton, Touch, TouchPhase, WindowEvent},
+	event_loop::{EventLoop, EventLoopWindowTarget},
+	window::{Window, WindowBuilder},
+};
+use xkbcommon::xkb::{
+	self, keysym_get_name, Keycode, Keymap, State, KEYMAP_COMPILE_NO_FLAGS, KEYMAP_FORMAT_TEXT_V1,
+};
+
+// evdev keycodes are offset by 8 in xkb
+const EVDEV_OFFSET: u32 = 8;
+// touches have their own ids, the mouse gets one that won't clash
+const MOUSE_POINTER: u64 = u64::MAX;
+// space between keys, in pixels
+const GAP: f32 = 3.0;
+const BACKGROUND_COLOR: u32 = 0x181818;
+const KEY_COLOR: u32 = 0x383838;
+const HELD_COLOR: u32 = 0x707070;
+const TEXT_COLOR: u32 = 0xFFFFFF;
+
+/// Send to the sink, and exit once it's gone since nothing typed here gets anywhere anymore.
+fn send(message: Message) {
+	match send_input_ipc(message) {
+		Ok(()) => (),
+		Err(Error::Disconnected) => exit(0),
+		Err(e) => warn!("{e}"),
+	}
+}
+
+/// The compositor's keymap if there is one, otherwise whatever `XKB_DEFAULT_*` describe.
+fn system_keymap(context: &xkb::Context) -> Keymap {
+	let keymap = wayland_client::Connection::connect_to_env()
+		.ok()
+		.and_then(|conn| compositor_keymap(&conn))
+		.and_then(|compositor| {
+			Keymap::new_from_string(
+				context,
+				compositor.keymap,
+				KEYMAP_FORMAT_TEXT_V1,
+				KEYMAP_COMPILE_NO_FLAGS,
+			)
+		});
+	keymap.unwrap_or_else(|| Keymap::new_from_names(context, "", "", "", "", None, 0).unwrap())
+}
+
+pub struct KeyboardWindow {
+	window: Rc<Window>,
+	surface: Surface<Rc<Window>, Rc<Window>>,
+	/// Follows what's held so the labels show what the keys would type right now.
+	xkb_state: State,
+	/// The key each mouse button or touch is holding down.
+	pointers: HashMap<u64, u32>,
+	/// Modifiers clicked on, waiting for the next key.
+	latched: BTreeSet<u32>,
+	cursor: PhysicalPosition<f64>,
+}
+impl KeyboardWindow {
+	pub fn new(event_loop: &EventLoop<()>) -> Self {
+		let window = Rc::new(
+			WindowBuilder::new()
+				.with_title("Constellation")
+				.with_inner_size(LogicalSize::new(900, 300))
+				.with_min_inner_size(LogicalSize::new(300, 100))
+				.build(event_loop)
+				.unwrap(),
+		);
+
+		let keymap = system_keymap(&xkb::Context::new(0));
+		send(Message::Keymap(keymap.get_as_string(KEYMAP_FORMAT_TEXT_V1)));
+
+		let context = softbuffer::Context::new(window.clone()).unwrap();
+		let surface = softbuffer::Surface::new(&context, window.clone()).unwrap();
+
+		KeyboardWindow {
+			window,
+			surface,
+			xkb_state: State::new(&keymap),
+			pointers: HashMap::new(),
+			latched: BTreeSet::new(),
+			cursor: PhysicalPosition::default(),
+		}
+	}
+
+	pub fn handle_event(&mut self, event: Event<()>, elwt: &EventLoopWindowTarget<()>) {
+		let Event::WindowEvent { window_id, event } = event else {
+			return;
+		};
+		if window_id != self.window.id() {
+			return;
+		}
+		match event {
+			WindowEvent::CursorMoved { position, .. } => self.cursor = position,
+			WindowEvent::MouseInput {
+				state,
+				button: MouseButton::Left,
+				..
+			} => match state {
+				ElementState::Pressed => self.pointer_down(MOUSE_POINTER, self.cursor),
+				ElementState::Released => self.pointer_up(MOUSE_POINTER),
+			},
+			WindowEvent::CursorLeft { .. } => self.pointer_up(MOUSE_POINTER),
+			WindowEvent::Touch(Touch {
+				phase,
+				location,
+				id,
+				..
+			}) => match phase {
+				TouchPhase::Started => self.pointer_down(id, location),
+				TouchPhase::Moved => (),
+				TouchPhase::Ended | TouchPhase::Cancelled => self.pointer_up(id),
+			},
+			WindowEvent::Resized(_) => self.window.request_redraw(),
+			WindowEvent::RedrawRequested => self.redraw(),
+			WindowEvent::CloseRequested | WindowEvent::Destroyed => {
+				ipc::shutdown::end_stream();
+				elwt.exit();
+				exit(0);
+			}
+			_ => (),
+		}
+	}
+
+	fn pointer_down(&mut self, pointer: u64, position: PhysicalPosition<f64>) {
+		let Some(keycode) = self.key_at(position.x as f32, position.y as f32) else {
+			return;
+		};
+		if is_modifier(keycode) {
+			let latched = !self.latched.remove(&keycode);
+			if latched {
+				self.latched.insert(keycode);
+			}
+			self.key(keycode, latched);
+		} else if !self.pointers.values().any(|held| *held == keycode) {
+			self.pointers.insert(pointer, keycode);
+			self.key(keycode, true);
+		}
+	}
+
+	fn pointer_up(&mut self, pointer: u64) {
+		let Some(keycode) = self.pointers.remove(&pointer) else {
+			return;
+		};
+		self.key(keycode, false);
+		for modifier in std::mem::take(&mut self.latched) {
+			self.key(modifier, false);
+		}
+	}
+
+	fn key(&mut self, keycode: u32, pressed: bool) {
+		let direction = if pressed {
+			xkb::KeyDirection::Down
+		} else {
+			xkb::KeyDirection::Up
+		};
+		self.xkb_state
+			.update_key(Keycode::new(keycode + EVDEV_OFFSET), direction);
+		send(Message::Key { keycode, pressed });
+		self.window.request_redraw();
+	}
+
+	/// Every key's keycode and where it is in the window right now.
+	fn key_rects(&self) -> impl Iterator<Item = (u32, [f32; 4])> {
+		let size = self.window.inner_size();
+		let unit = size.width as f32 / ROW_WIDTH;
+		let row_height = size.height as f32 / ROWS.len() as f32;
+		ROWS.iter().enumerate().flat_map(move |(row, keys)| {
+			let mut x = 0.0;
+			keys.iter().map(move |(keycode, width)| {
+				let rect = [
+					x * unit + GAP,
+					row as f32 * row_height + GAP,
+					width * unit - GAP * 2.0,
+					row_height - GAP * 2.0,
+				];
+				x += width;
+				(*keycode, rect)
+			})
+		})
+	}
+
+	fn key_at(&self, x: f32, y: f32) -> Option<u32> {
+		self.key_rects()
+			.find(|(_, [rx, ry, w, h])| x >= *rx && x < rx + w && y >= *ry && y < ry + h)
+			.map(|(keycode, _)| keycode)
+	}
+
+	/// What the key would type with what's held now, or its keysym's name if it doesn't type anything printable.
+	fn label(&self, keycode: u32) -> String {
+		if let Some(label) = fixed_label(keycode) {
+			return label.to_string();
+		}
+		let xkb_keycode = Keycode::new(keycode + EVDEV_OFFSET);
+		let text = self.xkb_state.key_get_utf8(xkb_keycode);
+		if !text.is_empty() && text.chars().all(|c| !c.is_control()) {
+			return text;
+		}
+		keysym_get_name(self.xkb_state.key_get_one_sym(xkb_keycode))
+	}
+
+	fn redraw(&mut self) {
+		let size = self.window.inner_size();
+		let (Some(width), Some(height)) =
+			(NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+		else {
+			return;
+		};
+		self.surface.resize(width, height).unwrap();
+		let keys = self
+			.key_rects()
+			.map(|(keycode, rect)| {
+				let held = self.latched.contains(&keycode)
+					|| self.pointers.values().any(|held| *held == keycode);
+				(rect, held, self.label(keycode))
+			})
+			.collect::<Vec<_>>();
+
+		let mut buffer = self.surface.buffer_mut().unwrap();
+		let mut canvas = Canvas {
+			pixels: &mut buffer,
+			width: size.width as usize,
+			height: size.height as usize,
+		};
+		canvas.pixels.fill(BACKGROUND_COLOR);
+		for ([x, y, w, h], held, label) in keys {
+			canvas.fill(x, y, w, h, if held { HELD_COLOR } else { KEY_COLOR });
+			canvas.text(&label, x + w / 2.0, y + h / 2.0, w, h);
+		}
+		buffer.present().unwrap();
+	}
+}
+
+struct Canvas<'a> {
+	pixels: &'a mut [u32],
+	width: usize,
+	height: usize,
+}
+impl Canvas<'_> {
+	fn set(&mut self, x: usize, y: usize, color: u32) {
+		if x < self.width && y < self.height {
+			self.pixels[x + y * self.width] = color;
+		}
+	}
+
+	fn fill(&mut self, x: f32, y: f32, w: f32, h: f32, color: u32) {
+		for py in y.max(0.0) as usize..(y + h).max(0.0) as usize {
+			for px in x.max(0.0) as usize..(x + w).max(0.0) as usize {
+				self.set(px, py, color);
+			}
+		}
+	}
+
+	/// Draw text centered on a point, as large as fits in the space, cutting it short if even the smallest doesn't.
+	fn text(&mut self, text: &str, center_x: f32, center_y: f32, max_w: f32, max_h: f32) {
+		let glyphs = text
+			.chars()
+			.filter_map(|c| BASIC_FONTS.get(c).or_else(|| LATIN_FONTS.get(c)))
+			.collect::<Vec<_>>();
+		if glyphs.is_empty() {
+			return;
+		}
+		let fits = |scale: usize| (glyphs.len() * 8 * scale) as f32 <= max_w * 0.8;
+		let scale = ((max_h / 2.0 / 8.0) as usize).max(1);
+		let scale = (1..=scale).rev().find(|scale| fits(*scale)).unwrap_or(1);
+		let shown = glyphs.len().min((max_w / (8 * scale) as f32) as usize);
+		let left = (center_x as usize).saturating_sub(shown * 8 * scale / 2);
+		let top = (center_y as usize).saturating_sub(8 * scale / 2);
+		for (i, glyph) in glyphs[..shown].iter().enumerate() {
+			for (row, bits) in glyph.iter().enumerate() {
+				for column in 0..8 {
+					if bits & (1 << column) == 0 {
+						continue;
+					}
+					for dy in 0..scale {
+						for dx in 0..scale {
+							self.set(
+								left + (i * 8 + column) * scale + dx,
+								top + row * scale + dy,
+								TEXT_COLOR,
+							);
+						}
+					}
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,128 @@
+//! Where the keys go, by evdev keycode. The keymap decides what they say.
+
+use input_event_codes::*;
+
+/// A key and its width, in the width of a letter key.
+pub type LayoutKey = (u32, f32);
+
+// every row adds up to this many letter keys wide
+pub const ROW_WIDTH: f32 = 15.0;
+
+pub const ROWS: [&[LayoutKey]; 5] = [
+	&[
+		(KEY_GRAVE!(), 1.0),
+		(KEY_1!(), 1.0),
+		(KEY_2!(), 1.0),
+		(KEY_3!(), 1.0),
+		(KEY_4!(), 1.0),
+		(KEY_5!(), 1.0),
+		(KEY_6!(), 1.0),
+		(KEY_7!(), 1.0),
+		(KEY_8!(), 1.0),
+		(KEY_9!(), 1.0),
+		(KEY_0!(), 1.0),
+		(KEY_MINUS!(), 1.0),
+		(KEY_EQUAL!(), 1.0),
+		(KEY_BACKSPACE!(), 2.0),
+	],
+	&[
+		(KEY_TAB!(), 1.5),
+		(KEY_Q!(), 1.0),
+		(KEY_W!(), 1.0),
+		(KEY_E!(), 1.0),
+		(KEY_R!(), 1.0),
+		(KEY_T!(), 1.0),
+		(KEY_Y!(), 1.0),
+		(KEY_U!(), 1.0),
+		(KEY_I!(), 1.0),
+		(KEY_O!(), 1.0),
+		(KEY_P!(), 1.0),
+		(KEY_LEFTBRACE!(), 1.0),
+		(KEY_RIGHTBRACE!(), 1.0),
+		(KEY_BACKSLASH!(), 1.5),
+	],
+	&[
+		(KEY_CAPSLOCK!(), 1.75),
+		(KEY_A!(), 1.0),
+		(KEY_S!(), 1.0),
+		(KEY_D!(), 1.0),
+		(KEY_F!(), 1.0),
+		(KEY_G!(), 1.0),
+		(KEY_H!(), 1.0),
+		(KEY_J!(), 1.0),
+		(KEY_K!(), 1.0),
+		(KEY_L!(), 1.0),
+		(KEY_SEMICOLON!(), 1.0),
+		(KEY_APOSTROPHE!(), 1.0),
+		(KEY_ENTER!(), 2.25),
+	],
+	&[
+		(KEY_LEFTSHIFT!(), 2.25),
+		(KEY_Z!(), 1.0),
+		(KEY_X!(), 1.0),
+		(KEY_C!(), 1.0),
+		(KEY_V!(), 1.0),
+		(KEY_B!(), 1.0),
+		(KEY_N!(), 1.0),
+		(KEY_M!(), 1.0),
+		(KEY_COMMA!(), 1.0),
+		(KEY_DOT!(), 1.0),
+		(KEY_SLASH!(), 1.0),
+		(KEY_RIGHTSHIFT!(), 2.75),
+	],
+	&[
+		(KEY_ESC!(), 1.25),
+		(KEY_LEFTCTRL!(), 1.25),
+		(KEY_LEFTMETA!(), 1.25),
+		(KEY_LEFTALT!(), 1.25),
+		(KEY_SPACE!(), 4.5),
+		(KEY_RIGHTALT!(), 1.5),
+		(KEY_LEFT!(), 1.0),
+		(KEY_UP!(), 1.0),
+		(KEY_DOWN!(), 1.0),
+		(KEY_RIGHT!(), 1.0),
+	],
+];
+
+/// Modifiers stay down after a click until the next key is typed, so they can be combined one click at a time.
+pub fn is_modifier(keycode: u32) -> bool {
+	[
+		KEY_LEFTSHIFT!(),
+		KEY_RIGHTSHIFT!(),
+		KEY_LEFTCTRL!(),
+		KEY_LEFTMETA!(),
+		KEY_LEFTALT!(),
+		KEY_RIGHTALT!(),
+	]
+	.contains(&keycode)
+}
+
+/// Labels for keys that don't type anything, or type something unprintable.
+pub fn fixed_label(keycode: u32) -> Option<&'static str> {
+	Some(match keycode {
+		KEY_BACKSPACE!() => "Bksp",
+		KEY_TAB!() => "Tab",
+		KEY_CAPSLOCK!() => "Caps",
+		KEY_ENTER!() => "Enter",
+		KEY_LEFTSHIFT!() | KEY_RIGHTSHIFT!() => "Shift",
+		KEY_ESC!() => "Esc",
+		KEY_LEFTCTRL!() => "Ctrl",
+		KEY_LEFTMETA!() => "Super",
+		KEY_LEFTALT!() => "Alt",
+		KEY_RIGHTALT!() => "AltGr",
+		KEY_SPACE!() => "",
+		KEY_LEFT!() => "<",
+		KEY_UP!() => "^",
+		KEY_DOWN!() => "v",
+		KEY_RIGHT!() => ">",
+		_ => return None,
+	})
+}
+
+#[test]
+fn test_row_widths() {
+	for row in ROWS {
+		let width: f32 = row.iter().map(|(_, width)| width).sum();
+		assert_eq!(width, ROW_WIDTH);
+	}
+}
@@ -0,0 +1,39 @@
+use clap::Parser;
+use keyboard_window::KeyboardWindow;
+use logging::LogArgs;
+use std::{io::IsTerminal, process::exit};
+use winit::event_loop::{ControlFlow, EventLoopBuilder};
+pub mod keyboard_window;
+pub mod layout;
+
+#[derive(Debug, Parser)]
+#[command(about = "Type into Stardust with an on-screen keyboard")]
+struct Args {
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn main() {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `constellation | azimuth`");
+	}
+	logging::init(&Args::parse().log).unwrap();
+	if let Err(e) = ipc::handshake::hello(ipc::Capabilities::KEYBOARD) {
+		logging::warn!("Couldn't greet the sink: {e}");
+		return;
+	}
+	ipc::heartbeat::start_heartbeat();
+	ipc::shutdown::on_signal(|| {
+		ipc::shutdown::end_stream();
+		exit(0)
+	});
+	let event_loop = EventLoopBuilder::new().build().unwrap();
+	let mut keyboard_window = KeyboardWindow::new(&event_loop);
+
+	event_loop
+		.run(move |event, elwt| {
+			elwt.set_control_flow(ControlFlow::Wait);
+			keyboard_window.handle_event(event, elwt);
+		})
+		.unwrap();
+}
@@ -0,0 +1,279 @@
+use ashpd::{
+	desktop::{
+		input_capture::{Activated, Barrier, Capabilities, Deactivated, InputCapture},
+		Session,
+	},
+	WindowIdentifier,
+};
+use clap::{Parser, ValueEnum};
+use color_eyre::{eyre::eyre, Result};
+use futures_util::{stream, Stream, StreamExt};
+use ipc::{send_input_ipc, Message};
+use logging::{info, warn, LogArgs};
+use reis::{
+	ei::{self, button::ButtonState, handshake::ContextType, keyboard::KeyState},
+	event::{DeviceCapability, EiEvent, Keymap},
+	tokio::{ei_handshake, EiConvertEventStream, EiEventStream},
+};
+use std::{
+	collections::HashMap,
+	fs::File,
+	io::{ErrorKind, IsTerminal},
+	os::unix::{fs::FileExt, net::UnixStream},
+	pin::Pin,
+};
+use xkbcommon::xkb::{Context, Keymap as XkbKeymap, KEYMAP_FORMAT_TEXT_V1};
+
+// scroll lock, since hardly anything else uses it
+const DEFAULT_RELEASE_KEY: u32 = 70;
+// discrete scroll comes in fractions of 120 per detent
+const DISCRETE_PER_DETENT: f32 = 120.0;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Edge {
+	Top,
+	Bottom,
+	Left,
+	Right,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Capture keyboard/mouse through libei and the InputCapture portal")]
+struct Args {
+	/// Screen edge that starts capturing input when the pointer is pushed against it
+	#[arg(long, value_enum, default_value_t = Edge::Top)]
+	edge: Edge,
+	/// Evdev keycode that gives input back to the desktop
+	#[arg(long, default_value_t = DEFAULT_RELEASE_KEY)]
+	release_key: u32,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+type Portal<'a> = (&'a InputCapture<'a>, &'a Session<'a, InputCapture<'a>>);
+type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + 'a>>;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `corona | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+
+	if let Some(context) = ei::Context::connect_to_env()? {
+		info!("Using the EIS server from LIBEI_SOCKET");
+		return forward(context, None, args.release_key).await;
+	}
+
+	let input_capture = InputCapture::new().await?;
+	let (session, _) = input_capture
+		.create_session(
+			&WindowIdentifier::default(),
+			Capabilities::Keyboard | Capabilities::Pointer,
+		)
+		.await?;
+	let fd = input_capture.connect_to_eis(&session).await?;
+	set_barriers(&input_capture, &session, args.edge).await?;
+	input_capture.enable(&session).await?;
+	info!(
+		"Push the pointer against the {:?} edge of the screen to capture input, keycode {} gives it back",
+		args.edge, args.release_key
+	);
+
+	let context = ei::Context::new(UnixStream::from(fd))?;
+	let result = forward(context, Some((&input_capture, &session)), args.release_key).await;
+	let _ = session.close().await;
+	result
+}
+
+/// Put a barrier along the chosen edge of every screen, the ones between screens get refused.
+async fn set_barriers(
+	input_capture: &InputCapture<'_>,
+	session: &Session<'_, InputCapture<'_>>,
+	edge: Edge,
+) -> Result<()> {
+	let zones = input_capture.zones(session).await?.response()?;
+	let barriers = zones
+		.regions()
+		.iter()
+		.enumerate()
+		.map(|(i, region)| {
+			let (x, y) = (region.x_offset(), region.y_offset());
+			let (w, h) = (region.width() as i32, region.height() as i32);
+			let position = match edge {
+				Edge::Top => (x, y, x + w - 1, y),
+				Edge::Bottom => (x, y + h, x + w - 1, y + h),
+				Edge::Left => (x, y, x, y + h - 1),
+				Edge::Right => (x + w, y, x + w, y + h - 1),
+			};
+			Barrier::new(i as u32 + 1, position)
+		})
+		.collect::<Vec<_>>();
+	let response = input_capture
+		.set_pointer_barriers(session, &barriers, zones.zone_set())
+		.await?
+		.response()?;
+	if response.failed_barriers().len() == barriers.len() {
+		return Err(eyre!(
+			"The desktop refused a barrier on every screen's {edge:?} edge, try another --edge"
+		));
+	}
+	Ok(())
+}
+
+fn interfaces() -> HashMap<&'static str, u32> {
+	HashMap::from([
+		("ei_connection", 1),
+		("ei_callback", 1),
+		("ei_pingpong", 1),
+		("ei_seat", 1),
+		("ei_device", 2),
+		("ei_pointer", 1),
+		("ei_scroll", 1),
+		("ei_button", 1),
+		("ei_keyboard", 1),
+	])
+}
+
+async fn forward(context: ei::Context, portal: Option<Portal<'_>>, release_key: u32) -> Result<()> {
+	let mut events = EiEventStream::new(context.clone())?;
+	let handshake = ei_handshake(&mut events, "corona", ContextType::Receiver, &interfaces())
+		.await
+		.map_err(|e| eyre!("EI handshake failed: {e}"))?;
+	let mut events = EiConvertEventStream::new(events, handshake.serial);
+
+	// without the portal nothing tells us when capturing starts or stops
+	let (mut activated, mut deactivated): (BoxStream<Activated>, BoxStream<Deactivated>) =
+		match portal {
+			Some((input_capture, _)) => (
+				Box::pin(input_capture.receive_activated().await?),
+				Box::pin(input_capture.receive_deactivated().await?),
+			),
+			None => (Box::pin(stream::pending()), Box::pin(stream::pending())),
+		};
+	let mut activation_id = None;
+
+	// the devices may never send a keymap, so start with the default one like eclipse does
+	let keymap = XkbKeymap::new_from_names(&Context::new(0), "evdev", "", "", "", None, 0)
+		.unwrap()
+		.get_as_string(KEYMAP_FORMAT_TEXT_V1);
+	ipc::handshake::hello(ipc::Capabilities::KEYBOARD | ipc::Capabilities::POINTER)?;
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keymap))?;
+
+	loop {
+		tokio::select! {
+			event = events.next() => {
+				let Some(event) = event else {
+					break;
+				};
+				let event = match event {
+					Ok(event) => event,
+					Err(e) => {
+						warn!("EI error: {e:?}");
+						break;
+					}
+				};
+				let sent = match event {
+					EiEvent::SeatAdded(seat) => {
+						seat.seat.bind_capabilities(&[
+							DeviceCapability::Pointer,
+							DeviceCapability::Keyboard,
+							DeviceCapability::Scroll,
+							DeviceCapability::Button,
+						]);
+						if let Err(e) = context.flush() {
+							warn!("Couldn't bind the seat: {e}");
+							break;
+						}
+						Ok(())
+					}
+					EiEvent::DeviceAdded(device) => match device.device.keymap() {
+						Some(keymap) => match read_keymap(keymap) {
+							Ok(keymap) => send_input_ipc(Message::Keymap(keymap)),
+							// keys still come through, as whichever keymap was sent before has them
+							Err(e) => {
+								warn!("Couldn't read a device's keymap, keeping the last one: {e}");
+								Ok(())
+							}
+						},
+						None => Ok(()),
+					},
+					EiEvent::PointerMotion(motion) => {
+						send_input_ipc(Message::MouseMove([motion.dx, motion.dy].into()))
+					}
+					EiEvent::Button(button) => send_input_ipc(Message::MouseButton {
+						button: button.button,
+						pressed: button.state == ButtonState::Press,
+					}),
+					EiEvent::ScrollDelta(scroll) => {
+						send_input_ipc(Message::MouseAxisContinuous([scroll.dx, scroll.dy].into()))
+					}
+					EiEvent::ScrollDiscrete(scroll) => send_input_ipc(Message::MouseAxisDiscrete(
+						[
+							scroll.discrete_dx as f32 / DISCRETE_PER_DETENT,
+							scroll.discrete_dy as f32 / DISCRETE_PER_DETENT,
+						]
+						.into(),
+					)),
+					EiEvent::KeyboardKey(key) => {
+						let pressed = key.state == KeyState::Press;
+						if key.key != release_key {
+							send_input_ipc(Message::Key {
+								keycode: key.key,
+								pressed,
+							})
+						} else {
+							if let (true, Some((input_capture, session))) = (pressed, portal) {
+								let released = input_capture.release(session, activation_id, None).await;
+								if let Err(e) = released {
+									warn!("Couldn't give input back to the desktop: {e}");
+									break;
+								}
+							}
+							Ok(())
+						}
+					}
+					EiEvent::Disconnected(_) => break,
+					_ => Ok(()),
+				};
+				match sent {
+					Ok(()) => (),
+					Err(ipc::Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+			}
+			Some(event) = activated.next() => {
+				activation_id = event.activation_id();
+				info!("Capturing input");
+			}
+			Some(_) = deactivated.next() => {
+				// whatever was held stays on the desktop's side now
+				match send_input_ipc(Message::ResetInput) {
+					Ok(()) => info!("Gave input back to the desktop"),
+					Err(ipc::Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+fn read_keymap(keymap: &Keymap) -> std::io::Result<String> {
+	if keymap.type_ != ei::keyboard::KeymapType::Xkb {
+		return Err(ErrorKind::InvalidData.into());
+	}
+	let file = File::from(keymap.fd.try_clone()?);
+	let mut data = vec![0_u8; keymap.size as usize];
+	file.read_exact_at(&mut data, 0)?;
+	// xkbcommon chokes on the null terminator
+	while let Some(0) = data.last() {
+		data.pop();
+	}
+	String::from_utf8(data).map_err(|_| ErrorKind::InvalidData.into())
+}
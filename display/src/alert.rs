@@ -0,0 +1,179 @@
+use ipc::Message;
+use std::{
+	collections::{BTreeMap, VecDeque},
+	io::Write,
+	str::FromStr,
+	time::{Duration, Instant},
+};
+
+/// A condition worth shouting about while it happens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertRule {
+	/// `held:<duration>`: a key or mouse button has been held longer than this
+	Held(Duration),
+	/// `resets:<count>`: more than this many input resets within a minute
+	Resets(usize),
+	/// `rate:<count>`: more than this many messages within a second
+	Rate(usize),
+}
+impl FromStr for AlertRule {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (kind, value) = s
+			.split_once(':')
+			.ok_or_else(|| format!("expected <rule>:<value>, got {s:?}"))?;
+		let count = || {
+			value
+				.parse::<usize>()
+				.map_err(|e| format!("invalid count {value:?}: {e}"))
+		};
+		match kind {
+			"held" => parse_duration(value).map(AlertRule::Held),
+			"resets" => count().map(AlertRule::Resets),
+			"rate" => count().map(AlertRule::Rate),
+			_ => Err(format!(
+				"unknown rule {kind:?}, expected held, resets or rate"
+			)),
+		}
+	}
+}
+
+/// Seconds by default, or with an `ms`/`s`/`m` suffix.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+	let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+		(ms, 0.001)
+	} else if let Some(s) = value.strip_suffix('s') {
+		(s, 1.0)
+	} else if let Some(m) = value.strip_suffix('m') {
+		(m, 60.0)
+	} else {
+		(value, 1.0)
+	};
+	let number = number
+		.parse::<f64>()
+		.map_err(|e| format!("invalid duration {value:?}: {e}"))?;
+	Duration::try_from_secs_f64(number * scale)
+		.map_err(|e| format!("invalid duration {value:?}: {e}"))
+}
+
+#[derive(Debug, Default)]
+pub struct Alerts {
+	max_held: Option<Duration>,
+	max_resets: Option<usize>,
+	max_rate: Option<usize>,
+	highlight: bool,
+	/// When each held key/button was pressed and whether it has been alerted on yet.
+	held: BTreeMap<(&'static str, u32), (Instant, bool)>,
+	resets: VecDeque<Instant>,
+	resets_alerted: bool,
+	messages: VecDeque<Instant>,
+	rate_alerted: bool,
+}
+impl Alerts {
+	/// If a rule is given more than once, the strictest threshold wins.
+	pub fn new(rules: &[AlertRule], highlight: bool) -> Self {
+		let mut alerts = Alerts {
+			highlight,
+			..Default::default()
+		};
+		for rule in rules {
+			match *rule {
+				AlertRule::Held(max) => {
+					alerts.max_held = Some(alerts.max_held.map_or(max, |m| m.min(max)))
+				}
+				AlertRule::Resets(max) => {
+					alerts.max_resets = Some(alerts.max_resets.map_or(max, |m| m.min(max)))
+				}
+				AlertRule::Rate(max) => {
+					alerts.max_rate = Some(alerts.max_rate.map_or(max, |m| m.min(max)))
+				}
+			}
+		}
+		alerts
+	}
+
+	pub fn record(
+		&mut self,
+		arrival: Instant,
+		message: &Message,
+		out: &mut impl Write,
+	) -> std::io::Result<()> {
+		match message {
+			Message::Key { keycode, pressed } => {
+				self.update_held("key", *keycode, *pressed, arrival)
+			}
+			Message::MouseButton { button, pressed } => {
+				self.update_held("mouse button", *button, *pressed, arrival)
+			}
+			Message::ResetInput => self.held.clear(),
+			_ => (),
+		}
+
+		if let (Some(max), Message::ResetInput) = (self.max_resets, message) {
+			self.resets.push_back(arrival);
+			let count = count_within(&mut self.resets, arrival, Duration::from_secs(60));
+			if count > max && !self.resets_alerted {
+				self.alert(&format!("{count} input resets in the last minute"), out)?;
+			}
+			self.resets_alerted = count > max;
+		}
+		if let Some(max) = self.max_rate {
+			self.messages.push_back(arrival);
+			let count = count_within(&mut self.messages, arrival, Duration::from_secs(1));
+			if count > max && !self.rate_alerted {
+				self.alert(&format!("{count} messages in the last second"), out)?;
+			}
+			self.rate_alerted = count > max;
+		}
+		Ok(())
+	}
+
+	fn update_held(&mut self, kind: &'static str, code: u32, pressed: bool, arrival: Instant) {
+		if pressed {
+			self.held.entry((kind, code)).or_insert((arrival, false));
+		} else {
+			self.held.remove(&(kind, code));
+		}
+	}
+
+	/// Check the time based rules, called periodically since they can trigger without any new messages.
+	pub fn tick(&mut self, now: Instant, out: &mut impl Write) -> std::io::Result<()> {
+		let Some(max) = self.max_held else {
+			return Ok(());
+		};
+		let mut stuck = Vec::new();
+		for ((kind, code), (pressed_at, alerted)) in &mut self.held {
+			let held_for = now.saturating_duration_since(*pressed_at);
+			if held_for > max && !*alerted {
+				*alerted = true;
+				stuck.push(format!(
+					"{kind} {code} held for {:.1}s",
+					held_for.as_secs_f32()
+				));
+			}
+		}
+		for warning in stuck {
+			self.alert(&warning, out)?;
+		}
+		Ok(())
+	}
+
+	fn alert(&self, warning: &str, out: &mut impl Write) -> std::io::Result<()> {
+		if self.highlight {
+			writeln!(out, "\x1b[1;31mALERT\x1b[0m {warning}")
+		} else {
+			writeln!(out, "ALERT {warning}")
+		}
+	}
+}
+
+/// Drop timestamps older than the window and count what's left.
+fn count_within(times: &mut VecDeque<Instant>, now: Instant, window: Duration) -> usize {
+	while times
+		.front()
+		.is_some_and(|t| now.saturating_duration_since(*t) > window)
+	{
+		times.pop_front();
+	}
+	times.len()
+}
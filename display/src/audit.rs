@@ -0,0 +1,98 @@
+use ipc::{ButtonBlot, Message};
+use std::{collections::VecDeque, io::Write};
+
+// how many of the preceding messages are shown with each warning
+const CONTEXT_LENGTH: usize = 8;
+
+/// Runs keys and mouse buttons through [`ButtonBlot`]s and warns whenever the stream would leave something logically held.
+#[derive(Debug, Default)]
+pub struct Audit {
+	keys: ButtonBlot,
+	buttons: ButtonBlot,
+	context: VecDeque<String>,
+	index: u64,
+	warnings: u64,
+}
+impl Audit {
+	pub fn check(&mut self, message: &Message, out: &mut impl Write) -> std::io::Result<()> {
+		self.index += 1;
+		let warning = match message {
+			Message::Key { keycode, pressed } => update(&mut self.keys, "key", *keycode, *pressed),
+			Message::MouseButton { button, pressed } => {
+				update(&mut self.buttons, "mouse button", *button, *pressed)
+			}
+			// a reset releases everything on the sink's side
+			Message::ResetInput => {
+				self.reset();
+				None
+			}
+			Message::Disconnect => {
+				self.check_clean("disconnect", out)?;
+				self.reset();
+				None
+			}
+			_ => None,
+		};
+		if let Some(warning) = warning {
+			self.warn(&format!("{warning} ({message})"), out)?;
+		}
+		if self.context.len() == CONTEXT_LENGTH {
+			self.context.pop_front();
+		}
+		self.context.push_back(format!("#{} {message}", self.index));
+		Ok(())
+	}
+
+	fn reset(&mut self) {
+		self.keys = ButtonBlot::default();
+		self.buttons = ButtonBlot::default();
+	}
+
+	/// Warn about anything still held, e.g. when the stream ends.
+	pub fn check_clean(&mut self, when: &str, out: &mut impl Write) -> std::io::Result<()> {
+		let mut stuck = self
+			.keys
+			.unbalanced()
+			.map(|(code, count)| format!("key {code} ({count:+})"))
+			.chain(
+				self.buttons
+					.unbalanced()
+					.map(|(code, count)| format!("mouse button {code} ({count:+})")),
+			)
+			.collect::<Vec<_>>();
+		if stuck.is_empty() {
+			return Ok(());
+		}
+		stuck.sort();
+		self.warn(&format!("unbalanced at {when}: {}", stuck.join(", ")), out)
+	}
+
+	fn warn(&mut self, warning: &str, out: &mut impl Write) -> std::io::Result<()> {
+		self.warnings += 1;
+		writeln!(out, "warning at #{}: {warning}", self.index)?;
+		for line in &self.context {
+			writeln!(out, "\t{line}")?;
+		}
+		Ok(())
+	}
+
+	pub fn print_summary(&self, out: &mut impl Write) -> std::io::Result<()> {
+		writeln!(
+			out,
+			"Audited {} messages, {} warnings",
+			self.index, self.warnings
+		)
+	}
+}
+
+fn update(blot: &mut ButtonBlot, kind: &str, code: u32, pressed: bool) -> Option<String> {
+	let held = blot.key_count(code);
+	blot.key_update(code, pressed);
+	if pressed && held > 0 {
+		Some(format!("{kind} {code} pressed again while already held"))
+	} else if !pressed && held <= 0 {
+		Some(format!("{kind} {code} released without being held"))
+	} else {
+		None
+	}
+}
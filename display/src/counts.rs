@@ -0,0 +1,63 @@
+use crate::keymap::KeymapSummary;
+use ipc::Message;
+use serde::Serialize;
+use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
+
+#[derive(Debug, Default, Serialize)]
+pub struct KeyCount {
+	name: Option<String>,
+	presses: u64,
+}
+
+/// How often each key and mouse button was pressed over a session.
+#[derive(Debug, Default, Serialize)]
+pub struct Counts {
+	keys: BTreeMap<u32, KeyCount>,
+	buttons: BTreeMap<u32, u64>,
+}
+impl Counts {
+	pub fn record(&mut self, message: &Message, keymap: Option<&KeymapSummary>) {
+		match message {
+			Message::Key {
+				keycode,
+				pressed: true,
+			} => {
+				let count = self.keys.entry(*keycode).or_default();
+				count.presses += 1;
+				if count.name.is_none() {
+					count.name = keymap
+						.and_then(|k| k.key_name(*keycode))
+						.map(str::to_string);
+				}
+			}
+			Message::MouseButton {
+				button,
+				pressed: true,
+			} => *self.buttons.entry(*button).or_default() += 1,
+			_ => (),
+		}
+	}
+
+	/// Write the counts as JSON if the path ends in `.json`, CSV otherwise.
+	pub fn export(&self, path: &Path) -> color_eyre::Result<()> {
+		let mut file = File::create(path)?;
+		if path.extension().is_some_and(|e| e == "json") {
+			serde_json::to_writer_pretty(&mut file, self)?;
+			writeln!(file)?;
+			return Ok(());
+		}
+		writeln!(file, "kind,code,name,presses")?;
+		for (code, count) in &self.keys {
+			writeln!(
+				file,
+				"key,{code},{},{}",
+				count.name.as_deref().unwrap_or(""),
+				count.presses
+			)?;
+		}
+		for (button, presses) in &self.buttons {
+			writeln!(file, "button,{button},,{presses}")?;
+		}
+		Ok(())
+	}
+}
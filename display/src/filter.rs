@@ -0,0 +1,70 @@
+use clap::ValueEnum;
+use ipc::Message;
+
+/// Broad groups of messages that can be shown on their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MessageClass {
+	/// Key presses and releases
+	Keys,
+	/// Mouse motion and buttons, touches and tablets
+	Mouse,
+	/// Continuous and discrete scrolling
+	Scroll,
+	/// Gamepad buttons, sticks and triggers, and gamepads being plugged in and out
+	Gamepad,
+	/// Keymaps, key repeat settings, input resets, disconnects and flow control
+	Meta,
+	/// Heartbeats, which are only shown when asked for since they come every second
+	Heartbeat,
+}
+impl MessageClass {
+	pub fn of(message: &Message) -> Self {
+		match message {
+			Message::Key { .. } => MessageClass::Keys,
+			Message::MouseMove(_)
+			| Message::MousePosition(_)
+			| Message::MouseButton { .. }
+			| Message::MultiClick { .. }
+			| Message::Magnify(_)
+			| Message::Rotate(_)
+			| Message::SmartMagnify
+			| Message::TouchDown { .. }
+			| Message::TouchMotion { .. }
+			| Message::TouchUp { .. }
+			| Message::TabletProximity { .. }
+			| Message::TabletAxis { .. }
+			| Message::TabletTip { .. }
+			| Message::TabletButton { .. } => MessageClass::Mouse,
+			Message::MouseAxisContinuous(_) | Message::MouseAxisDiscrete(_) => MessageClass::Scroll,
+			Message::GamepadConnected { .. }
+			| Message::GamepadDisconnected { .. }
+			| Message::GamepadButton { .. }
+			| Message::GamepadAxis { .. } => MessageClass::Gamepad,
+			Message::Keymap(_)
+			| Message::KeyRepeat { .. }
+			| Message::ResetInput
+			| Message::Disconnect
+			| Message::Pause
+			| Message::Resume
+			| Message::Hover { .. }
+			| Message::ClipboardSet(_)
+			| Message::DeviceAdded { .. }
+			| Message::DeviceRemoved { .. }
+			| Message::Hello { .. }
+			| Message::BatchBegin
+			| Message::BatchEnd => MessageClass::Meta,
+			Message::Heartbeat => MessageClass::Heartbeat,
+		}
+	}
+}
+
+/// Whether the message passes the `--only` filter, where an empty filter lets everything through
+/// but heartbeats.
+pub fn is_shown(only: &[MessageClass], message: &Message) -> bool {
+	let class = MessageClass::of(message);
+	if only.is_empty() {
+		class != MessageClass::Heartbeat
+	} else {
+		only.contains(&class)
+	}
+}
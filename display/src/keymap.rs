@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use xkbcommon::xkb::{
+	self, Context, KeyDirection, Keycode, Keymap, State, CONTEXT_NO_FLAGS, KEYMAP_COMPILE_NO_FLAGS,
+	KEYMAP_FORMAT_TEXT_V1,
+};
+
+// evdev keycodes are offset by 8 in xkb
+const EVDEV_OFFSET: u32 = 8;
+
+/// Turns raw evdev keycodes into keysym names and text using the stream's keymap, tracking modifiers along the way.
+pub struct KeyDecoder {
+	keymap: Keymap,
+	state: State,
+}
+impl KeyDecoder {
+	pub fn new(keymap: &str) -> Option<Self> {
+		let keymap = Keymap::new_from_string(
+			&Context::new(CONTEXT_NO_FLAGS),
+			keymap.to_string(),
+			KEYMAP_FORMAT_TEXT_V1,
+			KEYMAP_COMPILE_NO_FLAGS,
+		)?;
+		let state = State::new(&keymap);
+		Some(KeyDecoder { keymap, state })
+	}
+
+	/// Describe the key as it is interpreted right now, then apply the press/release to the modifier state.
+	pub fn decode(&mut self, keycode: u32, pressed: bool) -> String {
+		let keycode = Keycode::new(keycode + EVDEV_OFFSET);
+		let name = xkb::keysym_get_name(self.state.key_get_one_sym(keycode));
+		let text = self.state.key_get_utf8(keycode);
+		self.state.update_key(
+			keycode,
+			if pressed {
+				KeyDirection::Down
+			} else {
+				KeyDirection::Up
+			},
+		);
+		if text.is_empty() || text.chars().any(char::is_control) {
+			name
+		} else {
+			format!("{name} {text:?}")
+		}
+	}
+
+	pub fn keymap(&self) -> &Keymap {
+		&self.keymap
+	}
+
+	/// Forget all held keys and modifiers.
+	pub fn reset(&mut self) {
+		self.state = State::new(&self.keymap);
+	}
+}
+
+// how many changed keys are listed before the rest are just counted
+const MAX_LISTED_KEYS: usize = 16;
+
+/// The parts of a keymap worth comparing when a new one arrives.
+#[derive(Debug)]
+pub struct KeymapSummary {
+	/// The `xkb_keycodes`, `xkb_types`, `xkb_compat` and `xkb_symbols` section names, which name the rules/options they came from.
+	sections: Vec<String>,
+	layouts: Vec<String>,
+	/// Keysym names of every level in the first layout, by evdev keycode.
+	keys: BTreeMap<u32, (Option<String>, Vec<String>)>,
+}
+impl KeymapSummary {
+	pub fn new(text: &str, keymap: &Keymap) -> Self {
+		let sections = text
+			.lines()
+			.map(str::trim)
+			.filter(|l| l.starts_with("xkb_") && !l.starts_with("xkb_keymap"))
+			.map(|l| l.trim_end_matches('{').trim().to_string())
+			.collect();
+		let layouts = (0..keymap.num_layouts())
+			.map(|layout| keymap.layout_get_name(layout).to_string())
+			.collect();
+		let keys = (keymap.min_keycode().raw()..=keymap.max_keycode().raw())
+			.filter(|code| *code >= EVDEV_OFFSET)
+			.filter_map(|code| {
+				let keycode = Keycode::new(code);
+				let levels = (0..keymap.num_levels_for_key(keycode, 0))
+					.map(|level| {
+						keymap
+							.key_get_syms_by_level(keycode, 0, level)
+							.iter()
+							.map(|sym| xkb::keysym_get_name(*sym))
+							.collect::<Vec<_>>()
+							.join("+")
+					})
+					.collect::<Vec<_>>();
+				let name = keymap.key_get_name(keycode).map(str::to_string);
+				(!levels.is_empty()).then_some((code - EVDEV_OFFSET, (name, levels)))
+			})
+			.collect();
+		KeymapSummary {
+			sections,
+			layouts,
+			keys,
+		}
+	}
+
+	/// Keysym name of the key's first level, e.g. `a` or `Shift_L`.
+	pub fn key_name(&self, code: u32) -> Option<&str> {
+		let (_, levels) = self.keys.get(&code)?;
+		levels.first().map(String::as_str)
+	}
+
+	pub fn describe(&self) -> Vec<String> {
+		let mut lines = self.sections.clone();
+		lines.push(format!("layouts: {}", self.layouts.join(", ")));
+		lines.push(format!("{} keys", self.keys.len()));
+		lines
+	}
+
+	/// Human readable differences from an older keymap, empty if they're equivalent.
+	pub fn diff(&self, old: &KeymapSummary) -> Vec<String> {
+		let mut lines = Vec::new();
+		for section in old.sections.iter().filter(|s| !self.sections.contains(s)) {
+			lines.push(format!("- {section}"));
+		}
+		for section in self.sections.iter().filter(|s| !old.sections.contains(s)) {
+			lines.push(format!("+ {section}"));
+		}
+		if self.layouts != old.layouts {
+			lines.push(format!(
+				"layouts: {} -> {}",
+				old.layouts.join(", "),
+				self.layouts.join(", ")
+			));
+		}
+
+		let mut codes = old.keys.keys().chain(self.keys.keys()).collect::<Vec<_>>();
+		codes.sort();
+		codes.dedup();
+		let changed = codes
+			.into_iter()
+			.filter_map(|code| {
+				let old_key = old.keys.get(code);
+				let new_key = self.keys.get(code);
+				(old_key.map(|k| &k.1) != new_key.map(|k| &k.1)).then(|| {
+					let name = new_key
+						.or(old_key)
+						.and_then(|(name, _)| name.as_deref())
+						.unwrap_or("?");
+					format!(
+						"key {code} <{name}>: {} -> {}",
+						describe_levels(old_key),
+						describe_levels(new_key)
+					)
+				})
+			})
+			.collect::<Vec<_>>();
+		if !changed.is_empty() {
+			lines.push(format!("{} keys changed:", changed.len()));
+			lines.extend(changed.iter().take(MAX_LISTED_KEYS).cloned());
+			if changed.len() > MAX_LISTED_KEYS {
+				lines.push(format!("... and {} more", changed.len() - MAX_LISTED_KEYS));
+			}
+		}
+		lines
+	}
+}
+
+fn describe_levels(key: Option<&(Option<String>, Vec<String>)>) -> String {
+	match key {
+		Some((_, levels)) => levels.join(" "),
+		None => "(none)".to_string(),
+	}
+}
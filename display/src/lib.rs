@@ -0,0 +1,282 @@
+mod alert;
+mod audit;
+mod counts;
+mod filter;
+mod keymap;
+mod stats;
+mod timing;
+mod tui;
+
+use alert::{AlertRule, Alerts};
+use audit::Audit;
+use clap::Parser;
+use color_eyre::Result;
+use counts::Counts;
+use filter::{is_shown, MessageClass};
+use ipc::{
+	message_from_frame, receive_frame_async_ipc, record::Recorder, send_frame_ipc, Error, Message,
+};
+use keymap::{KeyDecoder, KeymapSummary};
+use logging::{info, warn, LogArgs};
+use stats::Stats;
+use std::{
+	fs::File,
+	io::{stderr, stdout, IsTerminal, Write},
+	path::PathBuf,
+	time::{Duration, Instant},
+};
+use timing::Timing;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Parser)]
+#[command(about = "Print the messages going through a non-spatial input pipe")]
+pub struct Args {
+	/// Only show these kinds of messages (can be repeated or comma separated)
+	#[arg(long, value_enum, value_delimiter = ',')]
+	only: Vec<MessageClass>,
+	/// Print rolling events/sec by message type instead of each message, and totals on exit
+	#[arg(long)]
+	stats: bool,
+	/// Measure the time between consecutive messages of each type, and how long timestamped ones took to get here, and print jitter and latency histograms on exit
+	#[arg(long)]
+	timing: bool,
+	/// Check that every key and mouse button press is matched by a release and warn about anything left stuck
+	#[arg(long)]
+	audit: bool,
+	/// Dump each frame's sequence number, length and payload as hex, including frames that don't deserialize
+	#[arg(long)]
+	raw: bool,
+	/// Start each message with the timestamp its source gave it in seconds, and how long it took to get here
+	#[arg(long)]
+	timestamps: bool,
+	/// Count presses of every key and mouse button and write them to this file on exit, as JSON if it ends in .json and CSV otherwise
+	#[arg(long, value_name = "FILE")]
+	counts: Option<PathBuf>,
+	/// Warn as soon as something looks wrong: held:<duration> (key/button held too long, e.g. held:10s),
+	/// resets:<count> (input resets per minute) or rate:<count> (messages per second)
+	#[arg(long, value_name = "RULE")]
+	alert: Vec<AlertRule>,
+	/// Show a live dashboard of held keys/buttons, mouse position, scroll and keymap instead
+	#[arg(long, conflicts_with_all = ["stats", "timing", "audit", "raw", "counts", "alert", "tee"])]
+	tui: bool,
+	/// Pass every frame through to stdout untouched and print to stderr instead, e.g. `eclipse | display --tee | azimuth`
+	#[arg(long)]
+	tee: bool,
+	/// Also archive every raw frame with its arrival time to this file
+	#[arg(long, value_name = "FILE")]
+	record: Option<PathBuf>,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn run(args: Args) -> Result<()> {
+	if std::io::stdin().is_terminal() {
+		panic!("You need to pipe azimuth or eclipse's output into this e.g. `eclipse | azimuth`");
+	}
+	if args.tee && std::io::stdout().is_terminal() {
+		panic!("You need to pipe display --tee into an input sink e.g. `eclipse | display --tee | azimuth`");
+	}
+	// console_subscriber::init();
+	color_eyre::install()?;
+	logging::init(&args.log)?;
+	if args.tee {
+		// so the sink can still pause the source through this
+		ipc::reverse::relay();
+	}
+
+	// reading happens in its own task so a half-read message is never dropped by the select below
+	let (frame_tx, mut frame_rx) = mpsc::unbounded_channel();
+	let mut tee = args.tee;
+	let mut recorder = args
+		.record
+		.as_ref()
+		.map(|path| Recorder::new(File::create(path)?))
+		.transpose()?;
+	tokio::spawn(async move {
+		while let Ok(frame) = receive_frame_async_ipc().await {
+			let arrival = Instant::now();
+			if tee {
+				match send_frame_ipc(&frame) {
+					Ok(()) => (),
+					// still worth showing what comes in
+					Err(Error::Disconnected) => {
+						info!("The sink went away, only showing input from now on");
+						tee = false;
+					}
+					Err(e) => warn!("{e}"),
+				}
+			}
+			if let Some(Err(e)) = recorder.as_mut().map(|r| r.record_frame(&frame)) {
+				warn!("Stopped recording: {e}");
+				recorder = None;
+			}
+			if frame_tx.send((arrival, frame)).is_err() {
+				break;
+			}
+		}
+	});
+
+	if args.tui {
+		return tui::run(frame_rx, args.only).await;
+	}
+
+	let mut out: Box<dyn Write> = if args.tee {
+		Box::new(stderr())
+	} else {
+		Box::new(stdout())
+	};
+	let mut key_decoder: Option<KeyDecoder> = None;
+	let mut keymap_summary: Option<KeymapSummary> = None;
+	let mut stats = args.stats.then(Stats::default);
+	let mut timing = args.timing.then(Timing::default);
+	let mut audit = args.audit.then(Audit::default);
+	let mut counts = args.counts.is_some().then(Counts::default);
+	let highlight = if args.tee {
+		stderr().is_terminal()
+	} else {
+		stdout().is_terminal()
+	};
+	let mut alerts = (!args.alert.is_empty()).then(|| Alerts::new(&args.alert, highlight));
+	let print_messages = !(args.stats || args.timing || args.audit);
+	let mut sequence = 0_u64;
+	let summary_period = Duration::from_secs(1);
+	let mut summary_interval =
+		tokio::time::interval_at(tokio::time::Instant::now() + summary_period, summary_period);
+	loop {
+		tokio::select! {
+			frame = frame_rx.recv() => {
+				let Some((arrival, frame)) = frame else {
+					break;
+				};
+				sequence += 1;
+				let message = message_from_frame(&frame);
+				if args.raw && message.as_ref().map_or(true, |m| is_shown(&args.only, m)) {
+					writeln!(out, "#{sequence} frame of {} bytes", frame.len())?;
+					hexdump(&frame, &mut out)?;
+				}
+				let Ok(message) = message else {
+					writeln!(out, "#{sequence} couldn't deserialize frame of {} bytes", frame.len())?;
+					continue;
+				};
+				// the decoder has to see every key to keep track of modifiers, even ones that aren't shown
+				let key_name = match (&message, &mut key_decoder) {
+					(Message::Key { keycode, pressed }, Some(decoder)) => {
+						Some(decoder.decode(*keycode, *pressed))
+					}
+					_ => None,
+				};
+				let mut keymap_changes = Vec::new();
+				match &message {
+					Message::Keymap(keymap) => {
+						key_decoder = KeyDecoder::new(keymap);
+						match &key_decoder {
+							Some(decoder) => {
+								let summary = KeymapSummary::new(keymap, decoder.keymap());
+								keymap_changes = match &keymap_summary {
+									Some(old) => summary.diff(old),
+									None => summary.describe(),
+								};
+								if keymap_changes.is_empty() {
+									keymap_changes.push("no changes".to_string());
+								}
+								keymap_summary.replace(summary);
+							}
+							None => {
+								warn!("Couldn't compile keymap, keys won't be decoded");
+								keymap_summary = None;
+							}
+						}
+					}
+					Message::ResetInput => key_decoder.iter_mut().for_each(KeyDecoder::reset),
+					_ => (),
+				}
+				if let Some(audit) = &mut audit {
+					audit.check(&message, &mut out)?;
+				}
+				if let Some(alerts) = &mut alerts {
+					alerts.record(arrival, &message, &mut out)?;
+				}
+				if !is_shown(&args.only, &message) {
+					continue;
+				}
+				if let Some(timing) = &mut timing {
+					timing.record(arrival, &message, ipc::timestamp::of_frame(&frame));
+				}
+				if let Some(stats) = &mut stats {
+					stats.record(&message);
+				}
+				if let Some(counts) = &mut counts {
+					counts.record(&message, keymap_summary.as_ref());
+				}
+				if print_messages {
+					if let Some(ts) = ipc::timestamp::of_frame(&frame).filter(|_| args.timestamps) {
+						write!(
+							out,
+							"[{:.6} +{:.3}ms] ",
+							ts as f64 / 1_000_000.0,
+							timing::latency(ts, arrival).as_secs_f64() * 1000.0
+						)?;
+					}
+					match key_name {
+						Some(key_name) => writeln!(out, "{message} ({key_name})")?,
+						None => writeln!(out, "{message}")?,
+					}
+					for change in &keymap_changes {
+						writeln!(out, "\t{change}")?;
+					}
+				}
+			}
+			_ = summary_interval.tick(), if stats.is_some() || timing.is_some() || alerts.is_some() => {
+				if let Some(stats) = &mut stats {
+					stats.print_rates(&mut out)?;
+				}
+				if let Some(timing) = &mut timing {
+					timing.print_window(&mut out)?;
+				}
+				if let Some(alerts) = &mut alerts {
+					alerts.tick(Instant::now(), &mut out)?;
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+
+	if let Some(stats) = &stats {
+		stats.print_totals(&mut out)?;
+	}
+	if let Some(timing) = &timing {
+		timing.print_totals(&mut out)?;
+	}
+	if let (Some(counts), Some(path)) = (&counts, &args.counts) {
+		counts.export(path)?;
+	}
+	if let Some(audit) = &mut audit {
+		audit.check_clean("end of stream", &mut out)?;
+		audit.print_summary(&mut out)?;
+	}
+	Ok(())
+}
+
+/// Print bytes xxd style, 16 per line with an ascii column.
+fn hexdump(bytes: &[u8], out: &mut impl Write) -> std::io::Result<()> {
+	for (line, chunk) in bytes.chunks(16).enumerate() {
+		let hex = chunk
+			.iter()
+			.map(|b| format!("{b:02x}"))
+			.collect::<Vec<_>>()
+			.join(" ");
+		let ascii = chunk
+			.iter()
+			.map(|b| {
+				if b.is_ascii_graphic() || *b == b' ' {
+					*b as char
+				} else {
+					'.'
+				}
+			})
+			.collect::<String>();
+		writeln!(out, "\t{:08x}: {hex:<47}  {ascii}", line * 16)?;
+	}
+	Ok(())
+}
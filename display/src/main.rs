@@ -1,14 +1,5 @@
-use ipc::receive_input_async_ipc;
-use std::io::IsTerminal;
+use clap::Parser;
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
-	if std::io::stdin().is_terminal() {
-		panic!("You need to pipe azimuth or eclipse's output into this e.g. `eclipse | azimuth`");
-	}
-	// console_subscriber::init();
-	color_eyre::install().unwrap();
-	while let Ok(message) = receive_input_async_ipc().await {
-		println!("{message}");
-	}
+fn main() -> color_eyre::Result<()> {
+	display::run(display::Args::parse())
 }
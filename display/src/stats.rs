@@ -0,0 +1,48 @@
+use ipc::Message;
+use std::{collections::BTreeMap, io::Write, time::Instant};
+
+/// Rolling per-type event counts, printed once per interval.
+#[derive(Debug)]
+pub struct Stats {
+	window_start: Instant,
+	window: BTreeMap<&'static str, u64>,
+	totals: BTreeMap<&'static str, u64>,
+}
+impl Default for Stats {
+	fn default() -> Self {
+		Self {
+			window_start: Instant::now(),
+			window: BTreeMap::new(),
+			totals: BTreeMap::new(),
+		}
+	}
+}
+impl Stats {
+	pub fn record(&mut self, message: &Message) {
+		*self.window.entry(message.name()).or_default() += 1;
+		*self.totals.entry(message.name()).or_default() += 1;
+	}
+
+	/// Print events/sec for everything seen since the last call, then start a new window.
+	pub fn print_rates(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+		let elapsed = self.window_start.elapsed().as_secs_f64();
+		self.window_start = Instant::now();
+		let total: u64 = self.window.values().sum();
+		let breakdown = self
+			.window
+			.iter()
+			.map(|(name, count)| format!("{name} {:.0}", *count as f64 / elapsed))
+			.collect::<Vec<_>>()
+			.join(", ");
+		self.window.clear();
+		writeln!(out, "{:.0} events/s [{breakdown}]", total as f64 / elapsed)
+	}
+
+	pub fn print_totals(&self, out: &mut impl Write) -> std::io::Result<()> {
+		writeln!(out, "Totals:")?;
+		for (name, count) in &self.totals {
+			writeln!(out, "\t{name}: {count}")?;
+		}
+		writeln!(out, "\tall: {}", self.totals.values().sum::<u64>())
+	}
+}
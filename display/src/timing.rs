@@ -0,0 +1,143 @@
+use ipc::Message;
+use std::{
+	collections::BTreeMap,
+	io::Write,
+	time::{Duration, Instant},
+};
+
+// upper bounds of each bucket in microseconds, anything slower goes in the last one
+const BUCKET_BOUNDS: [u64; 11] = [
+	100, 250, 500, 1_000, 2_000, 4_000, 8_000, 16_000, 32_000, 64_000, 128_000,
+];
+const BAR_WIDTH: u64 = 40;
+
+#[derive(Debug, Default, Clone)]
+pub struct Histogram {
+	counts: [u64; BUCKET_BOUNDS.len() + 1],
+	count: u64,
+	sum: f64,
+	sum_squared: f64,
+	max: Duration,
+}
+impl Histogram {
+	pub fn add(&mut self, duration: Duration) {
+		let micros = duration.as_micros() as u64;
+		let bucket = BUCKET_BOUNDS
+			.iter()
+			.position(|bound| micros < *bound)
+			.unwrap_or(BUCKET_BOUNDS.len());
+		self.counts[bucket] += 1;
+		self.count += 1;
+		self.sum += micros as f64;
+		self.sum_squared += (micros as f64).powi(2);
+		self.max = self.max.max(duration);
+	}
+
+	pub fn mean_ms(&self) -> f64 {
+		self.sum / self.count.max(1) as f64 / 1000.0
+	}
+	/// Standard deviation, i.e. how much the durations jitter around the mean.
+	pub fn deviation_ms(&self) -> f64 {
+		let count = self.count.max(1) as f64;
+		let mean = self.sum / count;
+		(self.sum_squared / count - mean.powi(2)).max(0.0).sqrt() / 1000.0
+	}
+
+	pub fn summary(&self) -> String {
+		format!(
+			"mean {:.2}ms ±{:.2}ms, max {:.2}ms over {}",
+			self.mean_ms(),
+			self.deviation_ms(),
+			self.max.as_secs_f64() * 1000.0,
+			self.count
+		)
+	}
+
+	pub fn print(&self, out: &mut impl Write) -> std::io::Result<()> {
+		let largest = self.counts.iter().copied().max().unwrap_or(0).max(1);
+		let mut lower = 0;
+		for (bucket, count) in self.counts.iter().enumerate() {
+			let label = match BUCKET_BOUNDS.get(bucket) {
+				Some(upper) => format!("{}-{}", format_micros(lower), format_micros(*upper)),
+				None => format!(">{}", format_micros(lower)),
+			};
+			let bar = "#".repeat((count * BAR_WIDTH).div_ceil(largest) as usize);
+			writeln!(out, "\t\t{label:>13} {count:>8} {bar}")?;
+			lower = BUCKET_BOUNDS.get(bucket).copied().unwrap_or(lower);
+		}
+		Ok(())
+	}
+}
+
+fn format_micros(micros: u64) -> String {
+	if micros < 1000 {
+		format!("{micros}us")
+	} else {
+		format!("{}ms", micros / 1000)
+	}
+}
+
+/// How long a message stamped at `ts` took to arrive at `arrival`.
+pub fn latency(ts: u64, arrival: Instant) -> Duration {
+	// from when it arrived rather than now, which could be a while later
+	let micros = ipc::timestamp::now()
+		.saturating_sub(ts)
+		.saturating_sub(arrival.elapsed().as_micros() as u64);
+	Duration::from_micros(micros)
+}
+
+/// Time between consecutive arrivals of each message type, and how long each took to get here
+/// from when its source stamped it.
+#[derive(Debug, Default)]
+pub struct Timing {
+	last_arrival: BTreeMap<&'static str, Instant>,
+	window: BTreeMap<&'static str, Histogram>,
+	intervals: BTreeMap<&'static str, Histogram>,
+	latency_window: BTreeMap<&'static str, Histogram>,
+	latencies: BTreeMap<&'static str, Histogram>,
+}
+impl Timing {
+	/// Record a message that arrived at `arrival`, with the timestamp its source gave it if any.
+	pub fn record(&mut self, arrival: Instant, message: &Message, ts: Option<u64>) {
+		let name = message.name();
+		if let Some(last) = self.last_arrival.insert(name, arrival) {
+			let interval = arrival.saturating_duration_since(last);
+			self.window.entry(name).or_default().add(interval);
+			self.intervals.entry(name).or_default().add(interval);
+		}
+		if let Some(ts) = ts {
+			let latency = latency(ts, arrival);
+			self.latency_window.entry(name).or_default().add(latency);
+			self.latencies.entry(name).or_default().add(latency);
+		}
+	}
+
+	/// Print interval and latency stats for everything seen since the last call.
+	pub fn print_window(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+		for (name, histogram) in std::mem::take(&mut self.window) {
+			writeln!(out, "{name} interval: {}", histogram.summary())?;
+		}
+		for (name, histogram) in std::mem::take(&mut self.latency_window) {
+			writeln!(out, "{name} latency: {}", histogram.summary())?;
+		}
+		Ok(())
+	}
+
+	pub fn print_totals(&self, out: &mut impl Write) -> std::io::Result<()> {
+		writeln!(out, "Inter-event intervals:")?;
+		for (name, histogram) in &self.intervals {
+			writeln!(out, "\t{name}: {}", histogram.summary())?;
+			histogram.print(out)?;
+		}
+		if self.latencies.is_empty() {
+			writeln!(out, "No latencies, the source didn't timestamp anything")?;
+			return Ok(());
+		}
+		writeln!(out, "Transport latencies:")?;
+		for (name, histogram) in &self.latencies {
+			writeln!(out, "\t{name}: {}", histogram.summary())?;
+			histogram.print(out)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,342 @@
+use crate::filter::{is_shown, MessageClass};
+use color_eyre::Result;
+use ipc::{message_from_frame, Message};
+use ratatui::{
+	crossterm::{
+		event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+		terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+		ExecutableCommand,
+	},
+	layout::{Constraint, Layout},
+	prelude::CrosstermBackend,
+	style::Color,
+	symbols::Marker,
+	widgets::{
+		canvas::{Canvas, Line, Points},
+		Block, Borders, Paragraph,
+	},
+	Frame, Terminal,
+};
+use std::{
+	collections::{BTreeSet, VecDeque},
+	io::stdout,
+	time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+// how much of the accumulated mouse path and how many clicks are traced
+const PATH_LENGTH: usize = 2048;
+const CLICK_COUNT: usize = 32;
+
+/// Live view of the input state implied by the stream so far.
+#[derive(Debug, Default)]
+struct Dashboard {
+	held_keys: BTreeSet<u32>,
+	held_buttons: BTreeSet<u32>,
+	/// Slots of the fingers down on a touchscreen.
+	touches: BTreeSet<u32>,
+	/// How hard the tablet tool in reach is pressed, if one is.
+	pen_pressure: Option<f32>,
+	mouse_position: [f32; 2],
+	absolute_position: Option<[f32; 2]>,
+	path: VecDeque<(f64, f64)>,
+	clicks: VecDeque<(f64, f64)>,
+	scroll_continuous: [f32; 2],
+	scroll_discrete: [f32; 2],
+	last_scroll: Option<Instant>,
+	/// Pinching and turning so far, until a smart magnify zooms back.
+	magnify: f32,
+	rotate: f32,
+	keymap: Option<(usize, Option<String>, Instant)>,
+	key_repeat: Option<String>,
+	message_count: u64,
+	invalid_frames: u64,
+	last_message: Option<String>,
+	disconnected: bool,
+}
+impl Dashboard {
+	fn update(&mut self, message: &Message) {
+		self.message_count += 1;
+		self.last_message = Some(message.to_string());
+		match message {
+			Message::Keymap(keymap) => {
+				let symbols = keymap
+					.lines()
+					.map(str::trim)
+					.find(|l| l.starts_with("xkb_symbols"))
+					.map(|l| l.trim_end_matches('{').trim().to_string());
+				self.keymap = Some((keymap.len(), symbols, Instant::now()));
+			}
+			Message::Key { keycode, pressed } => {
+				if *pressed {
+					self.held_keys.insert(*keycode);
+				} else {
+					self.held_keys.remove(keycode);
+				}
+			}
+			Message::MouseMove(delta) => {
+				self.mouse_position[0] += delta.x;
+				self.mouse_position[1] += delta.y;
+				if self.path.len() == PATH_LENGTH {
+					self.path.pop_front();
+				}
+				self.path.push_back(self.canvas_position());
+			}
+			Message::MousePosition(position) => {
+				self.absolute_position = Some([position.x, position.y]);
+			}
+			Message::MouseButton { button, pressed } => {
+				if *pressed {
+					if self.clicks.len() == CLICK_COUNT {
+						self.clicks.pop_front();
+					}
+					self.clicks.push_back(self.canvas_position());
+					self.held_buttons.insert(*button);
+				} else {
+					self.held_buttons.remove(button);
+				}
+			}
+			Message::MouseAxisContinuous(scroll) => {
+				self.scroll_continuous[0] += scroll.x;
+				self.scroll_continuous[1] += scroll.y;
+				self.last_scroll = Some(Instant::now());
+			}
+			Message::MouseAxisDiscrete(scroll) => {
+				self.scroll_discrete[0] += scroll.x;
+				self.scroll_discrete[1] += scroll.y;
+				self.last_scroll = Some(Instant::now());
+			}
+			Message::Magnify(delta) => self.magnify += delta,
+			Message::Rotate(delta) => self.rotate += delta,
+			Message::SmartMagnify => self.magnify = 0.0,
+			Message::ResetInput => {
+				self.held_keys.clear();
+				self.held_buttons.clear();
+				self.touches.clear();
+			}
+			Message::TouchDown { slot, .. } => {
+				self.touches.insert(*slot);
+			}
+			Message::TouchUp { slot } => {
+				self.touches.remove(slot);
+			}
+			Message::Disconnect => self.disconnected = true,
+			Message::MultiClick { .. } => (),
+			Message::KeyRepeat { .. } => self.key_repeat = Some(message.to_string()),
+			Message::Heartbeat
+			| Message::Pause
+			| Message::Resume
+			| Message::Hover { .. }
+			| Message::ClipboardSet(_)
+			| Message::TouchMotion { .. } => (),
+			Message::TabletProximity { in_proximity, .. } => {
+				self.pen_pressure = in_proximity.then_some(0.0);
+			}
+			Message::TabletAxis { pressure, .. } => self.pen_pressure = Some(*pressure),
+			Message::TabletTip { .. }
+			| Message::TabletButton { .. }
+			| Message::GamepadConnected { .. }
+			| Message::GamepadDisconnected { .. }
+			| Message::GamepadButton { .. }
+			| Message::GamepadAxis { .. }
+			| Message::DeviceAdded { .. }
+			| Message::DeviceRemoved { .. }
+			| Message::Hello { .. }
+			| Message::BatchBegin
+			| Message::BatchEnd => (),
+		}
+	}
+
+	/// Mouse position with y flipped, since the canvas' y axis points up.
+	fn canvas_position(&self) -> (f64, f64) {
+		(
+			self.mouse_position[0] as f64,
+			-self.mouse_position[1] as f64,
+		)
+	}
+
+	fn draw(&self, frame: &mut Frame) {
+		let [info_area, path_area] =
+			Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+				.areas(frame.size());
+		let [held_area, pointer_area, keymap_area, status_area] = Layout::vertical([
+			Constraint::Length(5),
+			Constraint::Length(8),
+			Constraint::Length(5),
+			Constraint::Min(3),
+		])
+		.areas(info_area);
+
+		let held = format!(
+			"Keys: {}\nButtons: {}\nTouches: {}",
+			join_codes(&self.held_keys),
+			join_codes(&self.held_buttons),
+			join_codes(&self.touches)
+		);
+		frame.render_widget(
+			Paragraph::new(held).block(Block::default().borders(Borders::ALL).title("Held")),
+			held_area,
+		);
+
+		let scroll_age = self
+			.last_scroll
+			.map(|t| format!("{:.1}s ago", t.elapsed().as_secs_f32()))
+			.unwrap_or_else(|| "never".to_string());
+		let absolute = self
+			.absolute_position
+			.map(|[x, y]| format!("{x:.3}, {y:.3}"))
+			.unwrap_or_else(|| "none".to_string());
+		let pen = self
+			.pen_pressure
+			.map(|pressure| format!("pressure {pressure:.2}"))
+			.unwrap_or_else(|| "out of reach".to_string());
+		let pointer = format!(
+			"Position: {:.0}, {:.0}\nAbsolute: {absolute}\nScroll continuous: {:.2}, {:.2}\nScroll discrete: {:.0}, {:.0} (last {scroll_age})\nMagnify: {:.2}, rotate: {:.0}°\nPen: {pen}",
+			self.mouse_position[0],
+			self.mouse_position[1],
+			self.scroll_continuous[0],
+			self.scroll_continuous[1],
+			self.scroll_discrete[0],
+			self.scroll_discrete[1],
+			self.magnify,
+			self.rotate,
+		);
+		frame.render_widget(
+			Paragraph::new(pointer).block(Block::default().borders(Borders::ALL).title("Mouse")),
+			pointer_area,
+		);
+
+		let mut keymap = match &self.keymap {
+			Some((length, symbols, received)) => format!(
+				"{length} bytes, received {:.1}s ago\n{}",
+				received.elapsed().as_secs_f32(),
+				symbols.as_deref().unwrap_or("no xkb_symbols section")
+			),
+			None => "No keymap received".to_string(),
+		};
+		keymap.push('\n');
+		keymap.push_str(
+			self.key_repeat
+				.as_deref()
+				.unwrap_or("No key repeat settings"),
+		);
+		frame.render_widget(
+			Paragraph::new(keymap).block(Block::default().borders(Borders::ALL).title("Keymap")),
+			keymap_area,
+		);
+
+		self.draw_path(frame, path_area);
+
+		let status = format!(
+			"{} messages, {} invalid frames{}\nLast: {}\n\nq to quit",
+			self.message_count,
+			self.invalid_frames,
+			if self.disconnected {
+				" (disconnected)"
+			} else {
+				""
+			},
+			self.last_message.as_deref().unwrap_or("-"),
+		);
+		frame.render_widget(
+			Paragraph::new(status).block(Block::default().borders(Borders::ALL).title("Stream")),
+			status_area,
+		);
+	}
+
+	/// Trace the accumulated mouse motion, scaled to fit, with clicks marked in red.
+	fn draw_path(&self, frame: &mut Frame, area: ratatui::layout::Rect) {
+		let (mut min, mut max) = (self.canvas_position(), self.canvas_position());
+		for (x, y) in self.path.iter().chain(&self.clicks) {
+			min = (min.0.min(*x), min.1.min(*y));
+			max = (max.0.max(*x), max.1.max(*y));
+		}
+		// pad the bounds so the path doesn't touch the border and a still mouse doesn't divide by zero
+		let padding = ((max.0 - min.0).max(max.1 - min.1) * 0.05).max(1.0);
+		let path = self.path.iter().collect::<Vec<_>>();
+		let clicks = self.clicks.iter().copied().collect::<Vec<_>>();
+		let position = [self.canvas_position()];
+		let canvas = Canvas::default()
+			.block(Block::default().borders(Borders::ALL).title("Mouse path"))
+			.marker(Marker::Braille)
+			.x_bounds([min.0 - padding, max.0 + padding])
+			.y_bounds([min.1 - padding, max.1 + padding])
+			.paint(|ctx| {
+				for segment in path.windows(2) {
+					ctx.draw(&Line::new(
+						segment[0].0,
+						segment[0].1,
+						segment[1].0,
+						segment[1].1,
+						Color::White,
+					));
+				}
+				ctx.draw(&Points {
+					coords: &clicks,
+					color: Color::Red,
+				});
+				ctx.draw(&Points {
+					coords: &position,
+					color: Color::Green,
+				});
+			});
+		frame.render_widget(canvas, area);
+	}
+}
+
+fn join_codes(codes: &BTreeSet<u32>) -> String {
+	if codes.is_empty() {
+		return "-".to_string();
+	}
+	codes
+		.iter()
+		.map(u32::to_string)
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// Run the dashboard until the user quits. The stream ending doesn't quit so the final state can be inspected.
+pub async fn run(
+	mut frame_rx: mpsc::UnboundedReceiver<(Instant, Vec<u8>)>,
+	only: Vec<MessageClass>,
+) -> Result<()> {
+	enable_raw_mode()?;
+	stdout().execute(EnterAlternateScreen)?;
+	let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+	let result = async {
+		let mut dashboard = Dashboard::default();
+		let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+		loop {
+			tokio::select! {
+				Some((_, frame)) = frame_rx.recv() => {
+					match message_from_frame(&frame) {
+						Ok(message) if is_shown(&only, &message) => dashboard.update(&message),
+						Ok(_) => (),
+						Err(_) => dashboard.invalid_frames += 1,
+					}
+				}
+				_ = redraw.tick() => {
+					terminal.draw(|frame| dashboard.draw(frame))?;
+					while event::poll(Duration::ZERO)? {
+						let Event::Key(key) = event::read()? else {
+							continue;
+						};
+						let ctrl_c = key.code == KeyCode::Char('c')
+							&& key.modifiers.contains(KeyModifiers::CONTROL);
+						if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c)
+						{
+							return Ok(());
+						}
+					}
+				}
+			}
+		}
+	}
+	.await;
+
+	disable_raw_mode()?;
+	stdout().execute(LeaveAlternateScreen)?;
+	result
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+// how far the pointer can move between clicks before they're clicks in different places
+const MAX_TRAVEL: f64 = 8.0;
+
+/// Counts presses of the same button that come quickly enough after each other, in about the same
+/// place, to be a double or triple click.
+#[derive(Debug, Default)]
+pub struct ClickCounter {
+	/// The last press's button, when it happened and how many clicks in a row it made.
+	last: Option<(u32, Duration, u32)>,
+	/// How far the pointer moved since then.
+	travel: f64,
+}
+impl ClickCounter {
+	/// Count a press at `time`, returning how many clicks in a row it makes.
+	pub fn press(&mut self, button: u32, time: Duration, interval: Duration) -> u32 {
+		let count = match self.last {
+			Some((last_button, last_time, count))
+				if last_button == button && time.saturating_sub(last_time) <= interval =>
+			{
+				count + 1
+			}
+			_ => 1,
+		};
+		self.last = Some((button, time, count));
+		self.travel = 0.0;
+		count
+	}
+
+	/// Count pointer motion, starting over once it's moved away from where the clicks were.
+	pub fn moved(&mut self, dx: f64, dy: f64) {
+		self.travel += dx.hypot(dy);
+		if self.travel > MAX_TRAVEL {
+			self.last = None;
+		}
+	}
+}
+
+#[test]
+fn test_click_counter() {
+	let interval = Duration::from_millis(400);
+	let ms = Duration::from_millis;
+	let mut clicks = ClickCounter::default();
+	assert_eq!(clicks.press(272, ms(0), interval), 1);
+	assert_eq!(clicks.press(272, ms(300), interval), 2);
+	assert_eq!(clicks.press(272, ms(650), interval), 3);
+	// too slow
+	assert_eq!(clicks.press(272, ms(1100), interval), 1);
+	// another button
+	assert_eq!(clicks.press(273, ms(1200), interval), 1);
+	assert_eq!(clicks.press(272, ms(1300), interval), 1);
+	// jitter between clicks is fine, but not moving somewhere else
+	clicks.moved(3.0, 4.0);
+	assert_eq!(clicks.press(272, ms(1400), interval), 2);
+	clicks.moved(6.0, 8.0);
+	assert_eq!(clicks.press(272, ms(1500), interval), 1);
+}
@@ -1,18 +1,45 @@
+mod clicks;
+mod realtime;
+mod touchpad;
+
+use clap::Parser;
+use clicks::ClickCounter;
+use config::{reload::Live, Config};
 use input::event::keyboard::KeyboardEventTrait;
-use input::event::pointer::{Axis, PointerScrollEvent};
+use input::event::pointer::{Axis, PointerEventTrait, PointerScrollEvent};
 use input::event::tablet_pad::{ButtonState, KeyState};
-use input::event::PointerEvent;
-use input::{Libinput, LibinputInterface};
-use ipc::{send_input_ipc, ButtonBlot, Message};
+use input::event::tablet_tool::{ProximityState, TabletToolEventTrait, TabletToolType, TipState};
+use input::event::touch::{TouchEvent, TouchEventPosition, TouchEventSlot, TouchEventTrait};
+use input::event::{DeviceEvent, EventTrait, PointerEvent, TabletToolEvent};
+use input::{Device, DeviceCapability, Libinput, LibinputInterface, SendEventsMode};
+use ipc::{
+	flow::PausePolicy, heartbeat::HEARTBEAT_TIMEOUT, send_input_ipc, send_stamped_input_ipc,
+	ButtonBlot, Capabilities, Message, Stamp, TabletTool,
+};
 use libc::{O_RDONLY, O_RDWR, O_WRONLY};
-use nix::poll::{poll, PollFd, PollFlags};
+use logging::{info, warn, LogArgs};
+use nix::{
+	errno::Errno,
+	poll::{poll, PollFd, PollFlags},
+};
 use std::fs::{File, OpenOptions};
+use std::io::IsTerminal;
 use std::os::fd::AsRawFd;
 use std::os::unix::{fs::OpenOptionsExt, io::OwnedFd};
 use std::path::Path;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+use touchpad::Touchpad;
 use xkbcommon::xkb::{Context, Keymap, KEYMAP_FORMAT_TEXT_V1};
 
+// how long a signal's handler gets to send a state change after the signal interrupts polling
+const SIGNAL_HANDLER_WAIT: Duration = Duration::from_millis(50);
+// how long polling waits for input before checking for a stop that came without a signal, like
+// the heartbeat finding the sink gone
+const IDLE_POLL_MS: i32 = 1000;
+// how quickly clicks have to follow each other to count as a double click, GTK's default
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
 pub enum StateChange {
 	Enable,
 	Disable,
@@ -35,74 +62,511 @@ impl LibinputInterface for Interface {
 		drop(File::from(fd));
 	}
 }
-pub fn input_loop(mut enabled: bool, state_rx: Receiver<StateChange>) {
+struct Settings {
+	seat: String,
+	// devices with any of these in their name are left to the desktop
+	ignored_devices: Vec<String>,
+	pause_policy: PausePolicy,
+	// libinput leaves repeating to the compositor, so this is what sinks are told to repeat at
+	key_repeat_rate: u32,
+	key_repeat_delay: u32,
+	multi_click_interval: Duration,
+	heartbeat_timeout: Duration,
+}
+impl Settings {
+	fn read(config: &Config) -> Self {
+		let config = config.section("eclipse");
+		Settings {
+			seat: config.get("seat", "seat0".to_string()),
+			ignored_devices: config.get_list("ignore-device"),
+			pause_policy: config
+				.get("pause-policy", "buffer".to_string())
+				.parse()
+				.unwrap_or_else(|e| {
+					warn!("{e}");
+					PausePolicy::Buffer
+				}),
+			key_repeat_rate: config.get("key-repeat-rate", 25),
+			key_repeat_delay: config.get("key-repeat-delay", 600),
+			multi_click_interval: config.get_millis("multi-click-ms", MULTI_CLICK_INTERVAL),
+			heartbeat_timeout: config.get_millis("heartbeat-timeout-ms", HEARTBEAT_TIMEOUT),
+		}
+	}
+
+	fn key_repeat(&self) -> Message {
+		Message::KeyRepeat {
+			rate: self.key_repeat_rate,
+			delay: self.key_repeat_delay,
+		}
+	}
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Read keyboards and mice with libinput and write their input to stdout")]
+pub struct Args {
+	#[command(flatten)]
+	pub modes: Modes,
+	#[command(flatten)]
+	pub latency: Latency,
+	/// Serve input on a socket in $XDG_RUNTIME_DIR called NAME for sinks to connect to, instead of writing it to stdout
+	#[arg(long, value_name = "NAME")]
+	pub socket: Option<String>,
+	#[command(flatten)]
+	pub log: LogArgs,
+}
+
+/// How devices are turned into input.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct Modes {
+	/// Treat touchpads as absolute devices, pointing wherever the finger is on them instead of moving the pointer along with it
+	#[arg(long, conflicts_with = "keyboard_only")]
+	pub absolute_touchpad: bool,
+	/// Only read keyboards, leaving mice and touchpads alone for another source to point with
+	#[arg(long, conflicts_with = "mouse_only")]
+	pub keyboard_only: bool,
+	/// Only read mice, touchpads, touchscreens and tablets, leaving keyboards alone for another source to type with
+	#[arg(long)]
+	pub mouse_only: bool,
+}
+impl Modes {
+	/// Whether `device` is read at all, or left to whatever else is reading it.
+	fn wants(&self, device: &Device) -> bool {
+		if self.keyboard_only {
+			device.has_capability(DeviceCapability::Keyboard)
+		} else if self.mouse_only {
+			device.has_capability(DeviceCapability::Pointer)
+				|| device.has_capability(DeviceCapability::Touch)
+				|| device.has_capability(DeviceCapability::TabletTool)
+		} else {
+			true
+		}
+	}
+
+	/// What kinds of input the devices this reads send.
+	fn capabilities(&self) -> Capabilities {
+		let mouse = Capabilities::POINTER
+			| Capabilities::TOUCH
+			| Capabilities::TABLET
+			| Capabilities::GESTURES;
+		if self.keyboard_only {
+			Capabilities::KEYBOARD
+		} else if self.mouse_only {
+			mouse
+		} else {
+			Capabilities::KEYBOARD | mouse
+		}
+	}
+}
+
+/// Trading CPU time for lower input latency.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct Latency {
+	/// Ask for realtime scheduling and pin the input loop to one CPU, falling back to a higher priority without the privileges for it
+	#[arg(long)]
+	pub realtime: bool,
+	/// With --realtime, keep polling without sleeping for this many microseconds after each event, in case another follows
+	#[arg(
+		long,
+		value_name = "MICROSECONDS",
+		default_value_t = 0,
+		requires = "realtime"
+	)]
+	pub busy_poll: u64,
+}
+
+pub fn run(args: Args) {
+	if args.socket.is_none() && std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `eclipse | azimuth`");
+	}
+	logging::init(&args.log).unwrap();
+	if let Some(name) = &args.socket {
+		ipc::socket::serve(name).unwrap();
+	}
+	// before anything else starts, so eclipse's other threads are realtime too
+	if args.latency.realtime {
+		realtime::raise_priority();
+	}
+	let (tx, rx) = mpsc::channel();
+	ipc::shutdown::on_signal(move || {
+		tx.send(StateChange::Stop).unwrap();
+	});
+	input_loop(true, rx, args.modes, args.latency)
+}
+
+pub fn input_loop(
+	mut enabled: bool,
+	state_rx: Receiver<StateChange>,
+	modes: Modes,
+	latency: Latency,
+) {
+	config::reload::watch();
+	let mut settings = Live::new(Settings::read);
+	// libinput is only given the seat once, so moving to another one takes a restart
+	let seat = settings.get().seat.clone();
+	let pause_policy = settings.get().pause_policy;
+
 	let mut input = Libinput::new_with_udev(Interface);
-	input.udev_assign_seat("seat0").unwrap();
+	input.udev_assign_seat(&seat).unwrap();
 	let pollfd = PollFd::new(input.as_raw_fd(), PollFlags::POLLIN);
 
 	let keymap = Keymap::new_from_names(&Context::new(0), "evdev", "", "", "", None, 0)
 		.unwrap()
 		.get_as_string(KEYMAP_FORMAT_TEXT_V1);
-	send_input_ipc(Message::Keymap(keymap));
+	// listening before saying hello, so the sink's hello back isn't missed
+	ipc::flow::accept_pauses(pause_policy);
+	if !still_connected(ipc::handshake::hello(modes.capabilities())) {
+		return;
+	}
+	ipc::heartbeat::set_timeout(settings.get().heartbeat_timeout);
+	ipc::heartbeat::start_heartbeat();
+	// libinput's own timestamps go on what comes from its events, this is for the rest
+	ipc::timestamp::stamp_sends();
+	// the keymap and repeat rate are up to whichever source has the keyboard
+	let mut key_repeat = settings.get().key_repeat();
+	if !modes.mouse_only {
+		let sent = send_input_ipc(Message::Keymap(keymap))
+			.and_then(|_| send_input_ipc(key_repeat.clone()));
+		if !still_connected(sent) {
+			return;
+		}
+	}
 
 	let mut mouse_blot = Some(ButtonBlot::default());
 	let mut key_blot = Some(ButtonBlot::default());
-	while poll(&mut [pollfd], -1).is_ok() {
-		if let Ok(state_change) = state_rx.try_recv() {
+	let mut touchpads: Vec<Touchpad> = Vec::new();
+	let mut clicks = ClickCounter::default();
+	// only this thread, the ones started already can go wherever
+	if latency.realtime {
+		realtime::pin_to_current_cpu();
+	}
+	let busy_poll = Duration::from_micros(latency.busy_poll);
+	let mut busy_until = Instant::now();
+	loop {
+		let mut pollfds = vec![pollfd];
+		pollfds.extend(
+			touchpads
+				.iter()
+				.map(|touchpad| PollFd::new(touchpad.as_raw_fd(), PollFlags::POLLIN)),
+		);
+		let timeout = if Instant::now() < busy_until {
+			0
+		} else {
+			IDLE_POLL_MS
+		};
+		let state_change = match poll(&mut pollfds, timeout) {
+			// spinning, nothing came yet
+			Ok(0) if timeout == 0 => continue,
+			Ok(0) => state_rx.try_recv().ok(),
+			Ok(_) => {
+				busy_until = Instant::now() + busy_poll;
+				state_rx.try_recv().ok()
+			}
+			// ctrl+c or a SIGHUP to reload the config, and only ctrl+c's handler sends a stop
+			Err(Errno::EINTR) => state_rx.recv_timeout(SIGNAL_HANDLER_WAIT).ok(),
+			Err(_) => break,
+		};
+		if let Some(state_change) = state_change {
 			match state_change {
 				StateChange::Enable => enabled = true,
 				StateChange::Disable => enabled = false,
-				StateChange::Stop => return,
+				StateChange::Stop => {
+					// the devices go back to the desktop when eclipse exits, so the sink has to let
+					// go of whatever they were holding
+					ipc::shutdown::end_stream();
+					return;
+				}
+			}
+		}
+		if settings.reload() {
+			if settings.get().seat != seat {
+				warn!(
+					"Staying on {seat}, restart eclipse to move to {}",
+					settings.get().seat
+				);
+			}
+			if settings.get().key_repeat() != key_repeat && !modes.mouse_only {
+				key_repeat = settings.get().key_repeat();
+				if !still_connected(send_input_ipc(key_repeat.clone())) {
+					return;
+				}
 			}
 		}
 		input.dispatch().unwrap();
 		if enabled {
-			for event in &mut input {
-				send_input_ipc(match event {
-					input::Event::Keyboard(input::event::KeyboardEvent::Key(k)) => {
-						key_blot
-							.as_mut()
-							.unwrap()
-							.key_update(k.key(), k.key_state() == KeyState::Pressed);
-						Message::Key {
-							keycode: k.key(),
-							pressed: k.key_state() == KeyState::Pressed,
-						}
+			let multi_click_interval = settings.get().multi_click_interval;
+			let ignored_devices = &settings.get().ignored_devices;
+			// everything from one poll goes to the sink as one batch
+			let events: Vec<_> = (&mut input).collect();
+			let batch = events.len() > 1;
+			if batch && !still_connected(send_input_ipc(Message::BatchBegin)) {
+				return;
+			}
+			for event in events {
+				if !ignored_devices.is_empty() {
+					let device = event.device();
+					if ignored_devices
+						.iter()
+						.any(|name| device.name().contains(name))
+					{
+						continue;
 					}
-					input::Event::Pointer(PointerEvent::Button(p)) => {
-						mouse_blot
-							.as_mut()
-							.unwrap()
-							.key_update(p.button(), p.button_state() == ButtonState::Pressed);
-						Message::MouseButton {
-							button: p.button(),
-							pressed: p.button_state() == ButtonState::Pressed,
+				}
+				// when the device sent it, on the same clock as timestamps, and which device it was
+				let ts = event_time(&event).unwrap_or_else(ipc::timestamp::now);
+				let stamp = Stamp {
+					ts: Some(ts),
+					device: Some(device_id(&event.device())),
+				};
+				let sent = send_stamped_input_ipc(
+					match event {
+						// disabled rather than skipped so libinput closes it and stops reading it
+						input::Event::Device(DeviceEvent::Added(added))
+							if !modes.wants(&added.device()) =>
+						{
+							let _ = added
+								.device()
+								.config_send_events_set_mode(SendEventsMode::DISABLED);
+							continue;
 						}
+						// the other half of a device that's both, e.g. a keyboard with a touchpad
+						input::Event::Keyboard(_) if modes.mouse_only => continue,
+						input::Event::Pointer(_)
+						| input::Event::Touch(_)
+						| input::Event::Tablet(_)
+							if modes.keyboard_only =>
+						{
+							continue
+						}
+						input::Event::Device(DeviceEvent::Added(added)) => {
+							let device = added.device();
+							if modes.absolute_touchpad
+								&& device.has_capability(DeviceCapability::Gesture)
+							{
+								touchpads.extend(Touchpad::open(device.sysname()));
+							}
+							Message::DeviceAdded {
+								id: device_id(&device),
+								name: device.name().to_string(),
+								capabilities: capabilities(&device),
+							}
+						}
+						input::Event::Device(DeviceEvent::Removed(removed)) => {
+							let device = removed.device();
+							touchpads.retain(|touchpad| touchpad.sysname != device.sysname());
+							Message::DeviceRemoved {
+								id: device_id(&device),
+							}
+						}
+						input::Event::Keyboard(input::event::KeyboardEvent::Key(k)) => {
+							key_blot
+								.as_mut()
+								.unwrap()
+								.key_update(k.key(), k.key_state() == KeyState::Pressed);
+							Message::Key {
+								keycode: k.key(),
+								pressed: k.key_state() == KeyState::Pressed,
+							}
+						}
+						input::Event::Pointer(PointerEvent::Button(p)) => {
+							let pressed = p.button_state() == ButtonState::Pressed;
+							mouse_blot.as_mut().unwrap().key_update(p.button(), pressed);
+							let sent = send_stamped_input_ipc(
+								Message::MouseButton {
+									button: p.button(),
+									pressed,
+								},
+								stamp,
+							);
+							if !still_connected(sent) {
+								return;
+							}
+							if !pressed {
+								continue;
+							}
+							let time = Duration::from_micros(p.time_usec());
+							match clicks.press(p.button(), time, multi_click_interval) {
+								1 => continue,
+								count => Message::MultiClick {
+									button: p.button(),
+									count,
+								},
+							}
+						}
+						// absolute touchpads point with the finger's position instead
+						input::Event::Pointer(PointerEvent::Motion(m))
+							if touchpads
+								.iter()
+								.any(|touchpad| touchpad.sysname == m.device().sysname()) =>
+						{
+							continue
+						}
+						input::Event::Pointer(PointerEvent::Motion(m)) => {
+							clicks.moved(m.dx(), m.dy());
+							Message::MouseMove([m.dx() as f32, m.dy() as f32].into())
+						}
+						input::Event::Pointer(PointerEvent::ScrollContinuous(s)) => {
+							Message::MouseAxisContinuous(
+								[
+									s.scroll_value(Axis::Horizontal) as f32,
+									s.scroll_value(Axis::Vertical) as f32,
+								]
+								.into(),
+							)
+						}
+						input::Event::Pointer(PointerEvent::ScrollWheel(s)) => {
+							Message::MouseAxisContinuous(
+								[
+									s.scroll_value_v120(Axis::Horizontal) as f32 / 120.0,
+									s.scroll_value_v120(Axis::Vertical) as f32 / 120.0,
+								]
+								.into(),
+							)
+						}
+						input::Event::Touch(TouchEvent::Down(t)) => Message::TouchDown {
+							slot: t.seat_slot(),
+							position: touch_position(&t),
+						},
+						input::Event::Touch(TouchEvent::Motion(t)) => Message::TouchMotion {
+							slot: t.seat_slot(),
+							position: touch_position(&t),
+						},
+						input::Event::Touch(TouchEvent::Up(t)) => Message::TouchUp {
+							slot: t.seat_slot(),
+						},
+						// a cancelled touch, e.g. one libinput took for a palm, is lifted all the same
+						input::Event::Touch(TouchEvent::Cancel(t)) => Message::TouchUp {
+							slot: t.seat_slot(),
+						},
+						input::Event::Tablet(TabletToolEvent::Proximity(p)) => {
+							let in_proximity = p.proximity_state() == ProximityState::In;
+							let sent = send_stamped_input_ipc(
+								Message::TabletProximity {
+									tool: tablet_tool(p.tool().tool_type()),
+									in_proximity,
+								},
+								stamp,
+							);
+							if !still_connected(sent) {
+								return;
+							}
+							// a tool coming into reach comes with where it is
+							if !in_proximity {
+								continue;
+							}
+							tablet_axis(&p)
+						}
+						input::Event::Tablet(TabletToolEvent::Axis(a)) => tablet_axis(&a),
+						input::Event::Tablet(TabletToolEvent::Tip(t)) => {
+							// so the tip goes down exactly where it touched
+							if !still_connected(send_stamped_input_ipc(tablet_axis(&t), stamp)) {
+								return;
+							}
+							Message::TabletTip {
+								down: t.tip_state() == TipState::Down,
+							}
+						}
+						input::Event::Tablet(TabletToolEvent::Button(b)) => Message::TabletButton {
+							button: b.button(),
+							pressed: b.button_state() == ButtonState::Pressed,
+						},
+						_ => continue,
+					},
+					stamp,
+				);
+				if !still_connected(sent) {
+					return;
+				}
+			}
+			for touchpad in &mut touchpads {
+				if let Some(position) = touchpad.read() {
+					if !still_connected(send_input_ipc(Message::MousePosition(position.into()))) {
+						return;
 					}
-					input::Event::Pointer(PointerEvent::Motion(m)) => {
-						Message::MouseMove([m.dx() as f32, m.dy() as f32].into())
-					}
-					input::Event::Pointer(PointerEvent::ScrollContinuous(s)) => {
-						Message::MouseAxisContinuous(
-							[
-								s.scroll_value(Axis::Horizontal) as f32,
-								s.scroll_value(Axis::Vertical) as f32,
-							]
-							.into(),
-						)
-					}
-					input::Event::Pointer(PointerEvent::ScrollWheel(s)) => {
-						Message::MouseAxisContinuous(
-							[
-								s.scroll_value_v120(Axis::Horizontal) as f32 / 120.0,
-								s.scroll_value_v120(Axis::Vertical) as f32 / 120.0,
-							]
-							.into(),
-						)
-					}
-					_ => continue,
-				})
+				}
 			}
+			if batch && !still_connected(send_input_ipc(Message::BatchEnd)) {
+				return;
+			}
+		}
+	}
+}
+
+/// Whether the sink is still there to send to, after warning about anything else going wrong.
+///
+/// Once it's gone eclipse stops, and the devices go back to the desktop with nothing to release.
+fn still_connected(sent: Result<(), ipc::Error>) -> bool {
+	match sent {
+		Ok(()) => true,
+		Err(ipc::Error::Disconnected) => {
+			info!("The sink went away, stopping");
+			false
 		}
+		Err(e) => {
+			warn!("{e}");
+			true
+		}
+	}
+}
+
+/// Where a touch is, from 0 to 1 across and down the touchscreen.
+fn touch_position(touch: &impl TouchEventPosition) -> mint::Vector2<f32> {
+	[touch.x_transformed(1) as f32, touch.y_transformed(1) as f32].into()
+}
+
+/// When libinput got the event from the device, in microseconds on `CLOCK_MONOTONIC`.
+fn event_time(event: &input::Event) -> Option<u64> {
+	Some(match event {
+		input::Event::Keyboard(k) => k.time_usec(),
+		input::Event::Pointer(p) => p.time_usec(),
+		input::Event::Touch(t) => t.time_usec(),
+		input::Event::Tablet(t) => t.time_usec(),
+		_ => return None,
+	})
+}
+
+/// The number of the device's `/dev/input/eventN` node, which stays the same for as long as it's
+/// plugged in.
+fn device_id(device: &Device) -> u32 {
+	device
+		.sysname()
+		.strip_prefix("event")
+		.and_then(|number| number.parse().ok())
+		.unwrap_or(u32::MAX)
+}
+
+/// What kinds of input a device can send.
+fn capabilities(device: &Device) -> Capabilities {
+	[
+		(DeviceCapability::Keyboard, Capabilities::KEYBOARD),
+		(DeviceCapability::Pointer, Capabilities::POINTER),
+		(DeviceCapability::Touch, Capabilities::TOUCH),
+		(DeviceCapability::TabletTool, Capabilities::TABLET),
+		(DeviceCapability::Gesture, Capabilities::GESTURES),
+	]
+	.into_iter()
+	.filter(|(capability, _)| device.has_capability(*capability))
+	.fold(Capabilities::default(), |all, (_, kind)| all | kind)
+}
+
+/// Where a tablet tool is, how hard it's pressed and how it's tilted.
+fn tablet_axis(event: &impl TabletToolEventTrait) -> Message {
+	Message::TabletAxis {
+		position: [event.x_transformed(1) as f32, event.y_transformed(1) as f32].into(),
+		pressure: event.pressure() as f32,
+		tilt: [event.tilt_x() as f32, event.tilt_y() as f32].into(),
+	}
+}
+
+fn tablet_tool(tool_type: Option<TabletToolType>) -> TabletTool {
+	match tool_type {
+		Some(TabletToolType::Eraser) => TabletTool::Eraser,
+		Some(TabletToolType::Brush) => TabletTool::Brush,
+		Some(TabletToolType::Pencil) => TabletTool::Pencil,
+		Some(TabletToolType::Airbrush) => TabletTool::Airbrush,
+		Some(TabletToolType::Mouse) => TabletTool::Mouse,
+		Some(TabletToolType::Lens) => TabletTool::Lens,
+		Some(TabletToolType::Totem) => TabletTool::Totem,
+		// pens, and whatever isn't known is most likely some sort of pen too
+		_ => TabletTool::Pen,
 	}
 }
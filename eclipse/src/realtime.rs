@@ -0,0 +1,56 @@
+//! Scheduling eclipse ahead of everything else, for the lowest worst case latency.
+//!
+//! Each step falls back to the next if eclipse isn't allowed to, e.g. without `CAP_SYS_NICE` or an
+//! `rtprio` limit, so `--realtime` never stops it from running.
+
+use logging::{info, warn};
+use std::io;
+
+// low enough to stay below the kernel's own threads and audio servers
+const REALTIME_PRIORITY: i32 = 20;
+// what's asked for when realtime scheduling isn't allowed
+const NICENESS: i32 = -10;
+
+/// Ask for realtime scheduling for this thread and any it starts, or else a higher priority.
+pub fn raise_priority() {
+	let param = libc::sched_param {
+		sched_priority: REALTIME_PRIORITY,
+	};
+	for (policy, name) in [
+		(libc::SCHED_FIFO, "SCHED_FIFO"),
+		(libc::SCHED_RR, "SCHED_RR"),
+	] {
+		if unsafe { libc::sched_setscheduler(0, policy, &param) } == 0 {
+			info!("Running with {name} at priority {REALTIME_PRIORITY}");
+			return;
+		}
+	}
+	let realtime_error = io::Error::last_os_error();
+	if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS) } == 0 {
+		warn!("Couldn't get realtime scheduling ({realtime_error}), running at niceness {NICENESS} instead");
+	} else {
+		warn!(
+			"Couldn't get realtime scheduling ({realtime_error}) or a higher priority ({}), running as usual",
+			io::Error::last_os_error()
+		);
+	}
+}
+
+/// Keep this thread on the CPU it's on now, so it never waits to be moved or for a cold cache.
+pub fn pin_to_current_cpu() {
+	let cpu = unsafe { libc::sched_getcpu() };
+	if cpu < 0 {
+		warn!("Couldn't tell which CPU the input loop is on, leaving it unpinned");
+		return;
+	}
+	let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+	unsafe { libc::CPU_SET(cpu as usize, &mut set) };
+	if unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) } == 0 {
+		info!("Pinned the input loop to CPU {cpu}");
+	} else {
+		warn!(
+			"Couldn't pin the input loop to CPU {cpu}: {}",
+			io::Error::last_os_error()
+		);
+	}
+}
@@ -0,0 +1,141 @@
+//! Touchpads as absolute devices, pointing wherever the finger is on them rather than moving the
+//! pointer along with it.
+//!
+//! libinput only hands out relative motion for touchpads, so the finger's position is read from
+//! the device's evdev node alongside it.
+
+use input_event_codes::{ABS_X, ABS_Y, BTN_TOUCH, EV_ABS, EV_KEY, EV_SYN, SYN_REPORT};
+use logging::warn;
+use std::{
+	fs::{File, OpenOptions},
+	io::Read,
+	mem::size_of,
+	os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+};
+
+nix::ioctl_read!(abs_x_info, b'E', 0x40 + ABS_X!(), libc::input_absinfo);
+nix::ioctl_read!(abs_y_info, b'E', 0x40 + ABS_Y!(), libc::input_absinfo);
+
+pub struct Touchpad {
+	/// The device's sysname in libinput, e.g. `event5`.
+	pub sysname: String,
+	file: File,
+	position: Position,
+}
+impl Touchpad {
+	pub fn open(sysname: &str) -> Option<Self> {
+		let file = OpenOptions::new()
+			.read(true)
+			.custom_flags(libc::O_NONBLOCK)
+			.open(format!("/dev/input/{sysname}"))
+			.map_err(|e| warn!("Couldn't open touchpad {sysname} to read where fingers are: {e}"))
+			.ok()?;
+		let mut x = unsafe { std::mem::zeroed() };
+		let mut y = unsafe { std::mem::zeroed() };
+		unsafe {
+			abs_x_info(file.as_raw_fd(), &mut x).ok()?;
+			abs_y_info(file.as_raw_fd(), &mut y).ok()?;
+		}
+		Some(Touchpad {
+			sysname: sysname.to_string(),
+			file,
+			position: Position::new([(x.minimum, x.maximum), (y.minimum, y.maximum)]),
+		})
+	}
+
+	/// Where the finger went since the last read, from 0 to 1 across and down the touchpad.
+	pub fn read(&mut self) -> Option<[f32; 2]> {
+		let mut buf = [0_u8; size_of::<libc::input_event>() * 64];
+		let mut moved = None;
+		// until there's nothing left to read, or the touchpad is gone and libinput will say so
+		while let Ok(read @ 1..) = self.file.read(&mut buf) {
+			for chunk in buf[..read].chunks_exact(size_of::<libc::input_event>()) {
+				let event: libc::input_event =
+					unsafe { std::ptr::read_unaligned(chunk.as_ptr().cast()) };
+				if let Some(position) = self.position.update(event.type_, event.code, event.value) {
+					moved = Some(position);
+				}
+			}
+		}
+		moved
+	}
+}
+impl AsRawFd for Touchpad {
+	fn as_raw_fd(&self) -> std::os::fd::RawFd {
+		self.file.as_raw_fd()
+	}
+}
+
+/// The finger's position as evdev reports it, a frame at a time.
+struct Position {
+	range: [(i32, i32); 2],
+	current: [Option<i32>; 2],
+	touching: bool,
+	changed: bool,
+}
+impl Position {
+	fn new(range: [(i32, i32); 2]) -> Self {
+		Position {
+			range,
+			current: [None; 2],
+			touching: false,
+			changed: false,
+		}
+	}
+
+	/// Take in an event, returning the normalized position at the end of a frame it moved in.
+	fn update(&mut self, kind: u16, code: u16, value: i32) -> Option<[f32; 2]> {
+		match (kind, code) {
+			(EV_ABS!(), ABS_X!()) => self.set(0, value),
+			(EV_ABS!(), ABS_Y!()) => self.set(1, value),
+			(EV_KEY!(), BTN_TOUCH!()) => {
+				self.touching = value != 0;
+				self.changed |= self.touching;
+			}
+			(EV_SYN!(), SYN_REPORT!()) if self.touching && self.changed => {
+				self.changed = false;
+				let [Some(x), Some(y)] = self.current else {
+					return None;
+				};
+				return Some([self.normalize(0, x), self.normalize(1, y)]);
+			}
+			_ => (),
+		}
+		None
+	}
+
+	fn set(&mut self, axis: usize, value: i32) {
+		self.changed |= self.current[axis] != Some(value);
+		self.current[axis] = Some(value);
+	}
+
+	fn normalize(&self, axis: usize, value: i32) -> f32 {
+		let (min, max) = self.range[axis];
+		((value - min) as f32 / (max - min).max(1) as f32).clamp(0.0, 1.0)
+	}
+}
+
+#[test]
+fn test_position() {
+	let mut position = Position::new([(0, 1000), (100, 600)]);
+	// moving without a finger down, e.g. a hovering pen, points nowhere
+	assert_eq!(position.update(EV_ABS!(), ABS_X!(), 500), None);
+	assert_eq!(position.update(EV_SYN!(), SYN_REPORT!(), 0), None);
+
+	position.update(EV_KEY!(), BTN_TOUCH!(), 1);
+	position.update(EV_ABS!(), ABS_Y!(), 350);
+	assert_eq!(
+		position.update(EV_SYN!(), SYN_REPORT!(), 0),
+		Some([0.5, 0.5])
+	);
+	// a frame where nothing moved isn't sent again
+	assert_eq!(position.update(EV_SYN!(), SYN_REPORT!(), 0), None);
+	position.update(EV_ABS!(), ABS_X!(), 1200);
+	assert_eq!(
+		position.update(EV_SYN!(), SYN_REPORT!(), 0),
+		Some([1.0, 0.5])
+	);
+	position.update(EV_KEY!(), BTN_TOUCH!(), 0);
+	position.update(EV_ABS!(), ABS_X!(), 0);
+	assert_eq!(position.update(EV_SYN!(), SYN_REPORT!(), 0), None);
+}
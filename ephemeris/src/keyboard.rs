@@ -0,0 +1,143 @@
+use input_event_codes::{
+	KEY_ENTER, KEY_LEFTALT, KEY_LEFTCTRL, KEY_LEFTMETA, KEY_LEFTSHIFT, KEY_RIGHTALT,
+};
+use std::collections::HashMap;
+use xkbcommon::xkb::{
+	keysym_from_name, keysym_get_name, Context, KeyDirection, Keycode, Keymap, State,
+	KEYMAP_COMPILE_NO_FLAGS, KEYMAP_FORMAT_TEXT_V1, KEYSYM_CASE_INSENSITIVE, KEYSYM_NO_FLAGS,
+};
+
+// evdev keycodes are offset by 8 in xkb
+const EVDEV_OFFSET: u32 = 8;
+// the modifiers that reach each shift level in most layouts, tried in this order
+const LEVEL_MODIFIERS: [&[u32]; 4] = [
+	&[],
+	&[KEY_LEFTSHIFT!()],
+	&[KEY_RIGHTALT!()],
+	&[KEY_LEFTSHIFT!(), KEY_RIGHTALT!()],
+];
+
+/// Finds the keys to press for text and key names in a layout.
+pub struct Keyboard {
+	keymap: Keymap,
+	/// The key and modifiers that type each character.
+	chars: HashMap<char, (u32, &'static [u32])>,
+	/// The key for each keysym, preferring the lowest level it's on.
+	keysyms: HashMap<u32, u32>,
+}
+impl Keyboard {
+	pub fn new(layout: &str) -> Option<Self> {
+		let keymap = Keymap::new_from_names(&Context::new(0), "evdev", "", layout, "", None, 0)?;
+		Some(Self::from_keymap(keymap))
+	}
+
+	/// The keys in a keymap a source sent, so names match what the sink will see.
+	pub fn from_keymap_string(keymap: &str) -> Option<Self> {
+		let keymap = Keymap::new_from_string(
+			&Context::new(0),
+			keymap.to_string(),
+			KEYMAP_FORMAT_TEXT_V1,
+			KEYMAP_COMPILE_NO_FLAGS,
+		)?;
+		Some(Self::from_keymap(keymap))
+	}
+
+	fn from_keymap(keymap: Keymap) -> Self {
+		let mut chars = HashMap::new();
+		for modifiers in LEVEL_MODIFIERS {
+			let mut state = State::new(&keymap);
+			for modifier in modifiers {
+				state.update_key(Keycode::new(modifier + EVDEV_OFFSET), KeyDirection::Down);
+			}
+			keymap.key_for_each(|_, keycode| {
+				let text = state.key_get_utf8(keycode);
+				let mut text = text.chars();
+				if let (Some(c), None) = (text.next(), text.next()) {
+					chars
+						.entry(c)
+						.or_insert((keycode.raw() - EVDEV_OFFSET, modifiers));
+				}
+			});
+		}
+		let mut keysyms = HashMap::new();
+		keymap.key_for_each(|keymap, keycode| {
+			for level in 0..keymap.num_levels_for_key(keycode, 0) {
+				for keysym in keymap.key_get_syms_by_level(keycode, 0, level) {
+					keysyms
+						.entry(keysym.raw())
+						.or_insert(keycode.raw() - EVDEV_OFFSET);
+				}
+			}
+		});
+		Keyboard {
+			keymap,
+			chars,
+			keysyms,
+		}
+	}
+
+	pub fn keymap_string(&self) -> String {
+		self.keymap.get_as_string(KEYMAP_FORMAT_TEXT_V1)
+	}
+
+	/// The keys to hold down in order to type `c`, modifiers first.
+	pub fn char_keys(&self, c: char) -> Option<Vec<u32>> {
+		// Return types a carriage return
+		if c == '\n' {
+			return Some(vec![KEY_ENTER!()]);
+		}
+		let (keycode, modifiers) = self.chars.get(&c)?;
+		Some(modifiers.iter().copied().chain([*keycode]).collect())
+	}
+
+	/// The key for a name like `ctrl`, `Return`, `t` or a raw evdev keycode.
+	pub fn named_key(&self, name: &str) -> Option<u32> {
+		let modifier = match name.to_lowercase().as_str() {
+			"ctrl" | "control" => Some(KEY_LEFTCTRL!()),
+			"shift" => Some(KEY_LEFTSHIFT!()),
+			"alt" => Some(KEY_LEFTALT!()),
+			"altgr" => Some(KEY_RIGHTALT!()),
+			"super" | "meta" | "logo" => Some(KEY_LEFTMETA!()),
+			_ => None,
+		};
+		if modifier.is_some() {
+			return modifier;
+		}
+		if let Ok(keycode) = name.parse() {
+			return Some(keycode);
+		}
+		[KEYSYM_NO_FLAGS, KEYSYM_CASE_INSENSITIVE]
+			.into_iter()
+			.find_map(|flags| self.keysyms.get(&keysym_from_name(name, flags).raw()))
+			.copied()
+	}
+
+	/// A name for the key that [`named_key`](Self::named_key) turns back into it, the keysym it
+	/// types on its own or the keycode if it has none.
+	pub fn key_name(&self, keycode: u32) -> String {
+		self.keymap
+			.key_get_syms_by_level(Keycode::new(keycode + EVDEV_OFFSET), 0, 0)
+			.first()
+			.map(|keysym| keysym_get_name(*keysym))
+			.filter(|name| self.named_key(name) == Some(keycode))
+			.unwrap_or_else(|| keycode.to_string())
+	}
+}
+
+#[test]
+fn test_keys() {
+	let keyboard = Keyboard::new("us").unwrap();
+	assert_eq!(keyboard.char_keys('a'), Some(vec![30]));
+	assert_eq!(keyboard.char_keys('A'), Some(vec![42, 30]));
+	assert_eq!(keyboard.char_keys('!'), Some(vec![42, 2]));
+	assert_eq!(keyboard.named_key("ctrl"), Some(29));
+	assert_eq!(keyboard.named_key("return"), Some(28));
+	assert_eq!(keyboard.named_key("T"), Some(20));
+	assert_eq!(keyboard.named_key("F5"), Some(63));
+	assert_eq!(keyboard.key_name(30), "a");
+	assert_eq!(keyboard.key_name(29), "Control_L");
+	assert_eq!(keyboard.key_name(28), "Return");
+
+	let sent = Keyboard::from_keymap_string(&Keyboard::new("de").unwrap().keymap_string()).unwrap();
+	assert_eq!(sent.key_name(21), "z");
+}
@@ -0,0 +1,8 @@
+//! Finding the keys that type text or match key names, and the script format, shared with other
+//! sources that take text or scripts. With the `wayland` feature, also the keymap the compositor
+//! gives its seat, for sources with a window.
+
+pub mod keyboard;
+pub mod script;
+#[cfg(feature = "wayland")]
+pub mod wayland;
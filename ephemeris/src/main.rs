@@ -0,0 +1,76 @@
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use ephemeris::{
+	keyboard::Keyboard,
+	script::{self, Step},
+};
+use ipc::{send_input_ipc, Capabilities, Message};
+use logging::{info, LogArgs};
+use std::{io::IsTerminal, path::PathBuf, process::exit, thread::sleep, time::Duration};
+
+#[derive(Debug, Parser)]
+#[command(about = "Send scripted keyboard/mouse input to stdout")]
+struct Args {
+	/// Run the commands in a file, one per line, instead of the arguments
+	#[arg(long, value_name = "FILE", conflicts_with = "commands")]
+	script: Option<PathBuf>,
+	/// Keyboard layout to type in, from XKB_DEFAULT_LAYOUT if not given
+	#[arg(long, default_value = "")]
+	layout: String,
+	/// Milliseconds between keys while typing
+	#[arg(long, default_value_t = 12)]
+	key_delay: u64,
+	/// Commands to run, e.g. `type hello key ctrl+a`
+	#[arg(
+		trailing_var_arg = true,
+		allow_hyphen_values = true,
+		required_unless_present = "script"
+	)]
+	commands: Vec<String>,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `ephemeris type hello | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	let commands = match &args.script {
+		Some(path) => script::parse_script(&std::fs::read_to_string(path)?),
+		None => script::parse_args(&args.commands),
+	}
+	.map_err(|e| eyre!("{e}"))?;
+	let keyboard = Keyboard::new(&args.layout)
+		.ok_or_else(|| eyre!("Couldn't make a keymap for layout {:?}", args.layout))?;
+	let key_delay = Duration::from_millis(args.key_delay);
+
+	// don't leave anything held down if the script is cut short
+	ipc::shutdown::on_signal(|| {
+		ipc::shutdown::end_stream();
+		exit(0)
+	});
+	let steps = script::steps(&commands, &keyboard, key_delay).map_err(|e| eyre!("{e}"))?;
+	match play(&keyboard, steps) {
+		// nothing left to type into
+		Err(ipc::Error::Disconnected) => info!("The sink went away before the script finished"),
+		result => result?,
+	}
+	Ok(())
+}
+
+fn play(keyboard: &Keyboard, steps: Vec<Step>) -> Result<(), ipc::Error> {
+	ipc::handshake::hello(Capabilities::KEYBOARD | Capabilities::POINTER)?;
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keyboard.keymap_string()))?;
+	for step in steps {
+		match step {
+			Step::Send(message) => send_input_ipc(message)?,
+			Step::Sleep(duration) => sleep(duration),
+		}
+	}
+	// a script ending in motion would otherwise exit before it's sent
+	ipc::flush_input_ipc()
+}
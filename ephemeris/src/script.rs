@@ -0,0 +1,288 @@
+//! Turning arguments or script files into commands, and commands into input.
+
+use crate::keyboard::Keyboard;
+use input_event_codes::{BTN_BACK, BTN_FORWARD, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT};
+use ipc::Message;
+use logging::warn;
+use std::{fmt, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	/// Press then release.
+	Tap,
+	Press,
+	Release,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+	Type(String),
+	Key { combo: Vec<String>, action: Action },
+	Move(f32, f32),
+	Click { button: u32, action: Action },
+	Scroll(f32, f32),
+	Sleep(Duration),
+}
+
+/// The command as a script line, which [`parse_script`] reads back.
+impl fmt::Display for Command {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = |action: &Action, tap, press, release| match action {
+			Action::Tap => tap,
+			Action::Press => press,
+			Action::Release => release,
+		};
+		match self {
+			Command::Type(text) => write!(f, "type {text}"),
+			Command::Key { combo, action } => write!(
+				f,
+				"{} {}",
+				name(action, "key", "keydown", "keyup"),
+				combo.join("+")
+			),
+			Command::Move(dx, dy) => write!(f, "move {dx} {dy}"),
+			Command::Click { button, action } => write!(
+				f,
+				"{} {}",
+				name(action, "click", "mousedown", "mouseup"),
+				button_name(*button)
+			),
+			Command::Scroll(dx, dy) => write!(f, "scroll {dx} {dy}"),
+			Command::Sleep(duration) => write!(f, "sleep {}", duration.as_millis()),
+		}
+	}
+}
+
+/// Parse commands given as separate arguments, e.g. `["type", "hello world", "key", "Return"]`.
+pub fn parse_args(args: &[String]) -> Result<Vec<Command>, String> {
+	let mut args = args.iter().map(String::as_str);
+	let mut commands = Vec::new();
+	while let Some(name) = args.next() {
+		commands.push(parse_command(name, &mut args)?);
+	}
+	Ok(commands)
+}
+
+/// Parse a script with one command per line, where `type` takes the rest of the line and `#` starts a comment line.
+pub fn parse_script(script: &str) -> Result<Vec<Command>, String> {
+	let mut commands = Vec::new();
+	for (i, line) in script.lines().enumerate() {
+		let line = line.trim_start();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+		let mut args: Box<dyn Iterator<Item = &str>> = if name == "type" {
+			Box::new(std::iter::once(rest))
+		} else {
+			Box::new(rest.split_whitespace())
+		};
+		let command = parse_command(name, &mut args).map_err(|e| format!("line {}: {e}", i + 1))?;
+		if args.next().is_some() {
+			return Err(format!("line {}: too many arguments for {name}", i + 1));
+		}
+		commands.push(command);
+	}
+	Ok(commands)
+}
+
+fn parse_command(name: &str, args: &mut dyn Iterator<Item = &str>) -> Result<Command, String> {
+	let mut arg = |what: &str| args.next().ok_or(format!("{name} needs {what}"));
+	Ok(match name {
+		"type" => Command::Type(arg("the text to type")?.to_string()),
+		"key" | "keydown" | "keyup" => Command::Key {
+			combo: arg("a key combo")?.split('+').map(str::to_string).collect(),
+			action: action(name),
+		},
+		"move" => Command::Move(number(arg("DX")?)?, number(arg("DY")?)?),
+		"click" | "mousedown" | "mouseup" => Command::Click {
+			button: mouse_button(arg("a button")?)?,
+			action: action(name),
+		},
+		"scroll" => Command::Scroll(number(arg("DX")?)?, number(arg("DY")?)?),
+		"sleep" => {
+			let ms = arg("a time in milliseconds")?;
+			Command::Sleep(Duration::from_millis(
+				ms.parse()
+					.map_err(|_| format!("{ms:?} isn't a time in milliseconds"))?,
+			))
+		}
+		_ => return Err(format!("unknown command {name:?}")),
+	})
+}
+
+/// What running a command comes down to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+	Send(Message),
+	Sleep(Duration),
+}
+
+/// The input the commands send, with keys looked up in `keyboard` and `key_delay` after each key
+/// that's typed or tapped.
+pub fn steps(
+	commands: &[Command],
+	keyboard: &Keyboard,
+	key_delay: Duration,
+) -> Result<Vec<Step>, String> {
+	let mut steps = Vec::new();
+	let key = |keycode: u32, pressed| Step::Send(Message::Key { keycode, pressed });
+	// press the keys in order then release them in reverse, like typing a shortcut
+	let tap = |steps: &mut Vec<Step>, keys: &[u32]| {
+		steps.extend(keys.iter().map(|keycode| key(*keycode, true)));
+		steps.extend(keys.iter().rev().map(|keycode| key(*keycode, false)));
+		steps.push(Step::Sleep(key_delay));
+	};
+	for command in commands {
+		match command {
+			Command::Type(text) => {
+				for c in text.chars() {
+					let Some(keys) = keyboard.char_keys(c) else {
+						warn!("Nothing types {c:?} in this layout, skipping it");
+						continue;
+					};
+					tap(&mut steps, &keys);
+				}
+			}
+			Command::Key { combo, action } => {
+				let keys = combo
+					.iter()
+					.map(|name| {
+						keyboard
+							.named_key(name)
+							.ok_or(format!("No key called {name:?}"))
+					})
+					.collect::<Result<Vec<_>, _>>()?;
+				match action {
+					Action::Tap => tap(&mut steps, &keys),
+					Action::Press => steps.extend(keys.iter().map(|keycode| key(*keycode, true))),
+					Action::Release => {
+						steps.extend(keys.iter().rev().map(|keycode| key(*keycode, false)))
+					}
+				}
+			}
+			Command::Move(dx, dy) => steps.push(Step::Send(Message::MouseMove([*dx, *dy].into()))),
+			Command::Click { button, action } => {
+				if *action != Action::Release {
+					steps.push(Step::Send(Message::MouseButton {
+						button: *button,
+						pressed: true,
+					}));
+				}
+				if *action != Action::Press {
+					steps.push(Step::Send(Message::MouseButton {
+						button: *button,
+						pressed: false,
+					}));
+				}
+			}
+			Command::Scroll(dx, dy) => {
+				steps.push(Step::Send(Message::MouseAxisDiscrete([*dx, *dy].into())))
+			}
+			Command::Sleep(duration) => steps.push(Step::Sleep(*duration)),
+		}
+	}
+	Ok(steps)
+}
+
+fn number(value: &str) -> Result<f32, String> {
+	value
+		.parse()
+		.map_err(|_| format!("{value:?} isn't a number"))
+}
+
+fn action(name: &str) -> Action {
+	if name.ends_with("down") {
+		Action::Press
+	} else if name.ends_with("up") {
+		Action::Release
+	} else {
+		Action::Tap
+	}
+}
+
+fn mouse_button(name: &str) -> Result<u32, String> {
+	Ok(match name {
+		"left" => BTN_LEFT!(),
+		"right" => BTN_RIGHT!(),
+		"middle" => BTN_MIDDLE!(),
+		"back" => BTN_BACK!(),
+		"forward" => BTN_FORWARD!(),
+		_ => name
+			.parse()
+			.map_err(|_| format!("unknown mouse button {name:?}"))?,
+	})
+}
+
+/// The name `click` takes for a button.
+pub fn button_name(button: u32) -> String {
+	match button {
+		BTN_LEFT!() => "left".to_string(),
+		BTN_RIGHT!() => "right".to_string(),
+		BTN_MIDDLE!() => "middle".to_string(),
+		BTN_BACK!() => "back".to_string(),
+		BTN_FORWARD!() => "forward".to_string(),
+		_ => button.to_string(),
+	}
+}
+
+#[test]
+fn test_parse() {
+	let args = [
+		"type", "hi there", "key", "ctrl+t", "move", "-5", "2", "mouseup", "left",
+	]
+	.map(String::from);
+	assert_eq!(
+		parse_args(&args).unwrap(),
+		[
+			Command::Type("hi there".to_string()),
+			Command::Key {
+				combo: vec!["ctrl".to_string(), "t".to_string()],
+				action: Action::Tap
+			},
+			Command::Move(-5.0, 2.0),
+			Command::Click {
+				button: BTN_LEFT!(),
+				action: Action::Release
+			},
+		]
+	);
+	assert_eq!(
+		parse_script("# greet\ntype  hi there\n\nsleep 50\n").unwrap(),
+		[
+			Command::Type(" hi there".to_string()),
+			Command::Sleep(Duration::from_millis(50)),
+		]
+	);
+	assert!(parse_script("sleep 50 60").is_err());
+	let script = "type  hi\nkeyup ctrl+t\nmove -5 2.5\nmousedown back\nscroll 0 -1\nsleep 20";
+	let lines: Vec<String> = parse_script(script)
+		.unwrap()
+		.iter()
+		.map(Command::to_string)
+		.collect();
+	assert_eq!(lines.join("\n"), script);
+	assert!(parse_args(&["move".to_string(), "1".to_string()]).is_err());
+	assert_eq!(mouse_button(&button_name(BTN_MIDDLE!())), Ok(BTN_MIDDLE!()));
+	assert_eq!(button_name(300), "300");
+
+	let keyboard = Keyboard::new("us").unwrap();
+	let key = |keycode, pressed| Step::Send(Message::Key { keycode, pressed });
+	assert_eq!(
+		steps(
+			&parse_args(&["type", "A", "keyup", "ctrl+t"].map(String::from)).unwrap(),
+			&keyboard,
+			Duration::from_millis(5)
+		)
+		.unwrap(),
+		[
+			key(42, true),
+			key(30, true),
+			key(30, false),
+			key(42, false),
+			Step::Sleep(Duration::from_millis(5)),
+			key(20, false),
+			key(29, false),
+		]
+	);
+}
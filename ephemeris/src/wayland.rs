@@ -1,3 +1,7 @@
+//! The keymap the compositor gives its seat, for sources with a window whose keys are typed
+//! with it.
+
+use logging::info;
 use std::{
 	io::Read,
 	os::{
@@ -8,14 +12,39 @@ use std::{
 use wayland_client::protocol::wl_keyboard::{Event as WlKeyboardEvent, KeymapFormat, WlKeyboard};
 use wayland_client::Dispatch;
 use wayland_client::{
-	globals::GlobalListContents,
+	globals::{registry_queue_init, GlobalListContents},
 	protocol::wl_seat::{self, WlSeat},
 	WEnum,
 };
 use wayland_client::{protocol::wl_registry, Connection, QueueHandle};
 
-pub struct WlHandler {
-	pub keymap: Option<Vec<u8>>,
+pub struct CompositorKeymap {
+	pub keymap: String,
+	/// Repeat rate and delay, which compositors send right after the keymap.
+	pub repeat: Option<(i32, i32)>,
+}
+
+/// Wait for the compositor `conn` is connected to to send its seat's keymap, `None` if it
+/// couldn't be asked or what it sent isn't text.
+pub fn compositor_keymap(conn: &Connection) -> Option<CompositorKeymap> {
+	let (globals, mut queue) = registry_queue_init::<WlHandler>(conn).ok()?;
+	let qh = queue.handle();
+	let _seat: WlSeat = globals.bind(&qh, 1..=8, ()).ok()?;
+	let mut wl_handler = WlHandler::default();
+	info!("Waiting for keymap from compositor");
+	while wl_handler.keymap.is_none() {
+		queue.roundtrip(&mut wl_handler).ok()?;
+	}
+	Some(CompositorKeymap {
+		keymap: String::from_utf8(wl_handler.keymap?).ok()?,
+		repeat: wl_handler.repeat,
+	})
+}
+
+#[derive(Default)]
+struct WlHandler {
+	keymap: Option<Vec<u8>>,
+	repeat: Option<(i32, i32)>,
 }
 
 // Implementation from https://github.com/wez/wezterm
@@ -28,6 +57,9 @@ impl Dispatch<WlKeyboard, ()> for WlHandler {
 		_conn: &wayland_client::Connection,
 		_qhandle: &wayland_client::QueueHandle<WlHandler>,
 	) {
+		if let WlKeyboardEvent::RepeatInfo { rate, delay } = event {
+			state.repeat = Some((rate, delay));
+		}
 		if let WlKeyboardEvent::Keymap { format, fd, size } = &event {
 			let mut file = unsafe { std::fs::File::from_raw_fd(fd.as_raw_fd()) };
 			if let KeymapFormat::XkbV1 = format.into_result().unwrap() {
@@ -12,50 +12,58 @@
   let supportedSystems = [ "aarch64-linux" "x86_64-linux" ];
       forAllSystems = nixpkgs.lib.genAttrs supportedSystems;
       nixpkgsFor = forAllSystems (system: import nixpkgs { inherit system; });
-  in {
-    packages = forAllSystems (system: let pkgs = nixpkgsFor.${system}; in {
-      default = crane.lib.${system}.buildPackage {
+      buildInputsFor = pkgs: with pkgs; [
+        # inputs
+        pkg-config
+        libxkbcommon
+
+        # eclipse
+        libinput
+
+        # manifold
+        libGL
+        xorg.libX11
+        xorg.libXcursor
+        xorg.libXrandr
+        xorg.libXi
+        wayland
+      ];
+      crateFor = system: {
         pname = "non-spatial-input";
         version = "0.1.0";
         src = ./.;
-        buildInputs = with pkgs; [
-          # inputs
-          pkg-config
-          libxkbcommon
-          
-          # eclipse
-          libinput
-          
-          # manifold
-          libGL
-          xorg.libX11
-          xorg.libXcursor
-          xorg.libXrandr
-          xorg.libXi
-          wayland
-        ];
+        buildInputs = buildInputsFor nixpkgsFor.${system};
       };
+  in {
+    packages = forAllSystems (system: {
+      default = crane.lib.${system}.buildPackage (crateFor system);
+    });
+
+    # `nix flake check`, with and without CBOR frames since nothing turns them on by default
+    checks = forAllSystems (system: let
+      craneLib = crane.lib.${system};
+      crate = crateFor system;
+      cargoArtifacts = craneLib.buildDepsOnly crate;
+    in {
+      clippy = craneLib.cargoClippy (crate // {
+        inherit cargoArtifacts;
+        cargoClippyExtraArgs = "--all-targets -- -D warnings";
+      });
+      clippy-cbor = craneLib.cargoClippy (crate // {
+        inherit cargoArtifacts;
+        cargoClippyExtraArgs = "--all-targets --features ipc/cbor -- -D warnings";
+      });
+      test = craneLib.cargoTest (crate // { inherit cargoArtifacts; });
+      test-cbor = craneLib.cargoTest (crate // {
+        inherit cargoArtifacts;
+        cargoTestExtraArgs = "--features ipc/cbor";
+      });
     });
 
     devShells = forAllSystems (system: let pkgs = nixpkgsFor.${system}; in {
       default = pkgs.mkShell rec {
-        buildInputs = (with pkgs; [
-          # inputs
-          pkg-config
-          libxkbcommon
-          
-          # eclipse
-          libinput
-          
-          # manifold
-          libGL
-          xorg.libX11
-          xorg.libXcursor
-          xorg.libXrandr
-          xorg.libXi
-          wayland
-        ]);
-        LD_LIBRARY_PATH = "${nixpkgs.lib.makeLibraryPath buildInputs}";        
+        buildInputs = buildInputsFor pkgs;
+        LD_LIBRARY_PATH = "${nixpkgs.lib.makeLibraryPath buildInputs}";
       };
     });
   };
@@ -0,0 +1,125 @@
+//! How messages are written in frames.
+//!
+//! Frames are flexbuffers, which every version reads. With the `cbor` feature, a source whose sink
+//! says in its [`hello`](crate::handshake) that it reads CBOR writes that instead, which takes
+//! about half the bytes. A CBOR frame starts with the CBOR self-describe tag, which no flexbuffer
+//! message starts with, so every frame says how it's written. Tools in the middle of a pipe read
+//! either as long as they're built with the feature, and [`reverse::relay`](crate::reverse::relay)
+//! leaves CBOR out of a sink's hello when they aren't.
+
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt::Display,
+	io::{self, ErrorKind},
+};
+
+// CBOR tag 55799, "self-described CBOR"
+const CBOR_MAGIC: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+/// Ways of writing frames, as a set, e.g. what a sink reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Codecs(u32);
+impl Codecs {
+	pub const FLEXBUFFERS: Self = Codecs(1);
+	pub const CBOR: Self = Codecs(1 << 1);
+
+	/// What this build reads and writes.
+	pub fn supported() -> Self {
+		if cfg!(feature = "cbor") {
+			Self::FLEXBUFFERS | Self::CBOR
+		} else {
+			Self::FLEXBUFFERS
+		}
+	}
+
+	/// The most compact of these this build writes, flexbuffers if there are none.
+	pub fn best(self) -> Self {
+		if (self & Self::supported()).contains(Self::CBOR) {
+			Self::CBOR
+		} else {
+			Self::FLEXBUFFERS
+		}
+	}
+
+	/// Whether all of `other` is in here.
+	pub fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+/// Only flexbuffers, for peers from before codecs that never say.
+impl Default for Codecs {
+	fn default() -> Self {
+		Self::FLEXBUFFERS
+	}
+}
+impl std::ops::BitOr for Codecs {
+	type Output = Self;
+	fn bitor(self, other: Self) -> Self {
+		Codecs(self.0 | other.0)
+	}
+}
+impl std::ops::BitAnd for Codecs {
+	type Output = Self;
+	fn bitand(self, other: Self) -> Self {
+		Codecs(self.0 & other.0)
+	}
+}
+impl Display for Codecs {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let names: Vec<&str> = [(Self::FLEXBUFFERS, "flexbuffers"), (Self::CBOR, "CBOR")]
+			.into_iter()
+			.filter(|(codec, _)| self.contains(*codec))
+			.map(|(_, name)| name)
+			.collect();
+		f.write_str(&names.join(", "))
+	}
+}
+
+/// Write `value` as a frame's payload with `codec`, one of [`Codecs::best`].
+pub(crate) fn encode(codec: Codecs, value: &impl Serialize) -> Vec<u8> {
+	#[cfg(feature = "cbor")]
+	if codec == Codecs::CBOR {
+		let mut frame = CBOR_MAGIC.to_vec();
+		frame.extend(minicbor_serde::to_vec(value).unwrap());
+		return frame;
+	}
+	flexbuffers::to_vec(value).unwrap()
+}
+
+/// Read a frame's payload however it was written.
+pub(crate) fn decode<'a, T: Deserialize<'a>>(frame: &'a [u8]) -> io::Result<T> {
+	match frame.strip_prefix(&CBOR_MAGIC) {
+		#[cfg(feature = "cbor")]
+		Some(cbor) => minicbor_serde::from_slice(cbor).map_err(|_| ErrorKind::InvalidData.into()),
+		#[cfg(not(feature = "cbor"))]
+		Some(_) => Err(io::Error::new(
+			ErrorKind::InvalidData,
+			"a CBOR frame, which this isn't built to read",
+		)),
+		None => flexbuffers::from_slice(frame).map_err(|_| ErrorKind::InvalidData.into()),
+	}
+}
+
+#[test]
+fn test_best() {
+	assert_eq!(Codecs::FLEXBUFFERS.best(), Codecs::FLEXBUFFERS);
+	assert_eq!(Codecs::default().best(), Codecs::FLEXBUFFERS);
+	let both = Codecs::FLEXBUFFERS | Codecs::CBOR;
+	assert_eq!(both.best() == Codecs::CBOR, cfg!(feature = "cbor"));
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn test_cbor() {
+	use crate::{Message, MessageRef};
+	let message = Message::Keymap("xkb_keymap {}".to_string());
+	let frame = encode(Codecs::CBOR, &message);
+	assert!(frame.len() < encode(Codecs::FLEXBUFFERS, &message).len());
+	assert_eq!(decode::<Message>(&frame).unwrap(), message);
+	// borrowed like a flexbuffer's would be
+	let MessageRef::Keymap(keymap) = decode(&frame).unwrap() else {
+		panic!("not a keymap");
+	};
+	assert!(frame.as_ptr_range().contains(&keymap.as_ptr()));
+}
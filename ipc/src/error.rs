@@ -0,0 +1,57 @@
+//! Why sending failed.
+
+use std::{
+	fmt::{self, Display},
+	io::{self, ErrorKind},
+};
+
+#[derive(Debug)]
+pub enum Error {
+	/// The sink went away, e.g. it exited and closed its end of the pipe. Nothing sent after this
+	/// gets anywhere.
+	Disconnected,
+	/// Writing failed some other way.
+	Io(io::Error),
+}
+impl Error {
+	pub fn is_disconnected(&self) -> bool {
+		matches!(self, Error::Disconnected)
+	}
+}
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		match e.kind() {
+			ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::NotConnected => {
+				Error::Disconnected
+			}
+			_ => Error::Io(e),
+		}
+	}
+}
+impl From<ErrorKind> for Error {
+	fn from(kind: ErrorKind) -> Self {
+		io::Error::from(kind).into()
+	}
+}
+impl Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Disconnected => f.write_str("the sink went away"),
+			Error::Io(e) => write!(f, "couldn't send to the sink: {e}"),
+		}
+	}
+}
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Disconnected => None,
+			Error::Io(e) => Some(e),
+		}
+	}
+}
+
+#[test]
+fn test_broken_pipe_is_disconnected() {
+	assert!(Error::from(ErrorKind::BrokenPipe).is_disconnected());
+	assert!(!Error::from(ErrorKind::InvalidInput).is_disconnected());
+}
@@ -0,0 +1,94 @@
+//! Letting a sink pause the source while it's busy with something slow, e.g. registering a keymap
+//! or reconnecting, instead of input piling up or getting lost in the meantime.
+//!
+//! Sources call [`accept_pauses`], after which a [`Message::Pause`] over the [`reverse`] channel
+//! holds off everything sent through [`send_input_ipc`](crate::send_input_ipc) until a
+//! [`Message::Resume`] or [`MAX_PAUSE`]. Sinks hold a [`pause_source`] guard around the slow part.
+
+use crate::{handshake, lanes, reverse, Message};
+use std::{str::FromStr, time::Duration};
+use tracing::{debug, info, warn};
+
+// well under the heartbeat timeout, so a paused source never looks wedged to the sink
+pub const MAX_PAUSE: Duration = Duration::from_secs(3);
+
+/// What a paused source does with the input it gets in the meantime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PausePolicy {
+	/// Keep all of it for when the sink resumes, with motion merged as usual.
+	#[default]
+	Buffer,
+	/// Drop motion and scrolling, and keep keys and buttons so nothing is left held.
+	Drop,
+}
+impl FromStr for PausePolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"buffer" => Ok(PausePolicy::Buffer),
+			"drop" => Ok(PausePolicy::Drop),
+			_ => Err(format!("{s:?} isn't a pause policy, use buffer or drop")),
+		}
+	}
+}
+
+/// Let the sink pause input from now on, handling it the way `policy` says in the meantime, and
+/// take its [`handshake`] hello back.
+pub fn accept_pauses(policy: PausePolicy) {
+	accept_pauses_with(policy, |message| {
+		warn!("Ignoring {} from the sink", message.name())
+	});
+}
+
+/// Like [`accept_pauses`], handing anything else the sink sends back to `handle`, since only one
+/// thing can listen on the [`reverse`] channel.
+pub fn accept_pauses_with(policy: PausePolicy, handle: impl Fn(Message) + Send + Sync + 'static) {
+	let listening = reverse::listen(move |message| match message {
+		Message::Pause => {
+			debug!("Sink paused input");
+			lanes::pause(policy, MAX_PAUSE);
+		}
+		Message::Resume => {
+			debug!("Sink resumed input");
+			lanes::resume();
+		}
+		Message::Hello {
+			version,
+			capabilities,
+			codecs,
+		} => handshake::sink_greeted(version, capabilities, codecs),
+		message => handle(message),
+	});
+	if let Err(e) = listening {
+		info!("The sink can't pause input: {e}");
+	}
+}
+
+/// Ask the source to pause until the guard is dropped, or for [`MAX_PAUSE`] at most.
+///
+/// Does nothing if the source doesn't accept pauses.
+pub fn pause_source() -> Paused {
+	let paused = reverse::send_upstream(&Message::Pause).is_ok();
+	Paused { paused }
+}
+
+/// Resumes the source when dropped, see [`pause_source`].
+#[must_use]
+pub struct Paused {
+	paused: bool,
+}
+impl Drop for Paused {
+	fn drop(&mut self) {
+		if self.paused {
+			let _ = reverse::send_upstream(&Message::Resume);
+		}
+	}
+}
+
+#[test]
+fn test_policy() {
+	assert_eq!("drop".parse(), Ok(PausePolicy::Drop));
+	assert_eq!("buffer".parse(), Ok(PausePolicy::Buffer));
+	assert!("hold".parse::<PausePolicy>().is_err());
+}
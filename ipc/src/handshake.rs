@@ -0,0 +1,178 @@
+//! Which version of the protocol each end of a pipe speaks and what kinds of input it deals in, so
+//! either can be updated without the other and sources can leave out what the sink would drop.
+//!
+//! Sources call [`hello`] before sending anything else, which sends a [`Message::Hello`] with
+//! [`PROTOCOL_VERSION`] and what kinds of input they send. Sinks reading with
+//! [`receive_input_async_ipc`](crate::receive_input_async_ipc) take it out of the stream and keep
+//! it for [`source`]. Every version only adds messages to the one before, so a source that's
+//! older than the sink is read as it is, and messages from a newer one that the sink doesn't know
+//! are skipped instead of ending the stream. Only a source older than [`MIN_PROTOCOL_VERSION`] is
+//! turned away. Sources from before handshakes don't say hello, and are read as they always were.
+//!
+//! Sinks that call [`consume`] say hello back over the [`reverse`] channel with what kinds of
+//! input they do anything with. Sources listening with [`accept_pauses`](crate::flow::accept_pauses)
+//! keep it for [`sink`], and from then on [`send_input_ipc`](crate::send_input_ipc) leaves out the
+//! rest. Until a sink says so, it's taken to want everything. Both ends say which
+//! [`codec`](crate::codec)s they know, and the source writes the most compact one the sink reads.
+
+use crate::{codec::Codecs, reverse, send_input_ipc, Capabilities, Error, Message};
+use std::{
+	io::{self, ErrorKind},
+	sync::Mutex,
+};
+use tracing::{debug, info, warn};
+
+/// The version of the protocol this speaks, bumped whenever a message is added.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// The oldest version a sink still reads.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+static SOURCE: Mutex<Option<Peer>> = Mutex::new(None);
+static SINK: Mutex<Option<Peer>> = Mutex::new(None);
+// what this sink says hello back with, once the source says hello
+static CONSUMES: Mutex<Option<Capabilities>> = Mutex::new(None);
+
+/// What the other end of a pipe said in its hello: what a source sends and writes it in, or what
+/// a sink does anything with and reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+	pub version: u32,
+	pub capabilities: Capabilities,
+	pub codecs: Codecs,
+}
+
+/// Tell the sink which version of the protocol this speaks and what kinds of input it sends.
+pub fn hello(capabilities: Capabilities) -> Result<(), Error> {
+	send_input_ipc(Message::Hello {
+		version: PROTOCOL_VERSION,
+		capabilities,
+		codecs: Codecs::supported(),
+	})
+}
+
+/// Tell the source which kinds of input this does anything with once it says hello, so it can
+/// leave out the rest.
+pub fn consume(capabilities: Capabilities) {
+	CONSUMES.lock().unwrap().replace(capabilities);
+}
+
+/// What the source on stdin said in its hello, `None` until it says one or if it never does.
+pub fn source() -> Option<Peer> {
+	*SOURCE.lock().unwrap()
+}
+
+/// What the sink on stdout said back, `None` until it says so or if it never does.
+pub fn sink() -> Option<Peer> {
+	*SINK.lock().unwrap()
+}
+
+/// Whether the sink does anything with all of these kinds of input, which it's taken to until it
+/// says otherwise.
+pub fn sink_consumes(capabilities: Capabilities) -> bool {
+	sink().is_none_or(|sink| sink.capabilities.contains(capabilities))
+}
+
+/// What frames to the sink are written in, flexbuffers until it says it reads anything else.
+pub(crate) fn codec() -> Codecs {
+	sink().map_or(Codecs::FLEXBUFFERS, |sink| sink.codecs.best())
+}
+
+/// Whether `message` is worth sending to the sink at all.
+pub(crate) fn wanted(message: &Message) -> bool {
+	message.capability().is_none_or(sink_consumes)
+}
+
+/// Whether the source on stdin speaks a version with messages this doesn't know.
+pub(crate) fn source_is_newer() -> bool {
+	source().is_some_and(|source| source.version > PROTOCOL_VERSION)
+}
+
+/// Take the source's hello and say hello back, or turn the source away if it's too old to read.
+pub(crate) fn greeted(version: u32, capabilities: Capabilities, codecs: Codecs) -> io::Result<()> {
+	if version < MIN_PROTOCOL_VERSION {
+		return Err(io::Error::new(
+			ErrorKind::Unsupported,
+			format!(
+				"the source speaks protocol version {version}, but this needs at least version {MIN_PROTOCOL_VERSION}"
+			),
+		));
+	}
+	if version > PROTOCOL_VERSION {
+		warn!(
+			"The source speaks protocol version {version}, newer than {PROTOCOL_VERSION}, so anything it sends that this doesn't know is skipped"
+		);
+	} else {
+		info!("The source speaks protocol version {version} and sends {capabilities}");
+	}
+	SOURCE.lock().unwrap().replace(Peer {
+		version,
+		capabilities,
+		codecs,
+	});
+	if let Some(consumes) = *CONSUMES.lock().unwrap() {
+		let hello = Message::Hello {
+			version: PROTOCOL_VERSION,
+			capabilities: consumes,
+			codecs: Codecs::supported(),
+		};
+		if let Err(e) = reverse::send_upstream(&hello) {
+			debug!("Couldn't tell the source what this consumes: {e}");
+		}
+	}
+	Ok(())
+}
+
+/// Take the sink's hello back.
+pub(crate) fn sink_greeted(version: u32, capabilities: Capabilities, codecs: Codecs) {
+	info!(
+		"The sink speaks protocol version {version}, only does anything with {capabilities} and reads {codecs}"
+	);
+	SINK.lock().unwrap().replace(Peer {
+		version,
+		capabilities,
+		codecs,
+	});
+}
+
+/// Forget what the sink said, when another one takes its place.
+pub(crate) fn sink_left() {
+	SINK.lock().unwrap().take();
+}
+
+#[test]
+fn test_greeted() {
+	let codecs = Codecs::FLEXBUFFERS;
+	assert!(greeted(0, Capabilities::KEYBOARD, codecs).is_err());
+	assert_eq!(source(), None);
+	greeted(PROTOCOL_VERSION, Capabilities::KEYBOARD, codecs).unwrap();
+	assert!(!source_is_newer());
+	greeted(PROTOCOL_VERSION + 1, Capabilities::POINTER, codecs).unwrap();
+	assert!(source_is_newer());
+	assert_eq!(
+		source(),
+		Some(Peer {
+			version: PROTOCOL_VERSION + 1,
+			capabilities: Capabilities::POINTER,
+			codecs,
+		})
+	);
+}
+
+#[test]
+fn test_sink_consumes() {
+	let touch = Message::TouchUp { slot: 0 };
+	assert!(wanted(&touch));
+	sink_greeted(
+		PROTOCOL_VERSION,
+		Capabilities::KEYBOARD | Capabilities::POINTER,
+		Codecs::FLEXBUFFERS,
+	);
+	assert!(!wanted(&touch));
+	assert!(wanted(&Message::MouseMove([1.0, 0.0].into())));
+	assert!(wanted(&Message::ResetInput));
+	assert!(!sink_consumes(
+		Capabilities::KEYBOARD | Capabilities::TABLET
+	));
+	// so the rest of the tests send everything
+	sink_left();
+}
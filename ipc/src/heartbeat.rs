@@ -0,0 +1,108 @@
+//! Heartbeats let each end of a pipe notice the other has wedged, since a stuck process keeps its
+//! end of the pipe open and nothing ever errors.
+//!
+//! Sources call [`start_heartbeat`], which sends [`Message::Heartbeat`] whenever nothing else was
+//! sent for [`HEARTBEAT_INTERVAL`], stops the source through [`shutdown`] once the sink is gone, and
+//! exits if a write has been stuck on the sink for [`timeout`] so any grabbed devices go back to
+//! the desktop. Sinks reading with
+//! [`receive_input_async_ipc`](crate::receive_input_async_ipc) get a [`Message::ResetInput`]
+//! once a source that was sending heartbeats goes quiet for as long. The timeout is
+//! [`HEARTBEAT_TIMEOUT`] unless [`set_timeout`] says otherwise.
+
+use crate::{flow::MAX_PAUSE, send_frame_ipc, shutdown, Error, Message};
+use std::{
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		OnceLock,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+// a few missed heartbeats, so a briefly busy peer isn't taken for a dead one
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// in milliseconds, 0 for HEARTBEAT_TIMEOUT
+static TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+// when the write in progress started, 0 when nothing is being written
+static WRITE_STARTED: AtomicU64 = AtomicU64::new(0);
+// when the last write finished, 0 before the first one
+static LAST_WRITE: AtomicU64 = AtomicU64::new(0);
+
+/// Microseconds since the first call, starting at 1 so 0 can mean never.
+fn now() -> u64 {
+	EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64 + 1
+}
+fn since(time: u64) -> Duration {
+	Duration::from_micros(now().saturating_sub(time))
+}
+
+pub(crate) fn write_started() {
+	WRITE_STARTED.store(now(), Ordering::Relaxed);
+}
+pub(crate) fn write_finished() {
+	WRITE_STARTED.store(0, Ordering::Relaxed);
+	LAST_WRITE.store(now(), Ordering::Relaxed);
+}
+
+/// Take the other end for gone after `timeout` instead of [`HEARTBEAT_TIMEOUT`], e.g. longer for a
+/// peer on a slow network. At least a [`HEARTBEAT_INTERVAL`] past [`MAX_PAUSE`], since a paused
+/// source sends no heartbeats until it resumes.
+pub fn set_timeout(timeout: Duration) {
+	let timeout = timeout.max(MAX_PAUSE + HEARTBEAT_INTERVAL);
+	TIMEOUT.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// How long the other end can go quiet, or a write to it can be stuck, before it's taken for gone.
+pub fn timeout() -> Duration {
+	match TIMEOUT.load(Ordering::Relaxed) {
+		0 => HEARTBEAT_TIMEOUT,
+		millis => Duration::from_millis(millis),
+	}
+}
+
+/// Send heartbeats on stdout from now on, stop once the sink is gone and exit if it stops taking
+/// input.
+pub fn start_heartbeat() {
+	let heartbeat = flexbuffers::to_vec(Message::Heartbeat).unwrap();
+	thread::spawn(move || loop {
+		thread::sleep(HEARTBEAT_INTERVAL);
+		// any other message shows the source is alive just as well
+		if since(LAST_WRITE.load(Ordering::Relaxed)) < HEARTBEAT_INTERVAL {
+			continue;
+		}
+		match send_frame_ipc(&heartbeat) {
+			Ok(()) => (),
+			Err(Error::Disconnected) => {
+				shutdown::sink_gone();
+				return;
+			}
+			Err(e) => tracing::warn!("Couldn't send a heartbeat: {e}"),
+		}
+	});
+	// separate from the sender, which is just as stuck as everything else once the pipe is full
+	thread::spawn(|| loop {
+		thread::sleep(HEARTBEAT_INTERVAL);
+		let started = WRITE_STARTED.load(Ordering::Relaxed);
+		if started != 0 && since(started) >= timeout() {
+			tracing::error!(
+				"The sink hasn't taken any input for {:?}, letting go of the devices",
+				timeout()
+			);
+			std::process::exit(1);
+		}
+	});
+}
+
+#[test]
+fn test_timeout() {
+	assert_eq!(timeout(), HEARTBEAT_TIMEOUT);
+	set_timeout(Duration::from_secs(30));
+	assert_eq!(timeout(), Duration::from_secs(30));
+	set_timeout(Duration::ZERO);
+	assert_eq!(timeout(), MAX_PAUSE + HEARTBEAT_INTERVAL);
+	set_timeout(HEARTBEAT_TIMEOUT);
+}
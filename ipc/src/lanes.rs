@@ -0,0 +1,357 @@
+//! Keeping key and button events from getting stuck behind motion when the sink is slow.
+//!
+//! [`send_input_ipc`](crate::send_input_ipc) hands motion and scrolling to a writer thread and
+//! returns straight away. While the writer is blocked on a full pipe, motion queued behind it is
+//! merged into one frame, so a burst of deltas collapses instead of piling up. Everything else
+//! waits until it's written after whatever was queued before it, so order is kept and a source
+//! can exit right after sending it, and the async send waits the same way without blocking the
+//! thread. The pipe to the sink is also shrunk so there's little motion sitting in it that a key
+//! release would have to wait behind.
+//!
+//! While the sink has the source paused (see [`flow`](crate::flow)) the writer holds off, and
+//! nothing waits to be written since that could be a while.
+
+use crate::{flow::PausePolicy, Error, Message, Stamp};
+use std::{
+	collections::VecDeque,
+	io::ErrorKind,
+	pin::pin,
+	sync::{Condvar, Mutex, Once},
+	thread,
+	time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tracing::warn;
+
+// small enough that a key release is only behind a few dozen frames in the pipe
+const PIPE_SIZE: i32 = 4096;
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue::new());
+static QUEUED: Condvar = Condvar::new();
+static WRITTEN: Condvar = Condvar::new();
+static WRITTEN_ASYNC: Notify = Notify::const_new();
+static WRITER: Once = Once::new();
+
+struct Queue {
+	messages: VecDeque<(u64, Message, Stamp)>,
+	/// Numbered in the order they were queued, merged motion keeps the number it had.
+	queued: u64,
+	written: u64,
+	/// When a pause from the sink runs out if it isn't resumed before then.
+	paused_until: Option<Instant>,
+	policy: PausePolicy,
+	/// Why writing failed, after which nothing more is written.
+	failed: Option<ErrorKind>,
+}
+impl Queue {
+	const fn new() -> Self {
+		Queue {
+			messages: VecDeque::new(),
+			queued: 0,
+			written: 0,
+			paused_until: None,
+			policy: PausePolicy::Buffer,
+			failed: None,
+		}
+	}
+
+	fn failed(&self) -> Result<(), Error> {
+		self.failed.map_or(Ok(()), |kind| Err(kind.into()))
+	}
+
+	/// How much longer the pause lasts, if there is one.
+	fn paused(&mut self) -> Option<Duration> {
+		let left = self.paused_until?.checked_duration_since(Instant::now());
+		if left.is_none() {
+			warn!("The sink didn't resume input in time, carrying on");
+			self.paused_until = None;
+		}
+		left
+	}
+
+	fn push(&mut self, message: Message, stamp: Stamp) -> u64 {
+		if let Some((number, last, last_stamp)) = self.messages.back_mut() {
+			if let Some(merged) = merge_motion((last, *last_stamp), (&message, stamp)) {
+				(*last, *last_stamp) = merged;
+				return *number;
+			}
+		}
+		self.queued += 1;
+		self.messages.push_back((self.queued, message, stamp));
+		self.queued
+	}
+}
+
+/// Fold `next` into `last` if they're motion that has the same effect sent as one message, for
+/// filters that hold frames back for a slow sink the way the writer does.
+pub fn merge_motion(
+	(last, last_stamp): (&Message, Stamp),
+	(next, stamp): (&Message, Stamp),
+) -> Option<(Message, Stamp)> {
+	// motion from two mice stays apart for sinks that keep devices apart
+	if last_stamp.device != stamp.device {
+		return None;
+	}
+	let merged = merge(last, next)?;
+	// merged motion is as recent as the latest of it
+	let ts = stamp.ts.or(last_stamp.ts);
+	Some((merged, Stamp { ts, ..stamp }))
+}
+
+/// Motion is summed into the motion before it, if nothing else came between them, and a position
+/// replaces the one before it, like a finger's position for the same finger, a tablet tool's or a
+/// gamepad stick's.
+fn merge(last: &Message, next: &Message) -> Option<Message> {
+	let sum = |a: &mint::Vector2<f32>, b: &mint::Vector2<f32>| [a.x + b.x, a.y + b.y].into();
+	Some(match (last, next) {
+		(Message::MouseMove(a), Message::MouseMove(b)) => Message::MouseMove(sum(a, b)),
+		(Message::MouseAxisContinuous(a), Message::MouseAxisContinuous(b)) => {
+			Message::MouseAxisContinuous(sum(a, b))
+		}
+		(Message::MouseAxisDiscrete(a), Message::MouseAxisDiscrete(b)) => {
+			Message::MouseAxisDiscrete(sum(a, b))
+		}
+		(Message::MousePosition(_), Message::MousePosition(b)) => Message::MousePosition(*b),
+		(Message::Magnify(a), Message::Magnify(b)) => Message::Magnify(a + b),
+		(Message::Rotate(a), Message::Rotate(b)) => Message::Rotate(a + b),
+		(Message::TouchMotion { slot: a, .. }, Message::TouchMotion { slot: b, .. }) if a == b => {
+			next.clone()
+		}
+		(Message::TabletAxis { .. }, Message::TabletAxis { .. }) => next.clone(),
+		(
+			Message::GamepadAxis {
+				id: a,
+				axis: a_axis,
+				..
+			},
+			Message::GamepadAxis {
+				id: b,
+				axis: b_axis,
+				..
+			},
+		) if (a, a_axis) == (b, b_axis) => next.clone(),
+		_ => return None,
+	})
+}
+
+fn is_motion(message: &Message) -> bool {
+	matches!(
+		message,
+		Message::MouseMove(_)
+			| Message::MousePosition(_)
+			| Message::MouseAxisContinuous(_)
+			| Message::MouseAxisDiscrete(_)
+			| Message::Magnify(_)
+			| Message::Rotate(_)
+			| Message::TouchMotion { .. }
+			| Message::TabletAxis { .. }
+			| Message::GamepadAxis { .. }
+	)
+}
+
+/// Queue a message for the writer, waiting until it's written unless it's motion or the sink has
+/// input paused.
+pub(crate) fn send(message: Message, stamp: Stamp) -> Result<(), Error> {
+	match queue(message, stamp)? {
+		Some(number) => wait_written(number),
+		None => Ok(()),
+	}
+}
+
+/// Like [`send`], waiting without blocking the thread.
+pub(crate) async fn send_async(message: Message, stamp: Stamp) -> Result<(), Error> {
+	match queue(message, stamp)? {
+		Some(number) => written(number).await,
+		None => Ok(()),
+	}
+}
+
+/// Queue a message for the writer, with the number to wait for if it should be waited for.
+fn queue(message: Message, stamp: Stamp) -> Result<Option<u64>, Error> {
+	WRITER.call_once(start_writer);
+	let mut queue = QUEUE.lock().unwrap();
+	queue.failed()?;
+	let paused = queue.paused().is_some();
+	if paused && queue.policy == PausePolicy::Drop && is_motion(&message) {
+		return Ok(None);
+	}
+	let wait = !paused && !is_motion(&message);
+	let number = queue.push(message, stamp);
+	drop(queue);
+	QUEUED.notify_one();
+	Ok(wait.then_some(number))
+}
+
+/// Hold off writing until [`resume`] or until `max` is up.
+pub(crate) fn pause(policy: PausePolicy, max: Duration) {
+	let mut queue = QUEUE.lock().unwrap();
+	queue.paused_until = Some(Instant::now() + max);
+	queue.policy = policy;
+}
+
+pub(crate) fn resume() {
+	QUEUE.lock().unwrap().paused_until = None;
+	QUEUED.notify_one();
+}
+
+/// Wait until everything queued so far is written.
+pub(crate) fn flush() -> Result<(), Error> {
+	let queued = QUEUE.lock().unwrap().queued;
+	wait_written(queued)
+}
+
+/// Like [`flush`], waiting without blocking the thread.
+pub(crate) async fn flush_async() -> Result<(), Error> {
+	let queued = QUEUE.lock().unwrap().queued;
+	written(queued).await
+}
+
+fn wait_written(number: u64) -> Result<(), Error> {
+	let queue = QUEUE.lock().unwrap();
+	WRITTEN
+		.wait_while(queue, |queue| queue.written < number)
+		.unwrap()
+		.failed()
+}
+
+async fn written(number: u64) -> Result<(), Error> {
+	loop {
+		let mut notified = pin!(WRITTEN_ASYNC.notified());
+		// registered before checking, so a write in between isn't missed
+		notified.as_mut().enable();
+		{
+			let queue = QUEUE.lock().unwrap();
+			if queue.written >= number {
+				return queue.failed();
+			}
+		}
+		notified.await;
+	}
+}
+
+fn start_writer() {
+	#[cfg(target_os = "linux")]
+	unsafe {
+		// fails on anything that isn't a pipe, which is fine since then there's no pipe to fill
+		libc::fcntl(libc::STDOUT_FILENO, libc::F_SETPIPE_SZ, PIPE_SIZE);
+	}
+	thread::spawn(|| loop {
+		let (number, message, stamp) = {
+			let mut queue = QUEUE.lock().unwrap();
+			loop {
+				if let Some(left) = queue.paused() {
+					queue = QUEUED.wait_timeout(queue, left).unwrap().0;
+				} else if queue.messages.is_empty() {
+					queue = QUEUED.wait(queue).unwrap();
+				} else {
+					break;
+				}
+			}
+			queue.messages.pop_front().unwrap()
+		};
+		let written = crate::sender::write_stdout(&crate::encode_stamped(
+			crate::handshake::codec(),
+			&message,
+			stamp,
+		));
+		let mut queue = QUEUE.lock().unwrap();
+		queue.written = number;
+		if let Err(e) = written {
+			// everything still queued is as good as written, since it can't be anymore
+			queue.failed = Some(e.kind());
+			queue.messages.clear();
+			queue.written = queue.queued;
+		}
+		drop(queue);
+		WRITTEN.notify_all();
+		WRITTEN_ASYNC.notify_waiters();
+	});
+}
+
+#[test]
+fn test_merge() {
+	let mut queue = Queue::new();
+	let key = Message::Key {
+		keycode: 30,
+		pressed: false,
+	};
+	let mut push = |message| queue.push(message, Stamp::default());
+	assert_eq!(push(Message::MouseMove([1.0, 2.0].into())), 1);
+	assert_eq!(push(Message::MouseMove([3.0, -1.0].into())), 1);
+	assert_eq!(push(Message::MouseAxisDiscrete([0.0, 1.0].into())), 2);
+	assert_eq!(push(key.clone()), 3);
+	assert_eq!(push(Message::MouseMove([1.0, 1.0].into())), 4);
+	assert_eq!(push(Message::MousePosition([0.5, 0.5].into())), 5);
+	assert_eq!(push(Message::MousePosition([0.25, 0.0].into())), 5);
+	assert_eq!(push(Message::Magnify(0.25)), 6);
+	assert_eq!(push(Message::Magnify(-0.5)), 6);
+	let touch = |slot: u32, x: f32| Message::TouchMotion {
+		slot,
+		position: [x, 0.5].into(),
+	};
+	assert_eq!(push(touch(0, 0.1)), 7);
+	assert_eq!(push(touch(0, 0.2)), 7);
+	// another finger's motion doesn't replace the first's
+	assert_eq!(push(touch(1, 0.9)), 8);
+	let pen = |pressure: f32| Message::TabletAxis {
+		position: [0.5, 0.5].into(),
+		pressure,
+		tilt: [0.0, 0.0].into(),
+	};
+	assert_eq!(push(pen(0.25)), 9);
+	assert_eq!(push(pen(0.5)), 9);
+	let stick = |axis: u32, value: f32| Message::GamepadAxis { id: 0, axis, value };
+	assert_eq!(push(stick(0, 0.5)), 10);
+	assert_eq!(push(stick(0, 1.0)), 10);
+	assert_eq!(push(stick(1, -1.0)), 11);
+	let messages: Vec<Message> = queue.messages.into_iter().map(|(_, m, _)| m).collect();
+	assert_eq!(
+		messages,
+		[
+			Message::MouseMove([4.0, 1.0].into()),
+			Message::MouseAxisDiscrete([0.0, 1.0].into()),
+			key,
+			Message::MouseMove([1.0, 1.0].into()),
+			Message::MousePosition([0.25, 0.0].into()),
+			Message::Magnify(-0.25),
+			touch(0, 0.2),
+			touch(1, 0.9),
+			pen(0.5),
+			stick(0, 1.0),
+			stick(1, -1.0),
+		]
+	);
+
+	// motion from two devices doesn't merge, even back to back
+	let mut queue = Queue::new();
+	let from = |device: u32, ts: u64| Stamp {
+		ts: Some(ts),
+		device: Some(device),
+	};
+	assert_eq!(
+		queue.push(Message::MouseMove([1.0, 0.0].into()), from(1, 10)),
+		1
+	);
+	assert_eq!(
+		queue.push(Message::MouseMove([1.0, 0.0].into()), from(1, 20)),
+		1
+	);
+	assert_eq!(
+		queue.push(Message::MouseMove([0.0, 1.0].into()), from(2, 30)),
+		2
+	);
+	let (_, message, stamp) = &queue.messages[0];
+	assert_eq!(*message, Message::MouseMove([2.0, 0.0].into()));
+	assert_eq!(*stamp, from(1, 20));
+}
+
+#[test]
+fn test_pause_runs_out() {
+	let mut queue = Queue::new();
+	assert_eq!(queue.paused(), None);
+	queue.paused_until = Some(Instant::now() + Duration::from_secs(60));
+	assert!(queue.paused().is_some());
+	queue.paused_until = Some(Instant::now());
+	assert_eq!(queue.paused(), None);
+	assert_eq!(queue.paused_until, None);
+}
@@ -1,5 +1,26 @@
 #![allow(unused)]
 
+pub mod codec;
+mod error;
+pub mod flow;
+pub mod handshake;
+pub mod heartbeat;
+mod lanes;
+mod receiver;
+pub mod record;
+pub mod reverse;
+mod sender;
+pub mod shutdown;
+pub mod socket;
+pub mod stuck;
+pub mod timestamp;
+
+pub use error::Error;
+pub use lanes::merge_motion;
+pub use receiver::IpcReceiver;
+pub use sender::IpcSender;
+
+use codec::Codecs;
 use flexbuffers::FlexbufferSerializer;
 use mint::Vector2;
 use rustc_hash::FxHashMap;
@@ -8,13 +29,23 @@ use std::{
 	collections::HashMap,
 	fmt::Display,
 	io::{ErrorKind, Read, Write},
-	sync::Mutex,
-	vec,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Mutex,
+	},
+	time::{Duration, Instant},
+};
+use tokio::{
+	io::AsyncReadExt,
+	sync::mpsc::{self, Receiver},
 };
-use tokio::io::AsyncReadExt;
 
-static MOUSE_BLOT: Mutex<Option<ButtonBlot>> = Mutex::new(None);
-static KEY_BLOT: Mutex<Option<ButtonBlot>> = Mutex::new(None);
+// how many frames a receiver's thread reads ahead
+const FRAME_BUFFER: usize = 64;
+// buffers of frames that have been dropped, for receivers' threads to read into again
+static SPARE_BUFFERS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+// enough for every frame read ahead plus the ones being handled
+const SPARE_BUFFER_LIMIT: usize = FRAME_BUFFER + 8;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "t", content = "c")]
@@ -22,12 +53,417 @@ pub enum Message {
 	Keymap(String),
 	Key { keycode: u32, pressed: bool },
 	MouseMove(Vector2<f32>),
+	/// Where an absolute device like a touchpad in absolute mode is pointing, from 0 to 1 across
+	/// and down its area.
+	MousePosition(Vector2<f32>),
+	MouseButton { button: u32, pressed: bool },
+	/// The press of `button` just sent was click number `count` in a row, for sinks that want double
+	/// and triple clicks without timing them. Only sent from the second click on.
+	MultiClick { button: u32, count: u32 },
+	MouseAxisContinuous(Vector2<f32>),
+	MouseAxisDiscrete(Vector2<f32>),
+	/// A touchpad was pinched to zoom by this much since the last one, with 0.1 for 10% bigger and
+	/// negative values zooming out.
+	Magnify(f32),
+	/// Two fingers on a touchpad turned by this many degrees since the last one, counterclockwise.
+	Rotate(f32),
+	/// A touchpad was double tapped with two fingers, to zoom to what's under the pointer or back.
+	SmartMagnify,
+	ResetInput,
+	Disconnect,
+	/// Keys held for `delay` milliseconds repeat `rate` times a second, as the user has it set up
+	/// for the source, with a `rate` of 0 turning repeating off.
+	KeyRepeat { rate: u32, delay: u32 },
+	/// Sent by sources when they have nothing else to send, see [`heartbeat`].
+	Heartbeat,
+	/// Sent by sinks over the [`reverse`] channel to hold off input for a moment, see [`flow`].
+	Pause,
+	/// Lets a paused source carry on, see [`flow`].
+	Resume,
+	/// Sent by sinks over the [`reverse`] channel when the pointer moves onto another input handler,
+	/// or off of all of them, and whether that handler has captured the pointer.
+	Hover { handler: Option<u64>, captured: bool },
+	/// Text copied on the source's desktop, for sinks to offer to what's in XR to paste.
+	ClipboardSet(String),
+	/// A finger touched a touchscreen at `position`, from 0 to 1 across and down the screen.
+	/// `slot` tells it apart from the other fingers down at the same time until it's lifted.
+	TouchDown { slot: u32, position: Vector2<f32> },
+	/// The finger in `slot` moved to `position`.
+	TouchMotion { slot: u32, position: Vector2<f32> },
+	/// The finger in `slot` was lifted, after which the slot can be used for another one. A
+	/// [`Message::ResetInput`] lifts every finger.
+	TouchUp { slot: u32 },
+	/// A tablet's tool came close enough to the tablet to be tracked, or went out of its reach.
+	TabletProximity { tool: TabletTool, in_proximity: bool },
+	/// Where the tablet tool in reach is, from 0 to 1 across and down the tablet, how hard it's
+	/// pressed from 0 to 1 and how far it's tilted in degrees, to the right and towards the user.
+	/// Tools without pressure or tilt always send 0.
+	TabletAxis {
+		position: Vector2<f32>,
+		pressure: f32,
+		tilt: Vector2<f32>,
+	},
+	/// The tablet tool's tip touched the tablet or was lifted off of it.
+	TabletTip { down: bool },
+	/// A button on the tablet tool, like `BTN_STYLUS`, was pressed or released.
+	TabletButton { button: u32, pressed: bool },
+	/// A gamepad was plugged in, or was already when the source started. `id` tells it apart from
+	/// other gamepads in the rest of the gamepad messages until it's unplugged.
+	GamepadConnected { id: u32, name: String },
+	/// The gamepad was unplugged, letting go of all of its buttons.
+	GamepadDisconnected { id: u32 },
+	/// A gamepad button, like `BTN_SOUTH`, was pressed or released. A [`Message::ResetInput`]
+	/// releases them all.
+	GamepadButton { id: u32, button: u32, pressed: bool },
+	/// A gamepad stick or trigger moved, like `ABS_X`, from -1 to 1 for sticks with 0 in the middle
+	/// and from 0 to 1 for triggers.
+	GamepadAxis { id: u32, axis: u32, value: f32 },
+	/// A device was plugged in, or was already when the source started. Input from it is stamped
+	/// with `id` (see [`Stamp`]) until it's removed.
+	DeviceAdded {
+		id: u32,
+		name: String,
+		capabilities: Capabilities,
+	},
+	/// The device was unplugged, and `id` may be given to another one. Ids that were never added
+	/// can come up here too, for devices the source left alone.
+	DeviceRemoved { id: u32 },
+	/// The first thing a source sends, with the [`handshake::PROTOCOL_VERSION`] it speaks, what
+	/// kinds of input it sends and which [`codec`]s it writes. Sources from before handshakes don't
+	/// send one. Sinks say it back over the [`reverse`] channel with what kinds of input they do
+	/// anything with and which codecs they read.
+	Hello {
+		version: u32,
+		capabilities: Capabilities,
+		#[serde(default)]
+		codecs: Codecs,
+	},
+	/// Everything from here to the next [`Message::BatchEnd`] happened at once, e.g. in one poll of
+	/// the source's devices, for sinks that would rather apply it all together. Batches don't nest.
+	BatchBegin,
+	/// The end of the batch started by the last [`Message::BatchBegin`].
+	BatchEnd,
+}
+impl Message {
+	/// Name of the variant, for grouping messages by type.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Message::Keymap(_) => "Keymap",
+			Message::Key { .. } => "Key",
+			Message::MouseMove(_) => "MouseMove",
+			Message::MousePosition(_) => "MousePosition",
+			Message::MouseButton { .. } => "MouseButton",
+			Message::MultiClick { .. } => "MultiClick",
+			Message::MouseAxisContinuous(_) => "MouseAxisContinuous",
+			Message::MouseAxisDiscrete(_) => "MouseAxisDiscrete",
+			Message::Magnify(_) => "Magnify",
+			Message::Rotate(_) => "Rotate",
+			Message::SmartMagnify => "SmartMagnify",
+			Message::ResetInput => "ResetInput",
+			Message::Disconnect => "Disconnect",
+			Message::KeyRepeat { .. } => "KeyRepeat",
+			Message::Heartbeat => "Heartbeat",
+			Message::Pause => "Pause",
+			Message::Resume => "Resume",
+			Message::Hover { .. } => "Hover",
+			Message::ClipboardSet(_) => "ClipboardSet",
+			Message::TouchDown { .. } => "TouchDown",
+			Message::TouchMotion { .. } => "TouchMotion",
+			Message::TouchUp { .. } => "TouchUp",
+			Message::TabletProximity { .. } => "TabletProximity",
+			Message::TabletAxis { .. } => "TabletAxis",
+			Message::TabletTip { .. } => "TabletTip",
+			Message::TabletButton { .. } => "TabletButton",
+			Message::GamepadConnected { .. } => "GamepadConnected",
+			Message::GamepadDisconnected { .. } => "GamepadDisconnected",
+			Message::GamepadButton { .. } => "GamepadButton",
+			Message::GamepadAxis { .. } => "GamepadAxis",
+			Message::DeviceAdded { .. } => "DeviceAdded",
+			Message::DeviceRemoved { .. } => "DeviceRemoved",
+			Message::Hello { .. } => "Hello",
+			Message::BatchBegin => "BatchBegin",
+			Message::BatchEnd => "BatchEnd",
+		}
+	}
+
+	/// The kind of input this is, `None` for messages about the stream itself that every sink
+	/// needs, like [`Message::ResetInput`].
+	pub fn capability(&self) -> Option<Capabilities> {
+		Some(match self {
+			Message::Keymap(_) | Message::Key { .. } | Message::KeyRepeat { .. } => {
+				Capabilities::KEYBOARD
+			}
+			Message::MouseMove(_)
+			| Message::MousePosition(_)
+			| Message::MouseButton { .. }
+			| Message::MultiClick { .. }
+			| Message::MouseAxisContinuous(_)
+			| Message::MouseAxisDiscrete(_) => Capabilities::POINTER,
+			Message::Magnify(_) | Message::Rotate(_) | Message::SmartMagnify => {
+				Capabilities::GESTURES
+			}
+			Message::TouchDown { .. } | Message::TouchMotion { .. } | Message::TouchUp { .. } => {
+				Capabilities::TOUCH
+			}
+			Message::TabletProximity { .. }
+			| Message::TabletAxis { .. }
+			| Message::TabletTip { .. }
+			| Message::TabletButton { .. } => Capabilities::TABLET,
+			Message::GamepadConnected { .. }
+			| Message::GamepadDisconnected { .. }
+			| Message::GamepadButton { .. }
+			| Message::GamepadAxis { .. } => Capabilities::GAMEPAD,
+			Message::ResetInput
+			| Message::Disconnect
+			| Message::Heartbeat
+			| Message::Pause
+			| Message::Resume
+			| Message::Hover { .. }
+			| Message::ClipboardSet(_)
+			| Message::DeviceAdded { .. }
+			| Message::DeviceRemoved { .. }
+			| Message::Hello { .. }
+			| Message::BatchBegin
+			| Message::BatchEnd => return None,
+		})
+	}
+}
+
+/// The kind of tool a tablet is used with, see [`Message::TabletProximity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TabletTool {
+	Pen,
+	/// The other end of a pen, or a tool that's only an eraser.
+	Eraser,
+	Brush,
+	Pencil,
+	Airbrush,
+	/// A mouse that only works on the tablet.
+	Mouse,
+	/// A mouse with a magnifying lens to line it up with.
+	Lens,
+	/// A knob put down on the tablet.
+	Totem,
+}
+
+/// Kinds of input, as a set, e.g. what a device can send.
+///
+/// Kinds this doesn't know of yet, from a newer source, are kept as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Capabilities(u32);
+impl Capabilities {
+	pub const KEYBOARD: Self = Capabilities(1);
+	pub const POINTER: Self = Capabilities(1 << 1);
+	pub const TOUCH: Self = Capabilities(1 << 2);
+	pub const TABLET: Self = Capabilities(1 << 3);
+	pub const GAMEPAD: Self = Capabilities(1 << 4);
+	/// Pinching, turning and the like on a touchpad.
+	pub const GESTURES: Self = Capabilities(1 << 5);
+	const NAMES: [(Self, &'static str); 6] = [
+		(Self::KEYBOARD, "keyboard"),
+		(Self::POINTER, "pointer"),
+		(Self::TOUCH, "touch"),
+		(Self::TABLET, "tablet"),
+		(Self::GAMEPAD, "gamepad"),
+		(Self::GESTURES, "gestures"),
+	];
+
+	/// Whether all of `other` is in here.
+	pub fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+
+	pub fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+}
+impl std::ops::BitOr for Capabilities {
+	type Output = Self;
+	fn bitor(self, other: Self) -> Self {
+		Capabilities(self.0 | other.0)
+	}
+}
+impl std::ops::BitOrAssign for Capabilities {
+	fn bitor_assign(&mut self, other: Self) {
+		self.0 |= other.0;
+	}
+}
+impl Display for Capabilities {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let names: Vec<&str> = Self::NAMES
+			.iter()
+			.filter(|(capability, _)| self.contains(*capability))
+			.map(|(_, name)| *name)
+			.collect();
+		if names.is_empty() {
+			f.write_str("nothing")
+		} else {
+			f.write_str(&names.join(", "))
+		}
+	}
+}
+
+/// What a source knows about a message besides the message itself, written next to it in the
+/// frame.
+///
+/// Sinks that don't look for it skip over it, and a message without any is written exactly as it
+/// was before stamps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stamp {
+	/// When the input happened, see [`timestamp`].
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ts: Option<u64>,
+	/// The device it came from, as announced with [`Message::DeviceAdded`], for sinks that keep
+	/// devices apart.
+	#[serde(rename = "dev", skip_serializing_if = "Option::is_none")]
+	pub device: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct Stamped<'a> {
+	#[serde(flatten)]
+	message: &'a Message,
+	#[serde(flatten)]
+	stamp: Stamp,
+}
+
+/// A [`Message`] borrowing from the frame it was read from, so reading one doesn't allocate.
+///
+/// Serialized the same way as [`Message`], see [`Frame::message`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c")]
+pub enum MessageRef<'a> {
+	Keymap(&'a str),
+	Key { keycode: u32, pressed: bool },
+	MouseMove(Vector2<f32>),
+	MousePosition(Vector2<f32>),
 	MouseButton { button: u32, pressed: bool },
+	MultiClick { button: u32, count: u32 },
 	MouseAxisContinuous(Vector2<f32>),
 	MouseAxisDiscrete(Vector2<f32>),
+	Magnify(f32),
+	Rotate(f32),
+	SmartMagnify,
 	ResetInput,
 	Disconnect,
+	KeyRepeat { rate: u32, delay: u32 },
+	Heartbeat,
+	Pause,
+	Resume,
+	Hover { handler: Option<u64>, captured: bool },
+	ClipboardSet(&'a str),
+	TouchDown { slot: u32, position: Vector2<f32> },
+	TouchMotion { slot: u32, position: Vector2<f32> },
+	TouchUp { slot: u32 },
+	TabletProximity { tool: TabletTool, in_proximity: bool },
+	TabletAxis {
+		position: Vector2<f32>,
+		pressure: f32,
+		tilt: Vector2<f32>,
+	},
+	TabletTip { down: bool },
+	TabletButton { button: u32, pressed: bool },
+	GamepadConnected { id: u32, name: &'a str },
+	GamepadDisconnected { id: u32 },
+	GamepadButton { id: u32, button: u32, pressed: bool },
+	GamepadAxis { id: u32, axis: u32, value: f32 },
+	DeviceAdded {
+		id: u32,
+		name: &'a str,
+		capabilities: Capabilities,
+	},
+	DeviceRemoved { id: u32 },
+	Hello {
+		version: u32,
+		capabilities: Capabilities,
+		#[serde(default)]
+		codecs: Codecs,
+	},
+	BatchBegin,
+	BatchEnd,
+}
+impl MessageRef<'_> {
+	/// The owned message, which only allocates for a keymap, clipboard text or a device's name.
+	pub fn to_message(self) -> Message {
+		match self {
+			MessageRef::Keymap(keymap) => Message::Keymap(keymap.to_string()),
+			MessageRef::Key { keycode, pressed } => Message::Key { keycode, pressed },
+			MessageRef::MouseMove(delta) => Message::MouseMove(delta),
+			MessageRef::MousePosition(position) => Message::MousePosition(position),
+			MessageRef::MouseButton { button, pressed } => Message::MouseButton { button, pressed },
+			MessageRef::MultiClick { button, count } => Message::MultiClick { button, count },
+			MessageRef::MouseAxisContinuous(a) => Message::MouseAxisContinuous(a),
+			MessageRef::MouseAxisDiscrete(a) => Message::MouseAxisDiscrete(a),
+			MessageRef::Magnify(delta) => Message::Magnify(delta),
+			MessageRef::Rotate(delta) => Message::Rotate(delta),
+			MessageRef::SmartMagnify => Message::SmartMagnify,
+			MessageRef::ResetInput => Message::ResetInput,
+			MessageRef::Disconnect => Message::Disconnect,
+			MessageRef::KeyRepeat { rate, delay } => Message::KeyRepeat { rate, delay },
+			MessageRef::Heartbeat => Message::Heartbeat,
+			MessageRef::Pause => Message::Pause,
+			MessageRef::Resume => Message::Resume,
+			MessageRef::Hover { handler, captured } => Message::Hover { handler, captured },
+			MessageRef::ClipboardSet(text) => Message::ClipboardSet(text.to_string()),
+			MessageRef::TouchDown { slot, position } => Message::TouchDown { slot, position },
+			MessageRef::TouchMotion { slot, position } => Message::TouchMotion { slot, position },
+			MessageRef::TouchUp { slot } => Message::TouchUp { slot },
+			MessageRef::TabletProximity { tool, in_proximity } => {
+				Message::TabletProximity { tool, in_proximity }
+			}
+			MessageRef::TabletAxis {
+				position,
+				pressure,
+				tilt,
+			} => Message::TabletAxis {
+				position,
+				pressure,
+				tilt,
+			},
+			MessageRef::TabletTip { down } => Message::TabletTip { down },
+			MessageRef::TabletButton { button, pressed } => Message::TabletButton { button, pressed },
+			MessageRef::GamepadConnected { id, name } => Message::GamepadConnected {
+				id,
+				name: name.to_string(),
+			},
+			MessageRef::GamepadDisconnected { id } => Message::GamepadDisconnected { id },
+			MessageRef::GamepadButton {
+				id,
+				button,
+				pressed,
+			} => Message::GamepadButton {
+				id,
+				button,
+				pressed,
+			},
+			MessageRef::GamepadAxis { id, axis, value } => Message::GamepadAxis { id, axis, value },
+			MessageRef::DeviceAdded {
+				id,
+				name,
+				capabilities,
+			} => Message::DeviceAdded {
+				id,
+				name: name.to_string(),
+				capabilities,
+			},
+			MessageRef::DeviceRemoved { id } => Message::DeviceRemoved { id },
+			MessageRef::Hello {
+				version,
+				capabilities,
+				codecs,
+			} => Message::Hello {
+				version,
+				capabilities,
+				codecs,
+			},
+			MessageRef::BatchBegin => Message::BatchBegin,
+			MessageRef::BatchEnd => Message::BatchEnd,
+		}
+	}
 }
+
 impl Display for Message {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.write_str(&match self {
@@ -40,6 +476,7 @@ impl Display for Message {
 				}
 			}
 			Message::MouseMove(delta) => format!("Mouse moved with delta {:?}", *delta),
+			Message::MousePosition(position) => format!("Mouse moved to {:?}", *position),
 			Message::MouseButton { button, pressed } => {
 				if *pressed {
 					format!("Pressed mouse {button}")
@@ -47,68 +484,260 @@ impl Display for Message {
 					format!("Released mouse {button}")
 				}
 			}
+			Message::MultiClick { button, count } => {
+				format!("Clicked mouse {button} {count} times")
+			}
 			Message::MouseAxisContinuous(a) => format!("Mouse axis continuous {a:?}"),
 			Message::MouseAxisDiscrete(a) => format!("Mouse axis discrete {a:?}"),
+			Message::Magnify(delta) => format!("Magnified by {delta}"),
+			Message::Rotate(delta) => format!("Rotated by {delta} degrees"),
+			Message::SmartMagnify => "Smart magnify".to_string(),
 			Message::ResetInput => "Reset input".to_string(),
 			Message::Disconnect => {
 				"Disconnect request".to_string()
 			}
-		})
-	}
-}
-
-pub fn send_input_ipc(message: Message) {
-	let mut messages = vec![message.clone()];
-	match &message {
-		Message::MouseButton { button, pressed } => MOUSE_BLOT
-			.lock()
-			.unwrap()
-			.get_or_insert(ButtonBlot::default())
-			.key_update(*button, *pressed),
-		Message::Key { keycode, pressed } => KEY_BLOT
-			.lock()
-			.unwrap()
-			.get_or_insert(ButtonBlot::default())
-			.key_update(*keycode, *pressed),
-		Message::ResetInput => {
-			// eprintln!("reset input");
-			messages.clear();
-			if let Some(blot) = MOUSE_BLOT.lock().unwrap().replace(ButtonBlot::default()) {
-				for (button, pressed) in blot.cleanup_presses_releases() {
-					messages.push(Message::MouseButton { button, pressed });
+			Message::KeyRepeat { rate: 0, .. } => "Key repeat off".to_string(),
+			Message::KeyRepeat { rate, delay } => {
+				format!("Key repeat {rate} times a second after {delay}ms")
+			}
+			Message::Heartbeat => "Heartbeat".to_string(),
+			Message::Pause => "Pause".to_string(),
+			Message::Resume => "Resume".to_string(),
+			Message::Hover { handler: None, .. } => "Hovering nothing".to_string(),
+			Message::Hover {
+				handler: Some(handler),
+				captured,
+			} => {
+				if *captured {
+					format!("Captured by handler {handler}")
+				} else {
+					format!("Hovering handler {handler}")
 				}
 			}
-			if let Some(blot) = KEY_BLOT.lock().unwrap().replace(ButtonBlot::default()) {
-				for (keycode, pressed) in blot.cleanup_presses_releases() {
-					messages.push(Message::Key { keycode, pressed });
+			// what's copied could be a password, so it stays out of logs
+			Message::ClipboardSet(text) => format!("Copied {} characters", text.chars().count()),
+			Message::TouchDown { slot, position } => format!("Touch {slot} down at {position:?}"),
+			Message::TouchMotion { slot, position } => format!("Touch {slot} moved to {position:?}"),
+			Message::TouchUp { slot } => format!("Touch {slot} up"),
+			Message::TabletProximity {
+				tool,
+				in_proximity: true,
+			} => format!("Tablet {tool:?} in reach"),
+			Message::TabletProximity { tool, .. } => format!("Tablet {tool:?} out of reach"),
+			Message::TabletAxis {
+				position,
+				pressure,
+				tilt,
+			} => format!("Tablet tool at {position:?} with pressure {pressure}, tilted {tilt:?}"),
+			Message::TabletTip { down: true } => "Tablet tip down".to_string(),
+			Message::TabletTip { down: false } => "Tablet tip up".to_string(),
+			Message::TabletButton { button, pressed } => {
+				if *pressed {
+					format!("Pressed tablet tool {button}")
+				} else {
+					format!("Released tablet tool {button}")
 				}
 			}
-		}
-		_ => (),
+			Message::GamepadConnected { id, name } => format!("Gamepad {id} connected: {name}"),
+			Message::GamepadDisconnected { id } => format!("Gamepad {id} disconnected"),
+			Message::GamepadButton {
+				id,
+				button,
+				pressed,
+			} => {
+				if *pressed {
+					format!("Pressed gamepad {id} button {button}")
+				} else {
+					format!("Released gamepad {id} button {button}")
+				}
+			}
+			Message::GamepadAxis { id, axis, value } => {
+				format!("Gamepad {id} axis {axis} at {value}")
+			}
+			Message::DeviceAdded {
+				id,
+				name,
+				capabilities,
+			} => format!("Device {id} added: {name} ({capabilities})"),
+			Message::DeviceRemoved { id } => format!("Device {id} removed"),
+			Message::Hello {
+				version,
+				capabilities,
+				codecs,
+			} => format!(
+				"Source speaking protocol version {version}, sending {capabilities} in {codecs}"
+			),
+			Message::BatchBegin => "Batch begins".to_string(),
+			Message::BatchEnd => "Batch ends".to_string(),
+		})
 	}
+}
+
+/// Send on stdout, see [`IpcSender::send`].
+///
+/// Fails with [`Error::Disconnected`] once the sink has gone away, which is usually the program's
+/// cue to clean up and exit.
+pub fn send_input_ipc(message: Message) -> Result<(), Error> {
+	send_stamped_input_ipc(message, Stamp::default())
+}
+
+/// Like [`send_input_ipc`], stamped with when the input happened, see [`timestamp`].
+pub fn send_timed_input_ipc(message: Message, ts: u64) -> Result<(), Error> {
+	send_stamped_input_ipc(
+		message,
+		Stamp {
+			ts: Some(ts),
+			..Default::default()
+		},
+	)
+}
 
-	let mut stdout = std::io::stdout().lock();
-	for message in messages {
-		let buf = flexbuffers::to_vec(message).unwrap();
-		stdout.write_all(&(buf.len() as u32).to_be_bytes()).unwrap();
-		stdout.write_all(&buf).unwrap();
-		stdout.flush();
+/// Like [`send_input_ipc`], with what else the source knows about the message.
+pub fn send_stamped_input_ipc(message: Message, stamp: Stamp) -> Result<(), Error> {
+	IpcSender::stdout().send_stamped(message, stamp)
+}
+
+/// Like [`send_input_ipc`], without blocking the thread while the pipe is full, see
+/// [`IpcSender::send_async`].
+pub async fn send_input_async_ipc(message: Message) -> Result<(), Error> {
+	send_stamped_input_async_ipc(message, Stamp::default()).await
+}
+
+/// Like [`send_input_async_ipc`], with what else the source knows about the message.
+pub async fn send_stamped_input_async_ipc(message: Message, stamp: Stamp) -> Result<(), Error> {
+	IpcSender::stdout().send_stamped_async(message, stamp).await
+}
+
+/// Wait until all input from [`send_input_ipc`] is written, e.g. before exiting after motion.
+///
+/// Motion and scrolling are queued and merged while the sink is slow, so a key or button sent
+/// after them isn't held up by a backlog, and only they return before they're written.
+pub fn flush_input_ipc() -> Result<(), Error> {
+	IpcSender::stdout().flush()
+}
+
+/// Write a raw frame payload to stdout with its length prefix, e.g. to pass on a frame from [`receive_frame_async_ipc`] untouched.
+///
+/// Goes after any input from [`send_input_ipc`] that's still queued.
+pub fn send_frame_ipc(frame: &[u8]) -> Result<(), Error> {
+	IpcSender::stdout().send_frame(frame)
+}
+
+/// Like [`send_frame_ipc`], without blocking the thread while the pipe is full.
+pub async fn send_frame_async_ipc(frame: &[u8]) -> Result<(), Error> {
+	IpcSender::stdout().send_frame_async(frame).await
+}
+
+/// A frame's payload as received, in a buffer that's read into again once it's dropped.
+#[derive(Debug)]
+pub struct Frame {
+	buf: Vec<u8>,
+}
+impl Frame {
+	/// The message in the frame, borrowed from it.
+	pub fn message(&self) -> std::io::Result<MessageRef<'_>> {
+		message_ref_from_frame(&self.buf)
+	}
+
+	/// What the source stamped the message with.
+	pub fn stamp(&self) -> Stamp {
+		stamp_from_frame(&self.buf)
+	}
+
+	/// The payload, keeping the buffer instead of giving it back to be read into again.
+	pub fn into_vec(mut self) -> Vec<u8> {
+		std::mem::take(&mut self.buf)
+	}
+}
+impl std::ops::Deref for Frame {
+	type Target = [u8];
+	fn deref(&self) -> &[u8] {
+		&self.buf
 	}
 }
+impl Drop for Frame {
+	fn drop(&mut self) {
+		let mut spare = SPARE_BUFFERS.lock().unwrap();
+		if spare.len() < SPARE_BUFFER_LIMIT && self.buf.capacity() > 0 {
+			spare.push(std::mem::take(&mut self.buf));
+		}
+	}
+}
+
+/// Read the next frame's raw flexbuffer payload (without the length prefix) from stdin.
+///
+/// Cancel safe, a frame is never lost if this is dropped before it finishes.
+pub async fn receive_frame_async_ipc() -> std::io::Result<Vec<u8>> {
+	receive_pooled_frame_async_ipc().await.map(Frame::into_vec)
+}
+
+/// Like [`receive_frame_async_ipc`], but the frame's buffer is reused for a later frame once
+/// it's dropped, so relays and sinks at high rates don't allocate for every frame.
+pub async fn receive_pooled_frame_async_ipc() -> std::io::Result<Frame> {
+	IpcReceiver::stdin().receive_frame().await
+}
+
+/// Read a frame into a buffer left from an earlier one, if there is one.
+fn read_pooled_frame(reader: &mut impl Read) -> std::io::Result<Frame> {
+	let mut length_buf = [0_u8; 4];
+	reader.read_exact(&mut length_buf)?;
+	let length = u32::from_be_bytes(length_buf);
+
+	let mut buf = SPARE_BUFFERS.lock().unwrap().pop().unwrap_or_default();
+	buf.clear();
+	buf.resize(length as usize, 0);
+	reader.read_exact(&mut buf)?;
+	Ok(Frame { buf })
+}
 
+/// How many frames have been read from stdin ahead of being received, e.g. for a sink that's falling behind.
+pub fn queued_frames() -> usize {
+	IpcReceiver::stdin().queued_frames()
+}
+
+/// Read the next message from stdin, see [`IpcReceiver::receive`].
 pub async fn receive_input_async_ipc() -> std::io::Result<Message> {
-	tokio::task::spawn_blocking(move || {
-		let mut stdin = std::io::stdin().lock();
-		let mut length_buf = [0_u8; 4];
-		stdin.read_exact(&mut length_buf)?;
-		let length = u32::from_be_bytes(length_buf);
+	IpcReceiver::stdin().receive().await
+}
+
+/// Like [`receive_input_async_ipc`], along with what the source stamped the message with.
+pub async fn receive_stamped_input_async_ipc() -> std::io::Result<(Message, Stamp)> {
+	IpcReceiver::stdin().receive_stamped().await
+}
 
-		let mut buf = vec::from_elem(0_u8, length as usize);
-		stdin.read_exact(&mut buf)?;
-		flexbuffers::from_slice(&buf).map_err(|_| ErrorKind::InvalidData.into())
-	})
-	.await
-	.unwrap()
+/// Deserialize a frame's payload as read by [`receive_frame_async_ipc`], whichever [`codec`] it's
+/// written in.
+pub fn message_from_frame(frame: &[u8]) -> std::io::Result<Message> {
+	codec::decode(frame)
+}
+
+/// Deserialize a frame's payload without copying out of it.
+pub fn message_ref_from_frame(frame: &[u8]) -> std::io::Result<MessageRef<'_>> {
+	codec::decode(frame)
+}
+
+/// Serialize a message into a frame's payload for [`send_frame_ipc`], as a flexbuffer which every
+/// sink reads.
+pub fn frame_from_message(message: &Message) -> Vec<u8> {
+	codec::encode(Codecs::FLEXBUFFERS, message)
+}
+
+/// Like [`frame_from_message`], with `stamp` next to the message.
+pub fn frame_from_stamped(message: &Message, stamp: Stamp) -> Vec<u8> {
+	encode_stamped(Codecs::FLEXBUFFERS, message, stamp)
+}
+
+/// Like [`frame_from_stamped`], written with `codec`.
+pub(crate) fn encode_stamped(codec: Codecs, message: &Message, stamp: Stamp) -> Vec<u8> {
+	if stamp == Stamp::default() {
+		return codec::encode(codec, message);
+	}
+	codec::encode(codec, &Stamped { message, stamp })
+}
+
+/// What the message in a frame was stamped with, nothing if the frame isn't a message at all.
+pub fn stamp_from_frame(frame: &[u8]) -> Stamp {
+	codec::decode(frame).unwrap_or_default()
 }
 
 #[test]
@@ -120,34 +749,143 @@ fn test_loop() {
 		pressed: true,
 	});
 	round_trip(Message::MouseMove([243.5, 162.62].into()));
+	round_trip(Message::MousePosition([0.25, 1.0].into()));
 	round_trip(Message::MouseButton {
 		button: 215,
 		pressed: true,
 	});
+	round_trip(Message::MultiClick {
+		button: 272,
+		count: 3,
+	});
 	round_trip(Message::MouseAxisDiscrete([168.9, -21.7].into()));
 	round_trip(Message::MouseAxisContinuous([1723.2, -482.4].into()));
+	round_trip(Message::Magnify(-0.05));
+	round_trip(Message::Rotate(12.5));
+	round_trip(Message::SmartMagnify);
 	round_trip(Message::ResetInput);
+	round_trip(Message::KeyRepeat {
+		rate: 25,
+		delay: 600,
+	});
+	round_trip(Message::Heartbeat);
+	round_trip(Message::Pause);
+	round_trip(Message::Resume);
+	round_trip(Message::Hover {
+		handler: Some(42),
+		captured: true,
+	});
+	round_trip(Message::Hover {
+		handler: None,
+		captured: false,
+	});
+	round_trip(Message::ClipboardSet("uwu 🦀".to_string()));
+	round_trip(Message::TouchDown {
+		slot: 2,
+		position: [0.25, 0.75].into(),
+	});
+	round_trip(Message::TouchMotion {
+		slot: 2,
+		position: [0.5, 0.5].into(),
+	});
+	round_trip(Message::TouchUp { slot: 2 });
+	round_trip(Message::TabletProximity {
+		tool: TabletTool::Eraser,
+		in_proximity: true,
+	});
+	round_trip(Message::TabletAxis {
+		position: [0.5, 0.25].into(),
+		pressure: 0.75,
+		tilt: [-10.0, 35.0].into(),
+	});
+	round_trip(Message::TabletTip { down: true });
+	round_trip(Message::TabletButton {
+		button: 331,
+		pressed: true,
+	});
+	round_trip(Message::GamepadConnected {
+		id: 1,
+		name: "Xbox Wireless Controller".to_string(),
+	});
+	round_trip(Message::GamepadButton {
+		id: 1,
+		button: 304,
+		pressed: true,
+	});
+	round_trip(Message::GamepadAxis {
+		id: 1,
+		axis: 0,
+		value: -0.5,
+	});
+	round_trip(Message::GamepadDisconnected { id: 1 });
+	round_trip(Message::DeviceAdded {
+		id: 5,
+		name: "Logitech USB Receiver".to_string(),
+		capabilities: Capabilities::POINTER | Capabilities::KEYBOARD,
+	});
+	round_trip(Message::DeviceRemoved { id: 5 });
+	round_trip(Message::Hello {
+		version: handshake::PROTOCOL_VERSION,
+		capabilities: Capabilities::KEYBOARD | Capabilities::POINTER,
+		codecs: Codecs::FLEXBUFFERS | Codecs::CBOR,
+	});
+	round_trip(Message::BatchBegin);
+	round_trip(Message::BatchEnd);
 }
 
 fn round_trip(message: Message) {
 	let serialized = flexbuffers::to_vec(message.clone()).unwrap();
 	let deserialized: Message = flexbuffers::from_slice(&serialized).unwrap();
-	assert_eq!(deserialized, message)
+	assert_eq!(deserialized, message);
+	let borrowed = message_ref_from_frame(&serialized).unwrap();
+	assert_eq!(borrowed.to_message(), message);
+	assert_eq!(flexbuffers::to_vec(borrowed).unwrap(), serialized);
+}
+
+#[test]
+fn test_borrowed_keymap() {
+	let frame = frame_from_message(&Message::Keymap("xkb_keymap {}".to_string()));
+	let MessageRef::Keymap(keymap) = message_ref_from_frame(&frame).unwrap() else {
+		panic!("not a keymap");
+	};
+	// pointing into the frame rather than at a copy
+	assert!(frame.as_ptr_range().contains(&keymap.as_ptr()));
 }
 
 /// Helper struct to clean up the button press/release mess for localized button input (keys, mouse buttons, etc.no
 #[derive(Debug, Default)]
 pub struct ButtonBlot {
 	keys: FxHashMap<u32, i32>,
+	/// When each held code was first pressed, only kept by [`ButtonBlot::timed`] ones.
+	pressed_at: Option<FxHashMap<u32, Instant>>,
+	/// Releases [`ButtonBlot::release_older_than`] already made for codes that are still physically held.
+	released_early: FxHashMap<u32, u32>,
 }
 impl ButtonBlot {
+	/// A blot that keeps track of how long codes are held, for [`ButtonBlot::release_older_than`].
+	pub fn timed() -> Self {
+		ButtonBlot {
+			pressed_at: Some(FxHashMap::default()),
+			..Default::default()
+		}
+	}
+
 	/// Positive keycode for pressed, negative for released.
 	pub fn key_math(&mut self, code: i32) {
 		let key_math = code.signum();
-		if let Some(key) = self.keys.get_mut(&code.unsigned_abs()) {
-			*key += key_math;
-		} else {
-			self.keys.insert(code.unsigned_abs(), key_math);
+		let count = self.keys.entry(code.unsigned_abs()).or_insert(0);
+		*count += key_math;
+		if let Some(pressed_at) = &mut self.pressed_at {
+			if *count > 0 {
+				pressed_at
+					.entry(code.unsigned_abs())
+					.or_insert_with(Instant::now);
+			} else {
+				pressed_at.remove(&code.unsigned_abs());
+			}
+		}
+		if key_math > 0 {
+			self.released_early.remove(&code.unsigned_abs());
 		}
 	}
 	pub fn key_pressed(&mut self, code: u32) {
@@ -164,6 +902,50 @@ impl ButtonBlot {
 	pub fn is_clean(&self) -> bool {
 		self.keys.values().all(|k| *k == 0)
 	}
+	/// Presses minus releases seen for this code.
+	pub fn key_count(&self, code: u32) -> i32 {
+		self.keys.get(&code).copied().unwrap_or(0)
+	}
+	/// Every code that hasn't been released exactly as many times as it was pressed, with its count.
+	pub fn unbalanced(&self) -> impl Iterator<Item = (u32, i32)> + '_ {
+		self.keys
+			.iter()
+			.filter(|(_, m)| **m != 0)
+			.map(|(k, m)| (*k, *m))
+	}
+
+	/// Forget codes a timed blot has seen held for longer than `age`, returning the releases to
+	/// send for them. Their real releases are then for [`ButtonBlot::take_released_early`].
+	pub fn release_older_than(&mut self, age: Duration) -> Vec<(u32, bool)> {
+		let Some(pressed_at) = &mut self.pressed_at else {
+			return Vec::new();
+		};
+		let stuck: Vec<u32> = pressed_at
+			.iter()
+			.filter(|(_, at)| at.elapsed() >= age)
+			.map(|(code, _)| *code)
+			.collect();
+		let mut releases = Vec::new();
+		for code in stuck {
+			pressed_at.remove(&code);
+			let count = self.keys.remove(&code).unwrap_or(0);
+			releases.extend(vec![(code, false); count.max(0) as usize]);
+			*self.released_early.entry(code).or_default() += count.max(0) as u32;
+		}
+		releases
+	}
+	/// Whether a release is for a code [`ButtonBlot::release_older_than`] already let go of, in
+	/// which case it shouldn't be counted or passed on.
+	pub fn take_released_early(&mut self, code: u32) -> bool {
+		let Some(left) = self.released_early.get_mut(&code) else {
+			return false;
+		};
+		*left -= 1;
+		if *left == 0 {
+			self.released_early.remove(&code);
+		}
+		true
+	}
 
 	pub fn cleanup_presses_releases(self) -> impl IntoIterator<Item = (u32, bool)> {
 		self.keys
@@ -174,3 +956,50 @@ impl ButtonBlot {
 		self.keys.into_iter().map(|(k, m)| (k, -m))
 	}
 }
+
+#[test]
+fn test_release_older_than() {
+	let mut blot = ButtonBlot::timed();
+	blot.key_pressed(30);
+	blot.key_pressed(30);
+	assert!(blot.release_older_than(Duration::from_secs(60)).is_empty());
+	blot.key_pressed(31);
+	std::thread::sleep(Duration::from_millis(20));
+	blot.key_released(31);
+	let released = blot.release_older_than(Duration::from_millis(10));
+	assert_eq!(released, [(30, false), (30, false)]);
+	assert!(blot.is_clean());
+
+	// the real releases turning up late are taken back, and nothing else is
+	assert!(blot.take_released_early(30));
+	assert!(blot.take_released_early(30));
+	assert!(!blot.take_released_early(30));
+	assert!(!blot.take_released_early(31));
+
+	// untimed blots never let go on their own
+	let mut blot = ButtonBlot::default();
+	blot.key_pressed(30);
+	assert!(blot.release_older_than(Duration::ZERO).is_empty());
+}
+
+#[test]
+fn test_stamped_frame() {
+	let message = Message::MouseMove([1.0, 2.0].into());
+	let stamp = Stamp {
+		ts: Some(1234),
+		device: Some(5),
+	};
+	let frame = frame_from_stamped(&message, stamp);
+	assert_eq!(stamp_from_frame(&frame), stamp);
+	assert_eq!(message_from_frame(&frame).unwrap(), message);
+	assert_eq!(message_ref_from_frame(&frame).unwrap().to_message(), message);
+	// even without any content
+	let frame = frame_from_stamped(&Message::ResetInput, stamp);
+	assert_eq!(message_from_frame(&frame).unwrap(), Message::ResetInput);
+	// and without a stamp it's the frame it always was
+	assert_eq!(
+		frame_from_stamped(&message, Stamp::default()),
+		frame_from_message(&message)
+	);
+	assert_eq!(stamp_from_frame(&frame_from_message(&message)), Stamp::default());
+}
@@ -0,0 +1,164 @@
+//! Where a stream of messages comes from.
+//!
+//! [`IpcReceiver::stdin`] is the stream the free functions like
+//! [`receive_input_async_ipc`](crate::receive_input_async_ipc) read: stdin, or the socket
+//! [connected](crate::socket::connect) to. [`IpcReceiver::new`] reads anything else. Either way
+//! frames are read ahead by a thread of their own, so a receive that's cancelled or times out
+//! doesn't drop the frame it was partway through reading.
+
+use crate::{
+	handshake, heartbeat, read_pooled_frame, socket, Frame, Message, MessageRef, Stamp,
+	FRAME_BUFFER,
+};
+use std::{
+	io::{self, ErrorKind, Read},
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		Arc, OnceLock,
+	},
+	thread,
+	time::Duration,
+};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+static STDIN: OnceLock<IpcReceiver> = OnceLock::new();
+
+pub struct IpcReceiver {
+	frames: tokio::sync::Mutex<Receiver<io::Result<Frame>>>,
+	// frames the reader has read that haven't been received yet
+	queued: Arc<AtomicUsize>,
+	// whether the source sends heartbeats, sources from before them never do
+	heartbeats_seen: AtomicBool,
+}
+impl IpcReceiver {
+	/// Read from `reader` on a thread of its own.
+	pub fn new(reader: impl Read + Send + 'static) -> Self {
+		Self::spawn(move |frames| read_all(reader, frames))
+	}
+
+	/// The receiver for stdin, or the socket connected to. Started on first use.
+	pub fn stdin() -> &'static IpcReceiver {
+		STDIN.get_or_init(|| match socket::source_name() {
+			// a sink connected to a source's socket reads from that instead
+			Some(name) => Self::spawn(move |frames| socket::read_source_frames(name, frames)),
+			None => Self::spawn(|frames| read_all(io::stdin().lock(), frames)),
+		})
+	}
+
+	fn spawn(read: impl FnOnce(FrameQueue) + Send + 'static) -> Self {
+		let (tx, rx) = mpsc::channel(FRAME_BUFFER);
+		let queued = Arc::new(AtomicUsize::new(0));
+		let frames = FrameQueue {
+			tx,
+			queued: queued.clone(),
+		};
+		thread::spawn(move || read(frames));
+		IpcReceiver {
+			frames: tokio::sync::Mutex::new(rx),
+			queued,
+			heartbeats_seen: AtomicBool::new(false),
+		}
+	}
+
+	/// The next frame, whose buffer is read into again once it's dropped.
+	///
+	/// Cancel safe, a frame is never lost if this is dropped before it finishes.
+	pub async fn receive_frame(&self) -> io::Result<Frame> {
+		self.receive_frame_timeout(None)
+			.await
+			.map(|frame| frame.unwrap())
+	}
+
+	/// The next message, leaving out heartbeats and the source's hello.
+	///
+	/// Once the source has sent a heartbeat, going quiet for [`heartbeat::timeout`]
+	/// comes out as a [`Message::ResetInput`] so nothing stays held down by a source that's
+	/// wedged. See [`handshake`] for how a source that's newer or older than this is read.
+	pub async fn receive(&self) -> io::Result<Message> {
+		self.receive_stamped().await.map(|(message, _)| message)
+	}
+
+	/// Like [`receive`](Self::receive), along with what the source stamped the message with.
+	pub async fn receive_stamped(&self) -> io::Result<(Message, Stamp)> {
+		loop {
+			let timeout = self
+				.heartbeats_seen
+				.load(Ordering::Relaxed)
+				.then(heartbeat::timeout);
+			let Some(frame) = self.receive_frame_timeout(timeout).await? else {
+				tracing::warn!(
+					"No heartbeat from the source for {:?}, resetting input",
+					heartbeat::timeout()
+				);
+				// expected again once it's back, so a source that stays quiet is only reset once
+				self.heartbeats_seen.store(false, Ordering::Relaxed);
+				return Ok((Message::ResetInput, Stamp::default()));
+			};
+			let message = match frame.message() {
+				Ok(message) => message,
+				Err(_) if handshake::source_is_newer() => {
+					tracing::debug!("Skipping a message from a newer source this doesn't know");
+					continue;
+				}
+				Err(e) => return Err(e),
+			};
+			match message {
+				MessageRef::Heartbeat => self.heartbeats_seen.store(true, Ordering::Relaxed),
+				MessageRef::Hello {
+					version,
+					capabilities,
+					codecs,
+				} => handshake::greeted(version, capabilities, codecs)?,
+				message => return Ok((message.to_message(), frame.stamp())),
+			}
+		}
+	}
+
+	/// How many frames have been read ahead of being received, e.g. for a sink that's falling
+	/// behind.
+	pub fn queued_frames(&self) -> usize {
+		self.queued.load(Ordering::Relaxed)
+	}
+
+	/// The next frame, or `None` if none came within the timeout.
+	async fn receive_frame_timeout(&self, timeout: Option<Duration>) -> io::Result<Option<Frame>> {
+		let mut frames = self.frames.lock().await;
+		let frame = match timeout {
+			Some(timeout) => match tokio::time::timeout(timeout, frames.recv()).await {
+				Ok(frame) => frame,
+				Err(_) => return Ok(None),
+			},
+			None => frames.recv().await,
+		};
+		if frame.is_some() {
+			self.queued.fetch_sub(1, Ordering::Relaxed);
+		}
+		// the reader stops after passing on an error, so the stream is over from then on
+		frame
+			.unwrap_or_else(|| Err(ErrorKind::UnexpectedEof.into()))
+			.map(Some)
+	}
+}
+
+/// Frames read ahead of being received, for the thread reading them.
+pub(crate) struct FrameQueue {
+	tx: Sender<io::Result<Frame>>,
+	queued: Arc<AtomicUsize>,
+}
+impl FrameQueue {
+	/// Pass a frame on to be received, false once nothing is receiving anymore.
+	pub(crate) fn push(&self, frame: io::Result<Frame>) -> bool {
+		self.queued.fetch_add(1, Ordering::Relaxed);
+		self.tx.blocking_send(frame).is_ok()
+	}
+}
+
+fn read_all(mut reader: impl Read, frames: FrameQueue) {
+	loop {
+		let frame = read_pooled_frame(&mut reader);
+		let failed = frame.is_err();
+		if !frames.push(frame) || failed {
+			break;
+		}
+	}
+}
@@ -0,0 +1,109 @@
+//! Recording format for archiving an ipc stream to a file.
+//!
+//! A recording starts with [`MAGIC`], followed by one entry per frame:
+//! microseconds since the recording started (`u64` BE), payload length (`u32` BE), then the
+//! flexbuffer payload exactly as it appeared on the wire.
+
+use crate::{message_from_frame, Message};
+use std::{
+	io::{ErrorKind, Read, Write},
+	time::{Duration, Instant},
+};
+
+pub const MAGIC: &[u8; 8] = b"NSIREC01";
+
+pub struct Recorder<W: Write> {
+	writer: W,
+	start: Instant,
+}
+impl<W: Write> Recorder<W> {
+	pub fn new(mut writer: W) -> std::io::Result<Self> {
+		writer.write_all(MAGIC)?;
+		writer.flush()?;
+		Ok(Recorder {
+			writer,
+			start: Instant::now(),
+		})
+	}
+
+	/// Append a raw frame payload, stamped with the time since the recording started.
+	pub fn record_frame(&mut self, frame: &[u8]) -> std::io::Result<()> {
+		let micros = self.start.elapsed().as_micros() as u64;
+		let mut entry = Vec::with_capacity(12 + frame.len());
+		entry.extend_from_slice(&micros.to_be_bytes());
+		entry.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+		entry.extend_from_slice(frame);
+		// flushed per frame so a recording cut short by ctrl+c is still usable
+		self.writer.write_all(&entry)?;
+		self.writer.flush()
+	}
+	pub fn record(&mut self, message: &Message) -> std::io::Result<()> {
+		let frame = flexbuffers::to_vec(message).map_err(|_| ErrorKind::InvalidData)?;
+		self.record_frame(&frame)
+	}
+}
+
+/// Reads back the entries of a recording as `(time since start, raw frame)`.
+pub struct RecordingReader<R: Read> {
+	reader: R,
+}
+impl<R: Read> RecordingReader<R> {
+	pub fn new(mut reader: R) -> std::io::Result<Self> {
+		let mut magic = [0_u8; 8];
+		reader.read_exact(&mut magic)?;
+		if &magic != MAGIC {
+			return Err(ErrorKind::InvalidData.into());
+		}
+		Ok(RecordingReader { reader })
+	}
+
+	pub fn next_frame(&mut self) -> std::io::Result<Option<(Duration, Vec<u8>)>> {
+		let mut micros_buf = [0_u8; 8];
+		match self.reader.read_exact(&mut micros_buf) {
+			Ok(()) => (),
+			Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+			Err(e) => return Err(e),
+		}
+		let mut length_buf = [0_u8; 4];
+		self.reader.read_exact(&mut length_buf)?;
+		let mut frame = vec![0_u8; u32::from_be_bytes(length_buf) as usize];
+		self.reader.read_exact(&mut frame)?;
+		Ok(Some((
+			Duration::from_micros(u64::from_be_bytes(micros_buf)),
+			frame,
+		)))
+	}
+	pub fn next_message(&mut self) -> std::io::Result<Option<(Duration, Message)>> {
+		let Some((time, frame)) = self.next_frame()? else {
+			return Ok(None);
+		};
+		Ok(Some((time, message_from_frame(&frame)?)))
+	}
+}
+
+#[test]
+fn test_recording() {
+	let messages = [
+		Message::Keymap("uwu owo nya".to_string()),
+		Message::Key {
+			keycode: 30,
+			pressed: true,
+		},
+		Message::MouseMove([4.0, -2.5].into()),
+		Message::Disconnect,
+	];
+	let mut recorder = Recorder::new(Vec::new()).unwrap();
+	for message in &messages {
+		recorder.record(message).unwrap();
+	}
+
+	let mut reader = RecordingReader::new(recorder.writer.as_slice()).unwrap();
+	let mut last_time = Duration::ZERO;
+	for message in messages {
+		let (time, read) = reader.next_message().unwrap().unwrap();
+		assert!(time >= last_time);
+		last_time = time;
+		assert_eq!(read, message);
+	}
+	assert!(reader.next_message().unwrap().is_none());
+}
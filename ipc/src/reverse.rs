@@ -0,0 +1,219 @@
+//! The reverse channel, for messages from the sink back to the source such as [`Message::Pause`].
+//!
+//! A pipe only goes one way, so each end finds the other through an abstract socket named after
+//! the pipe between them: the source listens on the one for its stdout, and the sink connects to
+//! the one for its stdin. Filters in between call [`relay`] so messages make it past them, and
+//! connections from other users are turned away. Only on Linux, elsewhere nothing listens and
+//! sending upstream fails. Over a [`socket`](crate::socket)
+//! instead of a pipe, messages go back over the socket itself.
+
+use crate::{codec::Codecs, frame_from_message, message_from_frame, socket, Message};
+use std::{
+	fs::File,
+	io::{self, ErrorKind, Read, Write},
+	os::{
+		fd::{AsFd, BorrowedFd},
+		unix::{
+			fs::{FileTypeExt, MetadataExt},
+			net::{UnixListener, UnixStream},
+		},
+	},
+	sync::{Mutex, OnceLock},
+	thread,
+};
+use tracing::{debug, warn};
+
+static UPSTREAM: Mutex<Option<Upstream>> = Mutex::new(None);
+// what a source serving a socket does with what its sink sends back over it
+static HANDLER: OnceLock<Box<dyn Fn(Message) + Send + Sync>> = OnceLock::new();
+
+/// The inode of the pipe on `fd`, which both of its ends share.
+fn pipe_id(fd: BorrowedFd) -> Option<u64> {
+	let metadata = File::from(fd.try_clone_to_owned().ok()?).metadata().ok()?;
+	metadata.file_type().is_fifo().then(|| metadata.ino())
+}
+
+#[cfg(target_os = "linux")]
+fn address(pipe: u64) -> io::Result<std::os::unix::net::SocketAddr> {
+	use std::os::linux::net::SocketAddrExt;
+	std::os::unix::net::SocketAddr::from_abstract_name(format!("stardust-input-pipe-{pipe}"))
+}
+#[cfg(not(target_os = "linux"))]
+fn address(_pipe: u64) -> io::Result<std::os::unix::net::SocketAddr> {
+	Err(ErrorKind::Unsupported.into())
+}
+
+/// Whether what's on the other end of `stream` runs as the same user as this. Abstract sockets
+/// have no permissions, so anyone who guesses a pipe's inode can connect.
+#[cfg(target_os = "linux")]
+fn same_user(stream: &UnixStream) -> bool {
+	use std::os::fd::AsRawFd;
+	let mut credentials = libc::ucred {
+		pid: 0,
+		uid: 0,
+		gid: 0,
+	};
+	let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+	let got = unsafe {
+		libc::getsockopt(
+			stream.as_raw_fd(),
+			libc::SOL_SOCKET,
+			libc::SO_PEERCRED,
+			&mut credentials as *mut libc::ucred as *mut libc::c_void,
+			&mut length,
+		)
+	};
+	got == 0 && credentials.uid == unsafe { libc::getuid() }
+}
+#[cfg(not(target_os = "linux"))]
+fn same_user(_stream: &UnixStream) -> bool {
+	false
+}
+
+/// Hand each message the sink sends back to `handle`, from another thread. Only one of these (or
+/// [`relay`]) can listen per process, since there's only one stdout.
+pub fn listen(handle: impl Fn(Message) + Send + Sync + 'static) -> io::Result<()> {
+	if socket::serving() {
+		return HANDLER
+			.set(Box::new(handle))
+			.map_err(|_| ErrorKind::AddrInUse.into());
+	}
+	listen_to(io::stdout().as_fd(), move |frame| {
+		handle_frame(&handle, frame)
+	})
+}
+
+/// Like [`listen`] for the pipe on `fd` instead of stdout, e.g. to a sink run as a child process,
+/// handing over each frame as it was sent.
+pub fn listen_to(fd: BorrowedFd, handle: impl Fn(&[u8]) + Send + Sync + 'static) -> io::Result<()> {
+	let pipe = pipe_id(fd).ok_or(ErrorKind::NotConnected)?;
+	let listener = UnixListener::bind_addr(&address(pipe)?)?;
+	let handle = std::sync::Arc::new(handle);
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let Ok(mut stream) = stream else {
+				continue;
+			};
+			if !same_user(&stream) {
+				warn!("Turning away another user trying to send to the source");
+				continue;
+			}
+			let handle = handle.clone();
+			thread::spawn(move || {
+				while let Ok(frame) = read_frame(&mut stream) {
+					handle(&frame);
+				}
+			});
+		}
+	});
+	Ok(())
+}
+
+fn handle_frame(handle: &dyn Fn(Message), frame: &[u8]) {
+	match message_from_frame(frame) {
+		Ok(message) => handle(message),
+		Err(_) => warn!("Ignoring a frame from the sink that isn't a message"),
+	}
+}
+
+/// Hand a frame the sink sent back over the socket to whatever is listening.
+pub(crate) fn handle_from_socket(frame: &[u8]) {
+	if let Some(handle) = HANDLER.get() {
+		handle_frame(handle.as_ref(), frame);
+	}
+}
+
+/// Send a message to whatever is writing to stdin, connecting on first use.
+pub fn send_upstream(message: &Message) -> io::Result<()> {
+	if socket::source_name().is_some() {
+		return socket::send_upstream(&frame_from_message(message));
+	}
+	let mut upstream = UPSTREAM.lock().unwrap();
+	if upstream.is_none() {
+		upstream.replace(Upstream::new(io::stdin().as_fd())?);
+	}
+	upstream
+		.as_mut()
+		.unwrap()
+		.send_frame(&frame_from_message(message))
+}
+
+/// The reverse channel to whatever is writing to a pipe, like [`send_upstream`] for one other
+/// than stdin, e.g. from a source run as a child process.
+pub struct Upstream {
+	address: std::os::unix::net::SocketAddr,
+	stream: Option<UnixStream>,
+}
+impl Upstream {
+	/// The reverse channel for the pipe on `fd`, connected to on first use.
+	pub fn new(fd: BorrowedFd) -> io::Result<Self> {
+		let pipe = pipe_id(fd).ok_or(ErrorKind::NotConnected)?;
+		Ok(Upstream {
+			address: address(pipe)?,
+			stream: None,
+		})
+	}
+
+	/// Send a frame's payload as it is, e.g. one passed on from further down the pipeline.
+	pub fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+		// a connection left from a source that's gone gets one more try on a fresh one
+		for _ in 0..2 {
+			if self.stream.is_none() {
+				self.stream = Some(UnixStream::connect_addr(&self.address)?);
+			}
+			match write_frame(self.stream.as_mut().unwrap(), frame) {
+				Ok(()) => return Ok(()),
+				Err(e) => {
+					debug!("Lost the reverse channel, reconnecting: {e}");
+					self.stream = None;
+				}
+			}
+		}
+		Err(ErrorKind::NotConnected.into())
+	}
+}
+
+/// Pass whatever the sink sends back on to the source, for filters in the middle of a pipe.
+pub fn relay() {
+	let relayed = listen(|mut message| {
+		// the source's frames go through this too, so it has to read them as well
+		if let Message::Hello { codecs, .. } = &mut message {
+			*codecs = *codecs & Codecs::supported();
+		}
+		if let Err(e) = send_upstream(&message) {
+			debug!("Couldn't pass {} on upstream: {e}", message.name());
+		}
+	});
+	if let Err(e) = relayed {
+		debug!("Not relaying the reverse channel: {e}");
+	}
+}
+
+pub(crate) fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+	let mut length = [0; 4];
+	reader.read_exact(&mut length)?;
+	let mut frame = vec![0; u32::from_be_bytes(length) as usize];
+	reader.read_exact(&mut frame)?;
+	Ok(frame)
+}
+
+pub(crate) fn write_frame(writer: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+	let length = u32::try_from(frame.len()).map_err(|_| ErrorKind::InvalidInput)?;
+	writer.write_all(&length.to_be_bytes())?;
+	writer.write_all(frame)
+}
+
+#[test]
+fn test_same_user() {
+	let (a, _b) = UnixStream::pair().unwrap();
+	assert_eq!(same_user(&a), cfg!(target_os = "linux"));
+}
+
+#[test]
+fn test_pipe_id() {
+	let (reader, writer) = std::io::pipe().unwrap();
+	let id = pipe_id(reader.as_fd());
+	assert!(id.is_some());
+	assert_eq!(pipe_id(writer.as_fd()), id);
+	assert_eq!(pipe_id(File::open("/dev/null").unwrap().as_fd()), None);
+}
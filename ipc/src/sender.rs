@@ -0,0 +1,304 @@
+//! Where a stream of messages goes, and what's held down on it so it can be released.
+//!
+//! [`IpcSender::stdout`] is the stream the free functions like
+//! [`send_input_ipc`](crate::send_input_ipc) send on: stdout, or the socket being
+//! [served](crate::socket::serve), with motion merged while the sink is slow and anything the sink
+//! said it does nothing with (see [`handshake`]) left out. [`IpcSender::new`] writes anywhere
+//! else, e.g. to a second sink or into a buffer in a test, each message as it's sent.
+
+use crate::{
+	frame_from_stamped, handshake, heartbeat, lanes, reverse, socket, stuck, timestamp, ButtonBlot,
+	Error, Message, Stamp,
+};
+use std::{
+	collections::BTreeMap,
+	io::{self, Write},
+	sync::Mutex,
+};
+
+static STDOUT: IpcSender = IpcSender {
+	output: Output::Stdout,
+	mouse_blot: Mutex::new(None),
+	key_blot: Mutex::new(None),
+	gamepad_blots: Mutex::new(BTreeMap::new()),
+};
+
+pub struct IpcSender {
+	output: Output,
+	pub(crate) mouse_blot: Mutex<Option<ButtonBlot>>,
+	pub(crate) key_blot: Mutex<Option<ButtonBlot>>,
+	/// Each connected gamepad's buttons, by its id.
+	pub(crate) gamepad_blots: Mutex<BTreeMap<u32, ButtonBlot>>,
+}
+enum Output {
+	/// Through the [`lanes`] writer, to stdout or the socket being served.
+	Stdout,
+	Writer(Mutex<Box<dyn Write + Send>>),
+}
+impl IpcSender {
+	/// Send to `writer`, writing each message before returning.
+	pub fn new(writer: impl Write + Send + 'static) -> Self {
+		IpcSender {
+			output: Output::Writer(Mutex::new(Box::new(writer))),
+			mouse_blot: Mutex::new(None),
+			key_blot: Mutex::new(None),
+			gamepad_blots: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	/// The sender for stdout, or the socket being served.
+	pub fn stdout() -> &'static IpcSender {
+		&STDOUT
+	}
+
+	pub fn send(&self, message: Message) -> Result<(), Error> {
+		self.send_stamped(message, Stamp::default())
+	}
+
+	/// Like [`send`](Self::send), with what else the source knows about the message.
+	pub fn send_stamped(&self, message: Message, mut stamp: Stamp) -> Result<(), Error> {
+		let Some(messages) = self.outgoing(message, &mut stamp) else {
+			return Ok(());
+		};
+		self.write_out(messages, stamp)
+	}
+
+	/// Send a release [`stuck`] already took out of the blots, so it isn't counted again.
+	pub(crate) fn send_swept(&self, message: Message) -> Result<(), Error> {
+		let mut stamp = Stamp::default();
+		if !self.stamp_wanted(&message, &mut stamp) {
+			return Ok(());
+		}
+		self.write_out(vec![message], stamp)
+	}
+
+	fn write_out(&self, messages: Vec<Message>, stamp: Stamp) -> Result<(), Error> {
+		for message in messages {
+			match &self.output {
+				Output::Stdout => lanes::send(message, stamp)?,
+				Output::Writer(_) => self.write_frame(&frame_from_stamped(&message, stamp))?,
+			}
+		}
+		Ok(())
+	}
+
+	/// Like [`send`](Self::send), waiting for a full pipe without blocking the thread, for sources
+	/// and filters that send from an async task.
+	///
+	/// Only stdout is written to from elsewhere, a writer given to [`new`](Self::new) is still
+	/// written to in place.
+	pub async fn send_async(&self, message: Message) -> Result<(), Error> {
+		self.send_stamped_async(message, Stamp::default()).await
+	}
+
+	/// Like [`send_async`](Self::send_async), with what else the source knows about the message.
+	pub async fn send_stamped_async(
+		&self,
+		message: Message,
+		mut stamp: Stamp,
+	) -> Result<(), Error> {
+		let Some(messages) = self.outgoing(message, &mut stamp) else {
+			return Ok(());
+		};
+		for message in messages {
+			match &self.output {
+				Output::Stdout => lanes::send_async(message, stamp).await?,
+				Output::Writer(_) => self.write_frame(&frame_from_stamped(&message, stamp))?,
+			}
+		}
+		Ok(())
+	}
+
+	/// What actually goes out for `message`, or `None` if nothing does.
+	fn outgoing(&self, message: Message, stamp: &mut Stamp) -> Option<Vec<Message>> {
+		if !self.stamp_wanted(&message, stamp) {
+			return None;
+		}
+		let mut messages = vec![message.clone()];
+		// a release the sink already got from the stuck input sweep isn't sent twice
+		let update = |blot: &Mutex<Option<ButtonBlot>>, code: u32, pressed: bool| {
+			let mut blot = blot.lock().unwrap();
+			let blot = blot.get_or_insert_with(stuck::new_blot);
+			if !pressed && blot.take_released_early(code) {
+				return false;
+			}
+			blot.key_update(code, pressed);
+			true
+		};
+		let pass_on = match &message {
+			Message::MouseButton { button, pressed } => update(&self.mouse_blot, *button, *pressed),
+			Message::Key { keycode, pressed } => update(&self.key_blot, *keycode, *pressed),
+			Message::GamepadButton {
+				id,
+				button,
+				pressed,
+			} => {
+				let mut blots = self.gamepad_blots.lock().unwrap();
+				let blot = blots.entry(*id).or_insert_with(stuck::new_blot);
+				if !pressed && blot.take_released_early(*button) {
+					false
+				} else {
+					blot.key_update(*button, *pressed);
+					true
+				}
+			}
+			// the sink lets go of its buttons itself
+			Message::GamepadDisconnected { id } => {
+				self.gamepad_blots.lock().unwrap().remove(id);
+				true
+			}
+			Message::ResetInput => {
+				// eprintln!("reset input");
+				messages.clear();
+				if let Some(blot) = self.mouse_blot.lock().unwrap().replace(stuck::new_blot()) {
+					for (button, pressed) in blot.cleanup_presses_releases() {
+						messages.push(Message::MouseButton { button, pressed });
+					}
+				}
+				if let Some(blot) = self.key_blot.lock().unwrap().replace(stuck::new_blot()) {
+					for (keycode, pressed) in blot.cleanup_presses_releases() {
+						messages.push(Message::Key { keycode, pressed });
+					}
+				}
+				for (id, blot) in std::mem::take(&mut *self.gamepad_blots.lock().unwrap()) {
+					for (button, pressed) in blot.cleanup_presses_releases() {
+						messages.push(Message::GamepadButton {
+							id,
+							button,
+							pressed,
+						});
+					}
+				}
+				// and then the reset itself, for what only the sink keeps track of, like fingers
+				messages.push(Message::ResetInput);
+				true
+			}
+			_ => true,
+		};
+		pass_on.then_some(messages)
+	}
+
+	/// Whether the sink wants `message` at all, giving it a timestamp if it does and has none.
+	fn stamp_wanted(&self, message: &Message, stamp: &mut Stamp) -> bool {
+		// nothing the sink said it would only drop
+		if matches!(self.output, Output::Stdout) && !handshake::wanted(message) {
+			return false;
+		}
+		if stamp.ts.is_none() {
+			stamp.ts = timestamp::default();
+		}
+		true
+	}
+
+	/// Write a raw frame payload, e.g. to pass on a frame from an
+	/// [`IpcReceiver`](crate::IpcReceiver) untouched, after anything sent before it.
+	pub fn send_frame(&self, frame: &[u8]) -> Result<(), Error> {
+		self.flush()?;
+		self.write_frame(frame)
+	}
+
+	/// Wait until everything sent is written, e.g. before exiting after motion, since motion sent
+	/// to stdout returns before it's written.
+	pub fn flush(&self) -> Result<(), Error> {
+		match &self.output {
+			Output::Stdout => lanes::flush()?,
+			Output::Writer(writer) => writer.lock().unwrap().flush()?,
+		}
+		Ok(())
+	}
+
+	/// Like [`send_frame`](Self::send_frame), without blocking the thread.
+	pub async fn send_frame_async(&self, frame: &[u8]) -> Result<(), Error> {
+		self.flush_async().await?;
+		if !matches!(self.output, Output::Stdout) {
+			return self.write_frame(frame);
+		}
+		let frame = frame.to_vec();
+		tokio::task::spawn_blocking(move || write_stdout(&frame))
+			.await
+			.map_err(io::Error::other)??;
+		Ok(())
+	}
+
+	/// Like [`flush`](Self::flush), without blocking the thread.
+	pub async fn flush_async(&self) -> Result<(), Error> {
+		match &self.output {
+			Output::Stdout => lanes::flush_async().await?,
+			Output::Writer(writer) => writer.lock().unwrap().flush()?,
+		}
+		Ok(())
+	}
+
+	fn write_frame(&self, frame: &[u8]) -> Result<(), Error> {
+		let Output::Writer(writer) = &self.output else {
+			return Ok(write_stdout(frame)?);
+		};
+		let mut writer = writer.lock().unwrap();
+		reverse::write_frame(&mut *writer, frame)?;
+		Ok(writer.flush()?)
+	}
+}
+
+pub(crate) fn write_stdout(frame: &[u8]) -> io::Result<()> {
+	// a source serving a socket writes to the sink connected to it instead
+	if socket::serving() {
+		socket::write_to_sink(frame);
+		return Ok(());
+	}
+	let mut stdout = io::stdout().lock();
+	// only once the lock is held, a heartbeat waiting on it isn't stuck on the sink yet
+	heartbeat::write_started();
+	let written = reverse::write_frame(&mut stdout, frame).and_then(|_| stdout.flush());
+	heartbeat::write_finished();
+	written
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sender() {
+	let (reader, writer) = std::io::pipe().unwrap();
+	let sender = IpcSender::new(writer);
+	let receiver = crate::IpcReceiver::new(reader);
+	let press = |keycode, pressed| Message::Key { keycode, pressed };
+	sender.send(press(30, true)).unwrap();
+	sender.send(press(31, true)).unwrap();
+	sender.send(press(30, false)).unwrap();
+	let south = |id, pressed| Message::GamepadButton {
+		id,
+		button: 0x130,
+		pressed,
+	};
+	sender.send(south(0, true)).unwrap();
+	sender.send(south(1, true)).unwrap();
+	sender.send(Message::GamepadDisconnected { id: 1 }).unwrap();
+	sender.send(Message::ResetInput).unwrap();
+	sender.send_async(Message::Disconnect).await.unwrap();
+	for expected in [
+		press(30, true),
+		press(31, true),
+		press(30, false),
+		south(0, true),
+		south(1, true),
+		Message::GamepadDisconnected { id: 1 },
+		press(31, false),
+		south(0, false),
+		Message::ResetInput,
+		Message::Disconnect,
+	] {
+		assert_eq!(receiver.receive().await.unwrap(), expected);
+	}
+	// the stdout sender's held keys aren't this one's
+	assert!(IpcSender::stdout().key_blot.lock().unwrap().is_none());
+}
+
+#[test]
+fn test_sink_gone() {
+	let (reader, writer) = std::io::pipe().unwrap();
+	let sender = IpcSender::new(writer);
+	drop(reader);
+	let sent = sender.send(Message::Key {
+		keycode: 30,
+		pressed: true,
+	});
+	assert!(sent.unwrap_err().is_disconnected());
+}
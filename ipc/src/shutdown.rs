@@ -0,0 +1,127 @@
+//! Stopping a pipeline without leaving keys stuck down or the pointer captured.
+//!
+//! On ctrl+c or SIGTERM every program in the pipeline gets the signal at once. Sources and filters
+//! finish with [`end_stream`], so the sink releases everything even if its own signal is lost.
+//! Sinks release everything they hold and let go of what they made before exiting, just like on a
+//! [`Message::Disconnect`]. Async programs wait on [`signalled`] and the rest register a cleanup
+//! with [`on_signal`]. Either way, a second signal quits straight away, for when cleaning up is
+//! stuck on a sink that's wedged. A source's [heartbeat](crate::heartbeat) finding the sink gone
+//! stops it the same way, so a source that's idle lets go of its devices too.
+
+use crate::{flush_input_ipc, send_input_ipc, Error, Message};
+use std::{
+	sync::{
+		atomic::{AtomicI32, AtomicUsize, Ordering},
+		Mutex, Once, OnceLock,
+	},
+	thread,
+};
+use tokio::sync::watch;
+
+static INSTALL: Once = Once::new();
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+// the write end of the pipe the signal handler wakes the cleanup thread with
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+static SIGNALLED: OnceLock<watch::Sender<bool>> = OnceLock::new();
+static CLEANUP: Mutex<Option<Box<dyn FnOnce() + Send>>> = Mutex::new(None);
+
+/// Release everything the stream holds and disconnect, waiting until it's written.
+///
+/// The last thing a source or filter sends. A sink that's already gone has nothing left to release,
+/// so it isn't an error.
+pub fn end_stream() {
+	let ended = send_input_ipc(Message::ResetInput)
+		.and_then(|_| send_input_ipc(Message::Disconnect))
+		.and_then(|_| flush_input_ipc());
+	match ended {
+		Ok(()) | Err(Error::Disconnected) => (),
+		Err(e) => tracing::warn!("Couldn't end the stream: {e}"),
+	}
+}
+
+/// Wait for ctrl+c or SIGTERM, or the heartbeat finding the sink gone, instead of
+/// [`tokio::signal::ctrl_c`].
+pub async fn signalled() {
+	install();
+	let mut signalled = signalled_tx().subscribe();
+	let _ = signalled.wait_for(|signalled| *signalled).await;
+}
+
+/// Run `cleanup` on its own thread on ctrl+c or SIGTERM, or once the heartbeat finds the sink gone,
+/// for programs without an async runtime.
+///
+/// It should exit once it's done.
+pub fn on_signal(cleanup: impl FnOnce() + Send + 'static) {
+	CLEANUP.lock().unwrap().replace(Box::new(cleanup));
+	install();
+}
+
+fn signalled_tx() -> &'static watch::Sender<bool> {
+	SIGNALLED.get_or_init(|| watch::channel(false).0)
+}
+
+fn install() {
+	INSTALL.call_once(|| {
+		let mut fds = [0; 2];
+		if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+			tracing::warn!(
+				"Couldn't watch for ctrl+c: {}",
+				std::io::Error::last_os_error()
+			);
+			return;
+		}
+		let [read, write] = fds;
+		PIPE.store(write, Ordering::SeqCst);
+		thread::spawn(move || {
+			let mut byte = 0_u8;
+			while unsafe { libc::read(read, (&mut byte as *mut u8).cast(), 1) } != 1 {}
+			stop();
+		});
+		unsafe {
+			let mut action: libc::sigaction = std::mem::zeroed();
+			action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+			// reads and writes carry on, though poll still returns EINTR so a poll loop can check
+			// for a stop
+			action.sa_flags = libc::SA_RESTART;
+			for signal in [libc::SIGINT, libc::SIGTERM] {
+				libc::sigaction(signal, &action, std::ptr::null_mut());
+			}
+		}
+	});
+}
+
+/// Stop like on a signal, since the sink is gone and nothing sent gets anywhere anymore.
+pub(crate) fn sink_gone() {
+	tracing::info!("The sink went away, stopping");
+	stop();
+}
+
+fn stop() {
+	signalled_tx().send_replace(true);
+	if let Some(cleanup) = CLEANUP.lock().unwrap().take() {
+		cleanup();
+	}
+}
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+	// nothing but async-signal-safe calls in here
+	if SIGNALS.fetch_add(1, Ordering::SeqCst) > 0 {
+		unsafe { libc::_exit(128 + signal) };
+	}
+	let byte = 0_u8;
+	unsafe { libc::write(PIPE.load(Ordering::SeqCst), (&byte as *const u8).cast(), 1) };
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_signalled() {
+	install();
+	let waiting = tokio::spawn(signalled());
+	unsafe { libc::raise(libc::SIGTERM) };
+	tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+		.await
+		.unwrap()
+		.unwrap();
+	// anyone else waiting from now on doesn't wait at all
+	signalled().await;
+}
@@ -0,0 +1,289 @@
+//! Carrying the stream over sockets instead of stdin/stdout.
+//!
+//! Frames are the same as on stdio: a `u32` BE payload length followed by the payload in the
+//! negotiated codec.
+//!
+//! A source that calls [`serve`] writes everything to whichever sink is connected to its
+//! [`IpcListener`] instead of stdout, and a sink that calls [`connect`] reads from an [`IpcStream`]
+//! instead of stdin. Unlike a pipe either end can come and go: a sink that connects is caught up
+//! with the hello, keymap and key repeat sent so far, and one whose source goes away gets a
+//! [`Message::ResetInput`] and connects again once it's back. What a sink sends back goes over
+//! the same socket instead of the [`reverse`](crate::reverse) channel.
+
+use crate::{
+	frame_from_message, handshake, heartbeat, message_from_frame, message_ref_from_frame,
+	read_pooled_frame, receiver::FrameQueue, reverse, Frame, Message, MessageRef,
+};
+use std::{
+	io::{self, ErrorKind},
+	os::unix::net::{UnixListener, UnixStream},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	},
+	thread,
+	time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, warn};
+
+// how often a sink tries to connect again while its source isn't there
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+// how long a source waits to accept again after it couldn't, e.g. out of file descriptors
+const ACCEPT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+static SERVING: AtomicBool = AtomicBool::new(false);
+static SINK: Mutex<Option<IpcStream>> = Mutex::new(None);
+// what a sink that connects partway through needs to make sense of the rest, by message name
+static CATCH_UP: Mutex<Vec<(&'static str, Vec<u8>)>> = Mutex::new(Vec::new());
+static SOURCE_NAME: Mutex<Option<String>> = Mutex::new(None);
+static SOURCE: Mutex<Option<IpcStream>> = Mutex::new(None);
+
+/// Where the socket called `name` lives, in `$XDG_RUNTIME_DIR` if it's set.
+pub fn socket_path(name: &str) -> PathBuf {
+	std::env::var_os("XDG_RUNTIME_DIR")
+		.map(PathBuf::from)
+		.unwrap_or_else(std::env::temp_dir)
+		.join(format!("stardust-input-{name}.sock"))
+}
+
+/// Read the next frame's payload, like [`crate::receive_frame_async_ipc`] does from stdin.
+pub async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
+	let length = reader.read_u32().await?;
+	let mut frame = vec![0_u8; length as usize];
+	reader.read_exact(&mut frame).await?;
+	Ok(frame)
+}
+
+/// Write a frame's payload with its length prefix, like [`crate::send_frame_ipc`] does to stdout.
+pub async fn write_frame(
+	writer: &mut (impl AsyncWrite + Unpin),
+	frame: &[u8],
+) -> std::io::Result<()> {
+	let length = u32::try_from(frame.len()).map_err(|_| ErrorKind::InvalidInput)?;
+	writer.write_all(&length.to_be_bytes()).await?;
+	writer.write_all(frame).await?;
+	writer.flush().await
+}
+
+/// The socket a source serves the stream on, see [`socket_path`]. Removed once dropped.
+#[derive(Debug)]
+pub struct IpcListener {
+	listener: UnixListener,
+	path: PathBuf,
+}
+impl IpcListener {
+	/// Listen on the socket called `name`, replacing one a source that didn't get to clean up
+	/// left behind.
+	pub fn bind(name: &str) -> io::Result<Self> {
+		let path = socket_path(name);
+		let _ = std::fs::remove_file(&path);
+		Ok(IpcListener {
+			listener: UnixListener::bind(&path)?,
+			path,
+		})
+	}
+
+	/// Wait for the next sink to connect.
+	pub fn accept(&self) -> io::Result<IpcStream> {
+		self.listener
+			.accept()
+			.map(|(stream, _)| IpcStream { stream })
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+}
+impl Drop for IpcListener {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.path);
+	}
+}
+
+/// A connection between a source and a sink, with the stream going down it and what the sink
+/// sends back going up it.
+#[derive(Debug)]
+pub struct IpcStream {
+	stream: UnixStream,
+}
+impl IpcStream {
+	/// Connect to the source serving the socket called `name`.
+	pub fn connect(name: &str) -> io::Result<Self> {
+		UnixStream::connect(socket_path(name)).map(|stream| IpcStream { stream })
+	}
+
+	/// Another handle to the same connection, e.g. to read on one thread and write on another.
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.stream.try_clone().map(|stream| IpcStream { stream })
+	}
+
+	pub fn read_frame(&mut self) -> io::Result<Vec<u8>> {
+		reverse::read_frame(&mut self.stream)
+	}
+
+	pub fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+		reverse::write_frame(&mut self.stream, frame)
+	}
+}
+
+/// Serve the stream on the socket called `name` from now on, instead of writing it to stdout.
+///
+/// Call it before anything is sent or [`reverse::listen`] is called, e.g. by
+/// [`accept_pauses`](crate::flow::accept_pauses). Only the sink that connected last is sent to.
+pub fn serve(name: &str) -> io::Result<()> {
+	let listener = IpcListener::bind(name)?;
+	info!("Serving input on {}", listener.path().display());
+	SERVING.store(true, Ordering::Relaxed);
+	thread::spawn(move || loop {
+		let mut stream = match listener.accept() {
+			Ok(stream) => stream,
+			Err(e) => {
+				warn!("Couldn't accept a sink: {e}");
+				thread::sleep(ACCEPT_RETRY_INTERVAL);
+				continue;
+			}
+		};
+		let Ok(mut upstream) = stream.try_clone() else {
+			continue;
+		};
+		thread::spawn(move || {
+			while let Ok(frame) = upstream.read_frame() {
+				reverse::handle_from_socket(&frame);
+			}
+		});
+		let mut sink = SINK.lock().unwrap();
+		// the last sink's hello back doesn't say anything about this one
+		handshake::sink_left();
+		let caught_up = CATCH_UP
+			.lock()
+			.unwrap()
+			.iter()
+			.try_for_each(|(_, frame)| stream.write_frame(frame));
+		if caught_up.is_ok() {
+			info!("A sink connected");
+			sink.replace(stream);
+		}
+	});
+	Ok(())
+}
+
+pub(crate) fn serving() -> bool {
+	SERVING.load(Ordering::Relaxed)
+}
+
+/// Write a frame to the sink that's connected, if there is one.
+pub(crate) fn write_to_sink(frame: &[u8]) {
+	let mut sink = SINK.lock().unwrap();
+	let catch_up_on = match message_ref_from_frame(frame) {
+		Ok(MessageRef::Hello { .. }) => Some("Hello"),
+		Ok(MessageRef::Keymap(_)) => Some("Keymap"),
+		Ok(MessageRef::KeyRepeat { .. }) => Some("KeyRepeat"),
+		_ => None,
+	};
+	if let Some(name) = catch_up_on {
+		let mut catch_up = CATCH_UP.lock().unwrap();
+		catch_up.retain(|(sent, _)| *sent != name);
+		// as a flexbuffer, since the next sink may not read what this one does
+		let frame =
+			message_from_frame(frame).map_or_else(|_| frame.to_vec(), |m| frame_from_message(&m));
+		catch_up.push((name, frame));
+	}
+	let Some(stream) = sink.as_mut() else {
+		return;
+	};
+	heartbeat::write_started();
+	let written = stream.write_frame(frame);
+	heartbeat::write_finished();
+	if let Err(e) = written {
+		info!("The sink went away: {e}");
+		sink.take();
+	}
+}
+
+/// Read the stream from the source serving the socket called `name` from now on, instead of
+/// from stdin. Call it before receiving anything.
+pub fn connect(name: &str) {
+	SOURCE_NAME.lock().unwrap().replace(name.to_string());
+}
+
+pub(crate) fn source_name() -> Option<String> {
+	SOURCE_NAME.lock().unwrap().clone()
+}
+
+/// Read frames from the source, connecting to it again whenever it goes away.
+pub(crate) fn read_source_frames(name: String, frames: FrameQueue) {
+	let reset = frame_from_message(&Message::ResetInput);
+	loop {
+		let Ok(mut stream) = IpcStream::connect(&name) else {
+			thread::sleep(RECONNECT_INTERVAL);
+			continue;
+		};
+		info!(
+			"Connected to the source on {}",
+			socket_path(&name).display()
+		);
+		let upstream = match stream.try_clone() {
+			Ok(upstream) => upstream,
+			Err(e) => {
+				warn!("Couldn't share the connection to the source, reconnecting: {e}");
+				thread::sleep(RECONNECT_INTERVAL);
+				continue;
+			}
+		};
+		SOURCE.lock().unwrap().replace(upstream);
+		while let Ok(frame) = read_pooled_frame(&mut stream.stream) {
+			if !frames.push(Ok(frame)) {
+				return;
+			}
+		}
+		warn!("Lost the source, waiting for it to come back");
+		SOURCE.lock().unwrap().take();
+		// nothing stays held down by a source that's gone
+		if !frames.push(Ok(Frame { buf: reset.clone() })) {
+			return;
+		}
+	}
+}
+
+/// Send a frame back to the source the sink is connected to.
+pub(crate) fn send_upstream(frame: &[u8]) -> io::Result<()> {
+	match SOURCE.lock().unwrap().as_mut() {
+		Some(stream) => stream.write_frame(frame),
+		None => Err(ErrorKind::NotConnected.into()),
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_socket_frames() {
+	let (mut a, mut b) = tokio::io::duplex(64);
+	let frames = [b"uwu".to_vec(), Vec::new(), vec![7; 300]];
+	let writer = async {
+		for frame in &frames {
+			write_frame(&mut a, frame).await.unwrap();
+		}
+	};
+	let reader = async {
+		for frame in &frames {
+			assert_eq!(&read_frame(&mut b).await.unwrap(), frame);
+		}
+	};
+	tokio::join!(writer, reader);
+}
+
+#[test]
+fn test_ipc_stream() {
+	let name = format!("test-{}", std::process::id());
+	let listener = IpcListener::bind(&name).unwrap();
+	let mut sink = IpcStream::connect(&name).unwrap();
+	let mut source = listener.accept().unwrap();
+	source.write_frame(b"uwu").unwrap();
+	assert_eq!(sink.read_frame().unwrap(), b"uwu");
+	sink.write_frame(b"owo").unwrap();
+	assert_eq!(source.read_frame().unwrap(), b"owo");
+	let path = listener.path().to_path_buf();
+	drop(listener);
+	assert!(!path.exists());
+}
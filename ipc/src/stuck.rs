@@ -0,0 +1,83 @@
+//! Healing keys, mouse buttons and gamepad buttons that were never released, e.g. because a frame
+//! got lost or the source crashed, instead of leaving them held downstream forever.
+//!
+//! Off unless [`release_stuck_input_after`] is called. After that, anything sent through
+//! [`send_input_ipc`](crate::send_input_ipc) that stays held for longer than the timeout is
+//! released, and the real release is dropped when it turns up.
+
+use crate::{ButtonBlot, Error, IpcSender, Message};
+use std::{
+	sync::{Mutex, OnceLock},
+	thread,
+	time::Duration,
+};
+use tracing::warn;
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Release keys, mouse buttons and gamepad buttons held for longer than `timeout` from now on.
+/// Call it before sending anything, since only what's pressed afterwards is timed.
+pub fn release_stuck_input_after(timeout: Duration) {
+	if TIMEOUT.set(timeout).is_err() {
+		return;
+	}
+	thread::spawn(move || loop {
+		thread::sleep(timeout.min(Duration::from_secs(1)));
+		let sender = IpcSender::stdout();
+		let sweep = |blot: &Mutex<Option<ButtonBlot>>| {
+			blot.lock()
+				.unwrap()
+				.as_mut()
+				.map(|blot| blot.release_older_than(timeout))
+				.unwrap_or_default()
+		};
+		// gathered first so the blots aren't locked while the releases wait to be written
+		let keys = sweep(&sender.key_blot);
+		let buttons = sweep(&sender.mouse_blot);
+		let pads: Vec<_> = sender
+			.gamepad_blots
+			.lock()
+			.unwrap()
+			.iter_mut()
+			.map(|(id, blot)| (*id, blot.release_older_than(timeout)))
+			.collect();
+		let mut releases = Vec::new();
+		for (keycode, pressed) in keys {
+			warn!("Key {keycode} was held for over {timeout:?}, releasing it");
+			releases.push(Message::Key { keycode, pressed });
+		}
+		for (button, pressed) in buttons {
+			warn!("Mouse button {button} was held for over {timeout:?}, releasing it");
+			releases.push(Message::MouseButton { button, pressed });
+		}
+		for (id, buttons) in pads {
+			for (button, pressed) in buttons {
+				warn!(
+					"Button {button} on gamepad {id} was held for over {timeout:?}, releasing it"
+				);
+				releases.push(Message::GamepadButton {
+					id,
+					button,
+					pressed,
+				});
+			}
+		}
+		for release in releases {
+			match sender.send_swept(release) {
+				Ok(()) => (),
+				// nothing is held down in a sink that's gone
+				Err(Error::Disconnected) => return,
+				Err(e) => warn!("Couldn't release stuck input: {e}"),
+			}
+		}
+	});
+}
+
+/// A blot for [`send_input_ipc`](crate::send_input_ipc), timed if stuck input is being released.
+pub(crate) fn new_blot() -> ButtonBlot {
+	if TIMEOUT.get().is_some() {
+		ButtonBlot::timed()
+	} else {
+		ButtonBlot::default()
+	}
+}
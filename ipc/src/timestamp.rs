@@ -0,0 +1,37 @@
+//! When input happened, as sources see it, for measuring latency down the pipeline and replaying a
+//! stream at the pace it was made.
+//!
+//! A timestamp is microseconds on `CLOCK_MONOTONIC`, the clock libinput stamps its events with, so
+//! it only means something on the machine it was made on. It goes in the frame's [`Stamp`](crate::Stamp), which
+//! filters that pass frames on untouched keep, and ones that decode and send messages again drop
+//! unless they stamp them themselves.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STAMP_SENDS: AtomicBool = AtomicBool::new(false);
+
+/// Microseconds on `CLOCK_MONOTONIC` right now.
+pub fn now() -> u64 {
+	let mut time = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+	unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+	time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1000
+}
+
+/// Stamp everything sent with [`send_input_ipc`](crate::send_input_ipc) with when it was sent,
+/// for sources that don't know when their input happened any better than that.
+pub fn stamp_sends() {
+	STAMP_SENDS.store(true, Ordering::Relaxed);
+}
+
+/// The timestamp for a message sent without one.
+pub(crate) fn default() -> Option<u64> {
+	STAMP_SENDS.load(Ordering::Relaxed).then(now)
+}
+
+/// The timestamp in a frame, if its source gave it one.
+pub fn of_frame(frame: &[u8]) -> Option<u64> {
+	crate::stamp_from_frame(frame).ts
+}
@@ -0,0 +1,157 @@
+//! Keymap ids the Stardust server gave out, kept on disk so sinks don't register the same keymap
+//! every time they or their source start.
+//!
+//! An id only means something to the server that gave it out, so the cache is for one server
+//! instance, told apart by its socket: a restarted server makes a new one. Ids for any other
+//! instance are dropped the next time the cache is written.
+
+use rustc_hash::FxHasher;
+use std::{
+	env, fs,
+	hash::Hasher,
+	os::unix::fs::MetadataExt,
+	path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// The keymaps registered with the running server and their ids.
+#[derive(Debug)]
+pub struct KeymapCache {
+	/// Where to write the cache, `None` when there's nowhere to or no server to tell apart.
+	path: Option<PathBuf>,
+	instance: String,
+	/// Keymap hash and id.
+	entries: Vec<(u64, u64)>,
+}
+impl KeymapCache {
+	/// The cache in `$XDG_CACHE_HOME/stardust-input/keymaps` for the server fusion connects to.
+	///
+	/// Without a cache directory or a server socket it still works, it just starts out empty and
+	/// is never saved.
+	pub fn open() -> Self {
+		let cache_home = env::var_os("XDG_CACHE_HOME")
+			.map(PathBuf::from)
+			.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+		match (cache_home, server_instance()) {
+			(Some(cache_home), Some(instance)) => {
+				Self::at(cache_home.join("stardust-input/keymaps"), instance)
+			}
+			_ => KeymapCache {
+				path: None,
+				instance: String::new(),
+				entries: Vec::new(),
+			},
+		}
+	}
+
+	/// The cache in the file at `path`, for the server instance `instance`.
+	pub fn at(path: PathBuf, instance: String) -> Self {
+		let entries = fs::read_to_string(&path)
+			.map(|cache| parse(&cache, &instance))
+			.unwrap_or_default();
+		KeymapCache {
+			path: Some(path),
+			instance,
+			entries,
+		}
+	}
+
+	/// The id the server gave `keymap` before, if it did.
+	pub fn get(&self, keymap: &str) -> Option<u64> {
+		let hash = hash(keymap);
+		self.entries
+			.iter()
+			.find(|(entry, _)| *entry == hash)
+			.map(|(_, id)| *id)
+	}
+
+	/// Remember the server gave `keymap` the id `id`, and save the cache.
+	pub fn insert(&mut self, keymap: &str, id: u64) {
+		let hash = hash(keymap);
+		self.entries.retain(|(entry, _)| *entry != hash);
+		self.entries.push((hash, id));
+		let Some(path) = &self.path else {
+			return;
+		};
+		let cache = self
+			.entries
+			.iter()
+			.map(|(hash, id)| format!("{}\t{hash:016x}\t{id}\n", self.instance))
+			.collect::<String>();
+		if let Err(e) = path
+			.parent()
+			.map_or(Ok(()), fs::create_dir_all)
+			.and_then(|_| fs::write(path, cache))
+		{
+			warn!("Couldn't save the keymap cache to {}: {e}", path.display());
+		}
+	}
+}
+
+/// The entries in `cache` for `instance`, one `instance\thash\tid` line each.
+fn parse(cache: &str, instance: &str) -> Vec<(u64, u64)> {
+	cache
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			if fields.next()? != instance {
+				return None;
+			}
+			let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+			let id = fields.next()?.parse().ok()?;
+			Some((hash, id))
+		})
+		.collect()
+}
+
+// FxHash rather than the std hasher, which is seeded differently every run
+fn hash(keymap: &str) -> u64 {
+	let mut hasher = FxHasher::default();
+	hasher.write(keymap.as_bytes());
+	hasher.write_usize(keymap.len());
+	hasher.finish()
+}
+
+/// The server's socket along with when it was made, which changes when the server restarts.
+fn server_instance() -> Option<String> {
+	// where fusion connects to
+	let runtime_dir = PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?);
+	let name = env::var("STARDUST_INSTANCE").unwrap_or_else(|_| "stardust-0".to_string());
+	instance_of(&runtime_dir.join(name))
+}
+
+fn instance_of(socket: &Path) -> Option<String> {
+	let metadata = fs::metadata(socket).ok()?;
+	Some(format!(
+		"{}:{}:{}.{}",
+		socket.display(),
+		metadata.ino(),
+		metadata.ctime(),
+		metadata.ctime_nsec()
+	))
+}
+
+#[test]
+fn test_cache() {
+	let dir = env::temp_dir().join(format!("keymap-cache-{}", std::process::id()));
+	let path = dir.join("keymaps");
+	let mut cache = KeymapCache::at(path.clone(), "server-a".to_string());
+	assert_eq!(cache.get("xkb_keymap { us }"), None);
+	cache.insert("xkb_keymap { us }", 1);
+	cache.insert("xkb_keymap { de }", 2);
+	cache.insert("xkb_keymap { us }", 3);
+	assert_eq!(cache.get("xkb_keymap { us }"), Some(3));
+
+	// read back by the next sink
+	let cache = KeymapCache::at(path.clone(), "server-a".to_string());
+	assert_eq!(cache.get("xkb_keymap { us }"), Some(3));
+	assert_eq!(cache.get("xkb_keymap { de }"), Some(2));
+	assert_eq!(cache.get("xkb_keymap { fr }"), None);
+
+	// a restarted server gave out none of them, and the old ones are dropped once it's written
+	let mut cache = KeymapCache::at(path.clone(), "server-b".to_string());
+	assert_eq!(cache.get("xkb_keymap { us }"), None);
+	cache.insert("xkb_keymap { fr }", 1);
+	assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+	fs::remove_dir_all(dir).unwrap();
+}
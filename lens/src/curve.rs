@@ -0,0 +1,218 @@
+use config::{Config, Section};
+use input_event_codes::{ABS_BRAKE, ABS_GAS, ABS_RX, ABS_RY, ABS_RZ, ABS_X, ABS_Y, ABS_Z};
+use ipc::Message;
+use logging::warn;
+use mint::Vector2;
+
+/// How one analog input gets calibrated, from the `lens` section of the config.
+///
+/// The offset is taken off first, then anything closer to the center than the deadzone is dropped
+/// and the rest is stretched so it starts from nothing just past it. The curve is an exponent on
+/// how far along the range a value is, so above 1 makes small movements finer and below 1
+/// coarser, with the range itself staying where it was.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curve {
+	offset: [f32; 2],
+	deadzone: f32,
+	range: f32,
+	exponent: f32,
+}
+impl Curve {
+	fn read(config: &Section, input: &str) -> Self {
+		let offset = match config.get_list::<f32>(&format!("{input}-offset"))[..] {
+			[] => [0.0, 0.0],
+			[both] => [both, both],
+			[x, y, ..] => [x, y],
+		};
+		let deadzone = config.get(&format!("{input}-deadzone"), 0.0_f32).max(0.0);
+		let mut range = config.get(&format!("{input}-range"), 1.0_f32);
+		if range <= deadzone {
+			warn!("Ignoring {input}-range {range}, it has to be bigger than the deadzone");
+			range = deadzone + 1.0;
+		}
+		Curve {
+			offset,
+			deadzone,
+			range,
+			exponent: config.get(&format!("{input}-curve"), 1.0_f32).max(0.0),
+		}
+	}
+
+	/// The calibrated value, or nothing if it's inside the deadzone.
+	pub fn apply(&self, v: Vector2<f32>) -> Option<Vector2<f32>> {
+		let x = v.x - self.offset[0];
+		let y = v.y - self.offset[1];
+		// radial so diagonals aren't cut off more than straight movement
+		let magnitude = x.hypot(y);
+		if magnitude <= self.deadzone {
+			return None;
+		}
+		let along = (magnitude - self.deadzone) / (self.range - self.deadzone);
+		let scale = self.range * along.powf(self.exponent) / magnitude;
+		Some([x * scale, y * scale].into())
+	}
+
+	/// The calibrated position of an absolute axis, like one of a stick's, using the `axis`th
+	/// offset. Unlike with motion the deadzone is where it rests, so values in it come out as 0.
+	pub fn apply_axis(&self, v: f32, axis: usize) -> f32 {
+		let v = v - self.offset[axis];
+		if v.abs() <= self.deadzone {
+			return 0.0;
+		}
+		let along = (v.abs() - self.deadzone) / (self.range - self.deadzone);
+		self.range * along.powf(self.exponent) * v.signum()
+	}
+}
+
+/// The curve for each analog input in the stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curves {
+	mouse: Curve,
+	scroll: Curve,
+	left_stick: Curve,
+	right_stick: Curve,
+	trigger: Curve,
+	/// A tablet tool's pressure.
+	pressure: Curve,
+}
+impl Curves {
+	pub fn read(config: &Config) -> Self {
+		let config = config.section("lens");
+		Curves {
+			mouse: Curve::read(&config, "mouse"),
+			scroll: Curve::read(&config, "scroll"),
+			left_stick: Curve::read(&config, "left-stick"),
+			right_stick: Curve::read(&config, "right-stick"),
+			trigger: Curve::read(&config, "trigger"),
+			pressure: Curve::read(&config, "pressure"),
+		}
+	}
+
+	/// The message with its analog values calibrated, or nothing if they all fell in the deadzone.
+	pub fn handle(&self, message: Message) -> Option<Message> {
+		match message {
+			Message::MouseMove(delta) => self.mouse.apply(delta).map(Message::MouseMove),
+			Message::MouseAxisContinuous(scroll) => {
+				self.scroll.apply(scroll).map(Message::MouseAxisContinuous)
+			}
+			Message::GamepadAxis { id, axis, value } => {
+				let (curve, index) = match axis {
+					ABS_X!() => (&self.left_stick, 0),
+					ABS_Y!() => (&self.left_stick, 1),
+					ABS_RX!() => (&self.right_stick, 0),
+					ABS_RY!() => (&self.right_stick, 1),
+					ABS_Z!() | ABS_RZ!() | ABS_GAS!() | ABS_BRAKE!() => (&self.trigger, 0),
+					// d-pads only ever rest or go all the way
+					_ => return Some(Message::GamepadAxis { id, axis, value }),
+				};
+				Some(Message::GamepadAxis {
+					id,
+					axis,
+					value: curve.apply_axis(value, index),
+				})
+			}
+			Message::TabletAxis {
+				position,
+				pressure,
+				tilt,
+			} => Some(Message::TabletAxis {
+				position,
+				pressure: self.pressure.apply_axis(pressure, 0),
+				tilt,
+			}),
+			// discrete scrolling comes in whole steps, there's nothing in between to calibrate
+			message => Some(message),
+		}
+	}
+}
+
+#[test]
+fn test_curves() {
+	let curves = Curves::read(
+		&Config::parse(
+			r#"
+			lens {
+				mouse-deadzone 0.5
+				mouse-range 2.5
+				scroll-offset 0 0.25
+				scroll-curve 2
+				scroll-range 2
+			}
+			"#,
+		)
+		.unwrap(),
+	);
+	let motion = |x, y| Message::MouseMove([x, y].into());
+	let scroll = |x, y| Message::MouseAxisContinuous([x, y].into());
+	assert_eq!(curves.handle(motion(0.3, -0.3)), None);
+	assert_eq!(curves.handle(motion(0.0, 1.5)), Some(motion(0.0, 1.25)));
+	assert_eq!(curves.handle(motion(-2.5, 0.0)), Some(motion(-2.5, 0.0)));
+	assert_eq!(curves.handle(scroll(0.0, 0.25)), None);
+	assert_eq!(curves.handle(scroll(1.0, 0.25)), Some(scroll(0.5, 0.0)));
+	assert_eq!(curves.handle(scroll(0.0, 2.25)), Some(scroll(0.0, 2.0)));
+	assert_eq!(
+		curves.handle(Message::MouseAxisDiscrete([0.0, 1.0].into())),
+		Some(Message::MouseAxisDiscrete([0.0, 1.0].into()))
+	);
+
+	// nothing set changes nothing
+	let curves = Curves::read(&Config::default());
+	assert_eq!(curves.handle(motion(0.0, -3.0)), Some(motion(0.0, -3.0)));
+}
+
+#[test]
+fn test_gamepad_and_tablet_curves() {
+	use input_event_codes::ABS_HAT0X;
+
+	let curves = Curves::read(
+		&Config::parse(
+			r#"
+			lens {
+				left-stick-offset 0 0.125
+				left-stick-deadzone 0.25
+				trigger-curve 2
+				pressure-deadzone 0.125
+				pressure-range 0.875
+			}
+			"#,
+		)
+		.unwrap(),
+	);
+	let axis = |axis, value| Message::GamepadAxis { id: 0, axis, value };
+	assert_eq!(
+		curves.handle(axis(ABS_X!(), 0.15)),
+		Some(axis(ABS_X!(), 0.0))
+	);
+	assert_eq!(
+		curves.handle(axis(ABS_Y!(), 0.25)),
+		Some(axis(ABS_Y!(), 0.0))
+	);
+	assert_eq!(
+		curves.handle(axis(ABS_X!(), -0.625)),
+		Some(axis(ABS_X!(), -0.5))
+	);
+	assert_eq!(
+		curves.handle(axis(ABS_Y!(), 1.125)),
+		Some(axis(ABS_Y!(), 1.0))
+	);
+	// the right stick has nothing set
+	assert_eq!(
+		curves.handle(axis(ABS_RX!(), 0.15)),
+		Some(axis(ABS_RX!(), 0.15))
+	);
+	assert_eq!(
+		curves.handle(axis(ABS_RZ!(), 0.5)),
+		Some(axis(ABS_RZ!(), 0.25))
+	);
+	assert_eq!(
+		curves.handle(axis(ABS_HAT0X!(), -1.0)),
+		Some(axis(ABS_HAT0X!(), -1.0))
+	);
+	let pressure = |pressure| Message::TabletAxis {
+		position: [0.5, 0.5].into(),
+		pressure,
+		tilt: [10.0, 0.0].into(),
+	};
+	assert_eq!(curves.handle(pressure(0.0625)), Some(pressure(0.0)));
+	assert_eq!(curves.handle(pressure(0.5)), Some(pressure(0.4375)));
+}
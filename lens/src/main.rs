@@ -0,0 +1,70 @@
+mod curve;
+
+use clap::Parser;
+use color_eyre::Result;
+use config::reload::Live;
+use curve::Curves;
+use ipc::{
+	message_from_frame, receive_frame_async_ipc, send_frame_async_ipc, send_input_async_ipc, Error,
+	Message,
+};
+use logging::{warn, LogArgs};
+use std::io::IsTerminal;
+
+#[derive(Debug, Parser)]
+#[command(
+	about = "Calibrate analog input with offsets, deadzones and response curves, between a source and a sink"
+)]
+struct Args {
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() || std::io::stdout().is_terminal() {
+		panic!("You need to put this between a source and a sink e.g. `eclipse | lens | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	config::reload::watch();
+	// so the sink can still pause the source through this
+	ipc::reverse::relay();
+
+	let mut curves = Live::new(Curves::read);
+	loop {
+		tokio::select! {
+			frame = receive_frame_async_ipc() => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				let message = match message_from_frame(&frame) {
+					Ok(message) => message,
+					Err(_) => {
+						match send_frame_async_ipc(&frame).await {
+							Ok(()) => (),
+							Err(Error::Disconnected) => break,
+							Err(e) => warn!("{e}"),
+						}
+						continue;
+					}
+				};
+				let disconnect = message == Message::Disconnect;
+				if let Some(message) = curves.get().handle(message) {
+					match send_input_async_ipc(message).await {
+						Ok(()) => (),
+						Err(Error::Disconnected) => break,
+						Err(e) => warn!("{e}"),
+					}
+				}
+				if disconnect {
+					return Ok(());
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
@@ -0,0 +1,44 @@
+//! The tracing setup every tool shares, so they all take the same flags and log the same way.
+
+use clap::Args;
+use std::{fs::File, path::PathBuf, sync::Mutex};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+pub use tracing::{debug, error, info, info_span, trace, warn};
+
+#[derive(Debug, Clone, Args)]
+pub struct LogArgs {
+	/// Least severe messages to log: error, warn, info, debug or trace, or a filter like `info,mux=debug`. RUST_LOG overrides this
+	#[arg(long, default_value = "info", global = true)]
+	pub log_level: String,
+	/// Write logs to this file instead of stderr
+	#[arg(long, value_name = "FILE", global = true)]
+	pub log_file: Option<PathBuf>,
+}
+
+/// Start logging, once at the start of main.
+pub fn init(args: &LogArgs) -> std::io::Result<()> {
+	let filter = EnvFilter::try_from_default_env()
+		.or_else(|_| EnvFilter::try_new(&args.log_level))
+		.unwrap_or_else(|e| {
+			eprintln!("Invalid --log-level {:?}, using info: {e}", args.log_level);
+			EnvFilter::new("info")
+		});
+	let registry = tracing_subscriber::registry().with(filter);
+	#[cfg(feature = "tracy")]
+	let registry = registry.with(tracing_tracy::TracyLayer::new());
+	match &args.log_file {
+		Some(path) => registry
+			.with(
+				fmt::layer()
+					.with_ansi(false)
+					.with_writer(Mutex::new(File::create(path)?)),
+			)
+			.init(),
+		// stdout is the input stream for most tools, so logs never go there
+		None => registry
+			.with(fmt::layer().with_writer(std::io::stderr))
+			.init(),
+	}
+	Ok(())
+}
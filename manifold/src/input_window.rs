@@ -1,15 +1,14 @@
 use as_raw_xcb_connection::{xcb_connection_t, ValidConnection};
+use config::{reload::Live, Config};
+use ephemeris::wayland::compositor_keymap;
 use glam::{vec2, Vec2};
-use ipc::{send_input_ipc, Message};
+use ipc::{send_input_ipc, Error, Message};
+use logging::{info, warn};
 use map_range::MapRange;
 use softbuffer::Surface;
 use std::process::exit;
 use std::{num::NonZeroU32, rc::Rc};
-use wayland_client::{
-	backend::Backend,
-	globals::registry_queue_init,
-	protocol::wl_seat,
-};
+use wayland_client::backend::Backend;
 use winit::raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
 use winit::{
 	dpi::{LogicalPosition, Size},
@@ -17,8 +16,8 @@ use winit::{
 		DeviceEvent, ElementState, Event, KeyEvent, Modifiers, MouseButton, MouseScrollDelta,
 		WindowEvent,
 	},
-	event_loop::{EventLoop, EventLoopWindowTarget},
-	keyboard::Key,
+	event_loop::EventLoop,
+	keyboard::{Key, NamedKey},
 	platform::scancode::PhysicalKeyExtScancode,
 	raw_window_handle::{WaylandDisplayHandle, XcbDisplayHandle},
 	window::{CursorGrabMode, Window, WindowBuilder},
@@ -29,7 +28,7 @@ use xkbcommon::xkb::{
 	Keymap, KEYMAP_COMPILE_NO_FLAGS, KEYMAP_FORMAT_TEXT_V1,
 };
 
-use crate::wayland::WlHandler;
+use crate::layouts::{self, Layout};
 
 fn line_dist(p: Vec2, l1: Vec2, l2: Vec2, thickness: f32) -> f32 {
 	let pa = p - l1;
@@ -45,9 +44,40 @@ pub struct InputWindow {
 	mouse_delta: Option<LogicalPosition<f64>>,
 	grabbed: bool,
 	modifiers: Modifiers,
+	settings: Live<Settings>,
+	/// Read from the registry the first time the layout picker opens.
+	layouts: Vec<Layout>,
+	/// The layout the picker is on while it's open.
+	picking: Option<usize>,
+	/// The layout last picked, where the picker opens next time.
+	picked: usize,
+	/// The handler the sink says the pointer is on, and whether it captured the pointer.
+	hover: Option<(u64, bool)>,
+	/// The desktop's clipboard, `None` if it couldn't be opened.
+	clipboard: Option<arboard::Clipboard>,
+	/// The text last sent from the clipboard, so the same copy isn't sent again.
+	shared_text: Option<String>,
+}
+
+struct Settings {
+	// pressed with super to let go of the cursor
+	release_key: String,
+	// pressed with super to pick another keyboard layout
+	layout_key: String,
+	mouse_sensitivity: f32,
+}
+impl Settings {
+	fn read(config: &Config) -> Self {
+		let config = config.section("manifold");
+		Settings {
+			release_key: config.get("release-key", "q".to_string()),
+			layout_key: config.get("layout-key", "l".to_string()),
+			mouse_sensitivity: config.get("mouse-sensitivity", 1.0),
+		}
+	}
 }
 impl InputWindow {
-	pub fn new(event_loop: &EventLoop<()>) -> Self {
+	pub fn new(event_loop: &EventLoop<Message>) -> Self {
 		let size = Size::Logical([128, 128].into());
 		let window = Rc::new(
 			WindowBuilder::new()
@@ -58,28 +88,25 @@ impl InputWindow {
 		);
 
 		let xcb_context = xkbcommon::xkb::Context::new(0);
+		let mut repeat = None;
 		let keymap = match window.display_handle().map(|handle| handle.as_raw()) {
-			Ok(RawDisplayHandle::Wayland(WaylandDisplayHandle { display, .. })) => unsafe {
-				let backend = Backend::from_foreign_display(
-					display.as_ptr() as *mut wayland_sys::client::wl_display
-				);
+			Ok(RawDisplayHandle::Wayland(WaylandDisplayHandle { display, .. })) => {
+				let backend = unsafe {
+					Backend::from_foreign_display(
+						display.as_ptr() as *mut wayland_sys::client::wl_display
+					)
+				};
 				let conn = wayland_client::Connection::from_backend(backend);
-				let (globals, mut queue) = registry_queue_init::<WlHandler>(&conn).unwrap();
-				let qh = queue.handle();
-				let _seat: wl_seat::WlSeat = globals.bind(&qh, 7..=8, ()).unwrap();
-				let mut wl_handler = WlHandler { keymap: None };
-				eprintln!("Waiting for keymap from compositor");
-				while wl_handler.keymap.is_none() {
-					queue.roundtrip(&mut wl_handler).unwrap();
-				}
+				let compositor = compositor_keymap(&conn).unwrap();
+				repeat = compositor.repeat;
 				Keymap::new_from_string(
 					&xcb_context,
-					String::from_utf8(wl_handler.keymap.unwrap()).unwrap(),
+					compositor.keymap,
 					KEYMAP_FORMAT_TEXT_V1,
 					KEYMAP_COMPILE_NO_FLAGS,
 				)
 				.unwrap()
-			},
+			}
 			Ok(RawDisplayHandle::Xcb(XcbDisplayHandle {
 				connection: Some(conn),
 				..
@@ -95,10 +122,6 @@ impl InputWindow {
 			},
 			_ => Keymap::new_from_names(&xcb_context, "", "", "", "", None, 0).unwrap(),
 		};
-		send_input_ipc(Message::Keymap(
-			keymap.get_as_string(XKB_KEYMAP_FORMAT_TEXT_V1),
-		));
-
 		let context = softbuffer::Context::new(window.clone()).unwrap();
 		let surface = softbuffer::Surface::new(&context, window.clone()).unwrap();
 
@@ -108,17 +131,45 @@ impl InputWindow {
 			mouse_delta: None,
 			grabbed: true,
 			modifiers: Modifiers::default(),
+			settings: Live::new(Settings::read),
+			layouts: Vec::new(),
+			picking: None,
+			picked: 0,
+			hover: None,
+			clipboard: arboard::Clipboard::new()
+				.map_err(|e| warn!("Can't share the clipboard: {e}"))
+				.ok(),
+			shared_text: None,
 		};
 
 		input_window.set_grab(false);
+		input_window.send(Message::Keymap(
+			keymap.get_as_string(XKB_KEYMAP_FORMAT_TEXT_V1),
+		));
+		// only wayland says how keys repeat
+		if let Some((rate, delay)) = repeat {
+			input_window.send(Message::KeyRepeat {
+				rate: rate.max(0) as u32,
+				delay: delay.max(0) as u32,
+			});
+		}
 		input_window
 	}
 
-	pub fn handle_event(&mut self, event: Event<()>, elwt: &EventLoopWindowTarget<()>) {
+	/// Send to the sink, and quit once it's gone since nothing here gets anywhere anymore.
+	fn send(&mut self, message: Message) {
+		match send_input_ipc(message) {
+			Ok(()) => (),
+			Err(Error::Disconnected) => self.quit(),
+			Err(e) => warn!("{e}"),
+		}
+	}
+
+	pub fn handle_event(&mut self, event: Event<Message>) {
 		match event {
 			Event::WindowEvent { window_id, event } if window_id == self.window.id() => match event
 			{
-				WindowEvent::CloseRequested => elwt.exit(),
+				WindowEvent::CloseRequested => self.quit(),
 				_ => self.handle_window_event(event),
 			},
 			Event::DeviceEvent {
@@ -130,14 +181,28 @@ impl InputWindow {
 			Event::AboutToWait => {
 				self.redraw();
 			}
+			Event::UserEvent(Message::Hover { handler, captured }) => {
+				self.handle_hover(handler, captured)
+			}
+			// ctrl+c
+			Event::UserEvent(Message::Disconnect) => self.quit(),
 			_ => {}
 		}
 	}
 
+	fn quit(&mut self) -> ! {
+		self.set_grab(false);
+		ipc::shutdown::end_stream();
+		exit(0)
+	}
+
 	fn handle_mouse_delta(&mut self, delta: (f64, f64)) {
 		if self.grabbed {
 			self.mouse_delta = Some(LogicalPosition::new(delta.0, delta.1));
-			send_input_ipc(Message::MouseMove([delta.0 as f32, delta.1 as f32].into()));
+			let sensitivity = self.settings.get().mouse_sensitivity;
+			self.send(Message::MouseMove(
+				(vec2(delta.0 as f32, delta.1 as f32) * sensitivity).into(),
+			));
 		} else {
 			self.mouse_delta = None;
 		};
@@ -148,31 +213,30 @@ impl InputWindow {
 			WindowEvent::MouseInput { state, button, .. } => self.handle_mouse_input(state, button),
 			WindowEvent::MouseWheel { delta, .. } => match delta {
 				MouseScrollDelta::LineDelta(x, y) => {
-					send_input_ipc(Message::MouseAxisContinuous(vec2(x, y).into()))
+					self.send(Message::MouseAxisContinuous(vec2(x, y).into()))
 				}
-				MouseScrollDelta::PixelDelta(p) => send_input_ipc(Message::MouseAxisDiscrete(
+				MouseScrollDelta::PixelDelta(p) => self.send(Message::MouseAxisDiscrete(
 					vec2(p.x as f32, p.y as f32).into(),
 				)),
 			},
+			// only ungrabbed clicks and scrolling are the desktop's
+			WindowEvent::TouchpadMagnify { delta, .. } if self.grabbed => {
+				self.send(Message::Magnify(delta as f32))
+			}
+			WindowEvent::TouchpadRotate { delta, .. } if self.grabbed => {
+				self.send(Message::Rotate(delta))
+			}
+			WindowEvent::SmartMagnify { .. } if self.grabbed => self.send(Message::SmartMagnify),
 			WindowEvent::KeyboardInput { event, .. } => self.handle_keyboard_input(event),
 			WindowEvent::ModifiersChanged(state) => self.modifiers = state,
 			WindowEvent::CursorEntered { .. } => {
-				send_input_ipc(Message::ResetInput);
+				self.send(Message::ResetInput);
 			}
 			WindowEvent::CursorLeft { .. } => {
-				send_input_ipc(Message::ResetInput);
+				self.send(Message::ResetInput);
 			}
 
-			WindowEvent::Destroyed => {
-				send_input_ipc(Message::ResetInput);
-				send_input_ipc(Message::Disconnect);
-				exit(0);
-			}
-			WindowEvent::CloseRequested => {
-				send_input_ipc(Message::ResetInput);
-				send_input_ipc(Message::Disconnect);
-				exit(0);
-			}
+			WindowEvent::Destroyed => self.quit(),
 			WindowEvent::RedrawRequested => {
 				self.redraw();
 			}
@@ -209,8 +273,8 @@ impl InputWindow {
 			};
 			for x in 0..(thickness as u32) {
 				for y in 0..(thickness as u32) {
-					let x = (window_center.x - thickness/2.0 + x as f32) as u32;
-					let y = (window_center.y - thickness/2.0 + y as f32) as u32;
+					let x = (window_center.x - thickness / 2.0 + x as f32) as u32;
+					let y = (window_center.y - thickness / 2.0 + y as f32) as u32;
 					buffer[(x + (y * window_size.width)) as usize] = color;
 				}
 			}
@@ -248,14 +312,27 @@ impl InputWindow {
 			MouseButton::Forward => input_event_codes::BTN_FORWARD!(),
 			MouseButton::Other(n) => n,
 		};
-		send_input_ipc(Message::MouseButton {
+		self.send(Message::MouseButton {
 			button: btn_id as u32,
 			pressed: state == ElementState::Pressed,
 		})
 	}
 
 	fn handle_keyboard_input(&mut self, input: KeyEvent) {
-		if input.logical_key.as_ref() == Key::Character("q")
+		if self.picking.is_some() {
+			if input.state == ElementState::Pressed {
+				self.handle_picker_key(input.logical_key);
+			}
+			return;
+		}
+		if input.logical_key.as_ref() == Key::Character(self.settings.get().layout_key.as_str())
+			&& input.state == ElementState::Released
+			&& self.modifiers.state().super_key()
+		{
+			self.open_picker();
+			return;
+		}
+		if input.logical_key.as_ref() == Key::Character(self.settings.get().release_key.as_str())
 			&& input.state == ElementState::Released
 			&& self.modifiers.state().super_key()
 		{
@@ -263,28 +340,144 @@ impl InputWindow {
 			return;
 		}
 		let pressed = input.state == ElementState::Pressed;
+		// so what's pasted in XR is what was last copied here
+		if pressed
+			&& input.logical_key.as_ref() == Key::Character("v")
+			&& self.modifiers.state().control_key()
+		{
+			self.share_clipboard();
+		}
 
 		let Some(keycode) = input.physical_key.to_scancode() else {
 			return;
 		};
-		send_input_ipc(Message::Key { keycode, pressed });
+		self.send(Message::Key { keycode, pressed });
+	}
+
+	fn open_picker(&mut self) {
+		if self.layouts.is_empty() {
+			self.layouts = layouts::available();
+		}
+		if self.layouts.is_empty() {
+			return;
+		}
+		// the keys held to open it are let go while it's open, and aren't sent
+		self.send(Message::ResetInput);
+		self.picking = Some(self.picked.min(self.layouts.len() - 1));
+		self.show_picker();
+	}
+
+	fn handle_picker_key(&mut self, key: Key) {
+		let Some(index) = self.picking else {
+			return;
+		};
+		let count = self.layouts.len();
+		let index = match key {
+			Key::Named(NamedKey::ArrowDown) => (index + 1) % count,
+			Key::Named(NamedKey::ArrowUp) => (index + count - 1) % count,
+			Key::Named(NamedKey::PageDown) => (index + 10).min(count - 1),
+			Key::Named(NamedKey::PageUp) => index.saturating_sub(10),
+			Key::Named(NamedKey::Enter) => {
+				self.pick(index);
+				return;
+			}
+			Key::Named(NamedKey::Escape) => {
+				self.close_picker();
+				return;
+			}
+			// skip ahead to the next layout starting with what was typed
+			Key::Character(typed) => {
+				let typed = typed.to_lowercase();
+				let Some(next) = (1..count)
+					.map(|offset| (index + offset) % count)
+					.find(|&i| {
+						self.layouts[i].variant.is_empty()
+							&& self.layouts[i].layout.starts_with(&typed)
+					})
+				else {
+					return;
+				};
+				next
+			}
+			_ => return,
+		};
+		self.picking = Some(index);
+		self.show_picker();
+	}
+
+	fn show_picker(&self) {
+		let Some(layout) = self.picking.map(|index| &self.layouts[index]) else {
+			return;
+		};
+		let name = match layout.variant.as_str() {
+			"" => layout.layout.clone(),
+			variant => format!("{}({variant})", layout.layout),
+		};
+		self.window.set_title(&format!(
+			"Layout: {} [{name}] (up/down to browse, enter to use, escape to cancel)",
+			layout.description
+		));
+	}
+
+	fn pick(&mut self, index: usize) {
+		let layout = &self.layouts[index];
+		match layout.compile() {
+			Some(keymap) => {
+				info!("Typing in {}", layout.description);
+				self.send(Message::Keymap(
+					keymap.get_as_string(XKB_KEYMAP_FORMAT_TEXT_V1),
+				));
+				self.picked = index;
+			}
+			None => warn!("Couldn't compile a keymap for {}", layout.description),
+		}
+		self.close_picker();
+	}
+
+	fn close_picker(&mut self) {
+		self.picking = None;
+		let window_title = self.window_title();
+		self.window.set_title(&window_title);
+	}
+
+	fn handle_hover(&mut self, handler: Option<u64>, captured: bool) {
+		self.hover = handler.map(|handler| (handler, captured));
+		// the picker's title stays until it's closed, which puts this in
+		if self.picking.is_none() {
+			let window_title = self.window_title();
+			self.window.set_title(&window_title);
+		}
+	}
+
+	fn window_title(&mut self) -> String {
+		let target = match self.hover {
+			Some((handler, true)) => format!(", captured by handler {handler}"),
+			Some((handler, false)) => format!(", pointing at handler {handler}"),
+			None => String::new(),
+		};
+		if self.grabbed {
+			let settings = self.settings.get();
+			format!(
+				"Manifold Input{target} (super+{} to release cursor, super+{} to change layout)",
+				settings.release_key, settings.layout_key
+			)
+		} else {
+			format!("Manifold Input{target} (click to grab input)")
+		}
 	}
 
-	const GRABBED_WINDOW_TITLE: &'static str = "Manifold Input (super+q to release cursor)";
-	const UNGRABBED_WINDOW_TITLE: &'static str = "Manifold Input (click to grab input)";
 	fn set_grab(&mut self, grab: bool) {
 		if grab == self.grabbed {
 			return;
 		}
 		self.grabbed = grab;
+		if grab {
+			self.share_clipboard();
+		}
 
 		self.window.set_cursor_visible(!grab);
 
-		let window_title = if grab {
-			Self::GRABBED_WINDOW_TITLE
-		} else {
-			Self::UNGRABBED_WINDOW_TITLE
-		};
+		let window_title = self.window_title();
 
 		let grab = if grab {
 			CursorGrabMode::Confined
@@ -292,7 +485,22 @@ impl InputWindow {
 			CursorGrabMode::None
 		};
 		if self.window.set_cursor_grab(grab).is_ok() {
-			self.window.set_title(window_title);
+			self.window.set_title(&window_title);
+		}
+	}
+	/// Send the clipboard's text to the sink if something new was copied since it was last sent.
+	fn share_clipboard(&mut self) {
+		let Some(clipboard) = &mut self.clipboard else {
+			return;
+		};
+		// empty, or not text
+		let Ok(text) = clipboard.get_text() else {
+			return;
+		};
+		if self.shared_text.as_ref() == Some(&text) {
+			return;
 		}
+		self.shared_text = Some(text.clone());
+		self.send(Message::ClipboardSet(text));
 	}
 }
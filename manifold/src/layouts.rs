@@ -0,0 +1,97 @@
+//! The keyboard layouts there are to pick from, as listed in xkeyboard-config's registry.
+
+use logging::warn;
+use std::{env, fs, path::PathBuf};
+use xkbcommon::xkb::{Context, Keymap, KEYMAP_COMPILE_NO_FLAGS};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+	pub layout: String,
+	/// Empty for the layout's default variant.
+	pub variant: String,
+	pub description: String,
+}
+impl Layout {
+	pub fn compile(&self) -> Option<Keymap> {
+		Keymap::new_from_names(
+			&Context::new(0),
+			"evdev",
+			"",
+			&self.layout,
+			&self.variant,
+			None,
+			KEYMAP_COMPILE_NO_FLAGS,
+		)
+	}
+}
+
+/// Every layout and variant in the registry, each layout followed by its variants.
+pub fn available() -> Vec<Layout> {
+	// xkbcommon looks here too
+	let root = env::var_os("XKB_CONFIG_ROOT")
+		.map(PathBuf::from)
+		.unwrap_or_else(|| PathBuf::from("/usr/share/X11/xkb"));
+	let path = root.join("rules/evdev.lst");
+	match fs::read_to_string(&path) {
+		Ok(list) => parse(&list),
+		Err(e) => {
+			warn!("Couldn't read the layouts in {}: {e}", path.display());
+			Vec::new()
+		}
+	}
+}
+
+/// Read the `! layout` and `! variant` sections of a rules `.lst` file.
+fn parse(list: &str) -> Vec<Layout> {
+	let mut layouts: Vec<Layout> = Vec::new();
+	let mut section = "";
+	for line in list.lines() {
+		if let Some(name) = line.strip_prefix('!') {
+			section = name.trim();
+			continue;
+		}
+		let Some((name, description)) = line.trim().split_once(char::is_whitespace) else {
+			continue;
+		};
+		let description = description.trim();
+		match section {
+			"layout" => layouts.push(Layout {
+				layout: name.to_string(),
+				variant: String::new(),
+				description: description.to_string(),
+			}),
+			// variants are listed as `name layout: description`
+			"variant" => {
+				let Some((layout, description)) = description.split_once(": ") else {
+					continue;
+				};
+				let Some(after) = layouts.iter().rposition(|l| l.layout == layout) else {
+					continue;
+				};
+				layouts.insert(
+					after + 1,
+					Layout {
+						layout: layout.to_string(),
+						variant: name.to_string(),
+						description: description.to_string(),
+					},
+				);
+			}
+			_ => (),
+		}
+	}
+	layouts
+}
+
+#[test]
+fn test_parse() {
+	let list = "! model\n  pc105           Generic 105-key PC\n\n! layout\n  us              English (US)\n  de              German\n\n! variant\n  dvorak          us: English (Dvorak)\n  neo             de: German (Neo 2)\n  colemak         us: English (Colemak)\n  nothing         xx: Not a layout\n\n! option\n  grp             Switching to another layout\n";
+	let names: Vec<_> = parse(list)
+		.into_iter()
+		.map(|l| format!("{}({})", l.layout, l.variant))
+		.collect();
+	assert_eq!(
+		names,
+		["us()", "us(dvorak)", "us(colemak)", "de()", "de(neo)"]
+	);
+}
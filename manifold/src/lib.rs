@@ -0,0 +1,71 @@
+use clap::Parser;
+use input_window::InputWindow;
+use ipc::{flow::PausePolicy, Capabilities, Message};
+use logging::LogArgs;
+use std::{io::IsTerminal, sync::Mutex};
+use winit::event_loop::ControlFlow;
+use winit::event_loop::EventLoopBuilder;
+pub mod input_window;
+pub mod layouts;
+
+#[derive(Debug, Parser)]
+#[command(about = "Take input from a window on your desktop and write it to stdout")]
+pub struct Args {
+	/// Serve input on a socket in $XDG_RUNTIME_DIR called NAME for sinks to connect to, instead of writing it to stdout
+	#[arg(long, value_name = "NAME")]
+	pub socket: Option<String>,
+	#[command(flatten)]
+	pub log: LogArgs,
+}
+
+pub fn run(args: Args) {
+	if args.socket.is_none() && std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `manifold | azimuth`");
+	}
+	logging::init(&args.log).unwrap();
+	if let Some(name) = &args.socket {
+		ipc::socket::serve(name).unwrap();
+	}
+	config::reload::watch();
+	let event_loop = EventLoopBuilder::with_user_event().build().unwrap();
+	// the window lets go of the cursor and the sink of everything held before exiting
+	let quit = Mutex::new(event_loop.create_proxy());
+	ipc::shutdown::on_signal(move || {
+		let _ = quit.lock().unwrap().send_event(Message::Disconnect);
+	});
+	let config = config::reload::current();
+	let settings = config.section("manifold");
+	let pause_policy = settings.get("pause-policy", "buffer".to_string());
+	ipc::heartbeat::set_timeout(
+		settings.get_millis("heartbeat-timeout-ms", ipc::heartbeat::HEARTBEAT_TIMEOUT),
+	);
+	// everything else the sink sends back, like what the pointer is on, is for the window
+	let proxy = Mutex::new(event_loop.create_proxy());
+	ipc::flow::accept_pauses_with(
+		pause_policy.parse().unwrap_or_else(|e| {
+			logging::warn!("{e}");
+			PausePolicy::Buffer
+		}),
+		move |message| {
+			let _ = proxy.lock().unwrap().send_event(message);
+		},
+	);
+	// only after listening, so the sink's hello back isn't missed
+	if let Err(e) = ipc::handshake::hello(
+		Capabilities::KEYBOARD | Capabilities::POINTER | Capabilities::GESTURES,
+	) {
+		logging::warn!("Couldn't greet the sink: {e}");
+		return;
+	}
+	ipc::heartbeat::start_heartbeat();
+	// winit doesn't say when events happened, so sending them is as close as it gets
+	ipc::timestamp::stamp_sends();
+	let mut input_window = InputWindow::new(&event_loop);
+
+	event_loop
+		.run(move |event, elwt| {
+			elwt.set_control_flow(ControlFlow::Wait);
+			input_window.handle_event(event);
+		})
+		.unwrap();
+}
@@ -0,0 +1,185 @@
+mod scan;
+
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use config::reload::Live;
+use ephemeris::{
+	keyboard::Keyboard,
+	script::{self, Step},
+};
+use ipc::{
+	message_from_frame, receive_frame_async_ipc, send_frame_async_ipc, send_input_async_ipc,
+	send_input_ipc, Error, Message,
+};
+use logging::{info, warn, LogArgs};
+use scan::{Outcome, Scanner, Settings};
+use std::{io::IsTerminal, process::Stdio, thread, time::Duration};
+use tokio::time::{interval, sleep_until, Instant, MissedTickBehavior};
+
+// how often the pointer moves while it's moving
+const POINTER_INTERVAL: Duration = Duration::from_millis(16);
+
+#[derive(Debug, Parser)]
+#[command(
+	about = "Switch access scanning: step through actions with one or two switches and select one to send it"
+)]
+struct Args {
+	/// Run this through `sh -c` with each item's name as `$1` when it comes up, e.g. `spd-say "$1"`
+	#[arg(long, value_name = "COMMAND")]
+	announce: Option<String>,
+	/// Milliseconds between keys while running an item
+	#[arg(long, default_value_t = 12)]
+	key_delay: u64,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() || std::io::stdout().is_terminal() {
+		panic!("You need to put this between the source your switches are on and a sink e.g. `eclipse | meridian | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	config::reload::watch();
+	// so the sink can still pause the source through this
+	ipc::reverse::relay();
+	let key_delay = Duration::from_millis(args.key_delay);
+	let highlight = |name: &str| {
+		info!("{name}");
+		if let Some(command) = &args.announce {
+			announce(command, name);
+		}
+	};
+
+	// key names in items are looked up in the source's keymap once it sends one
+	let mut keyboard =
+		Keyboard::new("").ok_or_else(|| eyre!("Couldn't make the default keymap"))?;
+	let mut settings = Live::new(Settings::read);
+	let mut scanner = Scanner::default();
+	if let Some(item) = scanner.current(settings.get()) {
+		highlight(&item.name);
+	}
+	let mut next_scan = Instant::now() + settings.get().interval;
+	let mut pointer = interval(POINTER_INTERVAL);
+	pointer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+	loop {
+		let auto_scan = settings.get().next_key.is_none();
+		let velocity = scanner.velocity(settings.get());
+		tokio::select! {
+			frame = receive_frame_async_ipc() => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				let Ok(message) = message_from_frame(&frame) else {
+					match send_frame_async_ipc(&frame).await {
+						Ok(()) => (),
+						Err(Error::Disconnected) => break,
+						Err(e) => warn!("{e}"),
+					}
+					continue;
+				};
+				let settings = settings.get();
+				let outcome = match message {
+					// the switches only scan, they never reach the sink
+					Message::Key { keycode, pressed } if keycode == settings.select_key => {
+						if !pressed {
+							continue;
+						}
+						scanner.select(settings)
+					}
+					Message::Key { keycode, pressed } if Some(keycode) == settings.next_key => {
+						if !pressed {
+							continue;
+						}
+						scanner.advance(settings)
+					}
+					message => {
+						if let Message::Keymap(keymap) = &message {
+							match Keyboard::from_keymap_string(keymap) {
+								Some(new_keyboard) => keyboard = new_keyboard,
+								None => warn!("Couldn't read the source's keymap, keeping the last one"),
+							}
+						}
+						let disconnect = message == Message::Disconnect;
+						match send_input_async_ipc(message).await {
+							Ok(()) => (),
+							Err(Error::Disconnected) => break,
+							Err(e) => warn!("{e}"),
+						}
+						if disconnect {
+							return Ok(());
+						}
+						continue;
+					}
+				};
+				match outcome {
+					Some(Outcome::Highlight(name)) | Some(Outcome::Moving(name)) => highlight(&name),
+					Some(Outcome::Run(commands)) => {
+						match script::steps(&commands, &keyboard, key_delay) {
+							Ok(steps) => run(steps),
+							Err(e) => warn!("{e}"),
+						}
+						if let Some(item) = scanner.current(settings) {
+							highlight(&item.name);
+						}
+					}
+					None => (),
+				}
+				// a whole interval on whatever's highlighted after a press
+				next_scan = Instant::now() + settings.interval;
+			}
+			_ = sleep_until(next_scan), if auto_scan && velocity.is_none() => {
+				if let Some(Outcome::Highlight(name)) = scanner.advance(settings.get()) {
+					highlight(&name);
+				}
+				next_scan = Instant::now() + settings.get().interval;
+			}
+			_ = pointer.tick(), if velocity.is_some() => {
+				let [x, y] = velocity.unwrap();
+				let seconds = POINTER_INTERVAL.as_secs_f32();
+				match send_input_async_ipc(Message::MouseMove([x * seconds, y * seconds].into())).await {
+					Ok(()) => (),
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+/// Send an item's input alongside the stream.
+fn run(steps: Vec<Step>) {
+	thread::spawn(move || {
+		for step in steps {
+			match step {
+				Step::Send(message) => match send_input_ipc(message) {
+					Ok(()) => (),
+					// the stream ends too, and with it this
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				},
+				Step::Sleep(duration) => thread::sleep(duration),
+			}
+		}
+	});
+}
+
+fn announce(command: &str, name: &str) {
+	let child = std::process::Command::new("sh")
+		.args(["-c", command, "sh", name])
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.spawn();
+	match child {
+		// waited on so it doesn't linger as a zombie
+		Ok(mut child) => {
+			thread::spawn(move || child.wait());
+		}
+		Err(e) => warn!("Couldn't announce {name:?}: {e}"),
+	}
+}
@@ -0,0 +1,209 @@
+use config::Config;
+use ephemeris::script::{self, Command};
+use logging::warn;
+use std::time::Duration;
+
+// what's cycled through when the config doesn't list anything
+const DEFAULT_ITEMS: [[&str; 2]; 12] = [
+	["up", "pointer up"],
+	["down", "pointer down"],
+	["left", "pointer left"],
+	["right", "pointer right"],
+	["click", "click left"],
+	["right click", "click right"],
+	["scroll up", "scroll 0 -1"],
+	["scroll down", "scroll 0 1"],
+	["enter", "key Return"],
+	["escape", "key Escape"],
+	["backspace", "key BackSpace"],
+	["space", "key space"],
+];
+
+/// What an item does when it's selected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+	/// Move the pointer this way until the next switch press.
+	Pointer([f32; 2]),
+	Script(Vec<Command>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+	pub name: String,
+	pub action: Action,
+}
+
+/// How scanning works, from the `meridian` section of the config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+	pub select_key: u32,
+	/// With a second switch it steps through the items, otherwise they're stepped through on a timer.
+	pub next_key: Option<u32>,
+	pub interval: Duration,
+	/// Pixels per second.
+	pub pointer_speed: f32,
+	pub items: Vec<Item>,
+}
+impl Settings {
+	pub fn read(config: &Config) -> Self {
+		let config = config.section("meridian");
+		let items: Vec<Item> = config
+			.get_each::<String>("item")
+			.into_iter()
+			.filter_map(|args| match &args[..] {
+				[name, commands @ ..] if !commands.is_empty() => item(name, &commands.join("\n")),
+				_ => {
+					warn!("Ignoring item {args:?}, it needs a name and what it does");
+					None
+				}
+			})
+			.collect();
+		Settings {
+			select_key: config.get("select-key", 57),
+			next_key: config.get_list("next-key").first().copied(),
+			interval: config.get_millis("scan-interval-ms", Duration::from_millis(1200)),
+			pointer_speed: config.get("pointer-speed", 300.0),
+			items: if items.is_empty() {
+				DEFAULT_ITEMS
+					.iter()
+					.filter_map(|[name, commands]| item(name, commands))
+					.collect()
+			} else {
+				items
+			},
+		}
+	}
+}
+
+/// An item that runs ephemeris commands, or `pointer DIRECTION`, one per line.
+fn item(name: &str, commands: &str) -> Option<Item> {
+	let direction = match commands.strip_prefix("pointer ") {
+		Some("up") => Some([0.0, -1.0]),
+		Some("down") => Some([0.0, 1.0]),
+		Some("left") => Some([-1.0, 0.0]),
+		Some("right") => Some([1.0, 0.0]),
+		Some(direction) => {
+			warn!("Ignoring item {name:?}, {direction:?} isn't up, down, left or right");
+			return None;
+		}
+		None => None,
+	};
+	let action = match direction {
+		Some(direction) => Action::Pointer(direction),
+		None => match script::parse_script(commands) {
+			Ok(commands) => Action::Script(commands),
+			Err(e) => {
+				warn!("Ignoring item {name:?}: {e}");
+				return None;
+			}
+		},
+	};
+	Some(Item {
+		name: name.to_string(),
+		action,
+	})
+}
+
+/// What happened from a switch press or the timer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+	/// This item is the one that would be selected now.
+	Highlight(String),
+	/// The pointer started moving, until the next press.
+	Moving(String),
+	/// Scanning starts over after these run.
+	Run(Vec<Command>),
+}
+
+/// Where scanning is up to.
+#[derive(Debug, Default)]
+pub struct Scanner {
+	index: usize,
+	moving: Option<[f32; 2]>,
+}
+impl Scanner {
+	/// How the pointer is moving, in pixels per second.
+	pub fn velocity(&self, settings: &Settings) -> Option<[f32; 2]> {
+		self.moving
+			.map(|[x, y]| [x * settings.pointer_speed, y * settings.pointer_speed])
+	}
+
+	pub fn current<'a>(&self, settings: &'a Settings) -> Option<&'a Item> {
+		settings.items.get(self.index)
+	}
+
+	/// Step to the next item, wrapping around.
+	pub fn advance(&mut self, settings: &Settings) -> Option<Outcome> {
+		if self.moving.is_some() || settings.items.is_empty() {
+			return None;
+		}
+		self.index = (self.index + 1) % settings.items.len();
+		Some(Outcome::Highlight(self.current(settings)?.name.clone()))
+	}
+
+	pub fn select(&mut self, settings: &Settings) -> Option<Outcome> {
+		// the items may have been reloaded since
+		if self.index >= settings.items.len() {
+			self.index = 0;
+		}
+		if self.moving.take().is_some() {
+			return Some(Outcome::Highlight(self.current(settings)?.name.clone()));
+		}
+		let item = self.current(settings)?;
+		Some(match &item.action {
+			Action::Pointer(direction) => {
+				self.moving = Some(*direction);
+				Outcome::Moving(item.name.clone())
+			}
+			Action::Script(commands) => {
+				self.index = 0;
+				Outcome::Run(commands.clone())
+			}
+		})
+	}
+}
+
+#[test]
+fn test_scanner() {
+	let settings = Settings::read(
+		&Config::parse(
+			r#"
+			meridian {
+				pointer-speed 100
+				item "left" "pointer left"
+				item "copy" "keydown ctrl" "key c" "keyup ctrl"
+				item "broken" "pointer sideways"
+			}
+			"#,
+		)
+		.unwrap(),
+	);
+	assert_eq!(settings.items.len(), 2);
+	assert_eq!(settings.next_key, None);
+	let mut scanner = Scanner::default();
+	assert_eq!(
+		scanner.select(&settings),
+		Some(Outcome::Moving("left".to_string()))
+	);
+	assert_eq!(scanner.velocity(&settings), Some([-100.0, 0.0]));
+	// nothing moves on while the pointer does
+	assert_eq!(scanner.advance(&settings), None);
+	assert_eq!(
+		scanner.select(&settings),
+		Some(Outcome::Highlight("left".to_string()))
+	);
+	assert_eq!(scanner.velocity(&settings), None);
+	assert_eq!(
+		scanner.advance(&settings),
+		Some(Outcome::Highlight("copy".to_string()))
+	);
+	let Some(Outcome::Run(commands)) = scanner.select(&settings) else {
+		panic!("selecting copy should run it");
+	};
+	assert_eq!(commands.len(), 3);
+	assert_eq!(scanner.current(&settings).unwrap().name, "left");
+
+	let defaults = Settings::read(&Config::default());
+	assert_eq!(defaults.items.len(), DEFAULT_ITEMS.len());
+	assert_eq!(defaults.select_key, 57);
+}
@@ -0,0 +1,228 @@
+//! Counters the sinks keep for monitoring, served in the Prometheus text format.
+//!
+//! Recording is always on since it's just atomics, [`init`] only decides whether anything serves
+//! them.
+
+use clap::Args;
+use std::{
+	collections::BTreeMap,
+	fmt::Write as _,
+	io::{Read, Write},
+	net::{SocketAddr, TcpListener},
+	sync::{
+		atomic::{AtomicI64, AtomicU64, Ordering},
+		Mutex,
+	},
+	thread,
+	time::Duration,
+};
+use tracing::{info, warn};
+
+pub static MESSAGES: LabeledCounter = LabeledCounter::new(
+	"stardust_input_messages_total",
+	"Messages read from the input stream",
+	"type",
+);
+pub static QUEUED_FRAMES: Gauge = Gauge::new(
+	"stardust_input_queued_frames",
+	"Frames read from stdin that the sink hasn't gotten to yet",
+);
+pub static RAY_MARCH: Latency = Latency::new(
+	"stardust_input_ray_march_seconds",
+	"Time taken by ray marches to find what's being pointed at",
+);
+pub static RAY_MARCH_TIMEOUTS: Counter = Counter::new(
+	"stardust_input_ray_march_timeouts_total",
+	"Ray marches that took longer than the timeout",
+);
+pub static FOCUS_SWITCHES: LabeledCounter = LabeledCounter::new(
+	"stardust_input_focus_switches_total",
+	"Times the hovered target changed",
+	"target",
+);
+
+#[derive(Debug, Clone, Args)]
+pub struct MetricsArgs {
+	/// Serve metrics in the Prometheus text format on this address, e.g. 127.0.0.1:9477
+	#[arg(long, value_name = "ADDRESS")]
+	pub metrics: Option<SocketAddr>,
+}
+
+/// Start serving the metrics if asked to, once at the start of main.
+pub fn init(args: &MetricsArgs) -> std::io::Result<()> {
+	let Some(address) = args.metrics else {
+		return Ok(());
+	};
+	let listener = TcpListener::bind(address)?;
+	info!("Serving metrics on http://{address}");
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let Ok(mut stream) = stream else {
+				continue;
+			};
+			// every path gets the metrics, so the request only has to be read
+			let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+			let _ = stream.read(&mut [0; 1024]);
+			let body = render();
+			let response = format!(
+				"HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+				body.len()
+			);
+			if let Err(e) = stream.write_all(response.as_bytes()) {
+				warn!("Couldn't send metrics: {e}");
+			}
+		}
+	});
+	Ok(())
+}
+
+/// Count a message the sink took off the stream, along with how many frames are waiting behind it.
+pub fn received(message_type: &'static str, queued_frames: usize) {
+	MESSAGES.inc(message_type);
+	QUEUED_FRAMES.set(queued_frames as i64);
+}
+
+/// Record a ray march to find what's being pointed at.
+pub fn ray_marched(took: Duration, timed_out: bool) {
+	RAY_MARCH.record(took);
+	if timed_out {
+		RAY_MARCH_TIMEOUTS.inc();
+	}
+}
+
+/// Every metric in the Prometheus text format.
+pub fn render() -> String {
+	let mut out = String::new();
+	MESSAGES.render(&mut out);
+	QUEUED_FRAMES.render(&mut out);
+	RAY_MARCH.render(&mut out);
+	RAY_MARCH_TIMEOUTS.render(&mut out);
+	FOCUS_SWITCHES.render(&mut out);
+	out
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+	let _ = writeln!(out, "# HELP {name} {help}");
+	let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+pub struct Counter {
+	name: &'static str,
+	help: &'static str,
+	value: AtomicU64,
+}
+impl Counter {
+	pub const fn new(name: &'static str, help: &'static str) -> Self {
+		Counter {
+			name,
+			help,
+			value: AtomicU64::new(0),
+		}
+	}
+	pub fn inc(&self) {
+		self.value.fetch_add(1, Ordering::Relaxed);
+	}
+	fn render(&self, out: &mut String) {
+		header(out, self.name, self.help, "counter");
+		let _ = writeln!(out, "{} {}", self.name, self.value.load(Ordering::Relaxed));
+	}
+}
+
+/// A counter split up by one label, e.g. message type.
+pub struct LabeledCounter {
+	name: &'static str,
+	help: &'static str,
+	label: &'static str,
+	values: Mutex<BTreeMap<&'static str, u64>>,
+}
+impl LabeledCounter {
+	pub const fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+		LabeledCounter {
+			name,
+			help,
+			label,
+			values: Mutex::new(BTreeMap::new()),
+		}
+	}
+	pub fn inc(&self, label: &'static str) {
+		*self.values.lock().unwrap().entry(label).or_default() += 1;
+	}
+	fn render(&self, out: &mut String) {
+		header(out, self.name, self.help, "counter");
+		for (label, value) in self.values.lock().unwrap().iter() {
+			let _ = writeln!(out, "{}{{{}=\"{label}\"}} {value}", self.name, self.label);
+		}
+	}
+}
+
+pub struct Gauge {
+	name: &'static str,
+	help: &'static str,
+	value: AtomicI64,
+}
+impl Gauge {
+	pub const fn new(name: &'static str, help: &'static str) -> Self {
+		Gauge {
+			name,
+			help,
+			value: AtomicI64::new(0),
+		}
+	}
+	pub fn set(&self, value: i64) {
+		self.value.store(value, Ordering::Relaxed);
+	}
+	fn render(&self, out: &mut String) {
+		header(out, self.name, self.help, "gauge");
+		let _ = writeln!(out, "{} {}", self.name, self.value.load(Ordering::Relaxed));
+	}
+}
+
+/// How long something takes, as a summary without quantiles: the average comes from sum/count.
+pub struct Latency {
+	name: &'static str,
+	help: &'static str,
+	count: AtomicU64,
+	micros: AtomicU64,
+}
+impl Latency {
+	pub const fn new(name: &'static str, help: &'static str) -> Self {
+		Latency {
+			name,
+			help,
+			count: AtomicU64::new(0),
+			micros: AtomicU64::new(0),
+		}
+	}
+	pub fn record(&self, duration: Duration) {
+		self.count.fetch_add(1, Ordering::Relaxed);
+		self.micros
+			.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+	}
+	fn render(&self, out: &mut String) {
+		header(out, self.name, self.help, "summary");
+		let seconds = self.micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+		let _ = writeln!(out, "{}_sum {seconds}", self.name);
+		let _ = writeln!(
+			out,
+			"{}_count {}",
+			self.name,
+			self.count.load(Ordering::Relaxed)
+		);
+	}
+}
+
+#[test]
+fn test_render() {
+	received("Key", 0);
+	received("Key", 0);
+	received("MouseMove", 3);
+	RAY_MARCH.record(Duration::from_millis(1500));
+	let text = render();
+	assert!(text.contains("# TYPE stardust_input_messages_total counter\n"));
+	assert!(text.contains("stardust_input_messages_total{type=\"Key\"} 2\n"));
+	assert!(text.contains("stardust_input_messages_total{type=\"MouseMove\"} 1\n"));
+	assert!(text.contains("stardust_input_queued_frames 3\n"));
+	assert!(text.contains("stardust_input_ray_march_seconds_sum 1.5\n"));
+	assert!(text.contains("stardust_input_ray_march_seconds_count 1\n"));
+	assert!(text.contains("stardust_input_ray_march_timeouts_total 0\n"));
+}
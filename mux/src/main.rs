@@ -0,0 +1,721 @@
+use clap::{Parser, Subcommand};
+use color_eyre::Result;
+use ipc::{
+	heartbeat::{self, start_heartbeat},
+	message_from_frame, receive_pooled_frame_async_ipc,
+	socket::{read_frame, socket_path, write_frame},
+	stamp_from_frame,
+	stuck::release_stuck_input_after,
+	Capabilities, IpcSender, Message, Stamp,
+};
+use logging::{info, warn, LogArgs};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	io::IsTerminal,
+	path::PathBuf,
+	time::Duration,
+};
+use tokio::{
+	net::{UnixListener, UnixStream},
+	sync::mpsc,
+};
+
+const SOCKET_NAME: &str = "mux";
+
+#[derive(Debug, Parser)]
+#[command(about = "Merge several input sources into one stream")]
+struct Args {
+	/// Socket the sources connect to, defaults to one in $XDG_RUNTIME_DIR
+	#[arg(long, global = true)]
+	socket: Option<PathBuf>,
+	#[command(subcommand)]
+	command: Command,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+	/// Accept sources on the socket and write the merged stream to stdout e.g. `mux serve | azimuth`
+	Serve {
+		/// Release keys and buttons held for longer than this many seconds, in case a release got lost
+		#[arg(long, value_name = "SECONDS")]
+		release_stuck_after: Option<f64>,
+	},
+	/// Pass the stream on stdin to a running `mux serve` e.g. `eclipse | mux feed --name eclipse`
+	Feed {
+		/// What to call this source in mux's log
+		#[arg(long)]
+		name: Option<String>,
+	},
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	let socket = args.socket.unwrap_or_else(|| socket_path(SOCKET_NAME));
+	match args.command {
+		Command::Serve {
+			release_stuck_after,
+		} => {
+			if let Some(seconds) = release_stuck_after {
+				release_stuck_input_after(Duration::from_secs_f64(seconds));
+			}
+			serve(socket).await
+		}
+		Command::Feed { name } => {
+			feed(
+				socket,
+				name.unwrap_or_else(|| format!("source {}", std::process::id())),
+			)
+			.await
+		}
+	}
+}
+
+async fn feed(socket: PathBuf, name: String) -> Result<()> {
+	if std::io::stdin().is_terminal() {
+		panic!("You need to pipe an input source into this e.g. `eclipse | mux feed`");
+	}
+	let mut stream = UnixStream::connect(&socket).await?;
+	// the first frame names the source, everything after is the stream itself
+	write_frame(&mut stream, name.as_bytes()).await?;
+	while let Ok(frame) = receive_pooled_frame_async_ipc().await {
+		write_frame(&mut stream, &frame).await?;
+	}
+	Ok(())
+}
+
+enum SourceEvent {
+	Connected(String),
+	Frame(Vec<u8>),
+	Disconnected,
+}
+
+async fn serve(socket: PathBuf) -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `mux serve | azimuth`");
+	}
+	// a mux that didn't get to clean up leaves its socket behind
+	let _ = std::fs::remove_file(&socket);
+	let listener = UnixListener::bind(&socket)?;
+	info!("Listening on {}", socket.display());
+	start_heartbeat();
+
+	let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+	let mut merger = Merger::new(IpcSender::stdout());
+	let mut next_id = 0_u64;
+	loop {
+		tokio::select! {
+			connection = listener.accept() => {
+				let (stream, _) = connection?;
+				next_id += 1;
+				tokio::spawn(read_source(next_id, stream, event_tx.clone()));
+			}
+			Some((id, event)) = event_rx.recv() => match merger.handle(id, event) {
+				Ok(()) => (),
+				Err(ipc::Error::Disconnected) => break,
+				Err(e) => warn!("{e}"),
+			},
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	// don't leave anything held down in the sink
+	ipc::shutdown::end_stream();
+	let _ = std::fs::remove_file(&socket);
+	Ok(())
+}
+
+async fn read_source(
+	id: u64,
+	mut stream: UnixStream,
+	event_tx: mpsc::UnboundedSender<(u64, SourceEvent)>,
+) {
+	let Ok(name) = read_frame(&mut stream).await else {
+		return;
+	};
+	let name = String::from_utf8_lossy(&name).into_owned();
+	let _ = event_tx.send((id, SourceEvent::Connected(name.clone())));
+	// a source that sent heartbeats and then went quiet is wedged, so it's dropped like one that left
+	let mut heartbeats = false;
+	loop {
+		let frame = if heartbeats {
+			match tokio::time::timeout(heartbeat::timeout(), read_frame(&mut stream)).await {
+				Ok(frame) => frame,
+				Err(_) => {
+					warn!(
+						"No heartbeat from {name} for {:?}, dropping it",
+						heartbeat::timeout()
+					);
+					break;
+				}
+			}
+		} else {
+			read_frame(&mut stream).await
+		};
+		let Ok(frame) = frame else {
+			break;
+		};
+		heartbeats |= matches!(message_from_frame(&frame), Ok(Message::Heartbeat));
+		if event_tx.send((id, SourceEvent::Frame(frame))).is_err() {
+			return;
+		}
+	}
+	let _ = event_tx.send((id, SourceEvent::Disconnected));
+}
+
+#[derive(Debug, Default)]
+struct Source {
+	name: String,
+	keymap: Option<String>,
+	held_keys: BTreeSet<u32>,
+	held_buttons: BTreeSet<u32>,
+	/// Slots of the fingers this source has down.
+	held_touches: BTreeSet<u32>,
+	tablet_tip_down: bool,
+	/// The id the sink knows each of this source's gamepads by, by the source's own id for it,
+	/// since every source numbers its gamepads from 0.
+	gamepads: BTreeMap<u32, u32>,
+	/// Gamepad buttons this source is holding, by the sink's id for the gamepad.
+	held_gamepad_buttons: BTreeSet<(u32, u32)>,
+	/// The id the sink knows this source by, as a device of mux's.
+	device: u32,
+	/// The id the sink knows each device this source stamps its input with by, by the source's
+	/// own id for it.
+	devices: BTreeMap<u32, u32>,
+}
+impl Source {
+	/// For what mux sends on the source's behalf, like releases once it's gone.
+	fn stamp(&self) -> Stamp {
+		Stamp {
+			ts: None,
+			device: Some(self.device),
+		}
+	}
+}
+
+/// Combines the sources' messages so the sink sees one consistent stream.
+struct Merger {
+	sender: &'static IpcSender,
+	sources: BTreeMap<u64, Source>,
+	/// The keymap the sink was sent last.
+	keymap: Option<String>,
+	/// The id the next gamepad any source connects gets in the sink.
+	next_gamepad: u32,
+	/// The id the next source or device of a source gets in the sink.
+	next_device: u32,
+}
+impl Merger {
+	fn new(sender: &'static IpcSender) -> Self {
+		Merger {
+			sender,
+			sources: BTreeMap::new(),
+			keymap: None,
+			next_gamepad: 0,
+			next_device: 0,
+		}
+	}
+
+	fn handle(&mut self, id: u64, event: SourceEvent) -> Result<(), ipc::Error> {
+		match event {
+			SourceEvent::Connected(name) => {
+				info!("{name} connected");
+				let device = self.next_device;
+				self.next_device += 1;
+				self.sources.insert(
+					id,
+					Source {
+						name: name.clone(),
+						device,
+						..Default::default()
+					},
+				);
+				self.sender.send(Message::DeviceAdded {
+					id: device,
+					name,
+					capabilities: any_input(),
+				})
+			}
+			SourceEvent::Frame(frame) => match message_from_frame(&frame) {
+				Ok(message) => self.message(id, message, stamp_from_frame(&frame)),
+				// can't make sense of it, so just pass it on
+				Err(_) => self.sender.send_frame(&frame),
+			},
+			SourceEvent::Disconnected => {
+				let released = self
+					.disconnect_gamepads(id)
+					.and_then(|()| self.release(id))
+					.and_then(|()| self.remove_devices(id));
+				if let Some(source) = self.sources.remove(&id) {
+					info!("{} disconnected", source.name);
+				}
+				released
+			}
+		}
+	}
+
+	fn message(&mut self, id: u64, message: Message, stamp: Stamp) -> Result<(), ipc::Error> {
+		let Some(source) = self.sources.get_mut(&id) else {
+			return Ok(());
+		};
+		// the sink can tell which source, and which of its devices, everything came from
+		let stamp = Stamp {
+			device: Some(match stamp.device {
+				Some(device) => device_id(&mut self.next_device, source, device),
+				None => source.device,
+			}),
+			..stamp
+		};
+		match message {
+			Message::Keymap(keymap) => {
+				// only replace the sink's keymap right away if it's this source's or there isn't one,
+				// otherwise it's switched in once this source actually types
+				if self.keymap.is_none() || self.keymap == source.keymap {
+					self.keymap = Some(keymap.clone());
+					self.sender.send(Message::Keymap(keymap.clone()))?;
+				}
+				source.keymap = Some(keymap);
+				Ok(())
+			}
+			Message::Key { keycode, pressed } => {
+				if source.keymap.is_some() && source.keymap != self.keymap {
+					self.keymap.clone_from(&source.keymap);
+					self.sender
+						.send(Message::Keymap(source.keymap.clone().unwrap()))?;
+				}
+				if update_held(&mut self.sources, id, keycode, pressed, |s| {
+					&mut s.held_keys
+				}) {
+					self.sender
+						.send_stamped(Message::Key { keycode, pressed }, stamp)?;
+				}
+				Ok(())
+			}
+			Message::MouseButton { button, pressed } => {
+				if update_held(&mut self.sources, id, button, pressed, |s| {
+					&mut s.held_buttons
+				}) {
+					self.sender
+						.send_stamped(Message::MouseButton { button, pressed }, stamp)?;
+				}
+				Ok(())
+			}
+			Message::TouchDown { slot, position } => {
+				if update_held(&mut self.sources, id, slot, true, |s| &mut s.held_touches) {
+					self.sender
+						.send_stamped(Message::TouchDown { slot, position }, stamp)?;
+				}
+				Ok(())
+			}
+			Message::TouchUp { slot } => {
+				if update_held(&mut self.sources, id, slot, false, |s| &mut s.held_touches) {
+					self.sender.send_stamped(Message::TouchUp { slot }, stamp)?;
+				}
+				Ok(())
+			}
+			Message::TabletTip { down } => {
+				let changed = source.tablet_tip_down != down;
+				source.tablet_tip_down = down;
+				if changed && !self.tablet_tip_down_elsewhere(id) {
+					self.sender
+						.send_stamped(Message::TabletTip { down }, stamp)?;
+				}
+				Ok(())
+			}
+			Message::DeviceAdded {
+				id: device,
+				name,
+				capabilities,
+			} => {
+				let id = device_id(&mut self.next_device, source, device);
+				let name = format!("{}: {name}", source.name);
+				self.sender.send_stamped(
+					Message::DeviceAdded {
+						id,
+						name,
+						capabilities,
+					},
+					stamp,
+				)
+			}
+			Message::DeviceRemoved { id: device } => match source.devices.remove(&device) {
+				Some(id) => self
+					.sender
+					.send_stamped(Message::DeviceRemoved { id }, stamp),
+				None => Ok(()),
+			},
+			// now that it's said what it sends
+			Message::Hello { capabilities, .. } => self.sender.send_stamped(
+				Message::DeviceAdded {
+					id: source.device,
+					name: source.name.clone(),
+					capabilities,
+				},
+				stamp,
+			),
+			Message::GamepadConnected { id: pad, name } => {
+				let id = gamepad_id(&mut self.next_gamepad, source, pad);
+				self.sender
+					.send_stamped(Message::GamepadConnected { id, name }, stamp)
+			}
+			Message::GamepadDisconnected { id: pad } => {
+				let Some(id) = source.gamepads.remove(&pad) else {
+					return Ok(());
+				};
+				source.held_gamepad_buttons.retain(|(held, _)| *held != id);
+				self.sender
+					.send_stamped(Message::GamepadDisconnected { id }, stamp)
+			}
+			Message::GamepadButton {
+				id: pad,
+				button,
+				pressed,
+			} => {
+				let id = gamepad_id(&mut self.next_gamepad, source, pad);
+				if pressed {
+					source.held_gamepad_buttons.insert((id, button));
+				} else {
+					source.held_gamepad_buttons.remove(&(id, button));
+				}
+				self.sender.send_stamped(
+					Message::GamepadButton {
+						id,
+						button,
+						pressed,
+					},
+					stamp,
+				)
+			}
+			Message::GamepadAxis {
+				id: pad,
+				axis,
+				value,
+			} => {
+				let id = gamepad_id(&mut self.next_gamepad, source, pad);
+				self.sender
+					.send_stamped(Message::GamepadAxis { id, axis, value }, stamp)
+			}
+			// a source leaving only lets go of what it was holding, the sink stays
+			Message::ResetInput | Message::Disconnect => self.release(id),
+			// the sink gets mux's own heartbeats instead
+			Message::Heartbeat => Ok(()),
+			// two sources' batches would end up interleaved, which makes them not batches anymore
+			Message::BatchBegin | Message::BatchEnd => Ok(()),
+			message => self.sender.send_stamped(message, stamp),
+		}
+	}
+
+	fn tablet_tip_down_elsewhere(&self, id: u64) -> bool {
+		self.sources
+			.iter()
+			.any(|(other, source)| *other != id && source.tablet_tip_down)
+	}
+
+	/// Unplug the source's gamepads in the sink, which lets go of their buttons too.
+	fn disconnect_gamepads(&mut self, id: u64) -> Result<(), ipc::Error> {
+		let Some(source) = self.sources.get_mut(&id) else {
+			return Ok(());
+		};
+		let stamp = source.stamp();
+		source.held_gamepad_buttons.clear();
+		for id in std::mem::take(&mut source.gamepads).into_values() {
+			self.sender
+				.send_stamped(Message::GamepadDisconnected { id }, stamp)?;
+		}
+		Ok(())
+	}
+
+	/// Tell the sink the source and its devices are gone.
+	fn remove_devices(&mut self, id: u64) -> Result<(), ipc::Error> {
+		let Some(source) = self.sources.get_mut(&id) else {
+			return Ok(());
+		};
+		for id in std::mem::take(&mut source.devices).into_values() {
+			self.sender.send(Message::DeviceRemoved { id })?;
+		}
+		self.sender
+			.send(Message::DeviceRemoved { id: source.device })
+	}
+
+	/// Release everything the source is holding that no other source is also holding.
+	fn release(&mut self, id: u64) -> Result<(), ipc::Error> {
+		let Some(source) = self.sources.get_mut(&id) else {
+			return Ok(());
+		};
+		let stamp = source.stamp();
+		let keys = std::mem::take(&mut source.held_keys);
+		let buttons = std::mem::take(&mut source.held_buttons);
+		let touches = std::mem::take(&mut source.held_touches);
+		let tablet_tip_down = std::mem::take(&mut source.tablet_tip_down);
+		let gamepad_buttons = std::mem::take(&mut source.held_gamepad_buttons);
+		for keycode in keys {
+			if !held_elsewhere(&mut self.sources, id, keycode, |s| &mut s.held_keys) {
+				self.sender.send_stamped(
+					Message::Key {
+						keycode,
+						pressed: false,
+					},
+					stamp,
+				)?;
+			}
+		}
+		for button in buttons {
+			if !held_elsewhere(&mut self.sources, id, button, |s| &mut s.held_buttons) {
+				self.sender.send_stamped(
+					Message::MouseButton {
+						button,
+						pressed: false,
+					},
+					stamp,
+				)?;
+			}
+		}
+		for slot in touches {
+			if !held_elsewhere(&mut self.sources, id, slot, |s| &mut s.held_touches) {
+				self.sender.send_stamped(Message::TouchUp { slot }, stamp)?;
+			}
+		}
+		if tablet_tip_down && !self.tablet_tip_down_elsewhere(id) {
+			self.sender
+				.send_stamped(Message::TabletTip { down: false }, stamp)?;
+		}
+		for (id, button) in gamepad_buttons {
+			self.sender.send_stamped(
+				Message::GamepadButton {
+					id,
+					button,
+					pressed: false,
+				},
+				stamp,
+			)?;
+		}
+		Ok(())
+	}
+}
+
+/// What a source that hasn't said hello yet could send.
+fn any_input() -> Capabilities {
+	Capabilities::KEYBOARD
+		| Capabilities::POINTER
+		| Capabilities::TOUCH
+		| Capabilities::TABLET
+		| Capabilities::GAMEPAD
+		| Capabilities::GESTURES
+}
+
+/// The sink's id for the source's device `device`, giving it the next free one the first time.
+fn device_id(next_device: &mut u32, source: &mut Source, device: u32) -> u32 {
+	*source.devices.entry(device).or_insert_with(|| {
+		let id = *next_device;
+		*next_device += 1;
+		id
+	})
+}
+
+/// The sink's id for the source's gamepad `pad`, giving it the next free one the first time.
+fn gamepad_id(next_gamepad: &mut u32, source: &mut Source, pad: u32) -> u32 {
+	*source.gamepads.entry(pad).or_insert_with(|| {
+		let id = *next_gamepad;
+		*next_gamepad += 1;
+		id
+	})
+}
+
+fn held_elsewhere(
+	sources: &mut BTreeMap<u64, Source>,
+	id: u64,
+	code: u32,
+	held: fn(&mut Source) -> &mut BTreeSet<u32>,
+) -> bool {
+	sources
+		.iter_mut()
+		.filter(|(other, _)| **other != id)
+		.any(|(_, source)| held(source).contains(&code))
+}
+
+/// Track a press/release from one source, returning whether the sink needs to hear about it.
+fn update_held(
+	sources: &mut BTreeMap<u64, Source>,
+	id: u64,
+	code: u32,
+	pressed: bool,
+	held: fn(&mut Source) -> &mut BTreeSet<u32>,
+) -> bool {
+	let elsewhere = held_elsewhere(sources, id, code, held);
+	let Some(source) = sources.get_mut(&id) else {
+		return false;
+	};
+	let changed = if pressed {
+		held(source).insert(code)
+	} else {
+		held(source).remove(&code)
+	};
+	changed && !elsewhere
+}
+
+#[cfg(test)]
+fn test_merger(sources: u64) -> (Merger, ipc::IpcReceiver) {
+	let (reader, writer) = std::io::pipe().unwrap();
+	let mut merger = Merger::new(Box::leak(Box::new(IpcSender::new(writer))));
+	for id in 1..=sources {
+		merger
+			.handle(id, SourceEvent::Connected(format!("source {id}")))
+			.unwrap();
+	}
+	(merger, ipc::IpcReceiver::new(reader))
+}
+
+#[cfg(test)]
+fn frame(message: Message) -> SourceEvent {
+	SourceEvent::Frame(ipc::frame_from_message(&message))
+}
+
+/// The next message that isn't about which devices there are.
+#[cfg(test)]
+async fn next_input(receiver: &ipc::IpcReceiver) -> Message {
+	loop {
+		match receiver.receive().await.unwrap() {
+			Message::DeviceAdded { .. } | Message::DeviceRemoved { .. } => (),
+			message => return message,
+		}
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_release_touches_and_tablet_tip() {
+	let (mut merger, receiver) = test_merger(2);
+	let down = |slot| Message::TouchDown {
+		slot,
+		position: [0.5, 0.5].into(),
+	};
+	for (id, message) in [
+		(1, down(0)),
+		(1, down(1)),
+		// the sink already has a finger in slot 0
+		(2, down(0)),
+		(1, Message::TabletTip { down: true }),
+		(1, Message::ResetInput),
+	] {
+		merger.handle(id, frame(message)).unwrap();
+	}
+	merger.handle(2, SourceEvent::Disconnected).unwrap();
+	merger.sender.send(Message::Disconnect).unwrap();
+	for expected in [
+		down(0),
+		down(1),
+		Message::TabletTip { down: true },
+		Message::TouchUp { slot: 1 },
+		Message::TabletTip { down: false },
+		// once the other source that had it down is gone too
+		Message::TouchUp { slot: 0 },
+		Message::Disconnect,
+	] {
+		assert_eq!(next_input(&receiver).await, expected);
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_gamepads_kept_apart() {
+	let (mut merger, receiver) = test_merger(2);
+	let connected = |id| Message::GamepadConnected {
+		id,
+		name: "Pad".to_string(),
+	};
+	let south = |id, pressed| Message::GamepadButton {
+		id,
+		button: 0x130,
+		pressed,
+	};
+	for id in [1, 2] {
+		merger.handle(id, frame(connected(0))).unwrap();
+		merger.handle(id, frame(south(0, true))).unwrap();
+	}
+	merger.handle(1, frame(Message::ResetInput)).unwrap();
+	merger.handle(2, SourceEvent::Disconnected).unwrap();
+	merger.sender.send(Message::Disconnect).unwrap();
+	for expected in [
+		connected(0),
+		south(0, true),
+		connected(1),
+		south(1, true),
+		south(0, false),
+		Message::GamepadDisconnected { id: 1 },
+		Message::Disconnect,
+	] {
+		assert_eq!(next_input(&receiver).await, expected);
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_tagged() {
+	let (mut merger, receiver) = test_merger(2);
+	let keyboard = Message::DeviceAdded {
+		id: 7,
+		name: "Keyboard".to_string(),
+		capabilities: Capabilities::KEYBOARD,
+	};
+	let press = Message::Key {
+		keycode: 30,
+		pressed: true,
+	};
+	let stamp = Stamp {
+		ts: Some(5),
+		device: Some(7),
+	};
+	merger.handle(1, frame(keyboard)).unwrap();
+	merger
+		.handle(
+			1,
+			SourceEvent::Frame(ipc::frame_from_stamped(&press, stamp)),
+		)
+		.unwrap();
+	merger
+		.handle(2, frame(Message::MouseMove([1.0, 0.0].into())))
+		.unwrap();
+	merger.handle(2, SourceEvent::Disconnected).unwrap();
+	let mut received = Vec::new();
+	for _ in 0..6 {
+		let (message, stamp) = receiver.receive_stamped().await.unwrap();
+		if message == press {
+			assert_eq!(stamp.ts, Some(5));
+		}
+		received.push((message, stamp.device));
+	}
+	assert_eq!(
+		received,
+		[
+			(
+				Message::DeviceAdded {
+					id: 0,
+					name: "source 1".to_string(),
+					capabilities: any_input(),
+				},
+				None
+			),
+			(
+				Message::DeviceAdded {
+					id: 1,
+					name: "source 2".to_string(),
+					capabilities: any_input(),
+				},
+				None
+			),
+			(
+				Message::DeviceAdded {
+					id: 2,
+					name: "source 1: Keyboard".to_string(),
+					capabilities: Capabilities::KEYBOARD,
+				},
+				Some(0)
+			),
+			(press, Some(2)),
+			(Message::MouseMove([1.0, 0.0].into()), Some(1)),
+			(Message::DeviceRemoved { id: 1 }, None),
+		]
+	);
+}
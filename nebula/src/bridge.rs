@@ -0,0 +1,235 @@
+use config::{Config, KdlValue};
+use ephemeris::keyboard::Keyboard;
+use ipc::Message;
+use logging::{debug, warn};
+use rosc::{OscMessage, OscType};
+use std::collections::HashMap;
+
+/// What messages to an OSC address turn into.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+	/// A key name or evdev keycode, held while the first argument is on.
+	Key(String),
+	Button(u32),
+	/// Mouse motion from an XY pad's position, or from deltas when relative.
+	Move {
+		scale: f32,
+		relative: bool,
+	},
+	/// Smooth scrolling from a fader's position, or from deltas when relative.
+	Scroll {
+		scale: f32,
+		relative: bool,
+	},
+}
+
+/// The address table, from the `nebula` section of the config.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mappings {
+	targets: HashMap<String, Target>,
+}
+impl Mappings {
+	pub fn read(config: &Config) -> Self {
+		let config = config.section("nebula");
+		let mut targets = HashMap::new();
+		for kind in ["key", "button", "move", "move-by", "scroll", "scroll-by"] {
+			for args in config.get_each::<KdlValue>(kind) {
+				let (Some(address), Some(value)) =
+					(args.first().and_then(KdlValue::as_string), args.get(1))
+				else {
+					warn!("Ignoring {kind} {args:?}, it needs an address and what it becomes");
+					continue;
+				};
+				let number = value.as_f64().or_else(|| value.as_i64().map(|i| i as f64));
+				let target = match (kind, number) {
+					("key", _) => Target::Key(
+						value
+							.as_string()
+							.map(str::to_string)
+							.unwrap_or_else(|| value.to_string()),
+					),
+					("button", Some(button)) => Target::Button(button as u32),
+					("move" | "move-by", Some(scale)) => Target::Move {
+						scale: scale as f32,
+						relative: kind == "move-by",
+					},
+					("scroll" | "scroll-by", Some(scale)) => Target::Scroll {
+						scale: scale as f32,
+						relative: kind == "scroll-by",
+					},
+					_ => {
+						warn!("Ignoring {kind} {address:?} {value}, it needs a number");
+						continue;
+					}
+				};
+				targets.insert(address.to_string(), target);
+			}
+		}
+		Mappings { targets }
+	}
+}
+
+/// Turns OSC messages into input, keeping track of where pads and faders were.
+#[derive(Debug, Default)]
+pub struct Bridge {
+	last: HashMap<String, [f32; 2]>,
+}
+impl Bridge {
+	pub fn handle(
+		&mut self,
+		mappings: &Mappings,
+		keyboard: &Keyboard,
+		message: &OscMessage,
+	) -> Vec<Message> {
+		let values: Vec<f32> = message.args.iter().filter_map(number).collect();
+		// TouchOSC sends ADDRESS/z when a pad is touched or let go of, so the next touch
+		// doesn't jump from where the last one ended
+		if let Some(pad) = message.addr.strip_suffix("/z") {
+			if mappings.targets.contains_key(pad) {
+				if !values.first().is_some_and(|v| *v >= 0.5) {
+					self.last.remove(pad);
+				}
+				return Vec::new();
+			}
+		}
+		let Some(target) = mappings.targets.get(&message.addr) else {
+			debug!("Nothing mapped to {}", message.addr);
+			return Vec::new();
+		};
+		let pressed = values.first().map(|v| *v >= 0.5);
+		match target {
+			Target::Key(name) => {
+				let Some(keycode) = keyboard.named_key(name) else {
+					warn!("No key called {name:?} for {}", message.addr);
+					return Vec::new();
+				};
+				press(pressed, |pressed| Message::Key { keycode, pressed })
+			}
+			Target::Button(button) => press(pressed, |pressed| Message::MouseButton {
+				button: *button,
+				pressed,
+			}),
+			Target::Move { scale, relative } => {
+				let [x, y] = match values[..] {
+					[x, y, ..] => [x, y],
+					_ => return Vec::new(),
+				};
+				self.delta(&message.addr, [x, y], *scale, *relative)
+					.map(|delta| vec![Message::MouseMove(delta.into())])
+					.unwrap_or_default()
+			}
+			Target::Scroll { scale, relative } => {
+				// a single fader scrolls vertically
+				let position = match values[..] {
+					[y] => [0.0, y],
+					[x, y, ..] => [x, y],
+					[] => return Vec::new(),
+				};
+				self.delta(&message.addr, position, *scale, *relative)
+					.map(|delta| vec![Message::MouseAxisContinuous(delta.into())])
+					.unwrap_or_default()
+			}
+		}
+	}
+
+	/// How far a position moved since the last one, or the values themselves when they're relative.
+	fn delta(
+		&mut self,
+		address: &str,
+		position: [f32; 2],
+		scale: f32,
+		relative: bool,
+	) -> Option<[f32; 2]> {
+		let [x, y] = if relative {
+			position
+		} else {
+			let [last_x, last_y] = self.last.insert(address.to_string(), position)?;
+			[position[0] - last_x, position[1] - last_y]
+		};
+		(x != 0.0 || y != 0.0).then_some([x * scale, y * scale])
+	}
+}
+
+/// Held while on, or tapped when the message has no arguments.
+fn press(pressed: Option<bool>, message: impl Fn(bool) -> Message) -> Vec<Message> {
+	match pressed {
+		Some(pressed) => vec![message(pressed)],
+		None => vec![message(true), message(false)],
+	}
+}
+
+fn number(arg: &OscType) -> Option<f32> {
+	Some(match arg {
+		OscType::Float(f) => *f,
+		OscType::Double(d) => *d as f32,
+		OscType::Int(i) => *i as f32,
+		OscType::Long(l) => *l as f32,
+		OscType::Bool(b) => *b as u8 as f32,
+		_ => return None,
+	})
+}
+
+#[test]
+fn test_bridge() {
+	let mappings = Mappings::read(
+		&Config::parse(
+			r#"
+			nebula {
+				key "/1/push1" "Return"
+				key "/1/push2" 30
+				button "/1/toggle1" 272
+				move "/1/xy" 1000
+				scroll-by "/1/encoder" 2
+				button "/broken" "left"
+			}
+			"#,
+		)
+		.unwrap(),
+	);
+	assert_eq!(mappings.targets.len(), 5);
+	let keyboard = Keyboard::new("us").unwrap();
+	let mut bridge = Bridge::default();
+	let mut handle = |addr: &str, args: Vec<OscType>| {
+		bridge.handle(
+			&mappings,
+			&keyboard,
+			&OscMessage {
+				addr: addr.to_string(),
+				args,
+			},
+		)
+	};
+	let key = |keycode, pressed| Message::Key { keycode, pressed };
+	assert_eq!(
+		handle("/1/push1", vec![OscType::Float(1.0)]),
+		[key(28, true)]
+	);
+	assert_eq!(
+		handle("/1/push1", vec![OscType::Float(0.0)]),
+		[key(28, false)]
+	);
+	assert_eq!(handle("/1/push2", vec![]), [key(30, true), key(30, false)]);
+	assert_eq!(
+		handle("/1/toggle1", vec![OscType::Bool(true)]),
+		[Message::MouseButton {
+			button: 272,
+			pressed: true
+		}]
+	);
+
+	let xy = |x, y| vec![OscType::Float(x), OscType::Float(y)];
+	assert!(handle("/1/xy", xy(0.5, 0.5)).is_empty());
+	assert_eq!(
+		handle("/1/xy", xy(0.75, 0.25)),
+		[Message::MouseMove([250.0, -250.0].into())]
+	);
+	// a new touch starts from where it lands
+	assert!(handle("/1/xy/z", vec![OscType::Float(0.0)]).is_empty());
+	assert!(handle("/1/xy", xy(0.0, 0.0)).is_empty());
+
+	assert_eq!(
+		handle("/1/encoder", vec![OscType::Int(-1)]),
+		[Message::MouseAxisContinuous([0.0, -2.0].into())]
+	);
+	assert!(handle("/unmapped", vec![OscType::Int(1)]).is_empty());
+}
@@ -0,0 +1,95 @@
+mod bridge;
+
+use bridge::{Bridge, Mappings};
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use config::reload::Live;
+use ephemeris::keyboard::Keyboard;
+use ipc::{heartbeat::HEARTBEAT_TIMEOUT, send_input_ipc, Capabilities, Error, Message};
+use logging::{debug, info, warn, LogArgs};
+use rosc::{OscMessage, OscPacket};
+use std::{
+	io::IsTerminal,
+	net::{IpAddr, SocketAddr, UdpSocket},
+	process::exit,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "Turn OSC messages from control surfaces into keyboard/mouse input")]
+struct Args {
+	/// Address to receive OSC on, e.g. 0.0.0.0:8000 for a tablet on the network
+	#[arg(long, default_value = "127.0.0.1:8000")]
+	listen: SocketAddr,
+	/// Only take OSC from these hosts, can be given more than once
+	#[arg(long, value_name = "IP")]
+	allow: Vec<IpAddr>,
+	/// Keyboard layout key names are looked up in, from XKB_DEFAULT_LAYOUT if not given
+	#[arg(long, default_value = "")]
+	layout: String,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `nebula | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	config::reload::watch();
+	let keyboard = Keyboard::new(&args.layout)
+		.ok_or_else(|| eyre!("Couldn't make a keymap for layout {:?}", args.layout))?;
+	let socket = UdpSocket::bind(args.listen)?;
+	info!("Listening for OSC on {}", args.listen);
+
+	// a button held on the surface shouldn't stay held after this stops
+	ipc::shutdown::on_signal(|| {
+		ipc::shutdown::end_stream();
+		exit(0)
+	});
+	ipc::handshake::hello(Capabilities::KEYBOARD | Capabilities::POINTER)?;
+	ipc::heartbeat::set_timeout(
+		config::reload::current()
+			.section("nebula")
+			.get_millis("heartbeat-timeout-ms", HEARTBEAT_TIMEOUT),
+	);
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keyboard.keymap_string()))?;
+
+	let mut mappings = Live::new(Mappings::read);
+	let mut bridge = Bridge::default();
+	let mut buf = vec![0; rosc::decoder::MTU];
+	loop {
+		let (len, from) = socket.recv_from(&mut buf)?;
+		if !args.allow.is_empty() && !args.allow.contains(&from.ip()) {
+			debug!("Ignoring OSC from {from}, it's not allowed");
+			continue;
+		}
+		let packet = match rosc::decoder::decode_udp(&buf[..len]) {
+			Ok((_, packet)) => packet,
+			Err(e) => {
+				debug!("Ignoring a packet from {from} that isn't OSC: {e}");
+				continue;
+			}
+		};
+		for message in messages(packet) {
+			for message in bridge.handle(mappings.get(), &keyboard, &message) {
+				match send_input_ipc(message) {
+					Ok(()) => (),
+					// the surface's input has nowhere to go anymore
+					Err(Error::Disconnected) => return Ok(()),
+					Err(e) => warn!("{e}"),
+				}
+			}
+		}
+	}
+}
+
+/// The messages in a packet, in order, with bundles opened up.
+fn messages(packet: OscPacket) -> Vec<OscMessage> {
+	match packet {
+		OscPacket::Message(message) => vec![message],
+		OscPacket::Bundle(bundle) => bundle.content.into_iter().flat_map(messages).collect(),
+	}
+}
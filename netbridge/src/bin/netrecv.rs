@@ -0,0 +1,89 @@
+use clap::Parser;
+use color_eyre::Result;
+use ipc::{message_from_frame, send_frame_ipc, send_input_ipc, Error, Message};
+use logging::{info, warn, LogArgs};
+use netbridge::{generate_token, load_token, SecureStream, DEFAULT_PORT, HANDSHAKE_TIMEOUT};
+use std::{io::IsTerminal, path::PathBuf};
+use tokio::{net::TcpListener, time::timeout};
+
+#[derive(Debug, Parser)]
+#[command(about = "Receive the input stream from netsend and write it to stdout")]
+struct Args {
+	/// Address to listen on
+	#[arg(long, default_value_t = format!("0.0.0.0:{DEFAULT_PORT}"))]
+	listen: String,
+	/// File with the token shared with netsend
+	#[arg(long, value_name = "FILE", required_unless_present = "generate_token")]
+	token_file: Option<PathBuf>,
+	/// Print a new random token to put in the token file on both machines and exit
+	#[arg(long)]
+	generate_token: bool,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	if args.generate_token {
+		println!("{}", generate_token()?);
+		return Ok(());
+	}
+	if std::io::stdout().is_terminal() {
+		panic!(
+			"You need to pipe this into an input sink e.g. `netrecv --token-file token | azimuth`"
+		);
+	}
+	let token = load_token(&args.token_file.unwrap())?;
+
+	let listener = TcpListener::bind(&args.listen).await?;
+	info!("Listening on {}", args.listen);
+	// one sender at a time, anyone else waits until it disconnects
+	loop {
+		let (stream, peer) = listener.accept().await?;
+		let mut stream =
+			match timeout(HANDSHAKE_TIMEOUT, SecureStream::accept(stream, &token)).await {
+				Ok(Ok(stream)) => stream,
+				Ok(Err(e)) => {
+					warn!("Rejected {peer}: {e}");
+					continue;
+				}
+				Err(_) => {
+					warn!("Rejected {peer}: handshake timed out");
+					continue;
+				}
+			};
+		info!("Accepted {peer}");
+		loop {
+			let frame = match stream.receive_frame().await {
+				Ok(frame) => frame,
+				Err(e) => {
+					warn!("Lost {peer}: {e}");
+					break;
+				}
+			};
+			// going through send_input_ipc keeps track of what's held so it can be released if the sender vanishes
+			let sent = match message_from_frame(&frame) {
+				Ok(Message::Disconnect) => {
+					ipc::shutdown::end_stream();
+					return Ok(());
+				}
+				Ok(message) => send_input_ipc(message),
+				Err(_) => send_frame_ipc(&frame),
+			};
+			match sent {
+				Ok(()) => (),
+				// nothing the sender sends gets anywhere anymore
+				Err(Error::Disconnected) => return Ok(()),
+				Err(e) => warn!("{e}"),
+			}
+		}
+		match send_input_ipc(Message::ResetInput) {
+			Ok(()) => (),
+			Err(Error::Disconnected) => return Ok(()),
+			Err(e) => warn!("{e}"),
+		}
+	}
+}
@@ -0,0 +1,39 @@
+use clap::Parser;
+use color_eyre::Result;
+use ipc::receive_pooled_frame_async_ipc;
+use logging::{info, LogArgs};
+use netbridge::{load_token, SecureStream, DEFAULT_PORT};
+use std::{io::IsTerminal, path::PathBuf};
+use tokio::net::TcpStream;
+
+#[derive(Debug, Parser)]
+#[command(about = "Send the input stream on stdin to netrecv over an encrypted connection")]
+struct Args {
+	/// Where netrecv is listening, e.g. headset.local:4747
+	address: String,
+	/// File with the token shared with netrecv, made by `netrecv --generate-token`
+	#[arg(long, value_name = "FILE")]
+	token_file: PathBuf,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() {
+		panic!("You need to pipe an input source into this e.g. `eclipse | netsend --token-file token headset.local:{DEFAULT_PORT}`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	let token = load_token(&args.token_file)?;
+
+	let stream = TcpStream::connect(&args.address).await?;
+	let mut stream = SecureStream::connect(stream, &token).await?;
+	info!("Connected to {}", args.address);
+
+	while let Ok(frame) = receive_pooled_frame_async_ipc().await {
+		stream.send_frame(&frame).await?;
+	}
+	Ok(())
+}
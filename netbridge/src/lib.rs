@@ -0,0 +1,160 @@
+use snow::{Builder, HandshakeState, TransportState};
+use std::{
+	io::{self, ErrorKind, Read},
+	path::Path,
+	time::Duration,
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpStream,
+};
+
+// the pre-shared token both authenticates the peers and is mixed into the session keys
+const NOISE_PARAMS: &str = "Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s";
+pub const TOKEN_LENGTH: usize = 32;
+// noise messages are at most this long, including the tag
+const MAX_MESSAGE_LENGTH: usize = 65535;
+const TAG_LENGTH: usize = 16;
+pub const DEFAULT_PORT: u16 = 4747;
+// how long a peer gets to finish the handshake before it's dropped
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type Token = [u8; TOKEN_LENGTH];
+
+/// A fresh random token, hex encoded.
+pub fn generate_token() -> io::Result<String> {
+	let mut token = [0; TOKEN_LENGTH];
+	std::fs::File::open("/dev/urandom")?.read_exact(&mut token)?;
+	Ok(token.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Read a hex encoded token as written by [`generate_token`].
+pub fn load_token(path: &Path) -> io::Result<Token> {
+	let text = std::fs::read_to_string(path)?;
+	let text = text.trim();
+	let invalid = || {
+		io::Error::new(
+			ErrorKind::InvalidData,
+			format!("token should be {} hex characters", TOKEN_LENGTH * 2),
+		)
+	};
+	if text.len() != TOKEN_LENGTH * 2 || !text.is_ascii() {
+		return Err(invalid());
+	}
+	let mut token = [0; TOKEN_LENGTH];
+	for (byte, hex) in token.iter_mut().zip(text.as_bytes().chunks(2)) {
+		let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+		*byte = u8::from_str_radix(hex, 16).map_err(|_| invalid())?;
+	}
+	Ok(token)
+}
+
+fn noise_error(error: snow::Error) -> io::Error {
+	io::Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+/// A TCP connection carrying IPC frames encrypted with noise.
+pub struct SecureStream {
+	stream: TcpStream,
+	noise: TransportState,
+	/// Decrypted bytes that haven't made up a whole frame yet.
+	plaintext: Vec<u8>,
+}
+impl SecureStream {
+	/// Handshake as the sending side.
+	pub async fn connect(mut stream: TcpStream, token: &Token) -> io::Result<Self> {
+		let mut noise = builder(token).build_initiator().map_err(noise_error)?;
+		send_handshake(&mut stream, &mut noise).await?;
+		receive_handshake(&mut stream, &mut noise).await?;
+		Self::new(stream, noise)
+	}
+
+	/// Handshake as the receiving side, failing if the peer doesn't have the same token.
+	pub async fn accept(mut stream: TcpStream, token: &Token) -> io::Result<Self> {
+		let mut noise = builder(token).build_responder().map_err(noise_error)?;
+		receive_handshake(&mut stream, &mut noise).await?;
+		send_handshake(&mut stream, &mut noise).await?;
+		Self::new(stream, noise)
+	}
+
+	fn new(stream: TcpStream, noise: HandshakeState) -> io::Result<Self> {
+		// input is tiny and latency sensitive
+		stream.set_nodelay(true)?;
+		Ok(SecureStream {
+			stream,
+			noise: noise.into_transport_mode().map_err(noise_error)?,
+			plaintext: Vec::new(),
+		})
+	}
+
+	/// Send a frame's payload as read by [`ipc::receive_frame_async_ipc`].
+	pub async fn send_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+		let mut plaintext = (frame.len() as u32).to_be_bytes().to_vec();
+		plaintext.extend_from_slice(frame);
+		// frames such as keymaps can be bigger than a noise message
+		let mut buf = vec![0; MAX_MESSAGE_LENGTH];
+		for chunk in plaintext.chunks(MAX_MESSAGE_LENGTH - TAG_LENGTH) {
+			let length = self
+				.noise
+				.write_message(chunk, &mut buf)
+				.map_err(noise_error)?;
+			write_message(&mut self.stream, &buf[..length]).await?;
+		}
+		self.stream.flush().await
+	}
+
+	/// Receive the next frame's payload, ready for [`ipc::send_frame_ipc`].
+	pub async fn receive_frame(&mut self) -> io::Result<Vec<u8>> {
+		loop {
+			if let Some(length) = self.plaintext.get(..4) {
+				let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+				if self.plaintext.len() >= 4 + length {
+					let frame = self.plaintext[4..4 + length].to_vec();
+					self.plaintext.drain(..4 + length);
+					return Ok(frame);
+				}
+			}
+			let message = read_message(&mut self.stream).await?;
+			let mut buf = vec![0; MAX_MESSAGE_LENGTH];
+			let length = self
+				.noise
+				.read_message(&message, &mut buf)
+				.map_err(noise_error)?;
+			self.plaintext.extend_from_slice(&buf[..length]);
+		}
+	}
+}
+
+fn builder(token: &Token) -> Builder<'_> {
+	Builder::new(NOISE_PARAMS.parse().unwrap()).psk(0, token)
+}
+
+async fn send_handshake(stream: &mut TcpStream, noise: &mut HandshakeState) -> io::Result<()> {
+	let mut buf = vec![0; MAX_MESSAGE_LENGTH];
+	let length = noise.write_message(&[], &mut buf).map_err(noise_error)?;
+	write_message(stream, &buf[..length]).await
+}
+
+async fn receive_handshake(stream: &mut TcpStream, noise: &mut HandshakeState) -> io::Result<()> {
+	let message = read_message(stream).await?;
+	let mut buf = vec![0; MAX_MESSAGE_LENGTH];
+	noise
+		.read_message(&message, &mut buf)
+		.map_err(noise_error)?;
+	Ok(())
+}
+
+/// Noise messages go over the wire with a u16 length prefix.
+async fn write_message(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+	stream
+		.write_all(&(message.len() as u16).to_be_bytes())
+		.await?;
+	stream.write_all(message).await
+}
+
+async fn read_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+	let length = stream.read_u16().await?;
+	let mut message = vec![0; length as usize];
+	stream.read_exact(&mut message).await?;
+	Ok(message)
+}
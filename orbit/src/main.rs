@@ -0,0 +1,197 @@
+use clap::Parser;
+use color_eyre::Result;
+use evdev::{AbsoluteAxisType, Device, InputEventKind, Key};
+use ipc::{send_input_ipc, Capabilities, Error, Message};
+use logging::{info, warn, LogArgs};
+use std::{
+	collections::HashSet,
+	io::IsTerminal,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use tokio::sync::mpsc;
+
+// how often to look for gamepads that were just plugged in or paired
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+// BTN_JOYSTICK up to the end of the BTN_GAMEPAD range, then the d-pad and extra buttons
+const BUTTONS: [std::ops::Range<u16>; 2] = [0x120..0x140, 0x220..0x2e8];
+// axes that rest at one end, unlike sticks which rest in the middle
+const TRIGGERS: [AbsoluteAxisType; 4] = [
+	AbsoluteAxisType::ABS_Z,
+	AbsoluteAxisType::ABS_RZ,
+	AbsoluteAxisType::ABS_GAS,
+	AbsoluteAxisType::ABS_BRAKE,
+];
+
+#[derive(Debug, Parser)]
+#[command(about = "Forward gamepad buttons, sticks and triggers to stdout")]
+struct Args {
+	/// Only use these devices instead of finding gamepads, e.g. /dev/input/by-id/usb-Microsoft_Controller-event-joystick
+	#[arg(long, value_name = "PATH")]
+	device: Vec<PathBuf>,
+	/// Take the gamepads away from the desktop so their input only goes to Stardust
+	#[arg(long)]
+	grab: bool,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+enum GamepadEvent {
+	Input(Message),
+	Lost(PathBuf, u32),
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `orbit | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+
+	ipc::handshake::hello(Capabilities::GAMEPAD)?;
+	ipc::heartbeat::start_heartbeat();
+
+	let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+	let mut open = HashSet::new();
+	let mut next_id = 0;
+	let mut rescan = tokio::time::interval(RESCAN_INTERVAL);
+	'events: loop {
+		tokio::select! {
+			_ = rescan.tick() => {
+				for (path, device) in find_gamepads(&args.device, &open) {
+					match listen(&path, device, next_id, args.grab, event_tx.clone()) {
+						Ok(name) => {
+							info!("Listening to {name} at {} as gamepad {next_id}", path.display());
+							let sent = send_input_ipc(Message::GamepadConnected { id: next_id, name });
+							open.insert(path);
+							next_id += 1;
+							match sent {
+								Ok(()) => (),
+								Err(Error::Disconnected) => break 'events,
+								Err(e) => warn!("{e}"),
+							}
+						}
+						Err(e) => warn!("Couldn't listen to {}: {e}", path.display()),
+					}
+				}
+			}
+			Some(event) = event_rx.recv() => {
+				let sent = match event {
+					GamepadEvent::Input(message) => send_input_ipc(message),
+					GamepadEvent::Lost(path, id) => {
+						warn!("Lost {}", path.display());
+						open.remove(&path);
+						// which lets go of its buttons, even ones held when it went out of range
+						send_input_ipc(Message::GamepadDisconnected { id })
+					}
+				};
+				match sent {
+					Ok(()) => (),
+					// the gamepads go back to the desktop
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+/// The devices given, or every device with gamepad buttons, that aren't open yet.
+fn find_gamepads(paths: &[PathBuf], open: &HashSet<PathBuf>) -> Vec<(PathBuf, Device)> {
+	if !paths.is_empty() {
+		return paths
+			.iter()
+			.filter(|path| !open.contains(*path))
+			.filter_map(|path| Some((path.clone(), Device::open(path).ok()?)))
+			.collect();
+	}
+	evdev::enumerate()
+		.filter(|(path, device)| {
+			if open.contains(path) {
+				return false;
+			}
+			let Some(keys) = device.supported_keys() else {
+				return false;
+			};
+			// joysticks without BTN_SOUTH have BTN_TRIGGER instead
+			keys.contains(Key::BTN_SOUTH) || keys.contains(Key::BTN_TRIGGER)
+		})
+		.collect()
+}
+
+/// Start passing the gamepad's input on as gamepad `id`, returning its name.
+fn listen(
+	path: &Path,
+	mut device: Device,
+	id: u32,
+	grab: bool,
+	event_tx: mpsc::UnboundedSender<GamepadEvent>,
+) -> std::io::Result<String> {
+	if grab {
+		device.grab()?;
+	}
+	let name = device.name().unwrap_or("unnamed gamepad").to_string();
+	let ranges = device
+		.get_abs_state()?
+		.map(|info| (info.minimum, info.maximum));
+	let mut events = device.into_event_stream()?;
+	let path = path.to_path_buf();
+	tokio::spawn(async move {
+		while let Ok(event) = events.next_event().await {
+			let message = match event.kind() {
+				// 2 is a repeat, which sinks do themselves
+				InputEventKind::Key(key)
+					if event.value() != 2 && BUTTONS.iter().any(|r| r.contains(&key.code())) =>
+				{
+					Message::GamepadButton {
+						id,
+						button: key.code() as u32,
+						pressed: event.value() == 1,
+					}
+				}
+				InputEventKind::AbsAxis(axis) => Message::GamepadAxis {
+					id,
+					axis: axis.0 as u32,
+					value: normalize(axis, event.value(), ranges[axis.0 as usize]),
+				},
+				_ => continue,
+			};
+			if event_tx.send(GamepadEvent::Input(message)).is_err() {
+				return;
+			}
+		}
+		let _ = event_tx.send(GamepadEvent::Lost(path, id));
+	});
+	Ok(name)
+}
+
+/// Scale a raw axis value to -1 to 1 for sticks and 0 to 1 for triggers.
+fn normalize(axis: AbsoluteAxisType, value: i32, (min, max): (i32, i32)) -> f32 {
+	if max <= min {
+		return 0.0;
+	}
+	let fraction = (value - min) as f32 / (max - min) as f32;
+	if TRIGGERS.contains(&axis) {
+		fraction.clamp(0.0, 1.0)
+	} else {
+		(fraction * 2.0 - 1.0).clamp(-1.0, 1.0)
+	}
+}
+
+#[test]
+fn test_normalize() {
+	let stick = AbsoluteAxisType::ABS_X;
+	assert_eq!(normalize(stick, -32768, (-32768, 32767)), -1.0);
+	assert_eq!(normalize(stick, 32767, (-32768, 32767)), 1.0);
+	// sticks that don't go below 0 still rest in the middle
+	assert_eq!(normalize(stick, 128, (0, 256)), 0.0);
+	assert_eq!(normalize(AbsoluteAxisType::ABS_HAT0X, -1, (-1, 1)), -1.0);
+	let trigger = AbsoluteAxisType::ABS_RZ;
+	assert_eq!(normalize(trigger, 0, (0, 1023)), 0.0);
+	assert_eq!(normalize(trigger, 1023, (0, 1023)), 1.0);
+}
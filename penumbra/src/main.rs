@@ -0,0 +1,324 @@
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use ipc::{receive_input_async_ipc, Capabilities, Message};
+use logging::{warn, LogArgs};
+use metrics::MetricsArgs;
+use std::{
+	collections::BTreeSet,
+	fs::File,
+	io::{IsTerminal, Write},
+	os::fd::{AsFd, FromRawFd},
+	time::Instant,
+};
+use wayland_client::{
+	delegate_noop,
+	globals::{registry_queue_init, GlobalListContents},
+	protocol::{
+		wl_keyboard::{KeyState, KeymapFormat},
+		wl_pointer::{Axis, AxisSource, ButtonState},
+		wl_registry::WlRegistry,
+		wl_seat::WlSeat,
+	},
+	Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+	zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+	zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+	zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1,
+	zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+use xkbcommon::xkb::{
+	Context, KeyDirection, Keycode, Keymap, State, CONTEXT_NO_FLAGS, KEYMAP_COMPILE_NO_FLAGS,
+	KEYMAP_FORMAT_TEXT_V1, STATE_LAYOUT_EFFECTIVE, STATE_MODS_DEPRESSED, STATE_MODS_LATCHED,
+	STATE_MODS_LOCKED,
+};
+
+// how far one wheel detent scrolls, matching what wlroots and weston use for real mice
+const SCROLL_PER_DETENT: f64 = 15.0;
+// evdev keycodes are offset by 8 in xkb
+const EVDEV_OFFSET: u32 = 8;
+// absolute positions go to the compositor as a fraction of this, which it maps onto the outputs
+const ABSOLUTE_EXTENT: u32 = 10_000;
+
+struct WlHandler;
+impl Dispatch<WlRegistry, GlobalListContents> for WlHandler {
+	fn event(
+		_: &mut Self,
+		_: &WlRegistry,
+		_: <WlRegistry as wayland_client::Proxy>::Event,
+		_: &GlobalListContents,
+		_: &Connection,
+		_: &QueueHandle<Self>,
+	) {
+	}
+}
+delegate_noop!(WlHandler: ignore WlSeat);
+delegate_noop!(WlHandler: ZwlrVirtualPointerManagerV1);
+delegate_noop!(WlHandler: ZwlrVirtualPointerV1);
+delegate_noop!(WlHandler: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(WlHandler: ZwpVirtualKeyboardV1);
+
+#[derive(Debug, Parser)]
+#[command(
+	about = "Drive a wlroots compositor's virtual pointer and keyboard from the input stream on stdin"
+)]
+struct Args {
+	#[command(flatten)]
+	log: LogArgs,
+	#[command(flatten)]
+	metrics: MetricsArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() {
+		panic!("You need to pipe an input source into this e.g. `eclipse | penumbra`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	metrics::init(&args.metrics)?;
+
+	let conn = Connection::connect_to_env()?;
+	let (globals, mut queue) = registry_queue_init::<WlHandler>(&conn)?;
+	let qh = queue.handle();
+	let seat: WlSeat = globals.bind(&qh, 1..=8, ())?;
+	let pointer_manager: ZwlrVirtualPointerManagerV1 = globals
+		.bind(&qh, 1..=2, ())
+		.map_err(|_| eyre!("The compositor doesn't support zwlr_virtual_pointer_manager_v1"))?;
+	let keyboard_manager: ZwpVirtualKeyboardManagerV1 = globals
+		.bind(&qh, 1..=1, ())
+		.map_err(|_| eyre!("The compositor doesn't support zwp_virtual_keyboard_manager_v1"))?;
+	let mut sink = Sink {
+		pointer: pointer_manager.create_virtual_pointer(Some(&seat), &qh, ()),
+		keyboard: keyboard_manager.create_virtual_keyboard(&seat, &qh, ()),
+		xkb_state: None,
+		held_keys: BTreeSet::new(),
+		held_buttons: BTreeSet::new(),
+		start: Instant::now(),
+	};
+	queue.roundtrip(&mut WlHandler)?;
+	// all there is is a virtual keyboard and pointer
+	ipc::handshake::consume(Capabilities::KEYBOARD | Capabilities::POINTER);
+
+	loop {
+		tokio::select! {
+			message = receive_input_async_ipc() => {
+				let Ok(message) = message else {
+					break;
+				};
+				metrics::received(message.name(), ipc::queued_frames());
+				if let Message::Disconnect = message {
+					break;
+				}
+				sink.handle(message)?;
+				conn.flush()?;
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	// don't leave anything held down in the compositor once the devices disappear
+	sink.release_all();
+	sink.pointer.destroy();
+	sink.keyboard.destroy();
+	queue.roundtrip(&mut WlHandler)?;
+	Ok(())
+}
+
+/// Translates messages into requests on a virtual pointer and keyboard.
+struct Sink {
+	pointer: ZwlrVirtualPointerV1,
+	keyboard: ZwpVirtualKeyboardV1,
+	/// Tracks modifiers to send along with keys, only once there's a keymap.
+	xkb_state: Option<State>,
+	held_keys: BTreeSet<u32>,
+	held_buttons: BTreeSet<u32>,
+	start: Instant,
+}
+impl Sink {
+	/// Event timestamps are milliseconds from an arbitrary base.
+	fn time(&self) -> u32 {
+		self.start.elapsed().as_millis() as u32
+	}
+
+	fn handle(&mut self, message: Message) -> Result<()> {
+		let time = self.time();
+		match message {
+			Message::Keymap(keymap) => self.set_keymap(&keymap)?,
+			Message::Key { keycode, pressed } => {
+				// the compositor has no idea what keys mean without a keymap
+				let Some(xkb_state) = &mut self.xkb_state else {
+					return Ok(());
+				};
+				if pressed {
+					self.held_keys.insert(keycode);
+				} else {
+					self.held_keys.remove(&keycode);
+				}
+				let direction = if pressed {
+					KeyDirection::Down
+				} else {
+					KeyDirection::Up
+				};
+				xkb_state.update_key(Keycode::new(keycode + EVDEV_OFFSET), direction);
+				let state = if pressed {
+					KeyState::Pressed
+				} else {
+					KeyState::Released
+				};
+				self.keyboard.key(time, keycode, state.into());
+				self.send_modifiers();
+			}
+			Message::MouseMove(delta) => {
+				self.pointer.motion(time, delta.x as f64, delta.y as f64);
+				self.pointer.frame();
+			}
+			Message::MousePosition(position) => {
+				let scale = |n: f32| (n.clamp(0.0, 1.0) * ABSOLUTE_EXTENT as f32) as u32;
+				self.pointer.motion_absolute(
+					time,
+					scale(position.x),
+					scale(position.y),
+					ABSOLUTE_EXTENT,
+					ABSOLUTE_EXTENT,
+				);
+				self.pointer.frame();
+			}
+			Message::MouseButton { button, pressed } => {
+				if pressed {
+					self.held_buttons.insert(button);
+				} else {
+					self.held_buttons.remove(&button);
+				}
+				let state = if pressed {
+					ButtonState::Pressed
+				} else {
+					ButtonState::Released
+				};
+				self.pointer.button(time, button, state);
+				self.pointer.frame();
+			}
+			Message::MouseAxisContinuous(scroll) => {
+				self.pointer.axis_source(AxisSource::Continuous);
+				for (axis, detents) in [
+					(Axis::HorizontalScroll, scroll.x),
+					(Axis::VerticalScroll, scroll.y),
+				] {
+					if detents != 0.0 {
+						self.pointer
+							.axis(time, axis, detents as f64 * SCROLL_PER_DETENT);
+					}
+				}
+				self.pointer.frame();
+			}
+			Message::MouseAxisDiscrete(scroll) => {
+				self.pointer.axis_source(AxisSource::Wheel);
+				for (axis, detents) in [
+					(Axis::HorizontalScroll, scroll.x),
+					(Axis::VerticalScroll, scroll.y),
+				] {
+					if detents != 0.0 {
+						self.pointer.axis_discrete(
+							time,
+							axis,
+							detents as f64 * SCROLL_PER_DETENT,
+							detents.round() as i32,
+						);
+					}
+				}
+				self.pointer.frame();
+			}
+			Message::ResetInput | Message::Disconnect => self.release_all(),
+			// virtual pointers can't pinch or turn
+			Message::Magnify(_) | Message::Rotate(_) | Message::SmartMagnify => (),
+			// the compositor repeats keys and counts clicks itself
+			Message::MultiClick { .. }
+			| Message::KeyRepeat { .. }
+			| Message::Heartbeat
+			| Message::Pause
+			| Message::Resume
+			| Message::Hover { .. } => (),
+			// it came from the desktop's clipboard in the first place
+			Message::ClipboardSet(_) => (),
+			// there's no virtual touchscreen protocol to pass them on with
+			Message::TouchDown { .. } | Message::TouchMotion { .. } | Message::TouchUp { .. } => (),
+			// nor a virtual tablet one
+			Message::TabletProximity { .. }
+			| Message::TabletAxis { .. }
+			| Message::TabletTip { .. }
+			| Message::TabletButton { .. } => (),
+			// gamepads aren't the compositor's to handle
+			Message::GamepadConnected { .. }
+			| Message::GamepadDisconnected { .. }
+			| Message::GamepadButton { .. }
+			| Message::GamepadAxis { .. } => (),
+			// the compositor sees one seat, whichever device the input came from
+			Message::DeviceAdded { .. } | Message::DeviceRemoved { .. } => (),
+			// the handshake is taken care of while receiving
+			Message::Hello { .. } => (),
+			// each request is flushed to the compositor as it comes, so there's nothing to hold back
+			Message::BatchBegin | Message::BatchEnd => (),
+		}
+		Ok(())
+	}
+
+	/// Hand the compositor the keymap through a memfd and start tracking modifiers with it.
+	fn set_keymap(&mut self, keymap: &str) -> Result<()> {
+		let Some(compiled) = Keymap::new_from_string(
+			&Context::new(CONTEXT_NO_FLAGS),
+			keymap.to_string(),
+			KEYMAP_FORMAT_TEXT_V1,
+			KEYMAP_COMPILE_NO_FLAGS,
+		) else {
+			warn!("Couldn't compile keymap, keys will be ignored");
+			return Ok(());
+		};
+		// keys held under the old keymap could mean something else under the new one
+		self.release_all();
+
+		let fd = unsafe { libc::memfd_create(c"penumbra-keymap".as_ptr(), libc::MFD_CLOEXEC) };
+		if fd < 0 {
+			return Err(std::io::Error::last_os_error().into());
+		}
+		let mut file = unsafe { File::from_raw_fd(fd) };
+		file.write_all(keymap.as_bytes())?;
+		// the compositor expects a null terminated string
+		file.write_all(&[0])?;
+		self.keyboard.keymap(
+			KeymapFormat::XkbV1.into(),
+			file.as_fd(),
+			keymap.len() as u32 + 1,
+		);
+		self.xkb_state = Some(State::new(&compiled));
+		Ok(())
+	}
+
+	fn send_modifiers(&self) {
+		let Some(xkb_state) = &self.xkb_state else {
+			return;
+		};
+		self.keyboard.modifiers(
+			xkb_state.serialize_mods(STATE_MODS_DEPRESSED),
+			xkb_state.serialize_mods(STATE_MODS_LATCHED),
+			xkb_state.serialize_mods(STATE_MODS_LOCKED),
+			xkb_state.serialize_layout(STATE_LAYOUT_EFFECTIVE),
+		);
+	}
+
+	fn release_all(&mut self) {
+		let time = self.time();
+		for keycode in std::mem::take(&mut self.held_keys) {
+			if let Some(xkb_state) = &mut self.xkb_state {
+				xkb_state.update_key(Keycode::new(keycode + EVDEV_OFFSET), KeyDirection::Up);
+			}
+			self.keyboard.key(time, keycode, KeyState::Released.into());
+		}
+		self.send_modifiers();
+		for button in std::mem::take(&mut self.held_buttons) {
+			self.pointer.button(time, button, ButtonState::Released);
+		}
+		self.pointer.frame();
+	}
+}
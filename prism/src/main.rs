@@ -0,0 +1,73 @@
+mod remap;
+
+use clap::Parser;
+use color_eyre::Result;
+use config::reload::Live;
+use ipc::{
+	message_from_frame, receive_frame_async_ipc, send_frame_async_ipc, send_input_async_ipc, Error,
+	Message,
+};
+use logging::{warn, LogArgs};
+use remap::{Remapper, Rules};
+use std::io::IsTerminal;
+
+#[derive(Debug, Parser)]
+#[command(
+	about = "Remap keys and buttons, scale motion and scrolling and turn chords into keys, between a source and a sink"
+)]
+struct Args {
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() || std::io::stdout().is_terminal() {
+		panic!("You need to put this between a source and a sink e.g. `eclipse | prism | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	config::reload::watch();
+	// so the sink can still pause the source through this
+	ipc::reverse::relay();
+
+	let mut rules = Live::new(Rules::read);
+	let mut remapper = Remapper::default();
+	'frames: loop {
+		tokio::select! {
+			frame = receive_frame_async_ipc() => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				let message = match message_from_frame(&frame) {
+					Ok(message) => message,
+					// nothing to remap in something it can't read, so pass it on as is
+					Err(_) => {
+						match send_frame_async_ipc(&frame).await {
+							Ok(()) => (),
+							Err(Error::Disconnected) => break,
+							Err(e) => warn!("{e}"),
+						}
+						continue;
+					}
+				};
+				let disconnect = message == Message::Disconnect;
+				// through send_input_async_ipc so what the remapped stream holds is released on a reset
+				for message in remapper.handle(rules.get(), message) {
+					match send_input_async_ipc(message).await {
+						Ok(()) => (),
+						Err(Error::Disconnected) => break 'frames,
+						Err(e) => warn!("{e}"),
+					}
+				}
+				if disconnect {
+					return Ok(());
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
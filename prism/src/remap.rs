@@ -0,0 +1,215 @@
+use config::Config;
+use ipc::Message;
+use logging::warn;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What to change about the stream, from the `prism` section of the config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+	keys: BTreeMap<u32, u32>,
+	buttons: BTreeMap<u32, u32>,
+	mouse_scale: [f32; 2],
+	scroll_scale: [f32; 2],
+	chords: Vec<Chord>,
+}
+
+/// Keys that are replaced by another one while they're all held.
+#[derive(Debug, Clone, PartialEq)]
+struct Chord {
+	keys: Vec<u32>,
+	output: u32,
+}
+
+impl Rules {
+	pub fn read(config: &Config) -> Self {
+		let config = config.section("prism");
+		let pairs = |key: &str| {
+			config
+				.get_each::<u32>(key)
+				.into_iter()
+				.filter_map(|codes| match codes[..] {
+					[from, to] => Some((from, to)),
+					_ => {
+						warn!("Ignoring {key} {codes:?}, it needs a code to change and what it becomes");
+						None
+					}
+				})
+				.collect()
+		};
+		// one factor for both axes, or one for each
+		let scale = |key: &str| match config.get_list::<f32>(key)[..] {
+			[] => [1.0, 1.0],
+			[both] => [both, both],
+			[x, y, ..] => [x, y],
+		};
+		let chords =
+			config
+				.get_each::<u32>("chord")
+				.into_iter()
+				.filter_map(|mut keys| {
+					if keys.len() < 2 {
+						warn!("Ignoring chord {keys:?}, it needs at least one key and what they become");
+						return None;
+					}
+					let output = keys.pop()?;
+					Some(Chord { keys, output })
+				})
+				.collect();
+		Rules {
+			keys: pairs("remap-key"),
+			buttons: pairs("remap-button"),
+			mouse_scale: scale("mouse-scale"),
+			scroll_scale: scale("scroll-scale"),
+			chords,
+		}
+	}
+}
+
+/// Applies the rules to each message, keeping track of what's held for chords.
+#[derive(Debug, Default)]
+pub struct Remapper {
+	held: BTreeSet<u32>,
+	/// Chord keys whose releases don't get passed on, since their presses were taken back.
+	swallowed: BTreeSet<u32>,
+	/// The output of the chord that's down.
+	chord_output: Option<u32>,
+}
+impl Remapper {
+	pub fn handle(&mut self, rules: &Rules, message: Message) -> Vec<Message> {
+		match message {
+			Message::Key { keycode, pressed } => {
+				let keycode = rules.keys.get(&keycode).copied().unwrap_or(keycode);
+				self.key(rules, keycode, pressed)
+			}
+			Message::MouseButton { button, pressed } => vec![Message::MouseButton {
+				button: rules.buttons.get(&button).copied().unwrap_or(button),
+				pressed,
+			}],
+			Message::MultiClick { button, count } => vec![Message::MultiClick {
+				button: rules.buttons.get(&button).copied().unwrap_or(button),
+				count,
+			}],
+			Message::MouseMove(delta) => vec![Message::MouseMove(scale(delta, rules.mouse_scale))],
+			Message::MouseAxisContinuous(scroll) => vec![Message::MouseAxisContinuous(scale(
+				scroll,
+				rules.scroll_scale,
+			))],
+			Message::MouseAxisDiscrete(scroll) => {
+				vec![Message::MouseAxisDiscrete(scale(
+					scroll,
+					rules.scroll_scale,
+				))]
+			}
+			Message::ResetInput | Message::Disconnect => {
+				*self = Remapper::default();
+				vec![message]
+			}
+			message => vec![message],
+		}
+	}
+
+	fn key(&mut self, rules: &Rules, keycode: u32, pressed: bool) -> Vec<Message> {
+		if !pressed {
+			self.held.remove(&keycode);
+			if !self.swallowed.remove(&keycode) {
+				return vec![Message::Key { keycode, pressed }];
+			}
+			// letting go of any of the chord's keys lets go of the chord
+			return match self.chord_output.take() {
+				Some(output) => vec![Message::Key {
+					keycode: output,
+					pressed: false,
+				}],
+				None => Vec::new(),
+			};
+		}
+
+		self.held.insert(keycode);
+		let completed = self.chord_output.is_none().then(|| {
+			rules.chords.iter().find(|chord| {
+				chord.keys.contains(&keycode) && chord.keys.iter().all(|k| self.held.contains(k))
+			})
+		});
+		let Some(chord) = completed.flatten() else {
+			return vec![Message::Key { keycode, pressed }];
+		};
+		// the sink already saw the rest of the chord go down, so take those back first
+		let mut messages: Vec<Message> = chord
+			.keys
+			.iter()
+			.filter(|k| **k != keycode && !self.swallowed.contains(k))
+			.map(|k| Message::Key {
+				keycode: *k,
+				pressed: false,
+			})
+			.collect();
+		self.swallowed.extend(&chord.keys);
+		self.chord_output = Some(chord.output);
+		messages.push(Message::Key {
+			keycode: chord.output,
+			pressed: true,
+		});
+		messages
+	}
+}
+
+fn scale(v: mint::Vector2<f32>, scale: [f32; 2]) -> mint::Vector2<f32> {
+	[v.x * scale[0], v.y * scale[1]].into()
+}
+
+#[test]
+fn test_remap() {
+	let rules = Rules::read(
+		&Config::parse(
+			r#"
+			prism {
+				remap-key 58 1
+				remap-button 272 273
+				remap-button 273 272
+				mouse-scale 2
+				scroll-scale 1 -1
+				// ctrl+alt+backspace becomes esc
+				chord 29 56 14 1
+			}
+			"#,
+		)
+		.unwrap(),
+	);
+	let key = |keycode, pressed| Message::Key { keycode, pressed };
+	let mut remapper = Remapper::default();
+	assert_eq!(remapper.handle(&rules, key(58, true)), [key(1, true)]);
+	assert_eq!(
+		remapper.handle(
+			&rules,
+			Message::MouseButton {
+				button: 273,
+				pressed: true
+			}
+		),
+		[Message::MouseButton {
+			button: 272,
+			pressed: true
+		}]
+	);
+	assert_eq!(
+		remapper.handle(&rules, Message::MouseMove([1.0, -1.5].into())),
+		[Message::MouseMove([2.0, -3.0].into())]
+	);
+	assert_eq!(
+		remapper.handle(&rules, Message::MouseAxisDiscrete([0.0, 1.0].into())),
+		[Message::MouseAxisDiscrete([0.0, -1.0].into())]
+	);
+
+	assert_eq!(remapper.handle(&rules, key(29, true)), [key(29, true)]);
+	assert_eq!(remapper.handle(&rules, key(56, true)), [key(56, true)]);
+	assert_eq!(
+		remapper.handle(&rules, key(14, true)),
+		[key(29, false), key(56, false), key(1, true)]
+	);
+	assert_eq!(remapper.handle(&rules, key(56, false)), [key(1, false)]);
+	assert!(remapper.handle(&rules, key(14, false)).is_empty());
+	assert!(remapper.handle(&rules, key(29, false)).is_empty());
+	assert_eq!(remapper.handle(&rules, key(14, true)), [key(14, true)]);
+
+	assert_eq!(Rules::read(&Config::default()).mouse_scale, [1.0, 1.0]);
+}
@@ -0,0 +1,178 @@
+use clap::Parser;
+use color_eyre::Result;
+use evdev::{Device, InputEventKind, Key};
+use ipc::{send_input_ipc, Capabilities, Error, Message};
+use logging::{info, warn, LogArgs};
+use std::{
+	collections::HashSet,
+	io::IsTerminal,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use tokio::sync::mpsc;
+use xkbcommon::xkb::{Context, Keymap, KEYMAP_FORMAT_TEXT_V1};
+
+// how often to look for remotes that were just plugged in or paired
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+// keys that presenter remotes and media controls send, any of these makes a device worth listening to
+const REMOTE_KEYS: [Key; 14] = [
+	Key::KEY_VOLUMEUP,
+	Key::KEY_VOLUMEDOWN,
+	Key::KEY_MUTE,
+	Key::KEY_PLAYPAUSE,
+	Key::KEY_PLAYCD,
+	Key::KEY_PAUSECD,
+	Key::KEY_STOPCD,
+	Key::KEY_NEXTSONG,
+	Key::KEY_PREVIOUSSONG,
+	Key::KEY_PAGEUP,
+	Key::KEY_PAGEDOWN,
+	Key::KEY_PRESENTATION,
+	Key::KEY_BRIGHTNESSUP,
+	Key::KEY_BRIGHTNESSDOWN,
+];
+// evdev's BTN_MISC up to the joystick buttons
+const MOUSE_BUTTONS: std::ops::Range<u32> = 0x100..0x120;
+// a device with all of these is a real keyboard, which eclipse already handles
+const KEYBOARD_KEYS: [Key; 4] = [Key::KEY_Q, Key::KEY_A, Key::KEY_Z, Key::KEY_M];
+
+#[derive(Debug, Parser)]
+#[command(about = "Forward presenter remote and media keys to stdout")]
+struct Args {
+	/// Only use these devices instead of finding remotes, e.g. /dev/input/by-id/usb-Logitech_USB_Receiver-event-kbd
+	#[arg(long, value_name = "PATH")]
+	device: Vec<PathBuf>,
+	/// Take the devices away from the desktop so their keys only go to Stardust
+	#[arg(long)]
+	grab: bool,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+enum DeviceEvent {
+	Input(Message),
+	Lost(PathBuf),
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `pulsar | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+
+	let keymap = Keymap::new_from_names(&Context::new(0), "evdev", "", "", "", None, 0)
+		.unwrap()
+		.get_as_string(KEYMAP_FORMAT_TEXT_V1);
+	ipc::handshake::hello(Capabilities::KEYBOARD | Capabilities::POINTER)?;
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keymap))?;
+
+	let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+	let mut open = HashSet::new();
+	let mut rescan = tokio::time::interval(RESCAN_INTERVAL);
+	loop {
+		tokio::select! {
+			_ = rescan.tick() => {
+				for (path, device) in find_devices(&args.device, &open) {
+					match listen(&path, device, args.grab, event_tx.clone()) {
+						Ok(name) => {
+							info!("Listening to {name} at {}", path.display());
+							open.insert(path);
+						}
+						Err(e) => warn!("Couldn't listen to {}: {e}", path.display()),
+					}
+				}
+			}
+			Some(event) = event_rx.recv() => {
+				let sent = match event {
+					DeviceEvent::Input(message) => send_input_ipc(message),
+					DeviceEvent::Lost(path) => {
+						warn!("Lost {}", path.display());
+						open.remove(&path);
+						// a remote going out of range mid-press never sends the release
+						send_input_ipc(Message::ResetInput)
+					}
+				};
+				match sent {
+					Ok(()) => (),
+					// the remotes go back to the desktop
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+/// The devices given, or every device that looks like a remote rather than a keyboard, that aren't open yet.
+fn find_devices(paths: &[PathBuf], open: &HashSet<PathBuf>) -> Vec<(PathBuf, Device)> {
+	if !paths.is_empty() {
+		return paths
+			.iter()
+			.filter(|path| !open.contains(*path))
+			.filter_map(|path| Some((path.clone(), Device::open(path).ok()?)))
+			.collect();
+	}
+	evdev::enumerate()
+		.filter(|(path, device)| {
+			if open.contains(path) {
+				return false;
+			}
+			let Some(keys) = device.supported_keys() else {
+				return false;
+			};
+			REMOTE_KEYS.iter().any(|key| keys.contains(*key))
+				&& !KEYBOARD_KEYS.iter().all(|key| keys.contains(*key))
+		})
+		.collect()
+}
+
+/// Start passing the device's keys on, returning its name.
+fn listen(
+	path: &Path,
+	mut device: Device,
+	grab: bool,
+	event_tx: mpsc::UnboundedSender<DeviceEvent>,
+) -> std::io::Result<String> {
+	if grab {
+		device.grab()?;
+	}
+	let name = device.name().unwrap_or("unnamed device").to_string();
+	let mut events = device.into_event_stream()?;
+	let path = path.to_path_buf();
+	tokio::spawn(async move {
+		while let Ok(event) = events.next_event().await {
+			let InputEventKind::Key(key) = event.kind() else {
+				continue;
+			};
+			// 2 is a key repeat, which sinks do themselves
+			if event.value() == 2 {
+				continue;
+			}
+			let (code, pressed) = (key.code() as u32, event.value() == 1);
+			// remotes with a laser pointer mode have mouse buttons too
+			let message = if MOUSE_BUTTONS.contains(&code) {
+				Message::MouseButton {
+					button: code,
+					pressed,
+				}
+			} else {
+				Message::Key {
+					keycode: code,
+					pressed,
+				}
+			};
+			if event_tx.send(DeviceEvent::Input(message)).is_err() {
+				return;
+			}
+		}
+		let _ = event_tx.send(DeviceEvent::Lost(path));
+	});
+	Ok(name)
+}
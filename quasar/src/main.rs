@@ -0,0 +1,154 @@
+#[cfg(feature = "vosk")]
+mod vosk;
+
+use clap::Parser;
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use ephemeris::{
+	keyboard::Keyboard,
+	script::{self, Command, Step},
+};
+use ipc::{send_input_ipc, Capabilities, Error, Message};
+use logging::{debug, info, warn, LogArgs};
+use std::{
+	io::{BufRead, BufReader, IsTerminal},
+	process::{exit, Stdio},
+	sync::mpsc,
+	thread::{self, sleep},
+	time::Duration,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "Type what you say, recognized locally")]
+struct Args {
+	/// Recognize speech with this vosk model directory
+	#[cfg(feature = "vosk")]
+	#[arg(long, value_name = "DIR", required_unless_present = "command")]
+	model: Option<std::path::PathBuf>,
+	/// Run this recognizer through `sh -c` and type each line it prints
+	#[arg(long, value_name = "COMMAND")]
+	command: Option<String>,
+	/// Keyboard layout to type in, from XKB_DEFAULT_LAYOUT if not given
+	#[arg(long, default_value = "")]
+	layout: String,
+	/// Milliseconds between keys while typing
+	#[arg(long, default_value_t = 12)]
+	key_delay: u64,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `quasar --model vosk-model-en-us | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	let keyboard = Keyboard::new(&args.layout)
+		.ok_or_else(|| eyre!("Couldn't make a keymap for layout {:?}", args.layout))?;
+	let key_delay = Duration::from_millis(args.key_delay);
+
+	let (utterances_tx, utterances) = mpsc::channel();
+	let recognizer = match &args.command {
+		Some(command) => {
+			let mut child = std::process::Command::new("sh")
+				.args(["-c", command])
+				.stdout(Stdio::piped())
+				.spawn()
+				.wrap_err_with(|| format!("Couldn't run {command:?}"))?;
+			let stdout = child.stdout.take().unwrap();
+			thread::spawn(move || {
+				for line in BufReader::new(stdout).lines() {
+					let Ok(line) = line else {
+						break;
+					};
+					if utterances_tx.send(line).is_err() {
+						break;
+					}
+				}
+			})
+		}
+		#[cfg(feature = "vosk")]
+		None => {
+			let model = args.model.clone().unwrap();
+			thread::spawn(move || {
+				if let Err(e) = vosk::listen(&model, utterances_tx) {
+					logging::error!("{e}");
+				}
+			})
+		}
+		#[cfg(not(feature = "vosk"))]
+		None => {
+			return Err(eyre!(
+				"Nothing to recognize speech with, give a --command or build with the vosk feature"
+			))
+		}
+	};
+
+	// don't leave anything held down if it's stopped mid-sentence
+	ipc::shutdown::on_signal(|| {
+		ipc::shutdown::end_stream();
+		exit(0)
+	});
+	ipc::handshake::hello(Capabilities::KEYBOARD)?;
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keyboard.keymap_string()))?;
+	for utterance in utterances {
+		let Some(text) = utterance_text(&utterance) else {
+			debug!("Nothing to type in {utterance:?}");
+			continue;
+		};
+		info!("Typing {text:?}");
+		// the stream has no way to send text as such, so it's typed out key by key
+		let steps = match script::steps(&[Command::Type(text)], &keyboard, key_delay) {
+			Ok(steps) => steps,
+			Err(e) => {
+				warn!("{e}");
+				continue;
+			}
+		};
+		for step in steps {
+			match step {
+				Step::Send(message) => match send_input_ipc(message) {
+					Ok(()) => (),
+					// nothing left to type into
+					Err(Error::Disconnected) => return Ok(()),
+					Err(e) => warn!("{e}"),
+				},
+				Step::Sleep(duration) => sleep(duration),
+			}
+		}
+	}
+	let _ = recognizer.join();
+	match send_input_ipc(Message::ResetInput) {
+		Ok(()) | Err(Error::Disconnected) => (),
+		Err(e) => warn!("{e}"),
+	}
+	Ok(())
+}
+
+/// What to type for an utterance, with a space after so the next one doesn't run into it, or
+/// nothing if it's only silence or noise like whisper's `[BLANK_AUDIO]` and `(wind blowing)`.
+fn utterance_text(utterance: &str) -> Option<String> {
+	let text = utterance.trim();
+	let marker = |open, close| text.starts_with(open) && text.ends_with(close);
+	if text.is_empty() || marker('[', ']') || marker('(', ')') || marker('*', '*') {
+		return None;
+	}
+	Some(format!("{text} "))
+}
+
+#[test]
+fn test_utterance_text() {
+	assert_eq!(
+		utterance_text(" hello there\n"),
+		Some("hello there ".to_string())
+	);
+	assert_eq!(utterance_text("[BLANK_AUDIO]"), None);
+	assert_eq!(utterance_text(" (wind blowing)"), None);
+	assert_eq!(utterance_text("*music*"), None);
+	assert_eq!(utterance_text(""), None);
+}
@@ -0,0 +1,68 @@
+//! Recognizing speech from the default microphone with vosk.
+
+use color_eyre::{eyre::eyre, Result};
+use cpal::{
+	traits::{DeviceTrait, HostTrait, StreamTrait},
+	SampleFormat,
+};
+use logging::{info, warn};
+use std::{path::Path, sync::mpsc};
+use vosk::{DecodingState, Model, Recognizer};
+
+/// Send each utterance heard on the default microphone, until the microphone goes away.
+pub fn listen(model: &Path, utterances: mpsc::Sender<String>) -> Result<()> {
+	let model = Model::new(model.to_string_lossy())
+		.ok_or_else(|| eyre!("Couldn't load the vosk model at {}", model.display()))?;
+	let device = cpal::default_host()
+		.default_input_device()
+		.ok_or_else(|| eyre!("No microphone to listen to"))?;
+	let config = device.default_input_config()?;
+	let channels = config.channels() as usize;
+	let mut recognizer = Recognizer::new(&model, config.sample_rate().0 as f32)
+		.ok_or_else(|| eyre!("Couldn't start recognizing at {}Hz", config.sample_rate().0))?;
+
+	// vosk wants mono 16 bit samples, so only the first channel is kept
+	let (audio_tx, audio_rx) = mpsc::channel::<Vec<i16>>();
+	let on_error = |e| warn!("Microphone error: {e}");
+	let stream = match config.sample_format() {
+		SampleFormat::I16 => device.build_input_stream(
+			&config.config(),
+			move |data: &[i16], _: &_| {
+				let _ = audio_tx.send(data.iter().step_by(channels).copied().collect());
+			},
+			on_error,
+			None,
+		)?,
+		SampleFormat::F32 => device.build_input_stream(
+			&config.config(),
+			move |data: &[f32], _: &_| {
+				let _ = audio_tx.send(
+					data.iter()
+						.step_by(channels)
+						.map(|sample| (sample * i16::MAX as f32) as i16)
+						.collect(),
+				);
+			},
+			on_error,
+			None,
+		)?,
+		format => return Err(eyre!("Can't listen to {format} samples")),
+	};
+	stream.play()?;
+	info!("Listening on {}", device.name().unwrap_or_default());
+
+	for samples in audio_rx {
+		match recognizer.accept_waveform(&samples) {
+			Ok(DecodingState::Finalized) => {
+				if let Some(result) = recognizer.result().single() {
+					if utterances.send(result.text.to_string()).is_err() {
+						break;
+					}
+				}
+			}
+			Ok(_) => (),
+			Err(e) => warn!("Couldn't recognize some audio: {e}"),
+		}
+	}
+	Ok(())
+}
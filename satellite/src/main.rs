@@ -0,0 +1,237 @@
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use ephemeris::keyboard::Keyboard;
+use input_event_codes::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT};
+use ipc::{send_input_ipc, Capabilities, Error, Message};
+use logging::{info, warn, LogArgs};
+use netbridge::{generate_token, load_token, HANDSHAKE_TIMEOUT};
+use serde::Deserialize;
+use std::{io::IsTerminal, path::PathBuf};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+	net::{TcpListener, TcpStream},
+	time::timeout,
+};
+
+// one past netrecv's, so both can run on the same machine
+const DEFAULT_PORT: u16 = 4748;
+// a hello is a token and a name, anything longer isn't one
+const MAX_HELLO_LENGTH: u64 = 1024;
+// enough for a pasted paragraph of text
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+/// What the companion app sends, one per line.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PhoneMessage {
+	Hello {
+		token: String,
+		#[serde(default)]
+		name: Option<String>,
+	},
+	Move {
+		dx: f32,
+		dy: f32,
+	},
+	Button {
+		button: PhoneButton,
+		pressed: bool,
+	},
+	Scroll {
+		dx: f32,
+		dy: f32,
+	},
+	Key {
+		keycode: u32,
+		pressed: bool,
+	},
+	Text {
+		text: String,
+	},
+	Gyro {
+		yaw: f32,
+		pitch: f32,
+	},
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PhoneButton {
+	Left,
+	Right,
+	Middle,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Take trackpad, keyboard and gyro input from a phone app and write it to stdout")]
+struct Args {
+	/// Address to listen on. Nothing is encrypted, so only listen beyond localhost (e.g. on
+	/// `0.0.0.0:4748`) on a network you trust
+	#[arg(long, default_value_t = format!("127.0.0.1:{DEFAULT_PORT}"))]
+	listen: String,
+	/// File with the token to enter in the app
+	#[arg(long, value_name = "FILE", required_unless_present = "generate_token")]
+	token_file: Option<PathBuf>,
+	/// Print a new random token to put in the token file and exit
+	#[arg(long)]
+	generate_token: bool,
+	/// How far the pointer moves for a radian of phone rotation
+	#[arg(long, default_value_t = 1000.0)]
+	gyro_speed: f32,
+	/// Keyboard layout the app's text is typed in
+	#[arg(long, default_value = "")]
+	layout: String,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	if args.generate_token {
+		println!("{}", generate_token()?);
+		return Ok(());
+	}
+	if std::io::stdout().is_terminal() {
+		panic!(
+			"You need to pipe this into an input sink e.g. `satellite --token-file token | azimuth`"
+		);
+	}
+	let token = load_token(&args.token_file.clone().unwrap())?;
+	let token = token.iter().map(|b| format!("{b:02x}")).collect::<String>();
+	let keyboard = Keyboard::new(&args.layout)
+		.ok_or_else(|| eyre!("Couldn't make a keymap for layout {:?}", args.layout))?;
+	ipc::handshake::hello(Capabilities::KEYBOARD | Capabilities::POINTER)?;
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keyboard.keymap_string()))?;
+
+	let listener = TcpListener::bind(&args.listen).await?;
+	info!("Listening on {}", args.listen);
+	// one phone at a time, anyone else waits until it disconnects
+	loop {
+		let (stream, peer) = tokio::select! {
+			connection = listener.accept() => connection?,
+			_ = ipc::shutdown::signalled() => break,
+		};
+		let mut stream = BufReader::new(stream);
+		let name = match timeout(HANDSHAKE_TIMEOUT, hello(&mut stream, &token)).await {
+			Ok(Ok(name)) => name.unwrap_or_else(|| peer.to_string()),
+			Ok(Err(e)) => {
+				warn!("Rejected {peer}: {e}");
+				continue;
+			}
+			Err(_) => {
+				warn!("Rejected {peer}: no hello in time");
+				continue;
+			}
+		};
+		info!("Accepted {name}");
+		let ended = tokio::select! {
+			result = forward(&mut stream, &args, &keyboard) => result,
+			_ = ipc::shutdown::signalled() => break,
+		};
+		match ended {
+			Ok(()) => info!("{name} disconnected"),
+			// nothing the phone sends gets anywhere anymore
+			Err(e) if matches!(e.downcast_ref(), Some(Error::Disconnected)) => break,
+			Err(e) => warn!("Lost {name}: {e}"),
+		}
+		// a phone that drops off the network mid-drag never lets go
+		match send_input_ipc(Message::ResetInput) {
+			Ok(()) => (),
+			Err(Error::Disconnected) => break,
+			Err(e) => warn!("{e}"),
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+type Stream = BufReader<TcpStream>;
+
+/// The next line, or `None` once the phone closes the connection. A line longer than `limit`
+/// is an error rather than something to keep buffering.
+async fn next_line(stream: &mut Stream, limit: u64) -> Result<Option<String>> {
+	let mut line = String::new();
+	let read = (&mut *stream).take(limit).read_line(&mut line).await?;
+	if read as u64 == limit && !line.ends_with('\n') {
+		return Err(eyre!("sent a line longer than {limit} bytes"));
+	}
+	Ok((read > 0).then_some(line))
+}
+
+/// Check the first message is a hello with the right token, returning the phone's name.
+async fn hello(stream: &mut Stream, token: &str) -> Result<Option<String>> {
+	let line = next_line(stream, MAX_HELLO_LENGTH)
+		.await?
+		.ok_or_else(|| eyre!("closed before saying hello"))?;
+	match serde_json::from_str(&line)? {
+		PhoneMessage::Hello {
+			token: phone_token,
+			name,
+		} if phone_token.trim().eq_ignore_ascii_case(token) => Ok(name),
+		PhoneMessage::Hello { .. } => Err(eyre!("wrong token")),
+		_ => Err(eyre!("didn't start with a hello")),
+	}
+}
+
+/// Pass the phone's messages on until it disconnects.
+async fn forward(stream: &mut Stream, args: &Args, keyboard: &Keyboard) -> Result<()> {
+	while let Some(line) = next_line(stream, MAX_LINE_LENGTH).await? {
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+		let message = match serde_json::from_str(line) {
+			Ok(message) => message,
+			Err(e) => {
+				warn!("Ignoring {line:?}: {e}");
+				continue;
+			}
+		};
+		match message {
+			PhoneMessage::Hello { .. } => (),
+			PhoneMessage::Move { dx, dy } => send_input_ipc(Message::MouseMove([dx, dy].into()))?,
+			PhoneMessage::Button { button, pressed } => send_input_ipc(Message::MouseButton {
+				button: match button {
+					PhoneButton::Left => BTN_LEFT!(),
+					PhoneButton::Right => BTN_RIGHT!(),
+					PhoneButton::Middle => BTN_MIDDLE!(),
+				},
+				pressed,
+			})?,
+			PhoneMessage::Scroll { dx, dy } => {
+				send_input_ipc(Message::MouseAxisContinuous([dx, dy].into()))?
+			}
+			PhoneMessage::Key { keycode, pressed } => {
+				send_input_ipc(Message::Key { keycode, pressed })?
+			}
+			PhoneMessage::Text { text } => {
+				for c in text.chars() {
+					let Some(keys) = keyboard.char_keys(c) else {
+						continue;
+					};
+					for keycode in &keys {
+						send_input_ipc(Message::Key {
+							keycode: *keycode,
+							pressed: true,
+						})?;
+					}
+					for keycode in keys.iter().rev() {
+						send_input_ipc(Message::Key {
+							keycode: *keycode,
+							pressed: false,
+						})?;
+					}
+				}
+			}
+			// turning right or tilting up moves the pointer right or up
+			PhoneMessage::Gyro { yaw, pitch } => send_input_ipc(Message::MouseMove(
+				[-yaw * args.gyro_speed, -pitch * args.gyro_speed].into(),
+			))?,
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,88 @@
+//! `--record-calls`: a line of JSON for every call simular makes on a handler, for working out
+//! whether input that never showed up in an app was sent to it at all.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+use stardust_xr_fusion::{data::PulseReceiver, node::NodeType};
+use std::{
+	fmt::Display,
+	fs::File,
+	io::{LineWriter, Write},
+	path::Path,
+	sync::OnceLock,
+	time::Instant,
+};
+
+static LOG: OnceLock<Mutex<CallLog>> = OnceLock::new();
+
+struct CallLog {
+	// written a line at a time so the log is complete up to whatever went wrong
+	file: LineWriter<File>,
+	start: Instant,
+}
+
+#[derive(Serialize)]
+struct Call<'a> {
+	/// Since simular started recording.
+	time_ms: f64,
+	/// The id of the node the call was made on, if it was made on one.
+	target: Option<u64>,
+	method: &'a str,
+	args: Value,
+	latency_ms: f64,
+	result: String,
+}
+
+/// Start recording calls to `path`, replacing whatever's there.
+pub fn init(path: &Path) -> std::io::Result<()> {
+	let file = LineWriter::new(File::create(path)?);
+	let _ = LOG.set(Mutex::new(CallLog {
+		file,
+		start: Instant::now(),
+	}));
+	Ok(())
+}
+
+/// Record a call that was started at `started`, if calls are being recorded.
+///
+/// `args` is only worked out when they are.
+pub fn record(
+	target: Option<u64>,
+	method: &str,
+	args: impl FnOnce() -> Value,
+	started: Instant,
+	result: impl Display,
+) {
+	let latency = started.elapsed();
+	let Some(log) = LOG.get() else {
+		return;
+	};
+	let mut log = log.lock();
+	let call = Call {
+		time_ms: started.duration_since(log.start).as_secs_f64() * 1000.0,
+		target,
+		method,
+		args: args(),
+		latency_ms: latency.as_secs_f64() * 1000.0,
+		result: result.to_string(),
+	};
+	let Ok(line) = serde_json::to_string(&call) else {
+		return;
+	};
+	let _ = writeln!(log.file, "{line}");
+}
+
+/// Send `event` to `receiver` with `send`, recording it.
+pub fn pulse<E: Serialize>(receiver: &PulseReceiver, event: &E, send: impl FnOnce()) {
+	let started = Instant::now();
+	send();
+	record(
+		receiver.node().get_id().ok(),
+		"send_event",
+		|| serde_json::to_value(event).unwrap_or_default(),
+		started,
+		// pulses aren't answered, so there's nothing more to go on
+		"sent",
+	);
+}
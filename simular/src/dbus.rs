@@ -0,0 +1,35 @@
+use zbus::{connection, interface, Connection};
+
+const PATH: &str = "/org/stardustxr/Simular";
+
+/// What was last copied on the source's desktop, for pasting into what's in XR.
+#[derive(Default)]
+pub struct Clipboard(String);
+#[interface(name = "org.stardustxr.Simular.Clipboard")]
+impl Clipboard {
+	/// The copied text, empty until the source sends some.
+	#[zbus(property)]
+	fn text(&self) -> String {
+		self.0.clone()
+	}
+}
+
+/// Serve [`Clipboard`] on the session bus for as long as the connection is kept.
+pub async fn serve() -> zbus::Result<Connection> {
+	connection::Builder::session()?
+		.name("org.stardustxr.Simular")?
+		.serve_at(PATH, Clipboard::default())?
+		.build()
+		.await
+}
+
+/// Offer `text` as the clipboard, letting anyone watching the property know.
+pub async fn set_clipboard(connection: &Connection, text: String) -> zbus::Result<()> {
+	let clipboard = connection
+		.object_server()
+		.interface::<_, Clipboard>(PATH)
+		.await?;
+	let mut current = clipboard.get_mut().await;
+	current.0 = text;
+	current.text_changed(clipboard.signal_context()).await
+}
@@ -0,0 +1,645 @@
+mod calls;
+mod dbus;
+mod handlers;
+mod motion;
+mod predict;
+mod rate;
+
+use clap::Parser;
+use color_eyre::Result;
+use config::{reload::Live, Config};
+use glam::Quat;
+use handlers::PulseReceiverCollector;
+use ipc::{heartbeat::HEARTBEAT_TIMEOUT, receive_input_async_ipc, Capabilities};
+use keymap_cache::KeymapCache;
+use logging::{debug, info, warn, LogArgs};
+use metrics::MetricsArgs;
+use parking_lot::Mutex;
+use predict::Prediction;
+use rate::{Motion, RateLimit};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use stardust_xr_fusion::{
+	client::Client,
+	core::values::Vector2,
+	data::{PulseReceiver, PulseSender, PulseSenderAspect},
+	fields::{FieldRefAspect, RayMarchResult},
+	node::NodeType,
+	objects::hmd,
+	root::{ClientState, FrameInfo, Root, RootAspect, RootHandler},
+	spatial::{SpatialRef, SpatialRefAspect, Transform},
+};
+use stardust_xr_molecules::{
+	keyboard::{KeyboardEvent, KEYBOARD_MASK},
+	mouse::{MouseEvent, MOUSE_MASK},
+};
+use std::{
+	io::IsTerminal,
+	path::PathBuf,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+use tokio::{
+	sync::{watch, Notify},
+	task::JoinSet,
+	time::{sleep_until, timeout},
+};
+
+// how long a single receiver gets to answer a ray march before it's skipped for this frame
+const RAY_MARCH_TIMEOUT: Duration = Duration::from_millis(50);
+// mouse motion further than this in pixels is sent in steps, 0 sends it as it comes
+const MOTION_STEP: f32 = 8.0;
+// how many times a second a handler can be sent motion or scrolling, 0 for no limit
+const MAX_MOTION_RATE: f32 = 0.0;
+// how far ahead of a turning head to aim, 0 aims straight ahead and saves asking where the head is
+const PREDICT_AHEAD: Duration = Duration::ZERO;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PointerDatamap {
+	select: f32,
+	grab: f32,
+	scroll_continuous: Vector2<f32>,
+	scroll_discrete: Vector2<f32>,
+}
+impl Default for PointerDatamap {
+	fn default() -> Self {
+		Self {
+			select: 0.0,
+			grab: 0.0,
+			scroll_continuous: [0.0; 2].into(),
+			scroll_discrete: [0.0; 2].into(),
+		}
+	}
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Beam the mouse and keyboard input on stdin to wherever you're looking")]
+pub struct Args {
+	#[command(flatten)]
+	pub log: LogArgs,
+	#[command(flatten)]
+	pub metrics: MetricsArgs,
+	/// Read input from the source serving a socket in $XDG_RUNTIME_DIR called NAME, instead of stdin
+	#[arg(long, value_name = "NAME")]
+	pub socket: Option<String>,
+	/// Write a line of JSON to this file for every call made on a handler, to see what an app was sent
+	#[arg(long, value_name = "FILE")]
+	pub record_calls: Option<PathBuf>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+pub async fn run(args: Args) -> Result<()> {
+	match &args.socket {
+		Some(name) => ipc::socket::connect(name),
+		None if std::io::stdin().is_terminal() => {
+			panic!(
+				"You need to pipe azimuth or eclipse's output into this e.g. `eclipse | azimuth`"
+			)
+		}
+		None => (),
+	}
+	// console_subscriber::init();
+	color_eyre::install().unwrap();
+	logging::init(&args.log)?;
+	metrics::init(&args.metrics)?;
+	if let Some(path) = &args.record_calls {
+		calls::init(path)?;
+	}
+	config::reload::watch();
+	ipc::heartbeat::set_timeout(
+		config::reload::current()
+			.section("simular")
+			.get_millis("heartbeat-timeout-ms", HEARTBEAT_TIMEOUT),
+	);
+	let ray_march_timeout = Live::new(|config: &Config| {
+		config
+			.section("simular")
+			.get_millis("ray-march-timeout-ms", RAY_MARCH_TIMEOUT)
+	});
+	let motion_step =
+		Live::new(|config: &Config| config.section("simular").get("motion-step", MOTION_STEP));
+	let predict_ahead = Live::new(|config: &Config| {
+		config
+			.section("simular")
+			.get_millis("predict-ahead-ms", PREDICT_AHEAD)
+	});
+	let max_motion_rate = Live::new(|config: &Config| {
+		config
+			.section("simular")
+			.get("max-motion-rate", MAX_MOTION_RATE)
+	});
+	let (client, event_loop) = Client::connect_with_async_loop()
+		.await
+		.expect("Couldn't connect");
+	let hmd = hmd(&client).await.unwrap();
+
+	// Pointer stuff
+	let mouse_sender = PulseSender::create(&hmd, Transform::identity(), &MOUSE_MASK)?
+		.wrap(PulseReceiverCollector::default())?;
+	let (hovered_mouse_tx, hovered_mouse) = watch::channel::<Option<PulseReceiver>>(None);
+
+	// Keyboard stuff
+	let keyboard_sender = PulseSender::create(&hmd, Transform::identity(), &KEYBOARD_MASK)?
+		.wrap(PulseReceiverCollector::default())?;
+	let (hovered_keyboard_tx, hovered_keyboard) = watch::channel::<Option<PulseReceiver>>(None);
+
+	let frame_notifier = Arc::new(Notify::new());
+	let _client_root = client.get_root().alias().wrap(FrameNotifier(
+		frame_notifier.clone(),
+		client.get_root().alias(),
+	))?;
+
+	let dbus = dbus::serve()
+		.await
+		.map_err(|e| warn!("Can't share the clipboard over D-Bus: {e}"))
+		.ok();
+
+	let input_loop = tokio::task::spawn(input_loop(
+		client.clone(),
+		keyboard_sender.node().alias(),
+		hovered_keyboard,
+		mouse_sender.node().alias(),
+		hovered_mouse,
+		dbus,
+		MotionQueue {
+			sender: mouse_sender.node().alias(),
+			limits: FxHashMap::default(),
+			max_rate: max_motion_rate,
+			step: motion_step,
+		},
+	));
+	tokio::task::spawn(pointer_frame_loop(
+		frame_notifier.clone(),
+		hmd.alias(),
+		client.get_root().alias(),
+		mouse_sender.wrapped().clone(),
+		hovered_mouse_tx,
+		ray_march_timeout.clone(),
+		predict_ahead.clone(),
+	));
+	tokio::task::spawn(keyboard_frame_loop(
+		frame_notifier.clone(),
+		hmd.alias(),
+		client.get_root().alias(),
+		keyboard_sender.wrapped().clone(),
+		hovered_keyboard_tx,
+		ray_march_timeout,
+		predict_ahead,
+	));
+
+	tokio::select! {
+		biased;
+		e = event_loop => e?.map_err(|e| e.into()),
+		_ = input_loop => Ok(()),
+	}
+}
+
+async fn input_loop(
+	client: Arc<Client>,
+	keyboard_sender: PulseSender,
+	hovered_keyboard: watch::Receiver<Option<PulseReceiver>>,
+	mouse_sender: PulseSender,
+	hovered_mouse: watch::Receiver<Option<PulseReceiver>>,
+	dbus: Option<zbus::Connection>,
+	mut motion_queue: MotionQueue,
+) {
+	let mut keymap_id: Option<u64> = None;
+	let mut mouse_state = MouseEvent {
+		raw_input_events: Some(FxHashSet::default()),
+		..Default::default()
+	};
+	let mut held_keys = FxHashSet::default();
+	let mut keymap_cache = KeymapCache::open();
+
+	// keyboards and mice are all the handlers get
+	ipc::handshake::consume(Capabilities::KEYBOARD | Capabilities::POINTER);
+	let next_message = receive_input_async_ipc();
+	tokio::pin!(next_message);
+	let signalled = ipc::shutdown::signalled();
+	tokio::pin!(signalled);
+	loop {
+		let due = motion_queue.due();
+		let message = tokio::select! {
+			message = &mut next_message => message,
+			// let go of everything just like when the source disconnects
+			_ = &mut signalled => Ok(ipc::Message::Disconnect),
+			_ = sleep_until(due.unwrap_or_else(Instant::now).into()), if due.is_some() => {
+				motion_queue.send_due();
+				continue;
+			}
+		};
+		next_message.set(receive_input_async_ipc());
+		let Ok(message) = message else {
+			break;
+		};
+		metrics::received(message.name(), ipc::queued_frames());
+		match message {
+			ipc::Message::Keymap(keymap) => {
+				if let Some(cached_id) = keymap_cache.get(&keymap) {
+					info!(cached_id, "Keymap was registered before");
+					keymap_id.replace(cached_id);
+					continue;
+				}
+				// keys sent while the keymap registers would otherwise pile up behind it
+				let _paused = ipc::flow::pause_source();
+				let started = Instant::now();
+				let length = keymap.len();
+				let result = match client.register_xkb_keymap(keymap.clone()) {
+					Ok(future) => future.await,
+					Err(e) => Err(e),
+				};
+				calls::record(
+					None,
+					"register_xkb_keymap",
+					|| json!({ "length": length }),
+					started,
+					match &result {
+						Ok(id) => id.to_string(),
+						Err(e) => e.to_string(),
+					},
+				);
+				let Ok(new_keymap_id) = result else {
+					continue;
+				};
+				info!(new_keymap_id, "Registered keymap");
+				keymap_cache.insert(&keymap, new_keymap_id);
+				keymap_id.replace(new_keymap_id);
+			}
+			ipc::Message::Key { keycode, pressed } => {
+				debug!(hovered = ?*hovered_keyboard.borrow(), "Key for hovered keyboard");
+				if pressed {
+					held_keys.insert(keycode);
+				} else {
+					held_keys.remove(&keycode);
+				}
+				let Some(hovered_keyboard) = &*hovered_keyboard.borrow() else {
+					continue;
+				};
+				let Some(keymap_id) = keymap_id else {
+					continue;
+				};
+
+				let event = KeyboardEvent {
+					keyboard: (),
+					xkbv1: (),
+					keymap_id,
+					keys: vec![if pressed {
+						keycode as i32
+					} else {
+						-(keycode as i32)
+					}]
+					.into_iter()
+					.collect(),
+				};
+				calls::pulse(hovered_keyboard, &event, || {
+					event.send_event(&keyboard_sender, &[hovered_keyboard])
+				})
+			}
+			ipc::Message::MouseMove(delta) => {
+				let Some(hovered_mouse) = &*hovered_mouse.borrow() else {
+					continue;
+				};
+				motion_queue.push(
+					hovered_mouse,
+					Motion {
+						delta: delta.into(),
+						..Default::default()
+					},
+				)
+			}
+			ipc::Message::MouseButton { button, pressed } => {
+				let Some(hovered_mouse) = &*hovered_mouse.borrow() else {
+					continue;
+				};
+				// the click lands where the motion before it left the pointer
+				motion_queue.send_now(hovered_mouse);
+				let raw_input_events = mouse_state.raw_input_events.as_mut().unwrap();
+				if pressed {
+					raw_input_events.insert(button);
+				} else {
+					raw_input_events.remove(&button);
+				}
+				calls::pulse(hovered_mouse, &mouse_state, || {
+					mouse_state.send_event(&mouse_sender, &[hovered_mouse])
+				})
+			}
+			ipc::Message::MouseAxisContinuous(scroll) => {
+				let Some(hovered_mouse) = &*hovered_mouse.borrow() else {
+					continue;
+				};
+				motion_queue.push(
+					hovered_mouse,
+					Motion {
+						scroll_continuous: scroll.into(),
+						..Default::default()
+					},
+				)
+			}
+			ipc::Message::MouseAxisDiscrete(scroll) => {
+				let Some(hovered_mouse) = &*hovered_mouse.borrow() else {
+					continue;
+				};
+				motion_queue.push(
+					hovered_mouse,
+					Motion {
+						scroll_discrete: scroll.into(),
+						..Default::default()
+					},
+				)
+			}
+			// also what a source that stopped sending heartbeats turns into, so let go of everything
+			// here rather than counting on the source to send the releases
+			message @ (ipc::Message::ResetInput | ipc::Message::Disconnect) => {
+				if let (Some(hovered_keyboard), Some(keymap_id)) =
+					(&*hovered_keyboard.borrow(), keymap_id)
+				{
+					let event = KeyboardEvent {
+						keyboard: (),
+						xkbv1: (),
+						keymap_id,
+						keys: held_keys.iter().map(|k| -(*k as i32)).collect(),
+					};
+					calls::pulse(hovered_keyboard, &event, || {
+						event.send_event(&keyboard_sender, &[hovered_keyboard])
+					})
+				}
+				held_keys.clear();
+				mouse_state.raw_input_events.as_mut().unwrap().clear();
+				if let Some(hovered_mouse) = &*hovered_mouse.borrow() {
+					motion_queue.send_now(hovered_mouse);
+					calls::pulse(hovered_mouse, &mouse_state, || {
+						mouse_state.send_event(&mouse_sender, &[hovered_mouse])
+					})
+				}
+				if message == ipc::Message::Disconnect {
+					break;
+				}
+			}
+			// handlers only take mouse motion as deltas, count clicks themselves and can't be pinched
+			ipc::Message::MousePosition(_)
+			| ipc::Message::MultiClick { .. }
+			| ipc::Message::Magnify(_)
+			| ipc::Message::Rotate(_)
+			| ipc::Message::SmartMagnify
+			| ipc::Message::KeyRepeat { .. }
+			| ipc::Message::Heartbeat
+			| ipc::Message::Pause
+			| ipc::Message::Resume
+			| ipc::Message::Hover { .. }
+			| ipc::Message::TouchDown { .. }
+			| ipc::Message::TouchMotion { .. }
+			| ipc::Message::TouchUp { .. }
+			| ipc::Message::TabletProximity { .. }
+			| ipc::Message::TabletAxis { .. }
+			| ipc::Message::TabletTip { .. }
+			| ipc::Message::TabletButton { .. }
+			| ipc::Message::GamepadConnected { .. }
+			| ipc::Message::GamepadDisconnected { .. }
+			| ipc::Message::GamepadButton { .. }
+			| ipc::Message::GamepadAxis { .. }
+			| ipc::Message::DeviceAdded { .. }
+			| ipc::Message::DeviceRemoved { .. }
+			| ipc::Message::Hello { .. } => (),
+			// each event goes to handlers as it comes, there's no frame to hold it back for
+			ipc::Message::BatchBegin | ipc::Message::BatchEnd => (),
+			ipc::Message::ClipboardSet(text) => {
+				let Some(dbus) = &dbus else {
+					continue;
+				};
+				if let Err(e) = dbus::set_clipboard(dbus, text).await {
+					warn!("Couldn't share the clipboard over D-Bus: {e}");
+				}
+			}
+		}
+	}
+}
+
+/// Motion and scrolling on their way to handlers, held back and merged for any that have had as
+/// much as `max-motion-rate` allows.
+struct MotionQueue {
+	sender: PulseSender,
+	/// By handler id, so what a handler is owed still reaches it once the pointer moves off.
+	limits: FxHashMap<u64, (PulseReceiver, RateLimit)>,
+	max_rate: Live<f32>,
+	step: Live<f32>,
+}
+impl MotionQueue {
+	fn push(&mut self, receiver: &PulseReceiver, motion: Motion) {
+		let Ok(id) = receiver.node().get_id() else {
+			return;
+		};
+		let max_rate = *self.max_rate.get();
+		let now = Instant::now();
+		// handlers that were hovered a while ago and are owed nothing needn't be kept around
+		self.limits.retain(|_, (_, limit)| !limit.idle(now));
+		let (receiver, limit) = self
+			.limits
+			.entry(id)
+			.or_insert_with(|| (receiver.alias(), RateLimit::default()));
+		if let Some(motion) = limit.push(motion, max_rate, now) {
+			send_motion(&self.sender, receiver, motion, *self.step.get());
+		}
+	}
+
+	/// Send `receiver` everything it's owed right away, e.g. before a click.
+	fn send_now(&mut self, receiver: &PulseReceiver) {
+		let Ok(id) = receiver.node().get_id() else {
+			return;
+		};
+		let Some((receiver, limit)) = self.limits.get_mut(&id) else {
+			return;
+		};
+		if let Some(motion) = limit.take() {
+			send_motion(&self.sender, receiver, motion, *self.step.get());
+		}
+	}
+
+	/// When the next handler that's owed something can be sent it.
+	fn due(&mut self) -> Option<Instant> {
+		let max_rate = *self.max_rate.get();
+		self.limits
+			.values()
+			.filter_map(|(_, limit)| limit.due(max_rate))
+			.min()
+	}
+
+	fn send_due(&mut self) {
+		let max_rate = *self.max_rate.get();
+		let step = *self.step.get();
+		let now = Instant::now();
+		for (receiver, limit) in self.limits.values_mut() {
+			if limit.due(max_rate).map_or(true, |due| due > now) {
+				continue;
+			}
+			if let Some(motion) = limit.push(Motion::default(), max_rate, now) {
+				send_motion(&self.sender, receiver, motion, step);
+			}
+		}
+	}
+}
+
+fn send_motion(sender: &PulseSender, receiver: &PulseReceiver, motion: Motion, step: f32) {
+	let non_zero = |v: [f32; 2]| (v != [0.0; 2]).then(|| v.into());
+	// scrolling goes with the first step of the motion
+	let mut scroll = Some((motion.scroll_continuous, motion.scroll_discrete));
+	for delta in motion::steps(motion.delta, step) {
+		let (scroll_continuous, scroll_discrete) = scroll.take().unwrap_or_default();
+		let event = MouseEvent {
+			delta: non_zero(delta),
+			scroll_continuous: non_zero(scroll_continuous),
+			scroll_discrete: non_zero(scroll_discrete),
+			..Default::default()
+		};
+		calls::pulse(receiver, &event, || event.send_event(sender, &[receiver]))
+	}
+}
+
+async fn pointer_frame_loop(
+	frame_notifier: Arc<Notify>,
+	hmd: SpatialRef,
+	root: Root,
+	mouse_sender: Arc<Mutex<PulseReceiverCollector>>,
+	hovered_mouse_tx: watch::Sender<Option<PulseReceiver>>,
+	mut ray_march_timeout: Live<Duration>,
+	mut predict_ahead: Live<Duration>,
+) {
+	let mut prediction = Prediction::default();
+	loop {
+		frame_notifier.notified().await;
+		let direction = aim(&hmd, &root, &mut prediction, *predict_ahead.get()).await;
+		detect_hover(
+			hmd.alias(),
+			mouse_sender.clone(),
+			&hovered_mouse_tx,
+			*ray_march_timeout.get(),
+			direction,
+			"mouse",
+		)
+		.await
+	}
+}
+
+async fn keyboard_frame_loop(
+	frame_notifier: Arc<Notify>,
+	hmd: SpatialRef,
+	root: Root,
+	keyboard_sender: Arc<Mutex<PulseReceiverCollector>>,
+	hovered_keyboard_tx: watch::Sender<Option<PulseReceiver>>,
+	mut ray_march_timeout: Live<Duration>,
+	mut predict_ahead: Live<Duration>,
+) {
+	let mut prediction = Prediction::default();
+	loop {
+		frame_notifier.notified().await;
+		let direction = aim(&hmd, &root, &mut prediction, *predict_ahead.get()).await;
+		detect_hover(
+			hmd.alias(),
+			keyboard_sender.clone(),
+			&hovered_keyboard_tx,
+			*ray_march_timeout.get(),
+			direction,
+			"keyboard",
+		)
+		.await
+	}
+}
+
+/// Which way to ray march from the head, straight ahead unless it's turning and `ahead` isn't 0.
+async fn aim(
+	hmd: &SpatialRef,
+	root: &Root,
+	prediction: &mut Prediction,
+	ahead: Duration,
+) -> [f32; 3] {
+	if ahead.is_zero() {
+		*prediction = Prediction::default();
+		return [0.0, 0.0, -1.0];
+	}
+	let Ok(Some(rotation)) = hmd.get_transform(root).await.map(|t| t.rotation) else {
+		return [0.0, 0.0, -1.0];
+	};
+	let rotation = Quat::from_xyzw(rotation.v.x, rotation.v.y, rotation.v.z, rotation.s);
+	prediction
+		.direction(rotation, Instant::now(), ahead)
+		.to_array()
+}
+
+async fn detect_hover(
+	hmd: SpatialRef,
+	sender: Arc<Mutex<PulseReceiverCollector>>,
+	hovered_tx: &watch::Sender<Option<PulseReceiver>>,
+	ray_march_timeout: Duration,
+	// in the head's space
+	direction: [f32; 3],
+	// what's being hovered, for the metrics
+	target: &'static str,
+) {
+	let mut closest_hit: Option<(PulseReceiver, RayMarchResult)> = None;
+	let mut join = JoinSet::new();
+	for (receiver, field) in sender.lock().0.values() {
+		let receiver = receiver.alias();
+		let field = field.alias();
+		let hmd = hmd.alias();
+		join.spawn(async move {
+			let start = Instant::now();
+			let result = timeout(
+				ray_march_timeout,
+				field.ray_march(&hmd, [0.0; 3], direction),
+			)
+			.await;
+			metrics::ray_marched(start.elapsed(), result.is_err());
+			calls::record(
+				field.node().get_id().ok(),
+				"ray_march",
+				|| json!({ "origin": [0.0, 0.0, 0.0], "direction": direction }),
+				start,
+				match &result {
+					Ok(Ok(ray_info)) => format!(
+						"min distance {}, deepest point distance {}",
+						ray_info.min_distance, ray_info.deepest_point_distance
+					),
+					Ok(Err(e)) => e.to_string(),
+					Err(_) => "timed out".to_string(),
+				},
+			);
+			(receiver, result)
+		});
+	}
+
+	while let Some(res) = join.join_next().await {
+		let Ok((receiver, Ok(Ok(ray_info)))) = res else {
+			continue;
+		};
+		if ray_info.min_distance > 0.0 || ray_info.deepest_point_distance <= 0.001 {
+			continue;
+		}
+		if let Some((hit_receiver, hit_info)) = &mut closest_hit {
+			if ray_info.deepest_point_distance < hit_info.deepest_point_distance {
+				*hit_receiver = receiver;
+				*hit_info = ray_info;
+			}
+		} else {
+			closest_hit.replace((receiver, ray_info));
+		}
+	}
+	let hovered = closest_hit.map(|(r, _)| r);
+	if receiver_id(&hovered) != receiver_id(&hovered_tx.borrow()) {
+		metrics::FOCUS_SWITCHES.inc(target);
+	}
+	let _ = hovered_tx.send(hovered);
+}
+
+fn receiver_id(receiver: &Option<PulseReceiver>) -> Option<u64> {
+	receiver.as_ref().and_then(|r| r.node().get_id().ok())
+}
+
+struct FrameNotifier(Arc<Notify>, Root);
+impl RootHandler for FrameNotifier {
+	fn frame(&mut self, _info: FrameInfo) {
+		self.0.notify_waiters();
+	}
+	fn save_state(&mut self) -> Result<ClientState> {
+		ClientState::from_root(&self.1)
+	}
+}
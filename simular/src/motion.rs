@@ -0,0 +1,28 @@
+//! Splitting up mouse motion that arrives in one big jump, e.g. after the source merged a burst of
+//! deltas while simular was busy, so handlers drawing or dragging see a line instead of a leap.
+
+// beyond this many steps the handler spends longer on the pulses than it's worth
+const MAX_STEPS: u32 = 16;
+
+/// `delta` in equal steps no longer than `max_step` pixels, or in one go if that's 0.
+pub fn steps(delta: [f32; 2], max_step: f32) -> impl Iterator<Item = [f32; 2]> {
+	let length = delta[0].hypot(delta[1]);
+	let count = if max_step > 0.0 && length.is_finite() {
+		((length / max_step).ceil() as u32).clamp(1, MAX_STEPS)
+	} else {
+		1
+	};
+	let step = delta.map(|d| d / count as f32);
+	(0..count).map(move |_| step)
+}
+
+#[test]
+fn test_steps() {
+	let steps = |delta, max_step| steps(delta, max_step).collect::<Vec<_>>();
+	assert_eq!(steps([3.0, 4.0], 10.0), vec![[3.0, 4.0]]);
+	assert_eq!(steps([30.0, 40.0], 10.0), vec![[6.0, 8.0]; 5]);
+	assert_eq!(steps([30.0, 40.0], 0.0), vec![[30.0, 40.0]]);
+	// a huge jump doesn't turn into a flood
+	assert_eq!(steps([0.0, 1000.0], 1.0).len(), MAX_STEPS as usize);
+	assert_eq!(steps([0.0, 0.0], 10.0), vec![[0.0, 0.0]]);
+}
@@ -0,0 +1,58 @@
+//! Aiming the ray a little ahead of where the head is turning, so focus keeps up with fast turns
+//! instead of landing on the last thing looked at a frame late.
+
+use glam::{Quat, Vec3};
+use std::time::{Duration, Instant};
+
+// no further ahead than this, so a jump in tracking can't send the ray off somewhere wild
+const MAX_LEAD_ANGLE: f32 = 30.0;
+
+#[derive(Debug, Default)]
+pub struct Prediction {
+	last: Option<(Quat, Instant)>,
+}
+impl Prediction {
+	/// The direction to ray march in, in the head's space, to hit what the head will be facing
+	/// `lead` from now if it keeps turning like it did since the last frame.
+	pub fn direction(&mut self, rotation: Quat, now: Instant, lead: Duration) -> Vec3 {
+		let Some((last_rotation, last_time)) = self.last.replace((rotation, now)) else {
+			return Vec3::NEG_Z;
+		};
+		let dt = now.duration_since(last_time).as_secs_f32();
+		if dt <= 0.0 {
+			return Vec3::NEG_Z;
+		}
+		let mut turn = rotation * last_rotation.inverse();
+		// the short way round
+		if turn.w < 0.0 {
+			turn = -turn;
+		}
+		let (axis, angle) = turn.to_axis_angle();
+		let ahead = (angle * lead.as_secs_f32() / dt).min(MAX_LEAD_ANGLE.to_radians());
+		let predicted = Quat::from_axis_angle(axis, ahead) * rotation;
+		(rotation.inverse() * predicted * Vec3::NEG_Z).normalize()
+	}
+}
+
+#[test]
+fn test_prediction() {
+	let start = Instant::now();
+	let frame = |n: u32| start + Duration::from_millis(10) * n;
+	let lead = Duration::from_millis(100);
+	let mut prediction = Prediction::default();
+	assert_eq!(
+		prediction.direction(Quat::IDENTITY, frame(0), lead),
+		Vec3::NEG_Z
+	);
+	// holding still keeps looking straight ahead
+	let still = prediction.direction(Quat::IDENTITY, frame(1), lead);
+	assert!(still.abs_diff_eq(Vec3::NEG_Z, 0.0001));
+	// turning left at 90 degrees a second looks 9 degrees further left
+	let left = prediction.direction(Quat::from_rotation_y(0.9_f32.to_radians()), frame(2), lead);
+	let expected = Quat::from_rotation_y(9.0_f32.to_radians()) * Vec3::NEG_Z;
+	assert!(left.abs_diff_eq(expected, 0.0001), "{left}");
+	// but never too far
+	let whip = prediction.direction(Quat::from_rotation_y(1.5), frame(3), lead);
+	let limit = Quat::from_rotation_y(MAX_LEAD_ANGLE.to_radians()) * Vec3::NEG_Z;
+	assert!(whip.abs_diff_eq(limit, 0.0001), "{whip}");
+}
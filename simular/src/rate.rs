@@ -0,0 +1,103 @@
+//! Capping how often a handler is sent motion and scrolling, merging whatever comes in between, so
+//! a chatty source can't bury an app in pulses.
+
+use std::time::{Duration, Instant};
+
+/// Motion and scrolling for one handler, added up while it waits its turn.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Motion {
+	pub delta: [f32; 2],
+	pub scroll_continuous: [f32; 2],
+	pub scroll_discrete: [f32; 2],
+}
+impl Motion {
+	fn add(&mut self, other: Motion) {
+		for i in 0..2 {
+			self.delta[i] += other.delta[i];
+			self.scroll_continuous[i] += other.scroll_continuous[i];
+			self.scroll_discrete[i] += other.scroll_discrete[i];
+		}
+	}
+
+	fn is_empty(&self) -> bool {
+		*self == Motion::default()
+	}
+}
+
+/// When a handler was last sent motion, and what it's still owed.
+#[derive(Debug, Default)]
+pub struct RateLimit {
+	last_sent: Option<Instant>,
+	pending: Motion,
+}
+impl RateLimit {
+	/// Add `motion`, returning everything owed if it's been long enough since the last send for
+	/// `max_per_second` sends a second, where 0 means no limit.
+	pub fn push(&mut self, motion: Motion, max_per_second: f32, now: Instant) -> Option<Motion> {
+		self.pending.add(motion);
+		if self
+			.next_send(max_per_second)
+			.is_some_and(|next| next > now)
+		{
+			return None;
+		}
+		self.last_sent = Some(now);
+		self.take()
+	}
+
+	/// When what's owed can be sent, if anything is.
+	pub fn due(&self, max_per_second: f32) -> Option<Instant> {
+		if self.pending.is_empty() {
+			return None;
+		}
+		self.next_send(max_per_second).or(self.last_sent)
+	}
+
+	fn next_send(&self, max_per_second: f32) -> Option<Instant> {
+		if max_per_second <= 0.0 {
+			return None;
+		}
+		let interval = Duration::from_secs_f32(1.0 / max_per_second);
+		self.last_sent.map(|last_sent| last_sent + interval)
+	}
+
+	/// Everything owed, regardless of the limit, e.g. so it goes before a click.
+	pub fn take(&mut self) -> Option<Motion> {
+		let pending = std::mem::take(&mut self.pending);
+		(!pending.is_empty()).then_some(pending)
+	}
+
+	/// Whether there's nothing owed and nothing has been sent in a while, so it can be forgotten.
+	pub fn idle(&self, now: Instant) -> bool {
+		self.pending.is_empty()
+			&& self.last_sent.map_or(true, |last_sent| {
+				now.duration_since(last_sent) > Duration::from_secs(1)
+			})
+	}
+}
+
+#[test]
+fn test_rate_limit() {
+	let start = Instant::now();
+	let at = |ms: u64| start + Duration::from_millis(ms);
+	let motion = |x: f32| Motion {
+		delta: [x, 0.0],
+		..Default::default()
+	};
+	let mut limit = RateLimit::default();
+	assert_eq!(limit.push(motion(1.0), 100.0, at(0)), Some(motion(1.0)));
+	// within 10ms of the last send, so it's held on to and merged
+	assert_eq!(limit.push(motion(2.0), 100.0, at(4)), None);
+	assert_eq!(limit.push(motion(3.0), 100.0, at(8)), None);
+	assert_eq!(limit.due(100.0), Some(at(10)));
+	assert_eq!(limit.push(motion(4.0), 100.0, at(12)), Some(motion(9.0)));
+	assert_eq!(limit.due(100.0), None);
+	// and taken early for a click
+	assert_eq!(limit.push(motion(1.0), 100.0, at(14)), None);
+	assert_eq!(limit.take(), Some(motion(1.0)));
+	assert_eq!(limit.take(), None);
+	// no limit at all
+	assert_eq!(limit.push(motion(5.0), 0.0, at(15)), Some(motion(5.0)));
+	assert!(!limit.idle(at(15)));
+	assert!(limit.idle(at(2000)));
+}
@@ -0,0 +1,174 @@
+use clap::Parser;
+use color_eyre::Result;
+use ipc::{
+	frame_from_stamped, merge_motion, message_from_frame, receive_frame_async_ipc,
+	socket::write_frame, stamp_from_frame, Message, Stamp,
+};
+use logging::{warn, LogArgs};
+use std::{
+	collections::VecDeque,
+	io::IsTerminal,
+	process::Stdio,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+};
+use tokio::{io::AsyncWrite, process::Command, sync::Notify};
+
+// how many messages a sink can fall behind before it's cut off
+const QUEUE_LIMIT: usize = 4096;
+
+#[derive(Debug, Parser)]
+#[command(about = "Duplicate the input stream on stdin to several sinks")]
+struct Args {
+	/// Sink to pass the stream to, run with `sh -c`, or - for stdout. Repeat for each sink
+	#[arg(long, value_name = "COMMAND", required = true)]
+	to: Vec<String>,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() {
+		panic!("You need to pipe an input source into this e.g. `eclipse | split --to azimuth --to simular`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+
+	let mut queues = Vec::new();
+	let mut writers = Vec::new();
+	let mut children = Vec::new();
+	for sink in args.to {
+		let queue = Arc::new(Queue::new(sink.clone()));
+		if sink == "-" {
+			writers.push(tokio::spawn(write_sink(queue.clone(), tokio::io::stdout())));
+		} else {
+			let mut child = Command::new("sh")
+				.arg("-c")
+				.arg(&sink)
+				.stdin(Stdio::piped())
+				.spawn()?;
+			let stdin = child.stdin.take().unwrap();
+			writers.push(tokio::spawn(write_sink(queue.clone(), stdin)));
+			children.push(child);
+		}
+		queues.push(queue);
+	}
+
+	while let Ok(frame) = receive_frame_async_ipc().await {
+		let message = message_from_frame(&frame)
+			.ok()
+			.map(|message| (message, stamp_from_frame(&frame)));
+		for queue in &queues {
+			queue.push(frame.clone(), message.as_ref());
+		}
+	}
+	// let every sink catch up before closing its input
+	for queue in &queues {
+		queue.end();
+	}
+	for writer in writers {
+		writer.await?;
+	}
+	for mut child in children {
+		child.wait().await?;
+	}
+	Ok(())
+}
+
+async fn write_sink(queue: Arc<Queue>, mut writer: impl AsyncWrite + Unpin) {
+	while let Some(frame) = queue.pop().await {
+		if let Err(e) = write_frame(&mut writer, &frame).await {
+			warn!("{} stopped taking input: {e}", queue.sink);
+			queue.cut_off();
+			return;
+		}
+	}
+}
+
+/// A frame, and the message in it with its stamp if it's one.
+type Queued = (Vec<u8>, Option<(Message, Stamp)>);
+
+/// Frames waiting to be written to one sink.
+#[derive(Debug)]
+struct Queue {
+	sink: String,
+	frames: Mutex<VecDeque<Queued>>,
+	notify: Notify,
+	ended: AtomicBool,
+	cut_off: AtomicBool,
+}
+impl Queue {
+	fn new(sink: String) -> Self {
+		Queue {
+			sink,
+			frames: Mutex::default(),
+			notify: Notify::new(),
+			ended: AtomicBool::new(false),
+			cut_off: AtomicBool::new(false),
+		}
+	}
+
+	/// Queue a frame, folding motion and scrolling into the previous frame if that's still waiting too.
+	fn push(&self, frame: Vec<u8>, message: Option<&(Message, Stamp)>) {
+		if self.cut_off.load(Ordering::Relaxed) {
+			return;
+		}
+		let mut frames = self.frames.lock().unwrap();
+		let merged = frames
+			.back()
+			.zip(message)
+			.and_then(|((_, last), (message, stamp))| {
+				let (last, last_stamp) = last.as_ref()?;
+				merge_motion((last, *last_stamp), (message, *stamp))
+			});
+		if let Some((merged, stamp)) = merged {
+			let frame = frame_from_stamped(&merged, stamp);
+			*frames.back_mut().unwrap() = (frame, Some((merged, stamp)));
+			return;
+		}
+		if frames.len() >= QUEUE_LIMIT {
+			// dropping keys or buttons would leave the sink in a state the source never had
+			warn!(
+				"{} fell more than {QUEUE_LIMIT} messages behind, cutting it off",
+				self.sink
+			);
+			frames.clear();
+			drop(frames);
+			self.cut_off();
+			return;
+		}
+		frames.push_back((frame, message.cloned()));
+		drop(frames);
+		self.notify.notify_one();
+	}
+
+	/// The next frame to write, or `None` once the stream has ended and everything's been written.
+	async fn pop(&self) -> Option<Vec<u8>> {
+		loop {
+			if self.cut_off.load(Ordering::Relaxed) {
+				return None;
+			}
+			if let Some((frame, _)) = self.frames.lock().unwrap().pop_front() {
+				return Some(frame);
+			}
+			if self.ended.load(Ordering::Relaxed) {
+				return None;
+			}
+			self.notify.notified().await;
+		}
+	}
+
+	fn end(&self) {
+		self.ended.store(true, Ordering::Relaxed);
+		self.notify.notify_one();
+	}
+
+	fn cut_off(&self) {
+		self.cut_off.store(true, Ordering::Relaxed);
+		self.notify.notify_one();
+	}
+}
@@ -0,0 +1,265 @@
+use clap::{Parser, ValueEnum};
+use color_eyre::{eyre::eyre, Result};
+use ipc::{
+	codec::Codecs,
+	frame_from_message, message_from_frame, receive_pooled_frame_async_ipc,
+	reverse::{self, Upstream},
+	send_frame_async_ipc, send_frame_ipc,
+	socket::{read_frame, write_frame},
+	Error, Message,
+};
+use logging::{debug, warn, LogArgs};
+use std::{io::IsTerminal, os::fd::AsFd, process::Stdio};
+use tokio::{
+	process::{Child, Command},
+	sync::mpsc,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "Run a source (or sink) on another machine over SSH and bridge its input stream")]
+struct Args {
+	/// Where to run the command, anything `ssh` accepts
+	#[arg(required_unless_present = "bridge")]
+	destination: Option<String>,
+	/// Send the stream on stdin to a sink on the remote machine instead of bringing a source's stream here
+	#[arg(long)]
+	sink: bool,
+	/// Run the command as it is, for remote machines without stardust-input-ssh, which leaves out the reverse channel
+	#[arg(long)]
+	direct: bool,
+	/// Extra argument to pass to ssh, e.g. `--ssh-arg=-p2222`. Repeat for each
+	#[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+	ssh_arg: Vec<String>,
+	/// What the remote end runs: the command, with its stream on stdout or stdin and its reverse channel on the other
+	#[arg(long, value_enum, hide = true)]
+	bridge: Option<Bridge>,
+	/// Command to run remotely, eclipse for a source by default
+	#[arg(last = true)]
+	command: Vec<String>,
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Bridge {
+	Source,
+	Sink,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	let command = if !args.command.is_empty() {
+		args.command.join(" ")
+	} else if args.sink || args.bridge == Some(Bridge::Sink) {
+		return Err(eyre!("Give the sink to run after --, e.g. `-- azimuth`"));
+	} else {
+		"eclipse".to_string()
+	};
+	match args.bridge {
+		Some(Bridge::Source) => return bridge_source(&command).await,
+		Some(Bridge::Sink) => return bridge_sink(&command).await,
+		None => (),
+	}
+	// the remote end runs this too, to pass the reverse channel back and forth over ssh
+	let remote = if args.direct {
+		command
+	} else {
+		let bridge = if args.sink { "sink" } else { "source" };
+		format!("stardust-input-ssh --bridge {bridge} -- {command}")
+	};
+
+	let mut ssh = Command::new("ssh");
+	// -T since the remote command's output is a binary stream, not a terminal
+	ssh.arg("-T")
+		.args(&args.ssh_arg)
+		.arg(args.destination.unwrap())
+		.arg("--")
+		.arg(&remote)
+		.kill_on_drop(true);
+	if args.sink {
+		if std::io::stdin().is_terminal() {
+			panic!("You need to pipe an input source into this e.g. `eclipse | stardust-input-ssh --sink host -- azimuth`");
+		}
+		if !args.direct {
+			ssh.stdout(Stdio::piped());
+		}
+		push(ssh.stdin(Stdio::piped()).spawn()?).await
+	} else {
+		if std::io::stdout().is_terminal() {
+			panic!(
+				"You need to pipe this into an input sink e.g. `stardust-input-ssh host | azimuth`"
+			);
+		}
+		if !args.direct {
+			ssh.stdin(Stdio::piped());
+		}
+		pull(ssh.stdout(Stdio::piped()).spawn()?).await
+	}
+}
+
+/// Pass the remote source's stream on to stdout, and what the sink sends back to the source.
+async fn pull(mut ssh: Child) -> Result<()> {
+	let mut remote = ssh.stdout.take().unwrap();
+	if let Some(mut reverse) = ssh.stdin.take() {
+		let (reverse_tx, mut reverse_rx) = mpsc::unbounded_channel();
+		let listened = reverse::listen(move |mut message| {
+			// frames are read on the way for a disconnect, so the source has to write ones this reads
+			if let Message::Hello { codecs, .. } = &mut message {
+				*codecs = *codecs & Codecs::supported();
+			}
+			let _ = reverse_tx.send(frame_from_message(&message));
+		});
+		if let Err(e) = listened {
+			warn!("Not passing what the sink sends back on to the source: {e}");
+		}
+		tokio::spawn(async move {
+			while let Some(frame) = reverse_rx.recv().await {
+				if write_frame(&mut reverse, &frame).await.is_err() {
+					return;
+				}
+			}
+		});
+	}
+	loop {
+		tokio::select! {
+			frame = read_frame(&mut remote) => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				// as it is, so its stamps make it through
+				match send_frame_async_ipc(&frame).await {
+					Ok(()) => (),
+					// and ssh with it, once it's dropped
+					Err(Error::Disconnected) => return Ok(()),
+					Err(e) => warn!("{e}"),
+				}
+				if let Ok(Message::Disconnect) = message_from_frame(&frame) {
+					return Ok(());
+				}
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	// the sink releases whatever the source held when the connection dropped
+	ipc::shutdown::end_stream();
+	finish(ssh).await
+}
+
+/// Pass the stream on stdin to the remote sink, and what it sends back to the source.
+async fn push(mut ssh: Child) -> Result<()> {
+	let mut remote = ssh.stdin.take().unwrap();
+	if let Some(mut reverse) = ssh.stdout.take() {
+		tokio::spawn(async move {
+			while let Ok(frame) = read_frame(&mut reverse).await {
+				let Ok(message) = message_from_frame(&frame) else {
+					warn!("Ignoring a frame from the sink that isn't a message");
+					continue;
+				};
+				if let Err(e) = reverse::send_upstream(&message) {
+					debug!("Couldn't pass {} on upstream: {e}", message.name());
+				}
+			}
+		});
+	}
+	loop {
+		tokio::select! {
+			frame = receive_pooled_frame_async_ipc() => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				if write_frame(&mut remote, &frame).await.is_err() {
+					break;
+				}
+			}
+			_ = ipc::shutdown::signalled() => {
+				// the sink can't release what's held once ssh is gone, so ask it to now
+				let reset = frame_from_message(&Message::ResetInput);
+				let _ = write_frame(&mut remote, &reset).await;
+				break;
+			}
+		}
+	}
+	// closing its stdin lets the sink finish up on its own
+	drop(remote);
+	finish(ssh).await
+}
+
+/// On the remote machine, pass the source's stream on to stdout and what comes back on stdin to
+/// the source.
+async fn bridge_source(command: &str) -> Result<()> {
+	let mut source = Command::new("sh")
+		.arg("-c")
+		.arg(command)
+		// stdin is what the sink sends back
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.kill_on_drop(true)
+		.spawn()?;
+	let mut stream = source.stdout.take().unwrap();
+	let mut upstream = Upstream::new(stream.as_fd())?;
+	// in its own task, since a frame half read from the source when one came back first would be lost
+	let mut reverse = tokio::spawn(async move {
+		while let Ok(frame) = receive_pooled_frame_async_ipc().await {
+			if let Err(e) = upstream.send_frame(&frame) {
+				debug!("Couldn't pass a frame back to the source: {e}");
+			}
+		}
+	});
+	loop {
+		tokio::select! {
+			frame = read_frame(&mut stream) => {
+				let Ok(frame) = frame else {
+					break;
+				};
+				match send_frame_async_ipc(&frame).await {
+					Ok(()) => (),
+					Err(Error::Disconnected) => break,
+					Err(e) => warn!("{e}"),
+				}
+			}
+			// the other end is gone
+			_ = &mut reverse => break,
+		}
+	}
+	drop(stream);
+	source.wait().await?;
+	Ok(())
+}
+
+/// On the remote machine, pass the stream on stdin to the sink and what it sends back to stdout.
+async fn bridge_sink(command: &str) -> Result<()> {
+	let mut sink = Command::new("sh")
+		.arg("-c")
+		.arg(command)
+		.stdin(Stdio::piped())
+		// stdout is what goes back to the source
+		.stdout(std::io::stderr())
+		.kill_on_drop(true)
+		.spawn()?;
+	let mut stream = sink.stdin.take().unwrap();
+	reverse::listen_to(stream.as_fd(), |frame| {
+		if let Err(e) = send_frame_ipc(frame) {
+			debug!("Couldn't pass a frame back to the source: {e}");
+		}
+	})?;
+	while let Ok(frame) = receive_pooled_frame_async_ipc().await {
+		if write_frame(&mut stream, &frame).await.is_err() {
+			break;
+		}
+	}
+	// closing its stdin lets the sink finish up on its own
+	drop(stream);
+	sink.wait().await?;
+	Ok(())
+}
+
+async fn finish(mut ssh: Child) -> Result<()> {
+	let status = ssh.wait().await?;
+	if !status.success() {
+		return Err(eyre!("ssh exited with {status}"));
+	}
+	Ok(())
+}
@@ -0,0 +1,103 @@
+use clap::{Parser, Subcommand};
+use color_eyre::Result;
+use std::{
+	os::unix::fs::symlink,
+	path::{Path, PathBuf},
+};
+
+// names `link` makes symlinks under, each one an alias of a subcommand below
+const TOOLS: &[&str] = &[
+	#[cfg(feature = "eclipse")]
+	"eclipse",
+	#[cfg(feature = "manifold")]
+	"manifold",
+	#[cfg(feature = "azimuth")]
+	"azimuth",
+	#[cfg(feature = "simular")]
+	"simular",
+	#[cfg(feature = "display")]
+	"display",
+];
+
+#[derive(Debug, Parser)]
+#[command(multicall = true)]
+enum Cli {
+	/// Every non-spatial input tool in one binary
+	#[command(name = "stardust-input", arg_required_else_help = true)]
+	StardustInput {
+		#[command(subcommand)]
+		tool: Tool,
+	},
+	// run through a symlink named after a tool
+	#[command(flatten)]
+	Tool(Tool),
+}
+
+#[derive(Debug, Subcommand)]
+enum Tool {
+	/// Read keyboards and mice with libinput and write their input to stdout (eclipse)
+	#[cfg(feature = "eclipse")]
+	#[command(alias = "eclipse")]
+	Capture(eclipse::Args),
+	/// Take input from a window on your desktop and write it to stdout (manifold)
+	#[cfg(feature = "manifold")]
+	#[command(alias = "manifold")]
+	Window(stardust_xr_manifold::Args),
+	/// Desktop style pointer all around you from the input on stdin (azimuth)
+	#[cfg(feature = "azimuth")]
+	#[command(alias = "azimuth")]
+	Pointer(azimuth::Args),
+	/// Beam the mouse and keyboard input on stdin to wherever you're looking (simular)
+	#[cfg(feature = "simular")]
+	#[command(alias = "simular")]
+	Beam(simular::Args),
+	/// Print the messages going through a non-spatial input pipe (display)
+	#[cfg(feature = "display")]
+	#[command(alias = "display")]
+	Inspect(display::Args),
+	/// Make a symlink to this binary for each tool, so they can be run by their usual names
+	Link {
+		/// Where to put the symlinks, next to this binary by default
+		dir: Option<PathBuf>,
+	},
+}
+
+fn main() -> Result<()> {
+	let tool = match Cli::parse() {
+		Cli::StardustInput { tool } | Cli::Tool(tool) => tool,
+	};
+	match tool {
+		#[cfg(feature = "eclipse")]
+		Tool::Capture(args) => eclipse::run(args),
+		#[cfg(feature = "manifold")]
+		Tool::Window(args) => stardust_xr_manifold::run(args),
+		#[cfg(feature = "azimuth")]
+		Tool::Pointer(args) => azimuth::run(args)?,
+		#[cfg(feature = "simular")]
+		Tool::Beam(args) => simular::run(args)?,
+		#[cfg(feature = "display")]
+		Tool::Inspect(args) => display::run(args)?,
+		Tool::Link { dir } => link(dir)?,
+	}
+	Ok(())
+}
+
+fn link(dir: Option<PathBuf>) -> Result<()> {
+	let exe = std::env::current_exe()?;
+	let dir = dir.unwrap_or_else(|| exe.parent().unwrap_or(Path::new(".")).to_path_buf());
+	for tool in TOOLS {
+		let path = dir.join(tool);
+		match path.symlink_metadata() {
+			Ok(metadata) if metadata.is_symlink() => std::fs::remove_file(&path)?,
+			// a separately installed binary of the same name is left alone
+			Ok(_) => {
+				eprintln!("Not replacing {}, it isn't a symlink", path.display());
+				continue;
+			}
+			Err(_) => (),
+		}
+		symlink(&exe, &path)?;
+		eprintln!("Linked {}", path.display());
+	}
+	Ok(())
+}
@@ -0,0 +1,242 @@
+mod protocol;
+
+use clap::Parser;
+use color_eyre::{eyre::eyre, Result};
+use input_event_codes::{BTN_EXTRA, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_SIDE};
+use ipc::{send_input_ipc, socket::write_frame, Capabilities, Error, Message};
+use logging::{info, warn, LogArgs};
+use protocol::{ServerMessage, DEFAULT_PORT};
+use std::{collections::HashMap, io::IsTerminal, time::Duration};
+use tokio::{net::TcpStream, time::timeout};
+use xkbcommon::xkb::{utf32_to_keysym, Context, Keymap, KEYMAP_FORMAT_TEXT_V1};
+
+// the server sends a keep alive every 3 seconds, so a few missed means it's gone
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+// how long to wait before trying to reach the server again
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+// evdev keycodes are offset by 8 in xkb
+const EVDEV_OFFSET: u32 = 8;
+// one wheel detent, as in Windows
+const WHEEL_PER_DETENT: f32 = 120.0;
+
+#[derive(Debug, Parser)]
+#[command(
+	about = "Be a screen for an input-leap/Barrier/Synergy server and write its input to stdout"
+)]
+struct Args {
+	/// Server to connect to, the port defaults to 24800
+	address: String,
+	/// Screen name, which has to match one in the server's layout
+	#[arg(long, default_value = "stardust")]
+	name: String,
+	/// Screen size to tell the server, which only changes how far the cursor can travel
+	#[arg(long, value_name = "WxH", default_value = "1920x1080", value_parser = parse_size)]
+	size: (i16, i16),
+	#[command(flatten)]
+	log: LogArgs,
+}
+
+fn parse_size(size: &str) -> Result<(i16, i16), String> {
+	let (width, height) = size.split_once('x').ok_or("expected WxH")?;
+	Ok((
+		width.parse().map_err(|_| "invalid width")?,
+		height.parse().map_err(|_| "invalid height")?,
+	))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdout().is_terminal() {
+		panic!("You need to pipe this into an input sink e.g. `transit server | azimuth`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	let address = if args.address.contains(':') {
+		args.address.clone()
+	} else {
+		format!("{}:{DEFAULT_PORT}", args.address)
+	};
+
+	let keymap = Keymap::new_from_names(&Context::new(0), "evdev", "", "", "", None, 0).unwrap();
+	let mut screen = Screen::new(&keymap, args.size);
+	ipc::handshake::hello(Capabilities::KEYBOARD | Capabilities::POINTER)?;
+	ipc::heartbeat::start_heartbeat();
+	send_input_ipc(Message::Keymap(keymap.get_as_string(KEYMAP_FORMAT_TEXT_V1)))?;
+
+	// the server comes and goes with the machine it's on, so keep trying to reach it
+	loop {
+		tokio::select! {
+			result = session(&address, &args.name, &mut screen) => match result {
+				Ok(Some(reason)) => {
+					ipc::shutdown::end_stream();
+					return Err(eyre!("{address} refused {}: {reason}", args.name));
+				}
+				Ok(None) => warn!("{address} closed the connection"),
+				// nothing the server sends gets anywhere anymore
+				Err(e) if matches!(e.downcast_ref(), Some(Error::Disconnected)) => break,
+				Err(e) => warn!("Lost {address}: {e}"),
+			},
+			_ = ipc::shutdown::signalled() => break,
+		}
+		match screen.leave() {
+			Ok(()) => (),
+			Err(Error::Disconnected) => break,
+			Err(e) => warn!("{e}"),
+		}
+		tokio::select! {
+			_ = tokio::time::sleep(RECONNECT_DELAY) => (),
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	ipc::shutdown::end_stream();
+	Ok(())
+}
+
+/// Talk to the server until it goes away or the sink does, returning why if it refused us.
+async fn session(address: &str, name: &str, screen: &mut Screen) -> Result<Option<&'static str>> {
+	let mut stream = TcpStream::connect(address).await?;
+	let hello = timeout(KEEP_ALIVE_TIMEOUT, protocol::read_packet(&mut stream)).await??;
+	let Some((protocol, major, minor)) = protocol::parse_hello(&hello) else {
+		return Ok(Some(
+			"it isn't an input-leap server, or it has TLS turned on",
+		));
+	};
+	write_frame(&mut stream, &protocol::hello_back(protocol, name)).await?;
+	info!("Connected to {address} ({protocol} protocol {major}.{minor})");
+
+	loop {
+		let packet = timeout(KEEP_ALIVE_TIMEOUT, protocol::read_packet(&mut stream)).await??;
+		let Some(message) = protocol::parse(&packet) else {
+			continue;
+		};
+		match message {
+			ServerMessage::QueryInfo => {
+				write_frame(&mut stream, &protocol::info(screen.size.0, screen.size.1)).await?
+			}
+			ServerMessage::KeepAlive => write_frame(&mut stream, &protocol::keep_alive()).await?,
+			ServerMessage::Close => return Ok(None),
+			ServerMessage::Refused(reason) => return Ok(Some(reason)),
+			message => screen.handle(message)?,
+		}
+	}
+}
+
+/// Turns what the server sends the screen into messages.
+struct Screen {
+	size: (i16, i16),
+	/// Where to find each keysym, since the server sends those rather than keycodes it could agree on.
+	keycodes: HashMap<u32, u32>,
+	/// What each of the server's pressed buttons turned into, so the release matches even if the keysym doesn't.
+	held_keys: HashMap<u16, u32>,
+	/// The cursor's last position, the server only sends absolute ones.
+	cursor: Option<(i16, i16)>,
+}
+impl Screen {
+	fn new(keymap: &Keymap, size: (i16, i16)) -> Self {
+		let mut keycodes = HashMap::new();
+		keymap.key_for_each(|keymap, keycode| {
+			for level in 0..keymap.num_levels_for_key(keycode, 0) {
+				for keysym in keymap.key_get_syms_by_level(keycode, 0, level) {
+					// prefer the key with the keysym on its lowest level, so shift isn't needed
+					keycodes
+						.entry(keysym.raw())
+						.or_insert(keycode.raw() - EVDEV_OFFSET);
+				}
+			}
+		});
+		Screen {
+			size,
+			keycodes,
+			held_keys: HashMap::new(),
+			cursor: None,
+		}
+	}
+
+	fn handle(&mut self, message: ServerMessage) -> Result<(), Error> {
+		match message {
+			ServerMessage::Enter { x, y } => {
+				self.cursor = Some((x, y));
+				info!("Cursor entered");
+				Ok(())
+			}
+			ServerMessage::Leave => {
+				info!("Cursor left");
+				self.leave()
+			}
+			ServerMessage::KeyDown { id, button } => {
+				let Some(&keycode) = self.keycodes.get(&keysym(id)) else {
+					warn!("No key for input-leap key {id:#06x}");
+					return Ok(());
+				};
+				self.held_keys.insert(button, keycode);
+				send_input_ipc(Message::Key {
+					keycode,
+					pressed: true,
+				})
+			}
+			ServerMessage::KeyUp { button, .. } => match self.held_keys.remove(&button) {
+				Some(keycode) => send_input_ipc(Message::Key {
+					keycode,
+					pressed: false,
+				}),
+				None => Ok(()),
+			},
+			ServerMessage::MouseDown(button) | ServerMessage::MouseUp(button) => {
+				let Some(button_code) = mouse_button(button) else {
+					return Ok(());
+				};
+				send_input_ipc(Message::MouseButton {
+					button: button_code,
+					pressed: matches!(message, ServerMessage::MouseDown(_)),
+				})
+			}
+			ServerMessage::MouseMove { x, y } => match self.cursor.replace((x, y)) {
+				Some((last_x, last_y)) => {
+					let delta = [x as f32 - last_x as f32, y as f32 - last_y as f32];
+					send_input_ipc(Message::MouseMove(delta.into()))
+				}
+				None => Ok(()),
+			},
+			ServerMessage::MouseRelativeMove { dx, dy } => {
+				send_input_ipc(Message::MouseMove([dx as f32, dy as f32].into()))
+			}
+			// positive is right and up here, but right and down in the stream
+			ServerMessage::MouseWheel { dx, dy } => send_input_ipc(Message::MouseAxisDiscrete(
+				[
+					dx as f32 / WHEEL_PER_DETENT,
+					-(dy as f32) / WHEEL_PER_DETENT,
+				]
+				.into(),
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	/// The server doesn't always send releases for what's held when the cursor leaves.
+	fn leave(&mut self) -> Result<(), Error> {
+		self.held_keys.clear();
+		self.cursor = None;
+		send_input_ipc(Message::ResetInput)
+	}
+}
+
+/// The keysym for an input-leap key ID, which is either Unicode or a function key.
+fn keysym(id: u16) -> u32 {
+	match id {
+		// X's 0xFFxx function keysyms, moved down to 0xEFxx to fit
+		0xE000..=0xEFFF => id as u32 | 0x1000,
+		_ => utf32_to_keysym(id as u32).raw(),
+	}
+}
+
+fn mouse_button(button: u8) -> Option<u32> {
+	Some(match button {
+		1 => BTN_LEFT!(),
+		2 => BTN_MIDDLE!(),
+		3 => BTN_RIGHT!(),
+		4 => BTN_SIDE!(),
+		5 => BTN_EXTRA!(),
+		_ => return None,
+	})
+}
@@ -0,0 +1,194 @@
+//! Just enough of the Synergy protocol (as spoken by input-leap and Barrier) to be a screen.
+//!
+//! Every packet is a `u32` BE length followed by a 4 letter code and its big endian arguments,
+//! apart from the hello which starts with the protocol's name instead.
+
+use std::io::{self, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// what input-leap and Barrier listen on
+pub const DEFAULT_PORT: u16 = 24800;
+// 1.6 is the newest version that doesn't need the keyboard language sent along with keys
+pub const PROTOCOL_VERSION: (u16, u16) = (1, 6);
+// input-leap drops a connection that sends a bigger packet than this, so it never sends one either
+const MAX_PACKET_SIZE: u32 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerMessage {
+	QueryInfo,
+	InfoAck,
+	KeepAlive,
+	Enter {
+		x: i16,
+		y: i16,
+	},
+	Leave,
+	KeyDown {
+		id: u16,
+		button: u16,
+	},
+	KeyUp {
+		id: u16,
+		button: u16,
+	},
+	MouseDown(u8),
+	MouseUp(u8),
+	MouseMove {
+		x: i16,
+		y: i16,
+	},
+	MouseRelativeMove {
+		dx: i16,
+		dy: i16,
+	},
+	MouseWheel {
+		dx: i16,
+		dy: i16,
+	},
+	Close,
+	/// The server refused this screen, with why.
+	Refused(&'static str),
+	/// Clipboard, options, screensaver and the like, none of which apply here.
+	Ignored,
+}
+
+/// Read the next packet, refusing one too big to be from input-leap rather than making room for it.
+pub async fn read_packet(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+	let length = reader.read_u32().await?;
+	if length > MAX_PACKET_SIZE {
+		return Err(io::Error::new(
+			ErrorKind::InvalidData,
+			format!("the server sent a {length} byte packet"),
+		));
+	}
+	let mut packet = vec![0_u8; length as usize];
+	reader.read_exact(&mut packet).await?;
+	Ok(packet)
+}
+
+/// The protocol name ("Barrier" or "Synergy") and version from the server's hello.
+pub fn parse_hello(packet: &[u8]) -> Option<(&str, u16, u16)> {
+	let name = std::str::from_utf8(packet.get(..7)?).ok()?;
+	let mut args = Args(&packet[7..]);
+	Some((name, args.u16()?, args.u16()?))
+}
+
+/// Reply to the hello with the same protocol name, our version and the screen's name.
+pub fn hello_back(protocol: &str, name: &str) -> Vec<u8> {
+	let mut packet = protocol.as_bytes().to_vec();
+	packet.extend(PROTOCOL_VERSION.0.to_be_bytes());
+	packet.extend(PROTOCOL_VERSION.1.to_be_bytes());
+	packet.extend((name.len() as u32).to_be_bytes());
+	packet.extend(name.as_bytes());
+	packet
+}
+
+/// Describe the screen, with the cursor in the middle of it.
+pub fn info(width: i16, height: i16) -> Vec<u8> {
+	let mut packet = b"DINF".to_vec();
+	// position, size, the long obsolete warp zone size, then the cursor position
+	for value in [0, 0, width, height, 0, width / 2, height / 2] {
+		packet.extend(value.to_be_bytes());
+	}
+	packet
+}
+
+pub fn keep_alive() -> Vec<u8> {
+	b"CALV".to_vec()
+}
+
+pub fn parse(packet: &[u8]) -> Option<ServerMessage> {
+	let code = packet.get(..4)?;
+	let mut args = Args(&packet[4..]);
+	Some(match code {
+		b"QINF" => ServerMessage::QueryInfo,
+		b"CIAK" => ServerMessage::InfoAck,
+		b"CALV" => ServerMessage::KeepAlive,
+		b"CINN" => ServerMessage::Enter {
+			x: args.i16()?,
+			y: args.i16()?,
+		},
+		b"COUT" => ServerMessage::Leave,
+		b"DKDN" => ServerMessage::KeyDown {
+			id: args.u16()?,
+			button: {
+				args.u16()?;
+				args.u16()?
+			},
+		},
+		b"DKUP" => ServerMessage::KeyUp {
+			id: args.u16()?,
+			button: {
+				args.u16()?;
+				args.u16()?
+			},
+		},
+		b"DMDN" => ServerMessage::MouseDown(args.u8()?),
+		b"DMUP" => ServerMessage::MouseUp(args.u8()?),
+		b"DMMV" => ServerMessage::MouseMove {
+			x: args.i16()?,
+			y: args.i16()?,
+		},
+		b"DMRM" => ServerMessage::MouseRelativeMove {
+			dx: args.i16()?,
+			dy: args.i16()?,
+		},
+		b"DMWM" => ServerMessage::MouseWheel {
+			dx: args.i16()?,
+			dy: args.i16()?,
+		},
+		b"CBYE" => ServerMessage::Close,
+		b"EICV" => ServerMessage::Refused("the server's protocol version is incompatible"),
+		b"EBSY" => ServerMessage::Refused("a screen with this name is already connected"),
+		b"EUNK" => ServerMessage::Refused("the server has no screen with this name in its layout"),
+		b"EBAD" => ServerMessage::Refused("the server couldn't understand us"),
+		_ => ServerMessage::Ignored,
+	})
+}
+
+/// Reads a packet's arguments in order.
+struct Args<'a>(&'a [u8]);
+impl Args<'_> {
+	fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+		let bytes = self.0.get(..N)?.try_into().ok()?;
+		self.0 = &self.0[N..];
+		Some(bytes)
+	}
+	fn u8(&mut self) -> Option<u8> {
+		self.take().map(u8::from_be_bytes)
+	}
+	fn u16(&mut self) -> Option<u16> {
+		self.take().map(u16::from_be_bytes)
+	}
+	fn i16(&mut self) -> Option<i16> {
+		self.take().map(i16::from_be_bytes)
+	}
+}
+
+#[test]
+fn test_parse() {
+	assert_eq!(parse_hello(b"Barrier\0\x01\0\x06"), Some(("Barrier", 1, 6)));
+	assert_eq!(
+		parse(b"DKDN\0\x61\0\0\0\x26"),
+		Some(ServerMessage::KeyDown {
+			id: 0x61,
+			button: 0x26
+		})
+	);
+	assert_eq!(
+		parse(b"DMWM\0\0\xff\x88"),
+		Some(ServerMessage::MouseWheel { dx: 0, dy: -120 })
+	);
+	assert_eq!(parse(b"DMMV\0\x01"), None);
+	assert_eq!(parse(b"DSOP\0\0\0\0"), Some(ServerMessage::Ignored));
+}
+
+#[tokio::test]
+async fn test_read_packet() {
+	let mut stream: &[u8] = b"\0\0\0\x04CALV\x7f\xff\xff\xff";
+	assert_eq!(read_packet(&mut stream).await.unwrap(), b"CALV");
+	assert_eq!(
+		read_packet(&mut stream).await.unwrap_err().kind(),
+		ErrorKind::InvalidData
+	);
+}
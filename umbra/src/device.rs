@@ -0,0 +1,131 @@
+use input_event_codes::{ABS_X, ABS_Y, EV_ABS, EV_KEY, EV_REL, EV_SYN, SYN_REPORT};
+use libc::{input_absinfo, input_event, input_id, uinput_abs_setup, uinput_setup};
+use std::{
+	fs::{File, OpenOptions},
+	io::{self, Write},
+	os::fd::{AsRawFd, RawFd},
+};
+
+// from linux/input.h, libc doesn't have the bus types
+const BUS_VIRTUAL: u16 = 0x06;
+
+nix::ioctl_none!(ui_dev_create, b'U', 1);
+nix::ioctl_none!(ui_dev_destroy, b'U', 2);
+nix::ioctl_write_ptr!(ui_dev_setup, b'U', 3, uinput_setup);
+nix::ioctl_write_ptr!(ui_abs_setup, b'U', 4, uinput_abs_setup);
+nix::ioctl_write_int!(ui_set_evbit, b'U', 100);
+nix::ioctl_write_int!(ui_set_keybit, b'U', 101);
+nix::ioctl_write_int!(ui_set_relbit, b'U', 102);
+nix::ioctl_write_int!(ui_set_absbit, b'U', 103);
+
+/// Which event codes a device can send, set up before it's created.
+#[derive(Debug, Default)]
+pub struct Capabilities {
+	pub keys: Vec<u16>,
+	pub relative_axes: Vec<u16>,
+	/// Absolute X/Y ranging from 0 to these maximums.
+	pub absolute_size: Option<[i32; 2]>,
+}
+
+/// A virtual evdev device backed by `/dev/uinput`, destroyed when dropped.
+pub struct VirtualDevice {
+	file: File,
+}
+impl VirtualDevice {
+	pub fn create(name: &str, capabilities: &Capabilities) -> io::Result<Self> {
+		let file = OpenOptions::new().write(true).open("/dev/uinput")?;
+		let fd = file.as_raw_fd();
+		// SAFETY: the fd stays open for the duration and every struct passed matches what the ioctl expects
+		unsafe { setup(fd, name, capabilities) }?;
+		Ok(VirtualDevice { file })
+	}
+
+	pub fn emit(&mut self, kind: u16, code: u16, value: i32) -> io::Result<()> {
+		// the kernel fills in the timestamp
+		let event = input_event {
+			time: libc::timeval {
+				tv_sec: 0,
+				tv_usec: 0,
+			},
+			type_: kind,
+			code,
+			value,
+		};
+		// SAFETY: input_event is plain old data
+		let bytes = unsafe {
+			std::slice::from_raw_parts(
+				&event as *const input_event as *const u8,
+				std::mem::size_of::<input_event>(),
+			)
+		};
+		self.file.write_all(bytes)
+	}
+
+	/// Mark the events emitted so far as one atomic update.
+	pub fn sync(&mut self) -> io::Result<()> {
+		self.emit(EV_SYN!(), SYN_REPORT!(), 0)
+	}
+}
+impl Drop for VirtualDevice {
+	fn drop(&mut self) {
+		let _ = unsafe { ui_dev_destroy(self.file.as_raw_fd()) };
+	}
+}
+
+unsafe fn setup(fd: RawFd, name: &str, capabilities: &Capabilities) -> nix::Result<()> {
+	if !capabilities.keys.is_empty() {
+		ui_set_evbit(fd, EV_KEY!())?;
+		for key in &capabilities.keys {
+			ui_set_keybit(fd, *key as _)?;
+		}
+	}
+	if !capabilities.relative_axes.is_empty() {
+		ui_set_evbit(fd, EV_REL!())?;
+		for axis in &capabilities.relative_axes {
+			ui_set_relbit(fd, *axis as _)?;
+		}
+	}
+	if let Some(size) = capabilities.absolute_size {
+		ui_set_evbit(fd, EV_ABS!())?;
+		for (code, maximum) in [(ABS_X!(), size[0]), (ABS_Y!(), size[1])] {
+			ui_set_absbit(fd, code as _)?;
+			ui_abs_setup(
+				fd,
+				&uinput_abs_setup {
+					code,
+					absinfo: input_absinfo {
+						value: 0,
+						minimum: 0,
+						maximum,
+						fuzz: 0,
+						flat: 0,
+						resolution: 0,
+					},
+				},
+			)?;
+		}
+	}
+
+	let mut device_name = [0; libc::UINPUT_MAX_NAME_SIZE];
+	for (c, byte) in device_name
+		.iter_mut()
+		.zip(name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1))
+	{
+		*c = byte as libc::c_char;
+	}
+	ui_dev_setup(
+		fd,
+		&uinput_setup {
+			id: input_id {
+				bustype: BUS_VIRTUAL,
+				vendor: 0,
+				product: 0,
+				version: 1,
+			},
+			name: device_name,
+			ff_effects_max: 0,
+		},
+	)?;
+	ui_dev_create(fd)?;
+	Ok(())
+}
@@ -0,0 +1,281 @@
+mod device;
+
+use clap::Parser;
+use color_eyre::Result;
+use device::{Capabilities, VirtualDevice};
+use input_event_codes::{
+	ABS_X, ABS_Y, BTN_LEFT, BTN_TASK, EV_ABS, EV_KEY, EV_REL, KEY_ESC, KEY_MICMUTE, REL_HWHEEL,
+	REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES, REL_X, REL_Y,
+};
+use ipc::{receive_input_async_ipc, Message};
+use logging::LogArgs;
+use metrics::MetricsArgs;
+use std::{collections::BTreeSet, io, io::IsTerminal};
+
+// evdev's high resolution scroll units per wheel detent
+const SCROLL_HI_RES_PER_DETENT: f32 = 120.0;
+
+#[derive(Debug, Parser)]
+#[command(about = "Inject the input stream into virtual evdev devices through uinput")]
+struct Args {
+	/// Use an absolute pointer of this size (e.g. 1920x1080) instead of a relative one, for VMs and remote desktops
+	#[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+	tablet: Option<[i32; 2]>,
+	#[command(flatten)]
+	log: LogArgs,
+	#[command(flatten)]
+	metrics: MetricsArgs,
+}
+
+fn parse_size(size: &str) -> Result<[i32; 2], String> {
+	let (width, height) = size
+		.split_once('x')
+		.ok_or_else(|| format!("expected WIDTHxHEIGHT, got {size:?}"))?;
+	let parse = |n: &str| {
+		n.parse::<i32>()
+			.ok()
+			.filter(|n| *n > 0)
+			.ok_or_else(|| format!("invalid size {n:?}"))
+	};
+	Ok([parse(width)?, parse(height)?])
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	if std::io::stdin().is_terminal() {
+		panic!("You need to pipe an input source into this e.g. `eclipse | umbra`");
+	}
+	color_eyre::install()?;
+	let args = Args::parse();
+	logging::init(&args.log)?;
+	metrics::init(&args.metrics)?;
+	let mut sink = Sink::new(args.tablet)?;
+	// all there is is a uinput keyboard and pointer
+	ipc::handshake::consume(ipc::Capabilities::KEYBOARD | ipc::Capabilities::POINTER);
+
+	loop {
+		tokio::select! {
+			message = receive_input_async_ipc() => {
+				let Ok(message) = message else {
+					break;
+				};
+				metrics::received(message.name(), ipc::queued_frames());
+				if let Message::Disconnect = message {
+					break;
+				}
+				sink.handle(message)?;
+			}
+			_ = ipc::shutdown::signalled() => break,
+		}
+	}
+	// don't leave anything held down on the desktop once the devices disappear
+	sink.release_all()?;
+	Ok(())
+}
+
+/// Translates messages into events on a virtual keyboard and pointer.
+struct Sink {
+	keyboard: VirtualDevice,
+	pointer: VirtualDevice,
+	/// Where the absolute pointer is and how big its area is, if it's a tablet.
+	tablet: Option<([f32; 2], [i32; 2])>,
+	held_keys: BTreeSet<u16>,
+	held_buttons: BTreeSet<u16>,
+	/// Sub-pixel motion and sub-unit scroll that hasn't been sent yet.
+	motion_remainder: [f32; 2],
+	scroll_remainder: [f32; 2],
+	/// High resolution scroll sent since the last whole detent, for apps that only read `REL_WHEEL`.
+	scroll_hi_res: [i32; 2],
+}
+impl Sink {
+	fn new(tablet: Option<[i32; 2]>) -> io::Result<Self> {
+		let keyboard = VirtualDevice::create(
+			"umbra keyboard",
+			&Capabilities {
+				keys: (KEY_ESC!()..=KEY_MICMUTE!()).collect(),
+				..Default::default()
+			},
+		)?;
+		let scroll_axes = vec![
+			REL_WHEEL!(),
+			REL_HWHEEL!(),
+			REL_WHEEL_HI_RES!(),
+			REL_HWHEEL_HI_RES!(),
+		];
+		let pointer = match tablet {
+			Some(size) => VirtualDevice::create(
+				"umbra tablet",
+				&Capabilities {
+					keys: (BTN_LEFT!()..=BTN_TASK!()).collect(),
+					relative_axes: scroll_axes,
+					absolute_size: Some(size.map(|n| n - 1)),
+				},
+			)?,
+			None => VirtualDevice::create(
+				"umbra pointer",
+				&Capabilities {
+					keys: (BTN_LEFT!()..=BTN_TASK!()).collect(),
+					relative_axes: [REL_X!(), REL_Y!()]
+						.into_iter()
+						.chain(scroll_axes)
+						.collect(),
+					absolute_size: None,
+				},
+			)?,
+		};
+		Ok(Sink {
+			keyboard,
+			pointer,
+			// start in the middle like a freshly warped cursor
+			tablet: tablet.map(|size| (size.map(|n| n as f32 / 2.0), size)),
+			held_keys: BTreeSet::new(),
+			held_buttons: BTreeSet::new(),
+			motion_remainder: [0.0; 2],
+			scroll_remainder: [0.0; 2],
+			scroll_hi_res: [0; 2],
+		})
+	}
+
+	fn handle(&mut self, message: Message) -> io::Result<()> {
+		match message {
+			// the desktop applies its own layout to the raw keycodes
+			Message::Keymap(_) => Ok(()),
+			Message::Key { keycode, pressed } => {
+				let Ok(keycode) = u16::try_from(keycode) else {
+					return Ok(());
+				};
+				update_held(&mut self.held_keys, keycode, pressed);
+				self.keyboard.emit(EV_KEY!(), keycode, pressed as i32)?;
+				self.keyboard.sync()
+			}
+			Message::MouseButton { button, pressed } => {
+				let Ok(button) = u16::try_from(button) else {
+					return Ok(());
+				};
+				update_held(&mut self.held_buttons, button, pressed);
+				self.pointer.emit(EV_KEY!(), button, pressed as i32)?;
+				self.pointer.sync()
+			}
+			Message::MouseMove(delta) => {
+				match &mut self.tablet {
+					Some((position, size)) => {
+						position[0] = (position[0] + delta.x).clamp(0.0, (size[0] - 1) as f32);
+						position[1] = (position[1] + delta.y).clamp(0.0, (size[1] - 1) as f32);
+						self.pointer.emit(EV_ABS!(), ABS_X!(), position[0] as i32)?;
+						self.pointer.emit(EV_ABS!(), ABS_Y!(), position[1] as i32)?;
+					}
+					None => {
+						for (axis, (code, delta)) in [(REL_X!(), delta.x), (REL_Y!(), delta.y)]
+							.into_iter()
+							.enumerate()
+						{
+							let Some(whole) = take_whole(&mut self.motion_remainder[axis], delta)
+							else {
+								continue;
+							};
+							self.pointer.emit(EV_REL!(), code, whole)?;
+						}
+					}
+				}
+				self.pointer.sync()
+			}
+			Message::MousePosition(absolute) => {
+				// a relative pointer has no idea where it is, so only a tablet can follow these
+				let Some((position, size)) = &mut self.tablet else {
+					return Ok(());
+				};
+				*position = [0, 1].map(|axis| {
+					[absolute.x, absolute.y][axis].clamp(0.0, 1.0) * (size[axis] - 1) as f32
+				});
+				self.pointer.emit(EV_ABS!(), ABS_X!(), position[0] as i32)?;
+				self.pointer.emit(EV_ABS!(), ABS_Y!(), position[1] as i32)?;
+				self.pointer.sync()
+			}
+			// both arrive in wheel detents, continuous ones just aren't whole
+			Message::MouseAxisContinuous(scroll) | Message::MouseAxisDiscrete(scroll) => {
+				// evdev's vertical wheel points up while the stream's points down
+				self.scroll([scroll.x, -scroll.y])
+			}
+			Message::ResetInput | Message::Disconnect => self.release_all(),
+			// gestures come from whatever reads a touchpad, and these devices aren't touchpads
+			Message::Magnify(_) | Message::Rotate(_) | Message::SmartMagnify => Ok(()),
+			// whatever reads the device repeats keys and counts clicks itself
+			Message::MultiClick { .. }
+			| Message::KeyRepeat { .. }
+			| Message::Heartbeat
+			| Message::Pause
+			| Message::Resume
+			| Message::Hover { .. } => Ok(()),
+			// uinput devices don't have a clipboard
+			Message::ClipboardSet(_) => Ok(()),
+			// neither device is a touchscreen, a tablet or a gamepad
+			Message::TouchDown { .. }
+			| Message::TouchMotion { .. }
+			| Message::TouchUp { .. }
+			| Message::TabletProximity { .. }
+			| Message::TabletAxis { .. }
+			| Message::TabletTip { .. }
+			| Message::TabletButton { .. }
+			| Message::GamepadConnected { .. }
+			| Message::GamepadDisconnected { .. }
+			| Message::GamepadButton { .. }
+			| Message::GamepadAxis { .. } => Ok(()),
+			// everything goes through the same two devices, whichever device it came from
+			Message::DeviceAdded { .. } | Message::DeviceRemoved { .. } => Ok(()),
+			// the handshake is taken care of while receiving
+			Message::Hello { .. } => Ok(()),
+			// every event is synced on its own already
+			Message::BatchBegin | Message::BatchEnd => Ok(()),
+		}
+	}
+
+	fn scroll(&mut self, detents: [f32; 2]) -> io::Result<()> {
+		let axes = [
+			(REL_HWHEEL!(), REL_HWHEEL_HI_RES!()),
+			(REL_WHEEL!(), REL_WHEEL_HI_RES!()),
+		];
+		for (axis, (code, hi_res_code)) in axes.into_iter().enumerate() {
+			let Some(hi_res) = take_whole(
+				&mut self.scroll_remainder[axis],
+				detents[axis] * SCROLL_HI_RES_PER_DETENT,
+			) else {
+				continue;
+			};
+			self.pointer.emit(EV_REL!(), hi_res_code, hi_res)?;
+			self.scroll_hi_res[axis] += hi_res;
+			let whole_detents = self.scroll_hi_res[axis] / SCROLL_HI_RES_PER_DETENT as i32;
+			if whole_detents != 0 {
+				self.scroll_hi_res[axis] -= whole_detents * SCROLL_HI_RES_PER_DETENT as i32;
+				self.pointer.emit(EV_REL!(), code, whole_detents)?;
+			}
+		}
+		self.pointer.sync()
+	}
+
+	fn release_all(&mut self) -> io::Result<()> {
+		for key in std::mem::take(&mut self.held_keys) {
+			self.keyboard.emit(EV_KEY!(), key, 0)?;
+		}
+		self.keyboard.sync()?;
+		for button in std::mem::take(&mut self.held_buttons) {
+			self.pointer.emit(EV_KEY!(), button, 0)?;
+		}
+		self.pointer.sync()
+	}
+}
+
+fn update_held(held: &mut BTreeSet<u16>, code: u16, pressed: bool) {
+	if pressed {
+		held.insert(code);
+	} else {
+		held.remove(&code);
+	}
+}
+
+/// Add to the remainder and take out the whole part, if there is one.
+fn take_whole(remainder: &mut f32, value: f32) -> Option<i32> {
+	*remainder += value;
+	let whole = remainder.trunc();
+	*remainder -= whole;
+	(whole != 0.0).then_some(whole as i32)
+}